@@ -1,8 +1,21 @@
 #![no_std]
+// `Err(())` means "no room"; callers never need more than that.
+#![allow(clippy::result_unit_err)]
+
+#[cfg(test)]
+#[macro_use]
+extern crate std;
 
 #[cfg(test)]
 mod tests;
 
+pub mod ring;
+mod string;
+
+pub use ring::RingBuffer;
+pub use string::StackString;
+
+use core::fmt;
 use core::iter::IntoIterator;
 use core::ops::{Deref, DerefMut};
 use core::slice;
@@ -15,7 +28,6 @@ use core::slice;
 /// result, `StackVec`'s capacity is _bounded_ by the user-supplied slice. This
 /// results in `push` being fallible: if `push` is called when the vector is
 /// full, an `Err` is returned.
-#[derive(Debug)]
 pub struct StackVec<'a, T: 'a> {
     storage: &'a mut [T],
     len: usize,
@@ -104,6 +116,56 @@ impl<'a, T: 'a> StackVec<'a, T> {
             Ok(())
         }
     }
+
+    /// Inserts `value` at position `index`, shifting all elements after it to
+    /// the right.
+    ///
+    /// # Error
+    ///
+    /// If this vector is full, an `Err` is returned and the vector is left
+    /// unchanged. Otherwise, `Ok` is returned.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index > len`.
+    pub fn insert(&mut self, index: usize, value: T) -> Result<(), ()> {
+        assert!(index <= self.len, "insertion index out of bounds");
+        if self.is_full() {
+            return Err(());
+        }
+
+        self.storage[self.len] = value;
+        self.storage[index..=self.len].rotate_right(1);
+        self.len += 1;
+        Ok(())
+    }
+
+    /// Retains only the elements for which `f` returns `true`, removing the
+    /// rest. The retained elements keep their order.
+    pub fn retain<F: FnMut(&T) -> bool>(&mut self, mut f: F) {
+        let mut kept = 0;
+        for i in 0..self.len {
+            if f(&self.storage[i]) {
+                self.storage.swap(kept, i);
+                kept += 1;
+            }
+        }
+        self.len = kept;
+    }
+
+    /// Appends the values of `iter` to the back of this vector until it's
+    /// exhausted or the vector is full.
+    ///
+    /// # Error
+    ///
+    /// If the vector fills up before `iter` is exhausted, an `Err` is returned;
+    /// the values that fit are kept. Otherwise, `Ok` is returned.
+    pub fn try_extend<I: IntoIterator<Item = T>>(&mut self, iter: I) -> Result<(), ()> {
+        for value in iter {
+            self.push(value)?;
+        }
+        Ok(())
+    }
 }
 
 impl<'a, T: Clone + 'a> StackVec<'a, T> {
@@ -118,6 +180,33 @@ impl<'a, T: Clone + 'a> StackVec<'a, T> {
             Some(t)
         }
     }
+
+    /// Removes the element at position `index` by cloning it and returns it,
+    /// shifting all elements after it to the left.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index >= len`.
+    pub fn remove(&mut self, index: usize) -> T {
+        assert!(index < self.len, "removal index out of bounds");
+        self.storage[index..self.len].rotate_left(1);
+        self.len -= 1;
+        self.storage[self.len].clone()
+    }
+
+    /// Removes the element at position `index` by cloning it and returns it,
+    /// replacing it with the last element. This doesn't preserve ordering, but
+    /// is O(1).
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index >= len`.
+    pub fn swap_remove(&mut self, index: usize) -> T {
+        assert!(index < self.len, "swap_remove index out of bounds");
+        self.storage.swap(index, self.len - 1);
+        self.len -= 1;
+        self.storage[self.len].clone()
+    }
 }
 
 // FIXME: Implement `Deref`, `DerefMut`, and `IntoIterator` for `StackVec`.
@@ -139,7 +228,7 @@ impl<'a, T> IntoIterator for StackVec<'a, T> {
     type IntoIter = slice::IterMut<'a, T>;
 
     fn into_iter(self) -> Self::IntoIter {
-        self.into_slice().into_iter()
+        self.into_slice().iter_mut()
     }
 }
 
@@ -152,3 +241,51 @@ impl<'a: 'b, 'b, T> IntoIterator for &'b StackVec<'a, T> {
         self.iter()
     }
 }
+
+impl<'a: 'b, 'b, T> IntoIterator for &'b mut StackVec<'a, T> {
+    type Item = &'b mut T;
+    type IntoIter = core::slice::IterMut<'b, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter_mut()
+    }
+}
+
+/// Like `Vec`'s, except that the vector can fill up.
+///
+/// # Panics
+///
+/// Panics if the vector fills up before the iterator is exhausted. Use
+/// `try_extend` to handle that instead.
+impl<'a, T> Extend<T> for StackVec<'a, T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        self.try_extend(iter).expect("StackVec is full")
+    }
+}
+
+impl<'a, 'b, T: PartialEq> PartialEq<StackVec<'b, T>> for StackVec<'a, T> {
+    fn eq(&self, other: &StackVec<'b, T>) -> bool {
+        self.as_slice() == other.as_slice()
+    }
+}
+
+impl<'a, T: Eq> Eq for StackVec<'a, T> {}
+
+impl<'a, T: PartialEq> PartialEq<[T]> for StackVec<'a, T> {
+    fn eq(&self, other: &[T]) -> bool {
+        self.as_slice() == other
+    }
+}
+
+impl<'a, 'b, T: PartialEq> PartialEq<&'b [T]> for StackVec<'a, T> {
+    fn eq(&self, other: &&'b [T]) -> bool {
+        self.as_slice() == *other
+    }
+}
+
+/// Formats only the vector's elements, not the rest of its storage.
+impl<'a, T: fmt::Debug> fmt::Debug for StackVec<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
@@ -0,0 +1,243 @@
+//! `RingBuffer`, a fixed-capacity FIFO or deque, and its iterator.
+
+use core::fmt;
+use core::iter::IntoIterator;
+use core::mem::MaybeUninit;
+use core::ptr;
+
+/// A double-ended queue with a fixed capacity of `N` values, stored inline.
+///
+/// Values are usually pushed at the back and popped from the front, making
+/// a FIFO. `push_back` fails when the buffer is full; `push_back_overwrite`
+/// drops the oldest value to make room instead, for buffers that should keep
+/// the latest values, such as a trace or a shell's history.
+///
+/// Nothing is allocated and `new` is a `const fn`, so a `RingBuffer` can sit
+/// in a `static` behind a lock and be used from an interrupt handler.
+pub struct RingBuffer<T, const N: usize> {
+    slots: [MaybeUninit<T>; N],
+    /// Index of the slot holding the front value.
+    head: usize,
+    len: usize,
+}
+
+impl<T, const N: usize> RingBuffer<T, N> {
+    /// Constructs a new, empty `RingBuffer`.
+    pub const fn new() -> RingBuffer<T, N> {
+        RingBuffer {
+            // An array of `MaybeUninit`s needs no initialization.
+            slots: unsafe { MaybeUninit::uninit().assume_init() },
+            head: 0,
+            len: 0,
+        }
+    }
+
+    /// Returns the number of values this buffer can hold.
+    pub fn capacity(&self) -> usize {
+        N
+    }
+
+    /// Returns the number of values in the buffer.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns true if the buffer contains no values.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns true if the buffer is at capacity.
+    pub fn is_full(&self) -> bool {
+        self.len == N
+    }
+
+    /// Returns the index of the slot holding the `i`th value from the front,
+    /// for `i` up to and including `len`.
+    fn slot(&self, i: usize) -> usize {
+        (self.head + i) % N
+    }
+
+    /// Appends `value` to the back of the buffer if it's not full.
+    ///
+    /// # Error
+    ///
+    /// If the buffer is full, `value` is returned as an `Err`. Otherwise, `Ok`
+    /// is returned.
+    pub fn push_back(&mut self, value: T) -> Result<(), T> {
+        if self.is_full() {
+            return Err(value);
+        }
+
+        let slot = self.slot(self.len);
+        self.slots[slot] = MaybeUninit::new(value);
+        self.len += 1;
+        Ok(())
+    }
+
+    /// Appends `value` to the back of the buffer. If the buffer is full, the
+    /// front value is removed to make room first, and returned.
+    pub fn push_back_overwrite(&mut self, value: T) -> Option<T> {
+        if N == 0 {
+            return Some(value);
+        }
+
+        let oldest = match self.is_full() {
+            true => self.pop_front(),
+            false => None,
+        };
+        let _ = self.push_back(value);
+        oldest
+    }
+
+    /// Prepends `value` to the front of the buffer if it's not full.
+    ///
+    /// # Error
+    ///
+    /// If the buffer is full, `value` is returned as an `Err`. Otherwise, `Ok`
+    /// is returned.
+    pub fn push_front(&mut self, value: T) -> Result<(), T> {
+        if self.is_full() {
+            return Err(value);
+        }
+
+        self.head = self.slot(N - 1);
+        self.slots[self.head] = MaybeUninit::new(value);
+        self.len += 1;
+        Ok(())
+    }
+
+    /// Removes the front value and returns it, or `None` if the buffer is
+    /// empty.
+    pub fn pop_front(&mut self) -> Option<T> {
+        if self.is_empty() {
+            return None;
+        }
+
+        let value = unsafe { self.slots[self.head].assume_init_read() };
+        self.head = self.slot(1);
+        self.len -= 1;
+        Some(value)
+    }
+
+    /// Removes the back value and returns it, or `None` if the buffer is
+    /// empty.
+    pub fn pop_back(&mut self) -> Option<T> {
+        if self.is_empty() {
+            return None;
+        }
+
+        self.len -= 1;
+        let slot = self.slot(self.len);
+        Some(unsafe { self.slots[slot].assume_init_read() })
+    }
+
+    /// Returns the `i`th value from the front, or `None` if there are no more
+    /// than `i` values.
+    pub fn get(&self, i: usize) -> Option<&T> {
+        match i < self.len {
+            true => Some(unsafe { self.slots[self.slot(i)].assume_init_ref() }),
+            false => None,
+        }
+    }
+
+    /// Returns the `i`th value from the front mutably, or `None` if there are
+    /// no more than `i` values.
+    pub fn get_mut(&mut self, i: usize) -> Option<&mut T> {
+        match i < self.len {
+            true => {
+                let slot = self.slot(i);
+                Some(unsafe { self.slots[slot].assume_init_mut() })
+            }
+            false => None,
+        }
+    }
+
+    /// Returns the front value, or `None` if the buffer is empty.
+    pub fn front(&self) -> Option<&T> {
+        self.get(0)
+    }
+
+    /// Returns the back value, or `None` if the buffer is empty.
+    pub fn back(&self) -> Option<&T> {
+        self.len.checked_sub(1).and_then(|i| self.get(i))
+    }
+
+    /// Removes all values.
+    pub fn clear(&mut self) {
+        while self.pop_front().is_some() {}
+        self.head = 0;
+    }
+
+    /// Returns an iterator over the values, from front to back.
+    pub fn iter(&self) -> Iter<'_, T, N> {
+        Iter { buffer: self, front: 0, back: self.len }
+    }
+}
+
+impl<T, const N: usize> Default for RingBuffer<T, N> {
+    fn default() -> RingBuffer<T, N> {
+        RingBuffer::new()
+    }
+}
+
+impl<T, const N: usize> Drop for RingBuffer<T, N> {
+    fn drop(&mut self) {
+        for i in 0..self.len {
+            let slot = self.slot(i);
+            unsafe { ptr::drop_in_place(self.slots[slot].as_mut_ptr()) }
+        }
+    }
+}
+
+impl<T: fmt::Debug, const N: usize> fmt::Debug for RingBuffer<T, N> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+/// An iterator over the values of a `RingBuffer`, from front to back.
+pub struct Iter<'a, T, const N: usize> {
+    buffer: &'a RingBuffer<T, N>,
+    /// The front and back of the values not iterated over yet.
+    front: usize,
+    back: usize,
+}
+
+impl<'a, T, const N: usize> Iterator for Iter<'a, T, N> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        if self.front == self.back {
+            return None;
+        }
+        self.front += 1;
+        self.buffer.get(self.front - 1)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.back - self.front;
+        (len, Some(len))
+    }
+}
+
+impl<'a, T, const N: usize> DoubleEndedIterator for Iter<'a, T, N> {
+    fn next_back(&mut self) -> Option<&'a T> {
+        if self.front == self.back {
+            return None;
+        }
+        self.back -= 1;
+        self.buffer.get(self.back)
+    }
+}
+
+impl<'a, T, const N: usize> ExactSizeIterator for Iter<'a, T, N> {}
+
+impl<'a, T, const N: usize> IntoIterator for &'a RingBuffer<T, N> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T, N>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
@@ -0,0 +1,119 @@
+use core::fmt;
+use core::ops::Deref;
+use core::str;
+
+/// A UTF-8 string with a fixed capacity of `N` bytes, stored inline.
+///
+/// `StackString` is to `String` what `StackVec` is to `Vec`: it needs no
+/// memory allocation, so appending to it is fallible. Appends that don't fit
+/// return an `Err` and leave the string unchanged. It implements
+/// `fmt::Write`, so `write!` can format into it.
+#[derive(Clone, Copy)]
+pub struct StackString<const N: usize> {
+    buf: [u8; N],
+    len: usize,
+}
+
+impl<const N: usize> StackString<N> {
+    /// Constructs a new, empty `StackString`.
+    pub const fn new() -> StackString<N> {
+        StackString { buf: [0; N], len: 0 }
+    }
+
+    /// Returns the number of bytes this string can hold.
+    pub fn capacity(&self) -> usize {
+        N
+    }
+
+    /// Extracts a string slice containing the entire string.
+    pub fn as_str(&self) -> &str {
+        // `buf[..len]` is only ever filled from `str`s and `char`s.
+        unsafe { str::from_utf8_unchecked(&self.buf[..self.len]) }
+    }
+
+    /// Empties the string.
+    pub fn clear(&mut self) {
+        self.len = 0;
+    }
+
+    /// Appends `s` to the end of this string if it fits.
+    ///
+    /// # Error
+    ///
+    /// If there isn't room for all of `s`, an `Err` is returned and nothing
+    /// is appended. Otherwise, `Ok` is returned.
+    pub fn push_str(&mut self, s: &str) -> Result<(), ()> {
+        let end = self.len + s.len();
+        if end > N {
+            return Err(());
+        }
+
+        self.buf[self.len..end].copy_from_slice(s.as_bytes());
+        self.len = end;
+        Ok(())
+    }
+
+    /// Appends the character `c` to the end of this string if it fits.
+    ///
+    /// # Error
+    ///
+    /// If there isn't room for `c`'s UTF-8 encoding, an `Err` is returned.
+    /// Otherwise, `Ok` is returned.
+    pub fn push(&mut self, c: char) -> Result<(), ()> {
+        self.push_str(c.encode_utf8(&mut [0; 4]))
+    }
+}
+
+impl<const N: usize> Default for StackString<N> {
+    fn default() -> StackString<N> {
+        StackString::new()
+    }
+}
+
+impl<const N: usize> Deref for StackString<N> {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+/// Fails with `fmt::Error` once the string is full; a piece that doesn't fit
+/// is dropped entirely.
+impl<const N: usize> fmt::Write for StackString<N> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.push_str(s).map_err(|_| fmt::Error)
+    }
+}
+
+impl<const N: usize> fmt::Display for StackString<N> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self.as_str(), f)
+    }
+}
+
+impl<const N: usize> fmt::Debug for StackString<N> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(self.as_str(), f)
+    }
+}
+
+impl<const N: usize, const M: usize> PartialEq<StackString<M>> for StackString<N> {
+    fn eq(&self, other: &StackString<M>) -> bool {
+        self.as_str() == other.as_str()
+    }
+}
+
+impl<const N: usize> Eq for StackString<N> {}
+
+impl<const N: usize> PartialEq<str> for StackString<N> {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl<'a, const N: usize> PartialEq<&'a str> for StackString<N> {
+    fn eq(&self, other: &&'a str) -> bool {
+        self.as_str() == *other
+    }
+}
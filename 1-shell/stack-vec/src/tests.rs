@@ -1,3 +1,5 @@
+use RingBuffer;
+use StackString;
 use StackVec;
 
 #[test]
@@ -119,7 +121,7 @@ fn pop() {
         assert_eq!(stack_vec.len(), i + 1);
     }
 
-    for i in 1023..=0 {
+    for i in (0..1024).rev() {
         assert_eq!(stack_vec.len(), i + 1);
         assert_eq!(stack_vec.pop(), Some(i));
         assert_eq!(stack_vec.len(), i);
@@ -171,16 +173,12 @@ fn iterator() {
         assert_eq!(*val, i * i);
     }
 
-    let mut i = 0;
-    for val in &stack_vec {
+    for (i, val) in (&stack_vec).into_iter().enumerate() {
         assert_eq!(*val, i * i);
-        i += 1;
     }
 
-    let mut i = 0;
-    for val in stack_vec {
+    for (i, val) in stack_vec.into_iter().enumerate() {
         assert_eq!(*val, i * i);
-        i += 1;
     }
 }
 
@@ -202,3 +200,234 @@ fn as_slice() {
     assert_eq!(stack_vec.as_slice(), &[102]);
     assert_eq!(stack_vec.as_mut_slice(), &mut [102]);
 }
+
+#[test]
+fn insert() {
+    let mut storage = [0usize; 4];
+    let mut stack_vec = StackVec::new(&mut storage);
+    stack_vec.insert(0, 2).expect("cap = 4");
+    stack_vec.insert(0, 0).expect("cap = 4");
+    stack_vec.insert(1, 1).expect("cap = 4");
+    stack_vec.insert(3, 3).expect("cap = 4");
+    assert_eq!(stack_vec.as_slice(), &[0, 1, 2, 3]);
+
+    assert!(stack_vec.insert(2, 4).is_err());
+    assert_eq!(stack_vec.as_slice(), &[0, 1, 2, 3]);
+}
+
+#[test]
+#[should_panic]
+fn insert_oob() {
+    let mut storage = [0usize; 4];
+    let mut stack_vec = StackVec::with_len(&mut storage, 2);
+    let _ = stack_vec.insert(3, 1);
+}
+
+#[test]
+fn remove() {
+    let mut storage = [0usize; 5];
+    let mut stack_vec = StackVec::new(&mut storage);
+    for i in 0..5 {
+        stack_vec.push(i).expect("cap = 5");
+    }
+
+    assert_eq!(stack_vec.remove(1), 1);
+    assert_eq!(stack_vec.as_slice(), &[0, 2, 3, 4]);
+    assert_eq!(stack_vec.remove(3), 4);
+    assert_eq!(stack_vec.as_slice(), &[0, 2, 3]);
+    assert_eq!(stack_vec.swap_remove(0), 0);
+    assert_eq!(stack_vec.as_slice(), &[3, 2]);
+    assert_eq!(stack_vec.swap_remove(1), 2);
+    assert_eq!(stack_vec.as_slice(), &[3]);
+
+    stack_vec.push(5).expect("cap = 5");
+    assert_eq!(stack_vec.as_slice(), &[3, 5]);
+}
+
+#[test]
+#[should_panic]
+fn remove_oob() {
+    let mut storage = [0usize; 4];
+    let mut stack_vec = StackVec::with_len(&mut storage, 2);
+    stack_vec.truncate(1);
+    stack_vec.remove(1);
+}
+
+#[test]
+fn retain() {
+    let mut storage = [0usize; 10];
+    let mut stack_vec = StackVec::new(&mut storage);
+    for i in 0..10 {
+        stack_vec.push(i).expect("cap = 10");
+    }
+
+    stack_vec.retain(|&i| i % 3 != 0);
+    assert_eq!(stack_vec.as_slice(), &[1, 2, 4, 5, 7, 8]);
+    stack_vec.retain(|_| false);
+    assert!(stack_vec.is_empty());
+}
+
+#[test]
+fn mut_iterator() {
+    let mut storage = [0usize; 8];
+    let mut stack_vec = StackVec::new(&mut storage);
+    stack_vec.extend(0..4);
+
+    for val in &mut stack_vec {
+        *val *= 2;
+    }
+    assert_eq!(stack_vec.as_slice(), &[0, 2, 4, 6]);
+
+    stack_vec.sort_by(|a, b| b.cmp(a));
+    assert_eq!(stack_vec.first(), Some(&6));
+    assert!(stack_vec.contains(&2));
+}
+
+#[test]
+fn extend() {
+    let mut storage = [0usize; 4];
+    let mut stack_vec = StackVec::new(&mut storage);
+    stack_vec.try_extend(1..3).expect("cap = 4");
+    assert_eq!(stack_vec.as_slice(), &[1, 2]);
+
+    assert!(stack_vec.try_extend(3..10).is_err());
+    assert_eq!(stack_vec.as_slice(), &[1, 2, 3, 4]);
+}
+
+#[test]
+#[should_panic]
+fn extend_too_far() {
+    let mut storage = [0usize; 4];
+    let mut stack_vec = StackVec::new(&mut storage);
+    stack_vec.extend(0..5);
+}
+
+#[test]
+fn eq_and_debug() {
+    let mut storage = [0usize; 4];
+    let mut stack_vec = StackVec::new(&mut storage);
+    stack_vec.extend(1..3);
+    let mut other_storage = [7usize; 8];
+    let mut other = StackVec::with_len(&mut other_storage, 1);
+    assert!(stack_vec != other);
+
+    other[0] = 1;
+    other.push(2).expect("cap = 8");
+    assert_eq!(stack_vec, other);
+    assert_eq!(stack_vec, &[1, 2][..]);
+    assert!(stack_vec == [1, 2][..]);
+    assert_eq!(format!("{:?}", stack_vec), "[1, 2]");
+}
+
+#[test]
+fn stack_string() {
+    let mut string = StackString::<8>::new();
+    assert!(string.is_empty());
+    assert_eq!(string.capacity(), 8);
+
+    string.push_str("abc").expect("cap = 8");
+    string.push('\u{e9}').expect("cap = 8");
+    assert_eq!(string, "abc\u{e9}");
+    assert_eq!(string.len(), 5);
+    assert!(string.starts_with("ab"));
+
+    assert!(string.push_str("defg").is_err());
+    assert_eq!(string, "abc\u{e9}");
+    string.push_str("def").expect("cap = 8");
+    assert!(string.push('g').is_err());
+    assert_eq!(string.as_str(), "abc\u{e9}def");
+
+    string.clear();
+    assert_eq!(string, StackString::<4>::new());
+}
+
+#[test]
+fn stack_string_write() {
+    use core::fmt::Write;
+
+    let mut string = StackString::<16>::new();
+    write!(string, "core-{:02}", 3).expect("cap = 16");
+    assert_eq!(string, "core-03");
+    assert_eq!(format!("{} {:?}", string, string), "core-03 \"core-03\"");
+
+    assert!(write!(string, "too long to fit").is_err());
+    assert_eq!(string, "core-03");
+}
+
+#[test]
+fn ring_buffer_fifo() {
+    let mut ring = RingBuffer::<usize, 4>::new();
+    assert!(ring.is_empty());
+    assert_eq!(ring.capacity(), 4);
+    assert_eq!(ring.pop_front(), None);
+
+    // Go around a few times, so the values wrap past the end of the slots.
+    for i in 0..10 {
+        ring.push_back(i * 2).expect("cap = 4");
+        ring.push_back(i * 2 + 1).expect("cap = 4");
+        assert_eq!(ring.len(), 2);
+        assert_eq!(ring.pop_front(), Some(i * 2));
+        assert_eq!(ring.pop_front(), Some(i * 2 + 1));
+    }
+
+    for i in 0..4 {
+        ring.push_back(i).expect("cap = 4");
+    }
+    assert!(ring.is_full());
+    assert_eq!(ring.push_back(4), Err(4));
+    assert_eq!(ring.front(), Some(&0));
+    assert_eq!(ring.back(), Some(&3));
+    assert_eq!(ring.get(2), Some(&2));
+    assert_eq!(ring.get(4), None);
+}
+
+#[test]
+fn ring_buffer_deque() {
+    let mut ring = RingBuffer::<usize, 4>::new();
+    ring.push_front(1).expect("cap = 4");
+    ring.push_front(0).expect("cap = 4");
+    ring.push_back(2).expect("cap = 4");
+    assert_eq!(ring.iter().cloned().collect::<std::vec::Vec<_>>(), [0, 1, 2]);
+
+    *ring.get_mut(1).expect("len = 3") = 5;
+    assert_eq!(ring.pop_back(), Some(2));
+    assert_eq!(ring.pop_back(), Some(5));
+    assert_eq!(ring.pop_front(), Some(0));
+    assert_eq!(ring.pop_back(), None);
+}
+
+#[test]
+fn ring_buffer_overwrite() {
+    let mut ring = RingBuffer::<usize, 3>::new();
+    for i in 0..3 {
+        assert_eq!(ring.push_back_overwrite(i), None);
+    }
+    assert_eq!(ring.push_back_overwrite(3), Some(0));
+    assert_eq!(ring.push_back_overwrite(4), Some(1));
+    assert_eq!(ring.iter().rev().cloned().collect::<std::vec::Vec<_>>(), [4, 3, 2]);
+    assert_eq!(format!("{:?}", ring), "[2, 3, 4]");
+
+    let mut empty = RingBuffer::<usize, 0>::new();
+    assert_eq!(empty.push_back_overwrite(1), Some(1));
+    assert!(empty.iter().next().is_none());
+}
+
+#[test]
+fn ring_buffer_drops_values() {
+    use std::rc::Rc;
+
+    let value = Rc::new(());
+    let mut ring = RingBuffer::<Rc<()>, 4>::new();
+    for _ in 0..6 {
+        ring.push_back_overwrite(value.clone());
+    }
+    ring.pop_front();
+    assert_eq!(Rc::strong_count(&value), 4);
+    ring.clear();
+    assert_eq!(Rc::strong_count(&value), 1);
+
+    ring.push_back(value.clone()).expect("cap = 4");
+    ring.push_front(value.clone()).expect("cap = 4");
+    drop(ring);
+    assert_eq!(Rc::strong_count(&value), 1);
+}
@@ -0,0 +1,126 @@
+//! `--auto-baud`: moving the link to the fastest rate both ends can handle,
+//! using the handshake in `xmodem::baud`.
+
+use std::io::{self, Read, Write};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serial::core::{BaudRate, SerialDevice, SerialPortSettings};
+use serial::SystemPort;
+use xmodem::baud;
+
+/// Rates to try, slowest first. The Pi's mini UART can't get close enough to
+/// anything faster.
+const RATES: [usize; 4] = [230_400, 460_800, 921_600, 1_500_000];
+
+/// How long to keep asking for the first rate. The bootloader only listens
+/// between receive attempts, which take several seconds when nobody sends.
+const FIRST_REQUEST_TIMEOUT: Duration = Duration::from_secs(8);
+
+/// How long to wait for an answer to a later request, which the bootloader
+/// is already listening for.
+const REQUEST_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// How often an unanswered request is sent again.
+const REQUEST_INTERVAL: Duration = Duration::from_millis(250);
+
+/// How long to wait for the probe pattern to come back. Also long enough for
+/// the bootloader to give up on a probe that didn't arrive.
+const PROBE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Moves `port`, running at `base`, up through `RATES` for as long as the
+/// other end accepts the next rate and the probe pattern survives it, and
+/// returns the rate the port ends up at. The port's timeout is changed.
+pub fn negotiate(port: &mut SystemPort, base: usize) -> io::Result<usize> {
+    let mut rate = base;
+    let mut timeout = FIRST_REQUEST_TIMEOUT;
+    for &next in RATES.iter().filter(|&&r| r > base) {
+        if !request(port, next as u32, timeout)? {
+            break;
+        }
+        timeout = REQUEST_TIMEOUT;
+
+        set_rate(port, next)?;
+        if probe(port)? {
+            rate = next;
+        } else {
+            set_rate(port, rate)?;
+            thread::sleep(PROBE_TIMEOUT);
+            break;
+        }
+    }
+
+    discard_input(port)?;
+    Ok(rate)
+}
+
+/// Switches `port` to `rate` once everything written has been sent.
+pub fn set_rate(port: &mut SystemPort, rate: usize) -> io::Result<()> {
+    port.flush()?;
+    let mut settings = port.read_settings()?;
+    settings.set_baud_rate(BaudRate::from_speed(rate))?;
+    port.write_settings(&settings)?;
+    Ok(())
+}
+
+/// Asks for `rate` until the other end echoes the request or `timeout` has
+/// passed, and returns whether it did. Anything else that arrives meanwhile,
+/// such as a waiting receiver's XMODEM start bytes, is skipped.
+fn request(port: &mut SystemPort, rate: u32, timeout: Duration) -> io::Result<bool> {
+    let request = baud::request_bytes(rate);
+    let deadline = Instant::now() + timeout;
+    port.set_timeout(REQUEST_INTERVAL)?;
+
+    let mut seen = Vec::new();
+    while Instant::now() < deadline {
+        port.write_all(&request)?;
+        port.flush()?;
+
+        let resend = Instant::now() + REQUEST_INTERVAL;
+        while Instant::now() < resend {
+            let mut buf = [0u8; 64];
+            match port.read(&mut buf) {
+                Ok(n) => seen.extend_from_slice(&buf[..n]),
+                Err(ref e) if e.kind() == io::ErrorKind::TimedOut => break,
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+            if seen.windows(request.len()).any(|w| w == request) {
+                return Ok(true);
+            }
+            let keep = seen.len().saturating_sub(request.len() - 1);
+            seen.drain(..keep);
+        }
+    }
+    Ok(false)
+}
+
+/// Sends the probe pattern and returns whether it came back intact.
+fn probe(port: &mut SystemPort) -> io::Result<bool> {
+    discard_input(port)?;
+    port.set_timeout(PROBE_TIMEOUT)?;
+    port.write_all(&baud::PATTERN)?;
+    port.flush()?;
+
+    let mut echo = [0u8; baud::PATTERN.len()];
+    match port.read_exact(&mut echo) {
+        Ok(()) => Ok(echo == baud::PATTERN),
+        Err(ref e) if e.kind() == io::ErrorKind::TimedOut => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+/// Drops whatever has arrived so far, such as bytes sent at the old rate.
+pub fn discard_input(port: &mut SystemPort) -> io::Result<()> {
+    port.set_timeout(Duration::from_millis(50))?;
+    let mut buf = [0u8; 64];
+    loop {
+        match port.read(&mut buf) {
+            Ok(0) => return Ok(()),
+            Ok(_) => {}
+            Err(ref e) if e.kind() == io::ErrorKind::TimedOut => return Ok(()),
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+}
@@ -0,0 +1,88 @@
+//! `--batch`: sending every file in a directory in one YMODEM session.
+
+use std::fs::{self, File};
+use std::io::{self, BufReader};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use serial::core::SerialDevice;
+use serial::SystemPort;
+use xmodem::Xmodem;
+
+use progress;
+
+/// Returns the files under `dir`, with the names they're sent under: their
+/// paths relative to `dir`, separated by `/`. Subdirectories such as
+/// `overlays` are included; hidden files, whose names start with a `.`, are
+/// not. The files are sorted by name.
+pub fn files(dir: &Path) -> io::Result<Vec<(String, PathBuf)>> {
+    let mut files = Vec::new();
+    collect(dir, "", &mut files)?;
+    files.sort();
+    Ok(files)
+}
+
+fn collect(dir: &Path, prefix: &str, files: &mut Vec<(String, PathBuf)>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name().into_string().map_err(|name| {
+            io::Error::new(io::ErrorKind::InvalidData, format!("{:?} isn't UTF-8", name))
+        })?;
+        if name.starts_with('.') {
+            continue;
+        }
+
+        let path = entry.path();
+        if path.is_dir() {
+            collect(&path, &format!("{}{}/", prefix, name), files)?;
+        } else {
+            files.push((format!("{}{}", prefix, name), path));
+        }
+    }
+    Ok(())
+}
+
+/// Sends every file under `dir` to `port` as a YMODEM batch and returns the
+/// number of bytes sent. The receiver has the port's timeout to start;
+/// after that, each packet has `packet_timeout`.
+pub fn send(
+    dir: &Path,
+    port: &mut SystemPort,
+    retries: usize,
+    packet_timeout: Duration,
+) -> io::Result<u64> {
+    let files = files(dir)?;
+    let mut total = 0;
+    for (_, path) in &files {
+        total += fs::metadata(path)?.len();
+    }
+
+    progress::start("Sent", Some(total));
+    let result = transmit(&files, port, retries, packet_timeout);
+    progress::finish();
+    let sent = result?;
+    eprintln!("Sent {} files", files.len());
+    Ok(sent)
+}
+
+fn transmit(
+    files: &[(String, PathBuf)],
+    port: &mut SystemPort,
+    retries: usize,
+    packet_timeout: Duration,
+) -> io::Result<u64> {
+    let mut transmitter = Xmodem::new_with_progress(port, progress::update);
+    transmitter.set_retries(retries);
+    transmitter.start_transmit()?;
+    transmitter.get_mut().set_timeout(packet_timeout)?;
+
+    let mut sent = 0;
+    for (name, path) in files {
+        let file = File::open(path)?;
+        let len = file.metadata()?.len();
+        let data = progress::Counting(BufReader::new(file));
+        sent += transmitter.transmit_file(name, len, data)? as u64;
+    }
+    transmitter.end_batch_transmit()?;
+    Ok(sent)
+}
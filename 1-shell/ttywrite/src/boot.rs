@@ -0,0 +1,86 @@
+//! `--boot`: the host's side of the loader protocol in `xmodem::loader`.
+
+use std::io;
+use std::time::{Duration, Instant};
+
+use serial::core::SerialDevice;
+use serial::SystemPort;
+use xmodem::loader::{self, Message};
+use xmodem::verify::Trailer;
+
+use autobaud;
+
+/// How often an unanswered query is sent again.
+const QUERY_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Finds the bootloader, which has `timeout` to answer, and describes
+/// `image` to it. Leaves the port's timeout at `timeout`.
+///
+/// # Errors
+///
+/// Returns an error of kind `TimedOut` if the bootloader doesn't answer, and
+/// one of kind `InvalidInput` if it refuses the image.
+pub fn open_session(port: &mut SystemPort, image: Trailer, timeout: Duration) -> io::Result<()> {
+    let deadline = Instant::now() + timeout;
+    port.set_timeout(QUERY_INTERVAL)?;
+    let (version, max_len) = loop {
+        if Instant::now() >= deadline {
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                "bootloader didn't answer the loader query",
+            ));
+        }
+
+        Message::Query.write(&mut *port)?;
+        match Message::read(&mut *port) {
+            Ok(Some(Message::Info { version, max_len })) => break (version, max_len),
+            Ok(_) => continue,
+            Err(ref e) if e.kind() == io::ErrorKind::TimedOut => continue,
+            Err(e) => return Err(e),
+        }
+    };
+    eprintln!("Bootloader speaks loader protocol v{}, takes up to {} bytes", version, max_len);
+
+    // Answers to queries that crossed the first answer.
+    autobaud::discard_input(port)?;
+    port.set_timeout(timeout)?;
+
+    if image.len > max_len {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("image of {} bytes is too large for the bootloader", image.len),
+        ));
+    }
+    Message::Image(image).write(&mut *port)?;
+    if !loader::read_answer(&mut *port)? {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "bootloader refused the image",
+        ));
+    }
+    Ok(())
+}
+
+/// Waits for the bootloader's verdict on the image it received and, if it's
+/// intact, tells it to start the kernel.
+///
+/// # Errors
+///
+/// Returns an error of kind `InvalidData` if the image arrived damaged.
+pub fn start_kernel(port: &mut SystemPort) -> io::Result<()> {
+    if !loader::read_answer(&mut *port)? {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "bootloader's image doesn't match what was sent",
+        ));
+    }
+
+    Message::Jump.write(&mut *port)?;
+    if !loader::read_answer(&mut *port)? {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "bootloader refused to start the kernel",
+        ));
+    }
+    Ok(())
+}
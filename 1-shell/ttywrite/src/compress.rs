@@ -0,0 +1,33 @@
+use std::io::{self, Read};
+
+use flate2::write::GzEncoder;
+use lz4_flex::frame::FrameEncoder;
+
+/// Ways the data can be compressed before it's sent.
+#[derive(Debug, Copy, Clone)]
+pub enum Compression {
+    /// An LZ4 frame, which the bootloader recognizes and unpacks.
+    Lz4,
+    /// A gzip member, which the bootloader also unpacks. Slower to unpack than
+    /// LZ4, but smaller, which matters more on a slow link.
+    Gzip,
+}
+
+/// Reads all of `input` and returns it compressed with `compression`.
+///
+/// The whole frame is built up front so that its length is known for the
+/// progress display, and so that a resumed transfer sends the same bytes.
+pub fn compress(mut input: impl Read, compression: Compression) -> io::Result<Vec<u8>> {
+    match compression {
+        Compression::Lz4 => {
+            let mut encoder = FrameEncoder::new(Vec::new());
+            io::copy(&mut input, &mut encoder)?;
+            encoder.finish().map_err(|e| io::Error::new(io::ErrorKind::Other, e))
+        }
+        Compression::Gzip => {
+            let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::best());
+            io::copy(&mut input, &mut encoder)?;
+            encoder.finish()
+        }
+    }
+}
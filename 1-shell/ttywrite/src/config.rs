@@ -0,0 +1,269 @@
+//! Defaults from `~/.ttywrite.toml`.
+//!
+//! Top-level keys apply to every run, and `--profile NAME` layers the
+//! `[profile.NAME]` table on top of them. Flags given on the command line win
+//! over both:
+//!
+//! ```toml
+//! device = "/dev/ttyUSB0"
+//! baud = 115200
+//! verify = true
+//!
+//! [profile.fast]
+//! baud = 230400
+//! flow_control = "hardware"
+//! auto_baud = true
+//! reset = "dtr"
+//! compress = "lz4"
+//! header = true
+//! ```
+
+use std::env;
+use std::fmt::Display;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use toml::{Table, Value};
+
+use parsers::{
+    parse_address, parse_baud_rate, parse_compression, parse_flow_control, parse_reset_line,
+    parse_stop_bits, parse_width,
+};
+use Opt;
+
+const FILE_NAME: &str = ".ttywrite.toml";
+
+/// Returns the path of the configuration file in the home directory.
+fn path() -> Option<PathBuf> {
+    env::var_os("HOME")
+        .or_else(|| env::var_os("USERPROFILE"))
+        .map(|home| PathBuf::from(home).join(FILE_NAME))
+}
+
+/// Reads the configuration file and returns its settings for `profile`, or its
+/// top-level settings if `profile` is `None`. A missing file has no settings,
+/// unless a profile was asked for.
+pub fn load(profile: Option<&str>) -> Result<Table, String> {
+    let path = path().ok_or("can't find the home directory")?;
+    load_from(&path, profile)
+}
+
+/// Like `load`, but reads the configuration file at `path`.
+fn load_from(path: &Path, profile: Option<&str>) -> Result<Table, String> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound && profile.is_none() => {
+            return Ok(Table::new())
+        }
+        Err(e) => return Err(format!("{}: {}", path.display(), e)),
+    };
+    let mut settings: Table = text
+        .parse()
+        .map_err(|e| format!("{}: {}", path.display(), e))?;
+
+    let profiles = settings.remove("profile");
+    if let Some(name) = profile {
+        let overrides = profiles
+            .as_ref()
+            .and_then(|profiles| profiles.get(name))
+            .and_then(Value::as_table)
+            .ok_or_else(|| format!("{}: no [profile.{}] table", path.display(), name))?;
+        for (key, value) in overrides {
+            settings.insert(key.clone(), value.clone());
+        }
+    }
+    Ok(settings)
+}
+
+/// Fills in the options of `opt` that weren't given on the command line from
+/// `settings`. `explicit(name)` says whether the option with argument name
+/// `name` was.
+pub fn apply<F>(settings: &Table, opt: &mut Opt, explicit: F) -> Result<(), String>
+where
+    F: Fn(&str) -> bool,
+{
+    if opt.tty_path.is_none() {
+        opt.tty_path = text(settings, "device")?.map(PathBuf::from);
+    }
+    if !explicit("baud_rate") {
+        setting(settings, "baud", parse_baud_rate, &mut opt.baud_rate)?;
+    }
+    if !explicit("char_width") {
+        setting(settings, "width", parse_width, &mut opt.char_width)?;
+    }
+    if !explicit("flow_control") {
+        setting(settings, "flow_control", parse_flow_control, &mut opt.flow_control)?;
+    }
+    if !explicit("stop_bits") {
+        setting(settings, "stop_bits", parse_stop_bits, &mut opt.stop_bits)?;
+    }
+    if !explicit("timeout") {
+        setting(settings, "timeout", str::parse, &mut opt.timeout)?;
+    }
+    if !explicit("retries") {
+        setting(settings, "retries", str::parse, &mut opt.retries)?;
+    }
+    if !explicit("packet_timeout") {
+        setting(settings, "packet_timeout", str::parse, &mut opt.packet_timeout)?;
+    }
+    if !explicit("load_address") {
+        setting(settings, "load_address", parse_address, &mut opt.load_address)?;
+    }
+    if !explicit("entry_offset") {
+        setting(settings, "entry_offset", parse_address, &mut opt.entry_offset)?;
+    }
+    if opt.compress.is_none() {
+        opt.compress = text(settings, "compress")?
+            .map(|s| parse_compression(&s).map_err(|e| invalid("compress", e)))
+            .transpose()?;
+    }
+    if opt.reset.is_none() {
+        opt.reset = text(settings, "reset")?
+            .map(|s| parse_reset_line(&s).map_err(|e| invalid("reset", e)))
+            .transpose()?;
+    }
+    opt.auto_baud |= flag(settings, "auto_baud")?;
+    opt.raw |= flag(settings, "raw")?;
+    opt.kermit |= flag(settings, "kermit")?;
+    opt.verify |= flag(settings, "verify")?;
+    opt.boot |= flag(settings, "boot")?;
+    opt.header |= flag(settings, "header")?;
+    Ok(())
+}
+
+/// Parses the setting `key` with `parse` into `field`, if it's present.
+fn setting<T, E, P>(settings: &Table, key: &str, parse: P, field: &mut T) -> Result<(), String>
+where
+    E: Display,
+    P: Fn(&str) -> Result<T, E>,
+{
+    if let Some(text) = text(settings, key)? {
+        *field = parse(&text).map_err(|e| invalid(key, e))?;
+    }
+    Ok(())
+}
+
+/// Returns the setting `key` as the text a flag would take.
+fn text(settings: &Table, key: &str) -> Result<Option<String>, String> {
+    match settings.get(key) {
+        None => Ok(None),
+        Some(Value::String(s)) => Ok(Some(s.clone())),
+        Some(Value::Integer(n)) => Ok(Some(n.to_string())),
+        Some(_) => Err(invalid(key, "expected a string or an integer")),
+    }
+}
+
+/// Returns the boolean setting `key`, `false` if it's absent.
+fn flag(settings: &Table, key: &str) -> Result<bool, String> {
+    match settings.get(key) {
+        None => Ok(false),
+        Some(Value::Boolean(b)) => Ok(*b),
+        Some(_) => Err(invalid(key, "expected true or false")),
+    }
+}
+
+fn invalid<E: Display>(key: &str, e: E) -> String {
+    format!("invalid `{}` in {}: {}", key, FILE_NAME, e)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use serial::core::{BaudRate, FlowControl};
+    use structopt::StructOpt;
+
+    /// Writes `text` to a configuration file of its own and loads it.
+    fn load_text(name: &str, text: &str, profile: Option<&str>) -> Result<Table, String> {
+        let name = format!("ttywrite-{}-{}.toml", std::process::id(), name);
+        let path = env::temp_dir().join(name);
+        fs::write(&path, text).unwrap();
+        let settings = load_from(&path, profile);
+        fs::remove_file(&path).unwrap();
+        settings
+    }
+
+    /// Parses the command line `args` and applies `settings` to it.
+    fn apply_to(settings: &str, args: &[&str]) -> Result<Opt, String> {
+        let settings: Table = settings.parse().unwrap();
+        let mut argv = vec!["ttywrite"];
+        argv.extend_from_slice(args);
+        let matches = Opt::clap().get_matches_from(argv);
+        let mut opt = Opt::from_clap(matches.clone());
+        apply(&settings, &mut opt, |name| matches.occurrences_of(name) > 0)?;
+        Ok(opt)
+    }
+
+    #[test]
+    fn missing_file() {
+        let path = env::temp_dir().join("ttywrite-no-such-config.toml");
+        assert_eq!(load_from(&path, None), Ok(Table::new()));
+        assert!(load_from(&path, Some("fast")).is_err());
+    }
+
+    #[test]
+    fn profile_overrides_top_level() {
+        let text = "baud = 115200\nverify = true\n\n[profile.fast]\nbaud = 230400\n";
+
+        let settings = load_text("top", text, None).unwrap();
+        assert_eq!(settings.get("baud"), Some(&Value::Integer(115200)));
+        assert!(settings.get("profile").is_none());
+
+        let settings = load_text("fast", text, Some("fast")).unwrap();
+        assert_eq!(settings.get("baud"), Some(&Value::Integer(230400)));
+        assert_eq!(settings.get("verify"), Some(&Value::Boolean(true)));
+
+        let e = load_text("slow", text, Some("slow")).unwrap_err();
+        assert!(e.ends_with("no [profile.slow] table"), "{}", e);
+        assert!(load_text("bad", "baud = ", None).is_err());
+    }
+
+    #[test]
+    fn fills_in_options() {
+        let settings = "device = \"/dev/ttyUSB1\"\n\
+                        baud = 230400\n\
+                        flow_control = \"hardware\"\n\
+                        retries = 3\n\
+                        load_address = \"0x100000\"\n\
+                        compress = \"lz4\"\n\
+                        verify = true\n\
+                        raw = false\n";
+        let opt = apply_to(settings, &[]).unwrap();
+        assert_eq!(opt.tty_path, Some(PathBuf::from("/dev/ttyUSB1")));
+        assert_eq!(opt.baud_rate, BaudRate::from_speed(230400));
+        assert_eq!(opt.flow_control, FlowControl::FlowHardware);
+        assert_eq!(opt.retries, 3);
+        assert_eq!(opt.load_address, 0x100000);
+        assert!(opt.compress.is_some());
+        assert!(opt.verify);
+        assert!(!opt.raw);
+    }
+
+    #[test]
+    fn command_line_wins() {
+        let settings = "device = \"/dev/ttyUSB1\"\nbaud = 230400\nretries = 3\n";
+        let opt = apply_to(settings, &["-b", "9600", "/dev/ttyS0"]).unwrap();
+        assert_eq!(opt.tty_path, Some(PathBuf::from("/dev/ttyS0")));
+        assert_eq!(opt.baud_rate, BaudRate::Baud9600);
+        assert_eq!(opt.retries, 3);
+
+        // Flags can only be turned on, so a setting can't turn one off.
+        let opt = apply_to("verify = false\n", &["--verify"]).unwrap();
+        assert!(opt.verify);
+    }
+
+    #[test]
+    fn rejects_invalid_settings() {
+        let e = apply_to("baud = \"fast\"\n", &[]).unwrap_err();
+        assert!(e.starts_with("invalid `baud` in .ttywrite.toml: "), "{}", e);
+        let e = apply_to("baud = 1.5\n", &[]).unwrap_err();
+        assert_eq!(e, "invalid `baud` in .ttywrite.toml: expected a string or an integer");
+        let e = apply_to("verify = \"yes\"\n", &[]).unwrap_err();
+        assert_eq!(e, "invalid `verify` in .ttywrite.toml: expected true or false");
+        assert!(apply_to("compress = \"zip\"\n", &[]).is_err());
+
+        // An invalid setting doesn't matter if the command line overrides it.
+        assert!(apply_to("baud = \"fast\"\n", &["-b", "9600"]).is_ok());
+    }
+}
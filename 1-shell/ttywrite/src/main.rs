@@ -5,29 +5,67 @@ extern crate xmodem;
 extern crate structopt_derive;
 #[macro_use]
 extern crate crossterm;
+extern crate lz4_flex;
+extern crate flate2;
+extern crate toml;
 
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Cursor, Read, Write};
+use std::path::PathBuf;
+use std::process;
 use std::time::Duration;
-use std::{io::stdout, path::PathBuf};
 
-use crossterm::{cursor, execute, style, terminal};
 use serial::core::{BaudRate, CharSize, FlowControl, SerialDevice, SerialPortSettings, StopBits};
+use serial::SystemPort;
 use structopt::StructOpt;
-use xmodem::{Progress, Xmodem};
+use xmodem::kermit::Kermit;
+use xmodem::{image, verify, Xmodem};
 
+mod autobaud;
+mod batch;
+mod boot;
+mod compress;
+mod config;
 mod parsers;
+mod ports;
+mod progress;
+mod records;
+mod reset;
 
-use parsers::{parse_baud_rate, parse_flow_control, parse_stop_bits, parse_width};
+use compress::Compression;
+use parsers::{
+    parse_address, parse_baud_rate, parse_compression, parse_flow_control, parse_reset_line,
+    parse_stop_bits, parse_width,
+};
+use reset::ResetLine;
 
 #[derive(StructOpt, Debug)]
-#[structopt(about = "Write to TTY using the XMODEM protocol by default.")]
+#[structopt(about = "Write to (or, with --receive, read from) TTY using the XMODEM protocol by default.")]
 struct Opt {
     #[structopt(
         short = "i",
-        help = "Input file (defaults to stdin if not set)",
+        help = "Input file (defaults to stdin if not set); .hex and .srec files are converted to a binary image",
         parse(from_os_str)
     )]
     input: Option<PathBuf>,
 
+    #[structopt(
+        short = "o",
+        help = "Output file for --receive (defaults to stdout if not set)",
+        parse(from_os_str)
+    )]
+    output: Option<PathBuf>,
+
+    #[structopt(long = "receive", help = "Receive from the TTY instead of sending to it")]
+    receive: bool,
+
+    #[structopt(
+        long = "batch",
+        help = "Send every file in a directory, including subdirectories, in one YMODEM session (instead of -i)",
+        parse(from_os_str)
+    )]
+    batch: Option<PathBuf>,
+
     #[structopt(
         short = "b",
         long = "baud",
@@ -37,11 +75,17 @@ struct Opt {
     )]
     baud_rate: BaudRate,
 
+    #[structopt(
+        long = "auto-baud",
+        help = "Before sending, move to the fastest baud rate the bootloader can run at; returns to --baud afterwards, or to retry a failed transfer"
+    )]
+    auto_baud: bool,
+
     #[structopt(
         short = "t",
         long = "timeout",
         parse(try_from_str),
-        help = "Set timeout in seconds",
+        help = "Set timeout in seconds (for XMODEM sends, to wait for the receiver to start)",
         default_value = "10"
     )]
     timeout: u64,
@@ -55,8 +99,20 @@ struct Opt {
     )]
     char_width: CharSize,
 
-    #[structopt(help = "Path to TTY device", parse(from_os_str))]
-    tty_path: PathBuf,
+    #[structopt(
+        help = "Path to TTY device (defaults to the only USB-serial adapter present)",
+        parse(from_os_str)
+    )]
+    tty_path: Option<PathBuf>,
+
+    #[structopt(short = "l", long = "list", help = "List serial devices and exit")]
+    list: bool,
+
+    #[structopt(
+        long = "profile",
+        help = "Use the [profile.<name>] defaults from ~/.ttywrite.toml on top of its top-level ones"
+    )]
+    profile: Option<String>,
 
     #[structopt(
         short = "f",
@@ -76,16 +132,124 @@ struct Opt {
     )]
     stop_bits: StopBits,
 
+    #[structopt(
+        long = "reset",
+        parse(try_from_str = "parse_reset_line"),
+        help = "Reset the board before sending by pulsing a modem-control line ('dtr' or 'rts') wired to its reset"
+    )]
+    reset: Option<ResetLine>,
+
     #[structopt(short = "r", long = "raw", help = "Disable XMODEM")]
     raw: bool,
+
+    #[structopt(
+        long = "kermit",
+        help = "Use Kermit instead of XMODEM, for links that can't carry binary data or flow-control characters"
+    )]
+    kermit: bool,
+
+    #[structopt(
+        long = "compress",
+        parse(try_from_str = "parse_compression"),
+        help = "Compress the data before sending ('lz4' or 'gzip'); the bootloader unpacks it"
+    )]
+    compress: Option<Compression>,
+
+    #[structopt(
+        long = "header",
+        help = "Put an image header in front of the data, which the bootloader checks and loads the kernel by"
+    )]
+    header: bool,
+
+    #[structopt(
+        long = "load-address",
+        parse(try_from_str = "parse_address"),
+        help = "Set the address the image header says to load the kernel to",
+        default_value = "0x80000"
+    )]
+    load_address: u64,
+
+    #[structopt(
+        long = "entry-offset",
+        parse(try_from_str = "parse_address"),
+        help = "Set the offset of the kernel's entry point the image header gives",
+        default_value = "0"
+    )]
+    entry_offset: u64,
+
+    #[structopt(
+        long = "boot",
+        help = "Use the loader protocol: check the image against the bootloader's limits first, and have it start the kernel once it has verified it"
+    )]
+    boot: bool,
+
+    #[structopt(
+        long = "verify",
+        help = "After sending, have the receiver check the data's length and CRC-32"
+    )]
+    verify: bool,
+
+    #[structopt(
+        long = "retries",
+        parse(try_from_str),
+        help = "Set how many times each XMODEM packet is sent before giving up",
+        default_value = "10"
+    )]
+    retries: usize,
+
+    #[structopt(
+        long = "packet-timeout",
+        parse(try_from_str),
+        help = "Set timeout in milliseconds for each XMODEM packet's response when sending",
+        default_value = "10000"
+    )]
+    packet_timeout: u64,
 }
 
 fn main() {
-    use std::fs::File;
-    use std::io::{self, BufReader};
+    let matches = Opt::clap().get_matches();
+    let mut opt = Opt::from_clap(matches.clone());
+    let settings = config::load(opt.profile.as_ref().map(String::as_str));
+    if let Err(e) = settings.and_then(|settings| {
+        config::apply(&settings, &mut opt, |name| matches.occurrences_of(name) > 0)
+    }) {
+        eprintln!("error: {}", e);
+        process::exit(1);
+    }
+    if opt.batch.is_some() && (opt.receive || opt.input.is_some() || opt.raw || opt.kermit || opt.boot) {
+        eprintln!("error: --batch can't be combined with --receive, -i, --raw, --kermit or --boot");
+        process::exit(1);
+    }
+    if opt.batch.is_some() && (opt.compress.is_some() || opt.header) {
+        eprintln!("error: --batch can't be combined with --compress or --header");
+        process::exit(1);
+    }
+    if opt.verify && (opt.raw || opt.kermit || opt.batch.is_some()) {
+        eprintln!("error: --verify can't be combined with --raw, --kermit or --batch");
+        process::exit(1);
+    }
+    if opt.list {
+        for port in ports::list() {
+            let kind = if port.usb { "usb-serial" } else { "" };
+            println!("{:<32} {}", port.path.display(), kind);
+        }
+        return;
+    }
 
-    let opt = Opt::from_args();
-    let mut serial = serial::open(&opt.tty_path).expect("path points to invalid TTY");
+    let tty_path = match opt.tty_path {
+        Some(ref path) => path.clone(),
+        None => match ports::detect() {
+            Ok(path) => {
+                eprintln!("Using {}", path.display());
+                path
+            }
+            Err(e) => {
+                eprintln!("error: {}", e);
+                process::exit(1);
+            }
+        },
+    };
+    let mut serial = serial::open(&tty_path).expect("path points to invalid TTY");
 
     // FIXME: Implement the `ttywrite` utility.
     let mut tty_settings = serial.read_settings().expect("read tty settings error");
@@ -97,30 +261,248 @@ fn main() {
     serial
         .write_settings(&tty_settings)
         .expect("write tty settings error");
+
+    if let (Some(line), false) = (opt.reset, opt.receive) {
+        reset::reset(&mut serial, line).expect("reset board");
+        eprintln!("Reset the board");
+    }
+
+    let base = opt.baud_rate.speed();
+    let mut rate = base;
+    if opt.auto_baud && !opt.receive && !opt.raw && !opt.kermit {
+        rate = autobaud::negotiate(&mut serial, base).expect("negotiate baud rate");
+        if rate != base {
+            eprintln!("Switched to {} baud", rate);
+        }
+    }
     serial
         .set_timeout(Duration::from_secs(opt.timeout))
         .expect("set timeout error");
 
-    let mut input: Box<dyn io::Read> = match opt.input {
-        Some(path) => Box::new(BufReader::new(File::open(path).unwrap())),
-        None => Box::new(io::stdin()),
+    if opt.receive {
+        let total = receive(&opt, serial);
+        eprintln!("Received {total} bytes");
+    } else {
+        let total = match send(&opt, &mut serial) {
+            Ok(total) => total,
+            // The bootloader drops back to the base rate after a failed
+            // attempt, and asks to resume after what it already has. Only a
+            // file can be read again.
+            Err(e) if rate != base && opt.input.is_some() => {
+                eprintln!("Transfer at {} baud failed ({}), retrying at {}", rate, e, base);
+                autobaud::set_rate(&mut serial, base).expect("set baud rate");
+                rate = base;
+                serial
+                    .set_timeout(Duration::from_secs(opt.timeout))
+                    .expect("set timeout error");
+                send(&opt, &mut serial).unwrap_or_else(|e| fail(e))
+            }
+            Err(e) => fail(e),
+        };
+        // Like the bootloader, leave the port at the base rate for the
+        // console, which a terminal opened next may not set itself.
+        if rate != base {
+            autobaud::set_rate(&mut serial, base).expect("set baud rate");
+            eprintln!("Switched back to {} baud", base);
+        }
+        println!("Sent {total} bytes");
+    }
+}
+
+fn fail(e: io::Error) -> ! {
+    eprintln!("error: {}", e);
+    process::exit(1);
+}
+
+/// Opens the input file or stdin, converted from hex records, given an image
+/// header and compressed if requested, returning it along with its length if
+/// known.
+fn open_input(opt: &Opt) -> (Box<dyn io::Read>, Option<u64>) {
+    let (mut input, len): (Box<dyn io::Read>, _) = match opt.input {
+        Some(ref path) => match records::Format::of(path) {
+            Some(format) => {
+                let text = std::fs::read_to_string(path).unwrap();
+                let image = records::parse(&text, format).unwrap_or_else(|e| {
+                    eprintln!("error: {}: {}", path.display(), e);
+                    process::exit(1);
+                });
+                let len = image.len() as u64;
+                (Box::new(Cursor::new(image)), Some(len))
+            }
+            None => {
+                let file = File::open(path).unwrap();
+                let len = file.metadata().ok().map(|m| m.len());
+                (Box::new(BufReader::new(file)), len)
+            }
+        },
+        None => (Box::new(io::stdin()), None),
     };
 
-    let total = if opt.raw {
-        io::copy(input.as_mut(), &mut serial).unwrap()
+    let (input, len) = if opt.header {
+        let mut image = Vec::new();
+        input.read_to_end(&mut image).expect("read input");
+        if image.len() > u32::MAX as usize || opt.entry_offset >= image.len() as u64 {
+            eprintln!("error: the entry offset must lie within the image, of at most 4 GiB");
+            process::exit(1);
+        }
+        let header = image::Header::new(&image, opt.load_address, opt.entry_offset as u32);
+        let mut data = header.to_bytes().to_vec();
+        data.extend_from_slice(&image);
+        let len = data.len() as u64;
+        (Box::new(Cursor::new(data)) as Box<dyn io::Read>, Some(len))
     } else {
-        fn progress_fn(progress: Progress) {
-            let mut stdout = stdout();
-            execute!(
-                stdout,
-                cursor::MoveToColumn(0),
-                terminal::Clear(terminal::ClearType::CurrentLine),
-                style::Print(format!("Progress: {:?}", progress))
-            )
-            .unwrap();
+        (input, len)
+    };
+
+    match opt.compress {
+        Some(compression) => {
+            let data = compress::compress(input, compression).expect("compress input");
+            if let Some(len) = len {
+                eprintln!("Compressed {} bytes to {}", len, data.len());
+            }
+            let len = data.len() as u64;
+            (Box::new(Cursor::new(data)), Some(len))
+        }
+        None => (input, len),
+    }
+}
+
+/// Sends the input file or stdin, or the files of the `--batch` directory, to
+/// `serial` and returns the number of bytes sent.
+fn send(opt: &Opt, serial: &mut SystemPort) -> io::Result<u64> {
+    if let Some(ref dir) = opt.batch {
+        let packet_timeout = Duration::from_millis(opt.packet_timeout);
+        return batch::send(dir, serial, opt.retries, packet_timeout);
+    }
+    let (mut input, total) = open_input(opt);
+
+    if opt.raw {
+        return io::copy(input.as_mut(), serial);
+    }
+    if opt.kermit {
+        let mut data = Vec::new();
+        input.read_to_end(&mut data)?;
+        let name = opt
+            .input
+            .as_ref()
+            .and_then(|path| path.file_name())
+            .map_or("stdin".into(), |name| name.to_string_lossy());
+        return Kermit::send(serial, &name, &data).map(|sent| sent as u64);
+    }
+
+    // The bootloader checks the image against a description sent up front,
+    // so it has to be read in first.
+    if opt.boot {
+        let mut data = Vec::new();
+        input.read_to_end(&mut data)?;
+        let image = verify::Trailer::of(&data);
+        boot::open_session(serial, image, Duration::from_secs(opt.timeout))?;
+        input = Box::new(Cursor::new(data));
+    }
+
+    progress::start("Sent", total);
+    let result = transmit(opt, serial, input, total);
+    progress::finish();
+    let (sent, trailer) = result?;
+
+    if opt.boot {
+        boot::start_kernel(serial)?;
+        eprintln!("Bootloader verified the image and started the kernel");
+    } else if opt.verify {
+        match verify::send(serial, trailer) {
+            Ok(true) => eprintln!("Receiver verified the data"),
+            Ok(false) => {
+                eprintln!("error: receiver's data doesn't match what was sent");
+                process::exit(1);
+            }
+            Err(e) => {
+                eprintln!("error: verification failed: {}", e);
+                process::exit(1);
+            }
+        }
+    }
+    Ok(sent)
+}
+
+/// Sends `input`, which is `total` bytes long if known, over XMODEM and
+/// returns the number of bytes sent along with their trailer.
+fn transmit(
+    opt: &Opt,
+    serial: &mut SystemPort,
+    input: Box<dyn io::Read>,
+    total: Option<u64>,
+) -> io::Result<(u64, verify::Trailer)> {
+    let mut transmitter = Xmodem::new_with_progress(serial, progress::update);
+    transmitter.set_retries(opt.retries);
+    transmitter.start_transmit()?;
+    transmitter
+        .get_mut()
+        .set_timeout(Duration::from_millis(opt.packet_timeout))
+        .expect("set timeout error");
+    let mut input = verify::Tracking::new(progress::Counting(input));
+
+    // A receiver holding part of the data from an interrupted transfer asks
+    // to skip it. Only a file can be read again if it turns out to differ.
+    let mut skipped = 0;
+    if let Some(held) = transmitter.resume_request() {
+        let accept = opt.input.is_some() && {
+            let _ = io::copy(&mut (&mut input).take(held.len as u64), &mut io::sink());
+            input.trailer() == held
+        };
+        if accept {
+            eprintln!("Resuming after {} bytes", held.len);
+            skipped = held.len as u64;
+        } else if opt.input.is_some() {
+            progress::start("Sent", total);
+            input = verify::Tracking::new(progress::Counting(open_input(opt).0));
         }
-        Xmodem::transmit_with_progress(input, serial, progress_fn).unwrap() as u64
+        transmitter.answer_resume(accept)?;
+    }
+
+    let sent = skipped + transmitter.transmit_from(&mut input)? as u64;
+    Ok((sent, input.trailer()))
+}
+
+/// Receives from `serial` into the output file or stdout and returns the
+/// number of bytes received. Raw receptions end once `serial` has been idle
+/// for the timeout. YMODEM senders' files are received at their exact length.
+fn receive(opt: &Opt, mut serial: SystemPort) -> u64 {
+    let mut output: Box<dyn io::Write> = match opt.output {
+        Some(ref path) => Box::new(BufWriter::new(File::create(path).unwrap())),
+        None => Box::new(io::stdout()),
+    };
+
+    let total = if opt.raw {
+        let mut buf = [0u8; 4096];
+        let mut total = 0;
+        loop {
+            match serial.read(&mut buf) {
+                Ok(0) => break,
+                Ok(n) => {
+                    output.write_all(&buf[..n]).unwrap();
+                    total += n as u64;
+                }
+                Err(ref e) if e.kind() == io::ErrorKind::TimedOut => break,
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => panic!("read error: {}", e),
+            }
+        }
+        total
+    } else if opt.kermit {
+        let received = Kermit::receive(&mut serial, output.as_mut()).unwrap();
+        if let Some(name) = received.name {
+            eprintln!("Received {}", name);
+        }
+        received.bytes
+    } else {
+        progress::start("Received", None);
+        let mut receiver = Xmodem::new_with_progress(serial, progress::update);
+        receiver.set_retries(opt.retries);
+        let received = receiver.receive_to_writer(progress::Counting(output.as_mut())).unwrap();
+        progress::finish();
+        received as u64
     };
 
-    println!("\nSent {total} bytes");
+    output.flush().unwrap();
+    total
 }
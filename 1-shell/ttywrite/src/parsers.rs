@@ -1,6 +1,9 @@
 use serial::core::{CharSize, BaudRate, StopBits, FlowControl};
 
-pub fn parse_width(s: &str) -> Result<CharSize, &str> {
+use compress::Compression;
+use reset::ResetLine;
+
+pub fn parse_width(s: &str) -> Result<CharSize, &'static str> {
     match s {
         "5" => Ok(CharSize::Bits5),
         "6" => Ok(CharSize::Bits6),
@@ -10,7 +13,7 @@ pub fn parse_width(s: &str) -> Result<CharSize, &str> {
     }
 }
 
-pub fn parse_stop_bits(s: &str) -> Result<StopBits, &str> {
+pub fn parse_stop_bits(s: &str) -> Result<StopBits, &'static str> {
     match s {
         "1" => Ok(StopBits::Stop1),
         "2" => Ok(StopBits::Stop2),
@@ -18,7 +21,7 @@ pub fn parse_stop_bits(s: &str) -> Result<StopBits, &str> {
     }
 }
 
-pub fn parse_flow_control(s: &str) -> Result<FlowControl, &str> {
+pub fn parse_flow_control(s: &str) -> Result<FlowControl, &'static str> {
     match s {
         "none" => Ok(FlowControl::FlowNone),
         "software" => Ok(FlowControl::FlowSoftware),
@@ -30,3 +33,26 @@ pub fn parse_flow_control(s: &str) -> Result<FlowControl, &str> {
 pub fn parse_baud_rate(s: &str) -> Result<BaudRate, ::std::num::ParseIntError> {
     Ok(BaudRate::from_speed(s.parse()?))
 }
+
+pub fn parse_address(s: &str) -> Result<u64, ::std::num::ParseIntError> {
+    match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => s.parse(),
+    }
+}
+
+pub fn parse_compression(s: &str) -> Result<Compression, &'static str> {
+    match s {
+        "lz4" => Ok(Compression::Lz4),
+        "gzip" => Ok(Compression::Gzip),
+        _ => Err("value must be 'lz4' or 'gzip'")
+    }
+}
+
+pub fn parse_reset_line(s: &str) -> Result<ResetLine, &'static str> {
+    match s {
+        "dtr" => Ok(ResetLine::Dtr),
+        "rts" => Ok(ResetLine::Rts),
+        _ => Err("value must be 'dtr' or 'rts'")
+    }
+}
@@ -0,0 +1,77 @@
+use std::path::PathBuf;
+
+/// Prefixes of the device names USB-serial adapters get: `ttyUSB` (FTDI,
+/// CP210x, PL2303) and `ttyACM` (CDC-ACM) on Linux, and the callout devices
+/// macOS creates for the same adapters.
+const USB_SERIAL_PREFIXES: &[&str] = &[
+    "ttyUSB",
+    "ttyACM",
+    "cu.usbserial",
+    "cu.usbmodem",
+    "cu.SLAB_USBtoUART",
+    "cu.wchusbserial",
+];
+
+/// Other serial devices worth listing: ARM on-board UARTs and the remaining
+/// macOS callout devices. `ttyS*` are left out, since Linux creates them
+/// whether or not there is a UART behind them.
+const OTHER_SERIAL_PREFIXES: &[&str] = &["ttyAMA", "cu."];
+
+/// A serial device found by `list`.
+#[derive(Debug)]
+pub struct Port {
+    pub path: PathBuf,
+    /// Whether the device looks like a USB-serial adapter.
+    pub usb: bool,
+}
+
+/// Returns the serial devices under `/dev`, USB-serial adapters first, each
+/// group sorted by name.
+#[cfg(unix)]
+pub fn list() -> Vec<Port> {
+    let entries = match std::fs::read_dir("/dev") {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+
+    let mut ports: Vec<Port> = entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            let has_prefix = |prefixes: &[&str]| prefixes.iter().any(|p| name.starts_with(p));
+            let usb = has_prefix(USB_SERIAL_PREFIXES);
+            if usb || has_prefix(OTHER_SERIAL_PREFIXES) {
+                Some(Port { path: entry.path(), usb })
+            } else {
+                None
+            }
+        })
+        .collect();
+    ports.sort_by(|a, b| b.usb.cmp(&a.usb).then_with(|| a.path.cmp(&b.path)));
+    ports
+}
+
+/// Returns the COM ports that can be opened. Windows names them all alike, so
+/// each is treated as a possible USB-serial adapter.
+#[cfg(windows)]
+pub fn list() -> Vec<Port> {
+    (1..=256)
+        .map(|n| PathBuf::from(format!("COM{}", n)))
+        .filter(|path| serial::open(path).is_ok())
+        .map(|path| Port { path, usb: true })
+        .collect()
+}
+
+/// Returns the only USB-serial adapter present, or an error describing why
+/// there isn't exactly one.
+pub fn detect() -> Result<PathBuf, String> {
+    let mut usb: Vec<_> = list().into_iter().filter(|port| port.usb).collect();
+    match usb.len() {
+        1 => Ok(usb.remove(0).path),
+        0 => Err("no USB-serial adapter found; pass the TTY path explicitly".to_string()),
+        _ => {
+            let paths: Vec<_> = usb.iter().map(|port| port.path.display().to_string()).collect();
+            Err(format!("several USB-serial adapters found ({}); pick one", paths.join(", ")))
+        }
+    }
+}
@@ -0,0 +1,150 @@
+use std::io::{self, stderr, Read, Write};
+use std::sync::Mutex;
+use std::time::Instant;
+
+use crossterm::{cursor, execute, style, terminal};
+use xmodem::{Progress, Stats};
+
+/// Width of the bar, in characters.
+const BAR_WIDTH: usize = 30;
+
+/// The transfer being displayed. `xmodem` takes a plain `fn` as its progress
+/// callback, so this lives in a static rather than in a closure.
+static TRANSFER: Mutex<Transfer> = Mutex::new(Transfer::new());
+
+struct Transfer {
+    verb: &'static str,
+    total: Option<u64>,
+    bytes: u64,
+    /// The statistics last reported by `xmodem`.
+    stats: Option<Stats>,
+    started: Option<Instant>,
+}
+
+impl Transfer {
+    const fn new() -> Transfer {
+        Transfer { verb: "", total: None, bytes: 0, stats: None, started: None }
+    }
+
+    fn render(&self) -> String {
+        let elapsed = self.started.map_or(0.0, |t| t.elapsed().as_secs_f64());
+        let rate = if elapsed > 0.0 { self.bytes as f64 / elapsed } else { 0.0 };
+
+        let mut line = match self.total {
+            None => format!("{} {}", self.verb, human(self.bytes as f64)),
+            Some(total) => {
+                let fraction = match total {
+                    0 => 1.0,
+                    _ => (self.bytes as f64 / total as f64).min(1.0),
+                };
+                let filled = (fraction * BAR_WIDTH as f64) as usize;
+                format!(
+                    "{} [{}{}] {:3.0}% {} / {}",
+                    self.verb,
+                    "#".repeat(filled),
+                    " ".repeat(BAR_WIDTH - filled),
+                    fraction * 100.0,
+                    human(self.bytes as f64),
+                    human(total as f64)
+                )
+            }
+        };
+
+        line += &format!("  {}/s", human(rate));
+        if let (Some(total), true) = (self.total, rate > 0.0) {
+            let eta = total.saturating_sub(self.bytes) as f64 / rate;
+            line += &format!("  ETA {}:{:02}", eta as u64 / 60, eta as u64 % 60);
+        }
+        // Tell a marginal link (mostly NAKs, i.e. corrupted packets) from a
+        // stalling one (mostly timeouts).
+        if let Some(stats) = self.stats.filter(|stats| stats.retransmits > 0) {
+            let attempts = stats.packets + stats.retransmits;
+            line += &format!(
+                "  retransmits: {} ({:.1}%; {} NAK, {} timeout)",
+                stats.retransmits,
+                stats.retransmits as f64 * 100.0 / attempts as f64,
+                stats.naks,
+                stats.timeouts
+            );
+        }
+        line
+    }
+}
+
+/// Formats `bytes` with a binary unit prefix.
+fn human(bytes: f64) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+    let mut value = bytes;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    match unit {
+        0 => format!("{} {}", value, UNITS[0]),
+        _ => format!("{:.1} {}", value, UNITS[unit]),
+    }
+}
+
+fn draw(line: &str) {
+    let _ = execute!(
+        stderr(),
+        cursor::MoveToColumn(0),
+        terminal::Clear(terminal::ClearType::CurrentLine),
+        style::Print(line)
+    );
+}
+
+/// Starts displaying a transfer of `total` bytes, if known. `verb` describes
+/// the direction, e.g. "Sent".
+pub fn start(verb: &'static str, total: Option<u64>) {
+    *TRANSFER.lock().unwrap() = Transfer { verb, total, ..Transfer::new() };
+}
+
+/// Progress callback for `xmodem`. The display goes to stderr so it can't mix
+/// with data received to stdout.
+pub fn update(progress: Progress) {
+    let mut transfer = TRANSFER.lock().unwrap();
+    match progress {
+        Progress::Waiting => return draw("Waiting for receiver..."),
+        Progress::Started => transfer.started = Some(Instant::now()),
+        Progress::Packet(stats) | Progress::Retry(stats) => transfer.stats = Some(stats),
+    }
+    draw(&transfer.render());
+}
+
+/// Redraws the display a final time and moves past it.
+pub fn finish() {
+    let transfer = TRANSFER.lock().unwrap();
+    if transfer.started.is_some() {
+        draw(&transfer.render());
+        eprintln!();
+    }
+}
+
+/// Counts the bytes read from or written to `T` as transferred.
+///
+/// Packets don't say how much data they carry, so the count is taken at the
+/// data's source (when sending) or sink (when receiving) instead. A sender
+/// reads at most one packet ahead.
+pub struct Counting<T>(pub T);
+
+impl<T: Read> Read for Counting<T> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.0.read(buf)?;
+        TRANSFER.lock().unwrap().bytes += n as u64;
+        Ok(n)
+    }
+}
+
+impl<T: Write> Write for Counting<T> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.0.write(buf)?;
+        TRANSFER.lock().unwrap().bytes += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
@@ -0,0 +1,270 @@
+//! Intel HEX and Motorola S-record inputs.
+//!
+//! Both formats describe memory as records of bytes at addresses. The
+//! bootloader wants the raw image it copies to `BINARY_START`, so the records
+//! are laid out from there, with any gaps between them filled with zeroes.
+
+use std::path::Path;
+
+/// Where the bootloader puts the image; see `KERNEL_START` in `pi::common`.
+const BINARY_START: u64 = 0x80000;
+
+/// Where the bootloader's stack, 1 MiB below the bootloader itself, starts;
+/// images must end before it. See `BOOTLOADER_START` in `pi::common`.
+const BINARY_END: u64 = 0x3F0_0000;
+
+/// Formats of record files.
+#[derive(Debug, Copy, Clone)]
+pub enum Format {
+    IntelHex,
+    Srec,
+}
+
+impl Format {
+    /// Returns the format a file with the path `path` is in, going by its
+    /// extension, or `None` if it's a plain binary.
+    pub fn of(path: &Path) -> Option<Format> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        match &extension[..] {
+            "hex" | "ihex" | "ihx" => Some(Format::IntelHex),
+            "srec" | "s19" | "s28" | "s37" | "mot" => Some(Format::Srec),
+            _ => None,
+        }
+    }
+}
+
+/// Bytes at an address.
+struct Segment {
+    addr: u64,
+    data: Vec<u8>,
+}
+
+/// Parses `text`, in the format `format`, into the image to load at
+/// `BINARY_START`.
+pub fn parse(text: &str, format: Format) -> Result<Vec<u8>, String> {
+    let mut segments = Vec::new();
+    let mut base = 0;
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let done = match format {
+            Format::IntelHex => intel_hex_record(line, &mut base, &mut segments),
+            Format::Srec => srec_record(line, &mut segments),
+        }
+        .map_err(|e| format!("line {}: {}", i + 1, e))?;
+        if done {
+            break;
+        }
+    }
+    layout(segments)
+}
+
+/// Lays `segments` out from `BINARY_START`.
+fn layout(mut segments: Vec<Segment>) -> Result<Vec<u8>, String> {
+    segments.retain(|segment| !segment.data.is_empty());
+    segments.sort_by_key(|segment| segment.addr);
+
+    let mut image = Vec::new();
+    for segment in segments {
+        let end = segment.addr + segment.data.len() as u64;
+        if segment.addr < BINARY_START || end > BINARY_END {
+            return Err(format!(
+                "data at {:#x}..{:#x} is outside {:#x}..{:#x}, where the bootloader loads images",
+                segment.addr, end, BINARY_START, BINARY_END
+            ));
+        }
+
+        let offset = (segment.addr - BINARY_START) as usize;
+        if offset < image.len() {
+            return Err(format!("records overlap at {:#x}", segment.addr));
+        }
+        image.resize(offset, 0);
+        image.extend_from_slice(&segment.data);
+    }
+    Ok(image)
+}
+
+/// Decodes the hexadecimal digits in `digits`.
+fn hex_bytes(digits: &str) -> Result<Vec<u8>, &'static str> {
+    if !digits.len().is_multiple_of(2) || !digits.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err("malformed hexadecimal digits");
+    }
+    Ok((0..digits.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&digits[i..i + 2], 16).unwrap())
+        .collect())
+}
+
+/// Reads a big-endian number from `bytes`.
+fn be(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0, |n, &b| n << 8 | b as u64)
+}
+
+/// Parses an Intel HEX record into `segments`. Returns `true` for the record
+/// that ends the file.
+///
+/// Extended segment and linear address records set `base`, which data
+/// records are relative to; start address records are ignored, since the
+/// bootloader always starts images at their beginning.
+fn intel_hex_record(
+    line: &str,
+    base: &mut u64,
+    segments: &mut Vec<Segment>,
+) -> Result<bool, &'static str> {
+    if !line.is_ascii() || !line.starts_with(':') {
+        return Err("record doesn't start with ':'");
+    }
+    let bytes = hex_bytes(&line[1..])?;
+    if bytes.len() < 5 || bytes.len() != 5 + bytes[0] as usize {
+        return Err("record length doesn't match its byte count");
+    }
+    if bytes.iter().fold(0u8, |sum, &b| sum.wrapping_add(b)) != 0 {
+        return Err("bad checksum");
+    }
+
+    let offset = be(&bytes[1..3]);
+    let data = &bytes[4..bytes.len() - 1];
+    match bytes[3] {
+        0x00 => segments.push(Segment { addr: *base + offset, data: data.to_vec() }),
+        0x01 => return Ok(true),
+        0x02 if data.len() == 2 => *base = be(data) << 4,
+        0x04 if data.len() == 2 => *base = be(data) << 16,
+        0x03 | 0x05 => {}
+        _ => return Err("unknown record type"),
+    }
+    Ok(false)
+}
+
+/// Parses an S-record into `segments`. Returns `true` for a record that ends
+/// the file. Header, count and start address records are ignored.
+fn srec_record(line: &str, segments: &mut Vec<Segment>) -> Result<bool, &'static str> {
+    if !line.is_ascii() || line.len() < 2 || !line.starts_with('S') {
+        return Err("record doesn't start with 'S'");
+    }
+    let kind = line.as_bytes()[1];
+    let bytes = hex_bytes(&line[2..])?;
+    if bytes.is_empty() || bytes.len() != 1 + bytes[0] as usize {
+        return Err("record length doesn't match its byte count");
+    }
+    if bytes.iter().fold(0u8, |sum, &b| sum.wrapping_add(b)) != 0xFF {
+        return Err("bad checksum");
+    }
+
+    let addr_len = match kind {
+        b'0' | b'1' | b'5' | b'9' => 2,
+        b'2' | b'6' | b'8' => 3,
+        b'3' | b'7' => 4,
+        _ => return Err("unknown record type"),
+    };
+    if bytes.len() < 2 + addr_len {
+        return Err("record too short for its address");
+    }
+    let addr = be(&bytes[1..1 + addr_len]);
+    let data = &bytes[1 + addr_len..bytes.len() - 1];
+    match kind {
+        b'1' | b'2' | b'3' => segments.push(Segment { addr, data: data.to_vec() }),
+        b'7' | b'8' | b'9' => return Ok(true),
+        _ => {}
+    }
+    Ok(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_of_extension() {
+        assert!(matches!(Format::of(Path::new("kernel.hex")), Some(Format::IntelHex)));
+        assert!(matches!(Format::of(Path::new("kernel.IHX")), Some(Format::IntelHex)));
+        assert!(matches!(Format::of(Path::new("kernel.s19")), Some(Format::Srec)));
+        assert!(Format::of(Path::new("kernel.bin")).is_none());
+        assert!(Format::of(Path::new("kernel")).is_none());
+    }
+
+    #[test]
+    fn intel_hex() {
+        // Base 0x80000, 4 bytes at 0, 2 bytes at 8, a start address, EOF.
+        let text = ":020000040008F2\n\
+                    :04000000DEADBEEFC4\n\
+                    \n\
+                    :020008000102F3\n\
+                    :0400000500080000EF\n\
+                    :00000001FF\n\
+                    this is past the end\n";
+        let image = parse(text, Format::IntelHex).unwrap();
+        assert_eq!(image, [0xDE, 0xAD, 0xBE, 0xEF, 0, 0, 0, 0, 1, 2]);
+    }
+
+    #[test]
+    fn intel_hex_segment_address() {
+        // Segment 0x8000 puts offset 0x10 at 0x80010.
+        let text = ":0200000280007C\n:01001000AA45\n:00000001FF\n";
+        let image = parse(text, Format::IntelHex).unwrap();
+        assert_eq!(image.len(), 0x11);
+        assert_eq!(image[0x10], 0xAA);
+        assert!(image[..0x10].iter().all(|&b| b == 0));
+    }
+
+    #[test]
+    fn intel_hex_errors() {
+        let error = |text| parse(text, Format::IntelHex).unwrap_err();
+        assert_eq!(error("020000040008F2"), "line 1: record doesn't start with ':'");
+        assert_eq!(error(":020000040008F2\n:04000000DEADBEEFC5"), "line 2: bad checksum");
+        assert_eq!(error(":03000000DEADBEEFC4"), "line 1: record length doesn't match its byte count");
+        assert_eq!(error(":0400000GDEADBEEFC4"), "line 1: malformed hexadecimal digits");
+        assert_eq!(error(":00000006FA"), "line 1: unknown record type");
+    }
+
+    #[test]
+    fn srec() {
+        // A header, records out of order, a count and the start address.
+        let text = "S0050000686929\n\
+                    S307000800040304E5\n\
+                    S307000800000102ED\n\
+                    S5030002FA\n\
+                    S70500080000F2\n";
+        let image = parse(text, Format::Srec).unwrap();
+        assert_eq!(image, [1, 2, 0, 0, 3, 4]);
+    }
+
+    #[test]
+    fn srec_errors() {
+        let error = |text| parse(text, Format::Srec).unwrap_err();
+        assert_eq!(error(":307000800000102ED"), "line 1: record doesn't start with 'S'");
+        assert_eq!(error("S307000800000102EE"), "line 1: bad checksum");
+        assert_eq!(error("S4030002FA"), "line 1: unknown record type");
+        assert_eq!(error("S3030002FA"), "line 1: record too short for its address");
+        assert!(error("S104100001EA").starts_with("data at 0x1000..0x1001 is outside"));
+    }
+
+    #[test]
+    fn layout_fills_gaps_in_order() {
+        let segments = vec![
+            Segment { addr: BINARY_START + 4, data: vec![3] },
+            Segment { addr: BINARY_START + 8, data: vec![] },
+            Segment { addr: BINARY_START, data: vec![1, 2] },
+        ];
+        assert_eq!(layout(segments).unwrap(), [1, 2, 0, 0, 3]);
+        assert_eq!(layout(vec![]).unwrap(), []);
+    }
+
+    #[test]
+    fn layout_rejects_overlaps_and_out_of_range_data() {
+        let overlapping = vec![
+            Segment { addr: BINARY_START, data: vec![1, 2] },
+            Segment { addr: BINARY_START + 1, data: vec![3] },
+        ];
+        assert_eq!(layout(overlapping).unwrap_err(), "records overlap at 0x80001");
+
+        let below = vec![Segment { addr: BINARY_START - 1, data: vec![1] }];
+        assert!(layout(below).is_err());
+
+        let past_end = vec![Segment { addr: BINARY_END - 1, data: vec![1, 2] }];
+        assert!(layout(past_end).is_err());
+        let up_to_end = vec![Segment { addr: BINARY_END - 1, data: vec![1] }];
+        assert_eq!(layout(up_to_end).unwrap().len(), (BINARY_END - BINARY_START) as usize);
+    }
+}
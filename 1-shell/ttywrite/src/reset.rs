@@ -0,0 +1,32 @@
+use std::thread;
+use std::time::Duration;
+
+use serial::core::SerialDevice;
+use serial::SystemPort;
+
+/// How long the reset line is held asserted.
+const PULSE: Duration = Duration::from_millis(100);
+
+/// Modem-control lines a board's reset circuit can be wired to.
+#[derive(Debug, Copy, Clone)]
+pub enum ResetLine {
+    Dtr,
+    Rts,
+}
+
+/// Resets the board by pulsing `line`: asserting it pulls the reset low
+/// through the transistor, and releasing it lets the board boot into the
+/// bootloader.
+pub fn reset(port: &mut SystemPort, line: ResetLine) -> serial::Result<()> {
+    let mut set = |level| match line {
+        ResetLine::Dtr => port.set_dtr(level),
+        ResetLine::Rts => port.set_rts(level),
+    };
+
+    // Opening the port may have asserted the line already.
+    set(false)?;
+    thread::sleep(PULSE);
+    set(true)?;
+    thread::sleep(PULSE);
+    set(false)
+}
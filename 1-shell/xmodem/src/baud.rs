@@ -0,0 +1,70 @@
+//! A handshake for moving a link to a faster baud rate before a transfer.
+//!
+//! Both ends start at an agreed rate. The host asks for a faster one with
+//! `request_bytes`; a device that can run at it answers by echoing the request
+//! and switches. The host switches too and sends `PATTERN`, which the device
+//! echoes at the new rate. If the pattern doesn't make it across intact, both
+//! ends go back to the rate they came from. The host can repeat this with
+//! ever faster rates until one fails.
+//!
+//! Only the device's side needs this module's I/O helpers; the host has a
+//! clock to bound its waits with and reads the responses itself.
+
+use crate::io;
+use crate::read_ext::ReadExt;
+
+/// Starts every request; the rate follows as a little-endian `u32`.
+pub const REQUEST: [u8; 4] = *b"BAUD";
+
+/// Sent at the new rate to check the link. It mixes alternating, solid and
+/// single-bit bytes, which fail in different ways at a wrong rate.
+pub const PATTERN: [u8; 16] = [
+    0x55, 0xAA, 0x00, 0xFF, 0x0F, 0xF0, 0x33, 0xCC, 0x01, 0x80, 0x7E, 0x81, 0x5A, 0xA5, 0x3C, 0xC3,
+];
+
+/// Longest stretch of other bytes `read_request` skips before giving up.
+const MAX_NOISE: usize = 64;
+
+/// Returns the request for `rate`. The device's answer is the same bytes.
+pub fn request_bytes(rate: u32) -> [u8; 8] {
+    let mut bytes = [0u8; 8];
+    bytes[..4].copy_from_slice(&REQUEST);
+    bytes[4..].copy_from_slice(&rate.to_le_bytes());
+    bytes
+}
+
+/// Waits for a request from the host and returns the rate it asks for, or
+/// `None` if the host stays silent until `port` times out.
+pub fn read_request<T: io::Read>(mut port: T) -> io::Result<Option<u32>> {
+    if !port.skip_to(&REQUEST, MAX_NOISE)? {
+        return Ok(None);
+    }
+
+    let mut rate = [0u8; 4];
+    port.read_exact(&mut rate)?;
+    Ok(Some(u32::from_le_bytes(rate)))
+}
+
+/// Tells the host the device is switching to `rate`. Switch once this
+/// returns, after the answer has left the transmitter.
+pub fn accept<T: io::Write>(mut port: T, rate: u32) -> io::Result<()> {
+    port.write_all(&request_bytes(rate))?;
+    port.flush()
+}
+
+/// Waits for `PATTERN` at the new rate and echoes it. Returns whether it
+/// arrived intact; if not, go back to the previous rate.
+pub fn answer_probe<T: io::Read + io::Write>(mut port: T) -> io::Result<bool> {
+    let mut probe = [0u8; PATTERN.len()];
+    match port.read_exact(&mut probe) {
+        Err(ref e) if crate::is_timeout(e) => return Ok(false),
+        result => result?,
+    }
+    if probe != PATTERN {
+        return Ok(false);
+    }
+
+    port.write_all(&PATTERN)?;
+    port.flush()?;
+    Ok(true)
+}
@@ -0,0 +1,71 @@
+//! The per-packet checks.
+//!
+//! Framing code only deals with a check through `PacketCheck`: how many bytes
+//! it takes after the payload and how to fill them in. `Checksum` names the
+//! checks that can be negotiated and hands out their `PacketCheck`.
+
+use crate::crc;
+
+/// Longest check on the wire, in bytes.
+pub const MAX_LEN: usize = 4;
+
+/// A way of checking a packet's payload for corruption.
+pub trait PacketCheck {
+    /// Returns the number of bytes the check takes on the wire, at most
+    /// `MAX_LEN`.
+    fn wire_len(&self) -> usize;
+
+    /// Writes the check of `payload` to `out`, which is `wire_len()` bytes
+    /// long, in the order it is sent.
+    fn write(&self, payload: &[u8], out: &mut [u8]);
+
+    /// Returns `true` if `check`, as received after `payload`, matches it.
+    fn verify(&self, payload: &[u8], check: &[u8]) -> bool {
+        let mut expected = [0u8; MAX_LEN];
+        let expected = &mut expected[..self.wire_len()];
+        self.write(payload, expected);
+        check == &expected[..]
+    }
+}
+
+/// The original 8-bit arithmetic sum of the payload.
+#[derive(Debug, Default, Copy, Clone)]
+pub struct Sum;
+
+impl PacketCheck for Sum {
+    fn wire_len(&self) -> usize {
+        1
+    }
+
+    fn write(&self, payload: &[u8], out: &mut [u8]) {
+        out[0] = payload.iter().fold(0u8, |sum, &b| sum.wrapping_add(b));
+    }
+}
+
+/// CRC-16/XMODEM of the payload, sent big-endian.
+#[derive(Debug, Default, Copy, Clone)]
+pub struct Crc16;
+
+impl PacketCheck for Crc16 {
+    fn wire_len(&self) -> usize {
+        2
+    }
+
+    fn write(&self, payload: &[u8], out: &mut [u8]) {
+        out.copy_from_slice(&crc::crc16(payload).to_be_bytes());
+    }
+}
+
+/// CRC-32 (IEEE 802.3) of the payload, sent big-endian.
+#[derive(Debug, Default, Copy, Clone)]
+pub struct Crc32;
+
+impl PacketCheck for Crc32 {
+    fn wire_len(&self) -> usize {
+        4
+    }
+
+    fn write(&self, payload: &[u8], out: &mut [u8]) {
+        out.copy_from_slice(&crc::crc32(payload).to_be_bytes());
+    }
+}
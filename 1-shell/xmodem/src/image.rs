@@ -0,0 +1,193 @@
+//! A header in front of a kernel image that says what it is and where it goes.
+//!
+//! `ttywrite --header` puts it in front of the kernel; the bootloader checks
+//! it before starting the kernel, so an image built for another architecture,
+//! or one that lost its end on the way, is rejected instead of jumped to.
+//! The header is `LEN` bytes, little-endian:
+//!
+//! | offset | size | field                                   |
+//! |--------|------|-----------------------------------------|
+//! | 0      | 4    | `MAGIC`                                 |
+//! | 4      | 2    | version of the format, `VERSION`        |
+//! | 6      | 2    | architecture, as an ELF machine number  |
+//! | 8      | 8    | address to load the image to            |
+//! | 16     | 4    | offset of the entry point in the image  |
+//! | 20     | 4    | size of the image                       |
+//! | 24     | 4    | CRC-32 of the image                     |
+//! | 28     | 4    | CRC-32 of the header's first 28 bytes   |
+//!
+//! The image itself follows the header. Images without one are plain
+//! binaries, loaded and started at the bootloader's usual address.
+
+use core::fmt;
+use core::ops::Range;
+
+use crate::crc::crc32;
+
+/// Starts every header.
+pub const MAGIC: [u8; 4] = *b"KIMG";
+
+/// The version of the format this module implements.
+pub const VERSION: u16 = 1;
+
+/// The ELF machine number of AArch64, which the Pi's kernels are built for.
+pub const ARCH_AARCH64: u16 = 183;
+
+/// Length of a header.
+pub const LEN: usize = 32;
+
+/// What a header says about the image that follows it.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Header {
+    pub version: u16,
+    pub arch: u16,
+    pub load_addr: u64,
+    pub entry_offset: u32,
+    pub size: u32,
+    pub crc: u32,
+}
+
+/// Ways an image with a header can be unusable.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// The header itself was cut short or corrupted.
+    BadHeader,
+    /// The header is in a version of the format this module doesn't know.
+    UnsupportedVersion(u16),
+    /// The image was built for another architecture.
+    WrongArch(u16),
+    /// The entry point lies outside the image.
+    BadEntry,
+    /// Less of the image arrived than the header says it has.
+    Truncated { expected: u32, received: usize },
+    /// The image doesn't match the header's CRC-32.
+    BadCrc { expected: u32, actual: u32 },
+    /// The image would end up outside the memory it can be loaded to.
+    OutOfRange { start: u64, end: u64 },
+    /// The image would overwrite the loader itself.
+    Overlaps { start: u64, end: u64 },
+}
+
+impl Header {
+    /// Returns the header for the AArch64 image `image`, to be loaded to
+    /// `load_addr` and entered `entry_offset` bytes into it.
+    pub fn new(image: &[u8], load_addr: u64, entry_offset: u32) -> Header {
+        Header {
+            version: VERSION,
+            arch: ARCH_AARCH64,
+            load_addr,
+            entry_offset,
+            size: image.len() as u32,
+            crc: crc32(image),
+        }
+    }
+
+    /// Reads the header at the start of `data`. Returns `None` if `data`
+    /// doesn't start with `MAGIC`, in which case it's a plain binary.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the header is damaged, isn't in version `VERSION`
+    /// of the format, describes an image for anything but AArch64 or has its
+    /// entry point outside the image.
+    pub fn parse(data: &[u8]) -> Result<Option<Header>, Error> {
+        if !data.starts_with(&MAGIC) {
+            return Ok(None);
+        }
+        if data.len() < LEN || crc32(&data[..LEN - 4]) != read_u32(&data[LEN - 4..]) {
+            return Err(Error::BadHeader);
+        }
+
+        let header = Header {
+            version: u16::from_le_bytes([data[4], data[5]]),
+            arch: u16::from_le_bytes([data[6], data[7]]),
+            load_addr: read_u32(&data[8..]) as u64 | (read_u32(&data[12..]) as u64) << 32,
+            entry_offset: read_u32(&data[16..]),
+            size: read_u32(&data[20..]),
+            crc: read_u32(&data[24..]),
+        };
+        if header.version != VERSION {
+            return Err(Error::UnsupportedVersion(header.version));
+        }
+        if header.arch != ARCH_AARCH64 {
+            return Err(Error::WrongArch(header.arch));
+        }
+        if header.entry_offset >= header.size {
+            return Err(Error::BadEntry);
+        }
+        Ok(Some(header))
+    }
+
+    /// Checks the image that followed the header, `image`, against it. Only
+    /// the first `size` bytes are checked; `image` may be longer, since the
+    /// last packet was padded.
+    pub fn check(&self, image: &[u8]) -> Result<(), Error> {
+        let size = self.size as usize;
+        if image.len() < size {
+            return Err(Error::Truncated { expected: self.size, received: image.len() });
+        }
+        let actual = crc32(&image[..size]);
+        if actual != self.crc {
+            return Err(Error::BadCrc { expected: self.crc, actual });
+        }
+        Ok(())
+    }
+
+    /// Returns the addresses the image takes up once loaded.
+    pub fn range(&self) -> Range<u64> {
+        self.load_addr..self.load_addr.saturating_add(self.size as u64)
+    }
+
+    /// Returns the address to start the image at once loaded.
+    pub fn entry(&self) -> u64 {
+        self.load_addr + self.entry_offset as u64
+    }
+
+    /// Returns the header as it's stored in front of the image.
+    pub fn to_bytes(&self) -> [u8; LEN] {
+        let mut bytes = [0u8; LEN];
+        bytes[..4].copy_from_slice(&MAGIC);
+        bytes[4..6].copy_from_slice(&self.version.to_le_bytes());
+        bytes[6..8].copy_from_slice(&self.arch.to_le_bytes());
+        bytes[8..16].copy_from_slice(&self.load_addr.to_le_bytes());
+        bytes[16..20].copy_from_slice(&self.entry_offset.to_le_bytes());
+        bytes[20..24].copy_from_slice(&self.size.to_le_bytes());
+        bytes[24..28].copy_from_slice(&self.crc.to_le_bytes());
+        let crc = crc32(&bytes[..LEN - 4]);
+        bytes[LEN - 4..].copy_from_slice(&crc.to_le_bytes());
+        bytes
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::BadHeader => write!(f, "image header is damaged"),
+            Error::UnsupportedVersion(version) => {
+                write!(f, "image header version {} isn't supported", version)
+            }
+            Error::WrongArch(arch) => {
+                write!(f, "image is for machine {}, not AArch64 ({})", arch, ARCH_AARCH64)
+            }
+            Error::BadEntry => write!(f, "image entry point lies outside the image"),
+            Error::Truncated { expected, received } => {
+                write!(f, "image truncated: expected {} bytes, received {}", expected, received)
+            }
+            Error::BadCrc { expected, actual } => write!(
+                f,
+                "image CRC-32 mismatch: expected {:#010x}, computed {:#010x}",
+                expected, actual
+            ),
+            Error::OutOfRange { start, end } => {
+                write!(f, "image at {:#x}..{:#x} doesn't fit in the free memory", start, end)
+            }
+            Error::Overlaps { start, end } => {
+                write!(f, "image at {:#x}..{:#x} would overwrite the loader", start, end)
+            }
+        }
+    }
+}
+
+fn read_u32(data: &[u8]) -> u32 {
+    u32::from_le_bytes([data[0], data[1], data[2], data[3]])
+}
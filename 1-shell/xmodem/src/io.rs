@@ -0,0 +1,154 @@
+//! The I/O traits and errors the crate is written against.
+//!
+//! With the `std` or `custom-std` feature, these are `std::io`'s. Without
+//! either, the crate only needs `core`, and this module provides small
+//! stand-ins in the style of `embedded-io`: implement `Read` and `Write` for a
+//! device, e.g. a UART, and the blocking API works over it as usual.
+
+#[cfg(any(feature = "std", feature = "custom-std"))]
+pub use std::io::{Error, ErrorKind, Read, Result, Write};
+
+#[cfg(not(any(feature = "std", feature = "custom-std")))]
+pub use self::bare::*;
+
+#[cfg(not(any(feature = "std", feature = "custom-std")))]
+mod bare {
+    use core::fmt;
+
+    /// The kinds of errors the protocol distinguishes. Transports should
+    /// report a read that timed out as `TimedOut`.
+    #[derive(Debug, Copy, Clone, PartialEq, Eq)]
+    pub enum ErrorKind {
+        TimedOut,
+        WouldBlock,
+        Interrupted,
+        InvalidData,
+        InvalidInput,
+        UnexpectedEof,
+        ConnectionAborted,
+        BrokenPipe,
+        WriteZero,
+        Other,
+    }
+
+    /// An I/O or protocol error.
+    #[derive(Debug, Copy, Clone, PartialEq, Eq)]
+    pub struct Error {
+        kind: ErrorKind,
+        msg: &'static str,
+    }
+
+    impl Error {
+        /// Returns a new error of kind `kind` described by `msg`.
+        pub const fn new(kind: ErrorKind, msg: &'static str) -> Error {
+            Error { kind, msg }
+        }
+
+        /// Returns the kind of this error.
+        pub fn kind(&self) -> ErrorKind {
+            self.kind
+        }
+    }
+
+    impl From<ErrorKind> for Error {
+        fn from(kind: ErrorKind) -> Error {
+            Error::new(kind, "")
+        }
+    }
+
+    impl fmt::Display for Error {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            match self.msg {
+                "" => write!(f, "{:?}", self.kind),
+                msg => write!(f, "{:?}: {}", self.kind, msg),
+            }
+        }
+    }
+
+    pub type Result<T> = core::result::Result<T, Error>;
+
+    /// A source of bytes, such as a UART's receiver.
+    pub trait Read {
+        /// Reads at least one byte into `buf`, unless it's empty, and returns
+        /// how many were read. Returns `Ok(0)` at the end of the stream.
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize>;
+
+        /// Fills `buf`, failing with `UnexpectedEof` if the stream ends first.
+        fn read_exact(&mut self, mut buf: &mut [u8]) -> Result<()> {
+            while !buf.is_empty() {
+                match self.read(buf) {
+                    Ok(0) => break,
+                    Ok(n) => buf = &mut buf[n..],
+                    Err(ref e) if e.kind() == ErrorKind::Interrupted => {}
+                    Err(e) => return Err(e),
+                }
+            }
+            if buf.is_empty() {
+                Ok(())
+            } else {
+                Err(Error::new(ErrorKind::UnexpectedEof, "failed to fill buffer"))
+            }
+        }
+    }
+
+    /// A sink for bytes, such as a UART's transmitter.
+    pub trait Write {
+        /// Writes some of `buf` and returns how many bytes were written.
+        fn write(&mut self, buf: &[u8]) -> Result<usize>;
+
+        /// Waits until everything written has been sent.
+        fn flush(&mut self) -> Result<()>;
+
+        /// Writes all of `buf`, failing with `WriteZero` if the sink is full.
+        fn write_all(&mut self, mut buf: &[u8]) -> Result<()> {
+            while !buf.is_empty() {
+                match self.write(buf) {
+                    Ok(0) => return Err(Error::new(ErrorKind::WriteZero, "failed to write")),
+                    Ok(n) => buf = &buf[n..],
+                    Err(ref e) if e.kind() == ErrorKind::Interrupted => {}
+                    Err(e) => return Err(e),
+                }
+            }
+            Ok(())
+        }
+    }
+
+    impl<R: Read + ?Sized> Read for &mut R {
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+            (**self).read(buf)
+        }
+    }
+
+    impl<W: Write + ?Sized> Write for &mut W {
+        fn write(&mut self, buf: &[u8]) -> Result<usize> {
+            (**self).write(buf)
+        }
+
+        fn flush(&mut self) -> Result<()> {
+            (**self).flush()
+        }
+    }
+
+    impl Read for &[u8] {
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+            let n = core::cmp::min(buf.len(), self.len());
+            buf[..n].copy_from_slice(&self[..n]);
+            *self = &self[n..];
+            Ok(n)
+        }
+    }
+
+    impl Write for &mut [u8] {
+        fn write(&mut self, buf: &[u8]) -> Result<usize> {
+            let n = core::cmp::min(buf.len(), self.len());
+            let (head, tail) = core::mem::take(self).split_at_mut(n);
+            head.copy_from_slice(&buf[..n]);
+            *self = tail;
+            Ok(n)
+        }
+
+        fn flush(&mut self) -> Result<()> {
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,524 @@
+//! A Kermit implementation for transferring a single file.
+//!
+//! Kermit never puts a control character on the wire besides the mark that
+//! starts a packet and the carriage return that ends it. Control characters
+//! in the data are prefixed and turned into printable ones, and so, when both
+//! ends agree, are bytes with the eighth bit set. That makes it slower than
+//! XMODEM, whose binary framing contains `XON`, `XOFF` and friends, but it
+//! gets through terminal servers and other hops that act on or strip them.
+//!
+//! Each packet is acknowledged before the next is sent. Only the basic
+//! protocol is implemented: packets of up to 94 bytes, no repeat counts, and
+//! one file per session.
+
+use std::prelude::v1::*;
+
+use std::io;
+
+/// Starts every packet.
+const MARK: u8 = 0x01;
+/// Ends every packet we send, and the packets we ask the peer to send.
+const CR: u8 = b'\r';
+
+// Packet types.
+const SEND_INIT: u8 = b'S';
+const FILE: u8 = b'F';
+const DATA: u8 = b'D';
+const EOF: u8 = b'Z';
+const BREAK: u8 = b'B';
+const ACK: u8 = b'Y';
+const NAK: u8 = b'N';
+const ERROR: u8 = b'E';
+
+/// Prefix of a control character in the data.
+const QCTL: u8 = b'#';
+/// Prefix of a byte with the eighth bit set, if both ends agree to use one.
+const QBIN: u8 = b'&';
+
+/// Longest packet in the basic protocol, counted from the sequence number
+/// through the check.
+const MAX_LEN: usize = 94;
+
+/// The `MAXL` a peer that doesn't say is taken to accept.
+const DEFAULT_MAX_LEN: usize = 80;
+
+/// Seconds we ask the peer to wait for a packet before it sends again.
+const TIMEOUT_SECS: u8 = 5;
+
+/// Bad packets and timeouts tolerated in a row before a transfer is given up.
+const MAX_RETRIES: usize = 10;
+
+/// Turns a number from 0 to 94 into a printable character.
+fn tochar(n: u8) -> u8 {
+    n + 32
+}
+
+/// Undoes `tochar`.
+fn unchar(c: u8) -> u8 {
+    c.wrapping_sub(32)
+}
+
+/// Toggles a control character to a printable one and back.
+fn ctl(c: u8) -> u8 {
+    c ^ 0x40
+}
+
+fn protocol_error(msg: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+fn is_timeout(e: &io::Error) -> bool {
+    e.kind() == io::ErrorKind::TimedOut || e.kind() == io::ErrorKind::WouldBlock
+}
+
+/// Computes CRC-16/KERMIT (CCITT polynomial, reflected, initial value `0`)
+/// of `data`.
+fn crc16(data: &[u8]) -> u16 {
+    let mut crc = 0u16;
+    for &byte in data {
+        crc ^= byte as u16;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0x8408 } else { crc >> 1 };
+        }
+    }
+    crc
+}
+
+/// The block check that ends a packet.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Check {
+    /// A 6-bit checksum in one character. Always used for `SEND_INIT` and
+    /// its acknowledgement, before the peers have agreed on anything else.
+    Sum1,
+    /// A 12-bit checksum in two characters.
+    Sum2,
+    /// A CRC-16 in three characters.
+    Crc3,
+}
+
+impl Check {
+    fn from_field(c: u8) -> Check {
+        match c {
+            b'2' => Check::Sum2,
+            b'3' => Check::Crc3,
+            _ => Check::Sum1,
+        }
+    }
+
+    fn field(self) -> u8 {
+        match self {
+            Check::Sum1 => b'1',
+            Check::Sum2 => b'2',
+            Check::Crc3 => b'3',
+        }
+    }
+
+    fn len(self) -> usize {
+        match self {
+            Check::Sum1 => 1,
+            Check::Sum2 => 2,
+            Check::Crc3 => 3,
+        }
+    }
+
+    /// Appends the check of `body`, a packet from its length through its
+    /// data, to `out`.
+    fn append(self, body: &[u8], out: &mut Vec<u8>) {
+        let sum = body.iter().map(|&b| b as u32).sum::<u32>();
+        match self {
+            Check::Sum1 => out.push(tochar(((sum + ((sum & 0xC0) >> 6)) & 0x3F) as u8)),
+            Check::Sum2 => {
+                out.push(tochar(((sum >> 6) & 0x3F) as u8));
+                out.push(tochar((sum & 0x3F) as u8));
+            }
+            Check::Crc3 => {
+                let crc = crc16(body);
+                out.push(tochar(((crc >> 12) & 0x0F) as u8));
+                out.push(tochar(((crc >> 6) & 0x3F) as u8));
+                out.push(tochar((crc & 0x3F) as u8));
+            }
+        }
+    }
+}
+
+/// The parameters a `SEND_INIT` packet and its acknowledgement carry: what
+/// each end wants from the packets the other sends it.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+struct Params {
+    /// Longest packet the sender of these parameters accepts.
+    max_len: usize,
+    /// End of line the sender of these parameters wants after packets.
+    eol: u8,
+    /// Control prefix the sender of these parameters uses in its data.
+    qctl: u8,
+    /// `Y` or `N` to agree to eighth-bit prefixing or not, or the prefix to
+    /// ask for it with.
+    qbin: u8,
+    check: Check,
+}
+
+impl Params {
+    /// What we ask for.
+    fn ours() -> Params {
+        Params { max_len: MAX_LEN, eol: CR, qctl: QCTL, qbin: QBIN, check: Check::Crc3 }
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        vec![
+            tochar(self.max_len as u8),
+            tochar(TIMEOUT_SECS),
+            tochar(0), // NPAD: no padding.
+            ctl(0),    // PADC
+            tochar(self.eol),
+            self.qctl,
+            self.qbin,
+            self.check.field(),
+        ]
+    }
+
+    /// Parses the peer's parameters. Fields the peer left out or that are
+    /// out of range take their defaults.
+    fn decode(data: &[u8]) -> Params {
+        let field = |i: usize| data.get(i).cloned().filter(|&c| c != b' ');
+        Params {
+            max_len: field(0)
+                .map(|c| unchar(c) as usize)
+                .filter(|len| (10..=MAX_LEN).contains(len))
+                .unwrap_or(DEFAULT_MAX_LEN),
+            eol: field(4).map(unchar).filter(|&c| c < 32).unwrap_or(CR),
+            qctl: field(5).filter(|c| c.is_ascii_graphic()).unwrap_or(QCTL),
+            qbin: field(6).unwrap_or(b'N'),
+            check: field(7).map(Check::from_field).unwrap_or(Check::Sum1),
+        }
+    }
+}
+
+/// A packet as read off the wire.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Packet {
+    seq: u8,
+    kind: u8,
+    data: Vec<u8>,
+}
+
+/// The result of a successful `Kermit::receive`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Received {
+    /// The name the file was sent under, or `None` if the sender had nothing
+    /// to send.
+    pub name: Option<String>,
+    /// The number of bytes written to the sink.
+    pub bytes: u64,
+}
+
+/// A Kermit session over `inner`.
+pub struct Kermit<T> {
+    inner: T,
+    /// Sequence number of the next packet, modulo 64.
+    seq: u8,
+    /// The block check both ends agreed on.
+    check: Check,
+    /// The peer's parameters, or defaults until it has sent them.
+    theirs: Params,
+    /// Eighth-bit prefix both ends agreed to use, if any.
+    qbin: Option<u8>,
+    /// The last acknowledgement sent, for when its packet arrives again.
+    last_ack: Vec<u8>,
+}
+
+impl Kermit<()> {
+    /// Sends `data` as a file named `name` to the receiver `to`. Returns the
+    /// number of bytes sent.
+    pub fn send<T: io::Read + io::Write>(to: T, name: &str, data: &[u8]) -> io::Result<usize> {
+        Kermit::new(to).send_file(name, data)
+    }
+
+    /// Receives a file from `from` and writes it to `into`.
+    pub fn receive<T, W>(from: T, into: W) -> io::Result<Received>
+    where
+        T: io::Read + io::Write,
+        W: io::Write,
+    {
+        Kermit::new(from).receive_file(into)
+    }
+}
+
+impl<T: io::Read + io::Write> Kermit<T> {
+    /// Returns a new session over `inner`. Reads from `inner` should time
+    /// out, so that lost packets are sent again.
+    pub fn new(inner: T) -> Kermit<T> {
+        Kermit {
+            inner,
+            seq: 0,
+            check: Check::Sum1,
+            theirs: Params::decode(&[]),
+            qbin: None,
+            last_ack: Vec::new(),
+        }
+    }
+
+    /// Settles on the parameters both ends can work with, given the peer's.
+    fn agree(&mut self, theirs: Params) {
+        let ours = Params::ours();
+        self.check = if theirs.check == ours.check { ours.check } else { Check::Sum1 };
+        self.qbin = match theirs.qbin {
+            b'Y' => Some(ours.qbin),
+            qbin if qbin == ours.qbin => Some(qbin),
+            _ => None,
+        };
+        self.theirs = theirs;
+    }
+
+    /// Appends the encoding of `byte` to `out`.
+    fn encode(&self, byte: u8, out: &mut Vec<u8>) {
+        let mut byte = byte;
+        if let Some(qbin) = self.qbin {
+            if byte & 0x80 != 0 {
+                out.push(qbin);
+                byte &= 0x7F;
+            }
+        }
+
+        let low = byte & 0x7F;
+        if low < 32 || low == 0x7F {
+            out.extend_from_slice(&[QCTL, ctl(byte)]);
+        } else if low == QCTL || Some(low) == self.qbin {
+            out.extend_from_slice(&[QCTL, byte]);
+        } else {
+            out.push(byte);
+        }
+    }
+
+    /// Decodes the data field of a packet from the peer into `out`.
+    fn decode(&self, data: &[u8], out: &mut Vec<u8>) -> io::Result<()> {
+        let qctl = self.theirs.qctl;
+        let mut bytes = data.iter().cloned();
+        while let Some(mut c) = bytes.next() {
+            let mut high = 0;
+            if Some(c) == self.qbin {
+                high = 0x80;
+                c = bytes.next().ok_or_else(|| protocol_error("data ends in a prefix"))?;
+            }
+            if c == qctl {
+                c = bytes.next().ok_or_else(|| protocol_error("data ends in a prefix"))?;
+                // Only prefixed control characters were toggled; anything
+                // else is a prefix character sent literally.
+                if (0x3F..=0x5F).contains(&(c & 0x7F)) {
+                    c = ctl(c);
+                }
+            }
+            out.push(c | high);
+        }
+        Ok(())
+    }
+
+    fn write_packet(&mut self, kind: u8, seq: u8, data: &[u8]) -> io::Result<()> {
+        let check = self.check;
+        let mut packet = vec![MARK, tochar((2 + data.len() + check.len()) as u8), tochar(seq), kind];
+        packet.extend_from_slice(data);
+        let body = packet[1..].to_vec();
+        check.append(&body, &mut packet);
+        packet.push(self.theirs.eol);
+        if kind == ACK {
+            self.last_ack = packet.clone();
+        }
+
+        self.inner.write_all(&packet)?;
+        self.inner.flush()
+    }
+
+    /// Reads the next packet, skipping anything before its mark. Returns
+    /// `None` for a packet that arrived damaged.
+    fn read_packet(&mut self) -> io::Result<Option<Packet>> {
+        let mut byte = [0u8; 1];
+        loop {
+            self.inner.read_exact(&mut byte)?;
+            if byte[0] == MARK {
+                break;
+            }
+        }
+
+        let mut body = Vec::new();
+        loop {
+            self.inner.read_exact(&mut byte)?;
+            match byte[0] {
+                CR => break,
+                // A new packet; the one before it was cut short.
+                MARK => body.clear(),
+                c if c & 0x7F < 32 || body.len() > MAX_LEN => return Ok(None),
+                c => body.push(c),
+            }
+        }
+
+        if body.len() < 3 || unchar(body[0]) as usize != body.len() - 1 {
+            return Ok(None);
+        }
+        // A `SEND_INIT` sent again after the peers agreed on another check
+        // still has the one it started with.
+        let kind = body[2];
+        let check = if kind == SEND_INIT { Check::Sum1 } else { self.check };
+        if body.len() < 3 + check.len() {
+            return Ok(None);
+        }
+
+        let (content, received) = body.split_at(body.len() - check.len());
+        let mut expected = Vec::new();
+        check.append(content, &mut expected);
+        if received != &expected[..] {
+            return Ok(None);
+        }
+
+        Ok(Some(Packet { seq: unchar(content[1]) % 64, kind, data: content[3..].to_vec() }))
+    }
+
+    /// Sends a packet with the next sequence number until the peer
+    /// acknowledges it, and returns the data of the acknowledgement.
+    fn exchange(&mut self, kind: u8, data: &[u8]) -> io::Result<Vec<u8>> {
+        let seq = self.seq;
+        let next = (seq + 1) % 64;
+        for _ in 0..MAX_RETRIES {
+            self.write_packet(kind, seq, data)?;
+            match self.read_packet() {
+                Ok(Some(ref p)) if p.kind == ACK && p.seq == seq => {
+                    self.seq = next;
+                    return Ok(p.data.clone());
+                }
+                // A NAK for the next packet means this one arrived. The
+                // acknowledgement of `SEND_INIT` carries the peer's
+                // parameters, though, so that one has to be waited for.
+                Ok(Some(ref p)) if p.kind == NAK && p.seq == next && kind != SEND_INIT => {
+                    self.seq = next;
+                    return Ok(Vec::new());
+                }
+                Ok(Some(p)) if p.kind == ERROR => return Err(self.peer_error(&p)),
+                Ok(_) => continue,
+                Err(ref e) if is_timeout(e) => continue,
+                Err(e) => return Err(e),
+            }
+        }
+        Err(io::Error::new(io::ErrorKind::TimedOut, "Kermit peer not responding"))
+    }
+
+    fn peer_error(&self, packet: &Packet) -> io::Error {
+        let mut msg = Vec::new();
+        let _ = self.decode(&packet.data, &mut msg);
+        io::Error::new(
+            io::ErrorKind::ConnectionAborted,
+            format!("Kermit peer aborted: {}", String::from_utf8_lossy(&msg)),
+        )
+    }
+
+    /// Asks the peer to send the packet we're waiting for again, unless it
+    /// has failed to arrive too often already.
+    fn nak(&mut self, errors: &mut usize) -> io::Result<()> {
+        *errors += 1;
+        if *errors > MAX_RETRIES {
+            return Err(io::Error::new(io::ErrorKind::BrokenPipe, "too many errors"));
+        }
+        let seq = self.seq;
+        self.write_packet(NAK, seq, &[])
+    }
+
+    /// Sends `data` as a file named `name`. See `Kermit::send`.
+    pub fn send_file(&mut self, name: &str, data: &[u8]) -> io::Result<usize> {
+        let reply = self.exchange(SEND_INIT, &Params::ours().encode())?;
+        self.agree(Params::decode(&reply));
+
+        let mut encoded = Vec::new();
+        name.bytes().for_each(|b| self.encode(b, &mut encoded));
+        self.exchange(FILE, &encoded)?;
+
+        let room = self.theirs.max_len - 2 - self.check.len();
+        let mut unit = Vec::new();
+        let mut pos = 0;
+        while pos < data.len() {
+            encoded.clear();
+            while pos < data.len() {
+                unit.clear();
+                self.encode(data[pos], &mut unit);
+                if encoded.len() + unit.len() > room {
+                    break;
+                }
+                encoded.extend_from_slice(&unit);
+                pos += 1;
+            }
+            self.exchange(DATA, &encoded)?;
+        }
+
+        self.exchange(EOF, &[])?;
+        self.exchange(BREAK, &[])?;
+        Ok(data.len())
+    }
+
+    /// Receives one file into `into`. See `Kermit::receive`.
+    pub fn receive_file<W: io::Write>(&mut self, mut into: W) -> io::Result<Received> {
+        let mut name = None;
+        let mut bytes = 0;
+        let mut decoded = Vec::new();
+        let mut errors = 0;
+        loop {
+            let packet = match self.read_packet() {
+                Ok(Some(packet)) => packet,
+                Ok(None) => {
+                    self.nak(&mut errors)?;
+                    continue;
+                }
+                Err(ref e) if is_timeout(e) => {
+                    self.nak(&mut errors)?;
+                    continue;
+                }
+                Err(e) => return Err(e),
+            };
+            if packet.kind == ERROR {
+                return Err(self.peer_error(&packet));
+            }
+
+            // Our acknowledgement got lost; the peer sent its packet again.
+            if packet.seq == (self.seq + 63) % 64 && !self.last_ack.is_empty() {
+                let ack = self.last_ack.clone();
+                self.inner.write_all(&ack)?;
+                self.inner.flush()?;
+                continue;
+            }
+            if packet.seq != self.seq {
+                continue;
+            }
+            errors = 0;
+
+            let seq = self.seq;
+            match packet.kind {
+                SEND_INIT => {
+                    // The acknowledgement still has the old check, but
+                    // should already end the way the peer asked.
+                    let theirs = Params::decode(&packet.data);
+                    self.theirs = theirs;
+                    self.write_packet(ACK, seq, &Params::ours().encode())?;
+                    self.agree(theirs);
+                }
+                FILE => {
+                    decoded.clear();
+                    self.decode(&packet.data, &mut decoded)?;
+                    name = Some(String::from_utf8_lossy(&decoded).into_owned());
+                    self.write_packet(ACK, seq, &[])?;
+                }
+                DATA => {
+                    decoded.clear();
+                    self.decode(&packet.data, &mut decoded)?;
+                    into.write_all(&decoded)?;
+                    bytes += decoded.len() as u64;
+                    self.write_packet(ACK, seq, &[])?;
+                }
+                EOF => self.write_packet(ACK, seq, &[])?,
+                BREAK => {
+                    self.write_packet(ACK, seq, &[])?;
+                    return Ok(Received { name, bytes });
+                }
+                _ => return Err(protocol_error("unexpected Kermit packet")),
+            }
+            self.seq = (seq + 1) % 64;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests;
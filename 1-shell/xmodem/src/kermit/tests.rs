@@ -0,0 +1,175 @@
+use super::*;
+use std::io::Cursor;
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::time::Duration;
+
+/// One end of a byte pipe whose reads time out. Bytes written can be
+/// corrupted on their way by `corrupt`, which maps the index of each byte to
+/// an XOR mask.
+struct Pipe {
+    tx: Sender<u8>,
+    rx: Receiver<u8>,
+    sent: usize,
+    corrupt: fn(usize) -> u8,
+}
+
+fn pipe(corrupt: fn(usize) -> u8) -> (Pipe, Pipe) {
+    let ((tx1, rx1), (tx2, rx2)) = (channel(), channel());
+    (
+        Pipe { tx: tx1, rx: rx2, sent: 0, corrupt },
+        Pipe { tx: tx2, rx: rx1, sent: 0, corrupt: |_| 0 },
+    )
+}
+
+impl io::Read for Pipe {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self.rx.recv_timeout(Duration::from_millis(100)) {
+            Ok(byte) => {
+                buf[0] = byte;
+                Ok(1)
+            }
+            Err(RecvTimeoutError::Timeout) => Err(io::ErrorKind::TimedOut.into()),
+            Err(RecvTimeoutError::Disconnected) => Ok(0),
+        }
+    }
+}
+
+impl io::Write for Pipe {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        for &byte in buf {
+            let _ = self.tx.send(byte ^ (self.corrupt)(self.sent));
+            self.sent += 1;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn data(len: usize) -> Vec<u8> {
+    // Include every byte value, so all prefixes are exercised.
+    (0..len).map(|i| (i * 7 + i / 256) as u8).collect()
+}
+
+fn transfer(corrupt: fn(usize) -> u8, data: Vec<u8>) -> (usize, Received, Vec<u8>) {
+    let (sender, receiver) = pipe(corrupt);
+    let tx = std::thread::spawn(move || Kermit::send(sender, "kernel8.img", &data));
+    let rx = std::thread::spawn(move || {
+        let mut out = vec![];
+        Kermit::receive(receiver, &mut out).map(|r| (r, out))
+    });
+
+    let sent = tx.join().expect("tx join okay").expect("tx okay");
+    let (received, out) = rx.join().expect("rx join okay").expect("rx okay");
+    (sent, received, out)
+}
+
+#[test]
+fn test_transfer() {
+    let input = data(5000);
+    let (sent, received, out) = transfer(|_| 0, input.clone());
+    assert_eq!(sent, input.len());
+    assert_eq!(received, Received { name: Some("kernel8.img".into()), bytes: 5000 });
+    assert_eq!(out, input);
+}
+
+#[test]
+fn test_empty_file() {
+    let (sent, received, out) = transfer(|_| 0, vec![]);
+    assert_eq!((sent, received.bytes), (0, 0));
+    assert!(out.is_empty());
+}
+
+#[test]
+fn test_corruption_recovery() {
+    let input = data(3000);
+    // Damage the handshake, a data packet and an end of line.
+    let (sent, received, out) = transfer(
+        |i| match i {
+            3 | 500 => 0x01,
+            1000 => 0x40,
+            _ => 0,
+        },
+        input.clone(),
+    );
+    assert_eq!(sent, input.len());
+    assert_eq!(received.bytes, input.len() as u64);
+    assert_eq!(out, input);
+}
+
+#[test]
+fn test_only_printable_on_the_wire() {
+    let input = data(1000);
+    let (sender, receiver) = pipe(|_| 0);
+    let wire = std::thread::spawn(move || {
+        let mut kermit = Kermit::new(receiver);
+        let mut wire = vec![];
+        // Acknowledge everything, with our parameters for `SEND_INIT`.
+        loop {
+            let packet = match kermit.read_packet() {
+                Ok(Some(packet)) => packet,
+                _ => return wire,
+            };
+            wire.extend_from_slice(&packet.data);
+            let params = if packet.kind == SEND_INIT { Params::ours().encode() } else { vec![] };
+            kermit.write_packet(ACK, packet.seq, &params).unwrap();
+            if packet.kind == SEND_INIT {
+                kermit.agree(Params::decode(&packet.data));
+            }
+        }
+    });
+
+    Kermit::send(sender, "kernel8.img", &input).expect("send okay");
+    let wire = wire.join().expect("join okay");
+    assert!(wire.iter().all(|&b| (32..127).contains(&b)));
+}
+
+#[test]
+fn test_encode_decode() {
+    for &qbin in &[None, Some(QBIN)] {
+        let mut kermit = Kermit::new(Cursor::new(vec![]));
+        kermit.qbin = qbin;
+
+        let input = data(512);
+        let mut encoded = vec![];
+        input.iter().for_each(|&b| kermit.encode(b, &mut encoded));
+        assert!(encoded.iter().all(|&b| b & 0x7F >= 32 && b & 0x7F != 0x7F));
+
+        let mut decoded = vec![];
+        kermit.decode(&encoded, &mut decoded).unwrap();
+        assert_eq!(decoded, input);
+    }
+}
+
+#[test]
+fn test_peer_params() {
+    // A C-Kermit-style Send-Init that turns down eighth-bit prefixing and
+    // asks for the 1-character checksum.
+    let theirs = Params::decode(b"~* @-#N1");
+    assert_eq!(theirs.max_len, 94);
+    assert_eq!((theirs.eol, theirs.qctl, theirs.qbin, theirs.check), (CR, QCTL, b'N', Check::Sum1));
+
+    let mut kermit = Kermit::new(Cursor::new(vec![]));
+    kermit.agree(theirs);
+    assert_eq!((kermit.check, kermit.qbin), (Check::Sum1, None));
+    kermit.agree(Params::decode(b"~* @-#Y3"));
+    assert_eq!((kermit.check, kermit.qbin), (Check::Crc3, Some(QBIN)));
+
+    assert_eq!(Params::decode(b""), Params { max_len: 80, eol: CR, qctl: QCTL, qbin: b'N', check: Check::Sum1 });
+}
+
+#[test]
+fn test_crc16() {
+    assert_eq!(crc16(b"123456789"), 0x2189);
+}
+
+#[test]
+fn test_peer_error() {
+    let mut kermit = Kermit::new(Cursor::new(vec![]));
+    kermit.write_packet(ERROR, 0, b"disk full").unwrap();
+    kermit.inner.set_position(0);
+    let e = kermit.receive_file(io::sink()).expect_err("aborted");
+    assert_eq!(e.kind(), io::ErrorKind::ConnectionAborted);
+}
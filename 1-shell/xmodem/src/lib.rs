@@ -1,6 +1,6 @@
 #![no_std]
 
-#[cfg(not(feature = "custom-std"))]
+#[cfg(all(feature = "std", not(feature = "custom-std")))]
 #[allow(unused_imports)]
 #[macro_use]
 extern crate std;
@@ -10,33 +10,145 @@ extern crate std;
 extern crate custom_std as std;
 
 // re-add std/custom-std prelude
+#[cfg(any(feature = "std", feature = "custom-std"))]
+#[allow(unused_imports)]
 use std::prelude::v1::*;
 
-use std::io;
-
+pub mod baud;
+pub mod check;
+mod crc;
+pub mod image;
+pub mod io;
+#[cfg(any(feature = "std", feature = "custom-std"))]
+pub mod kermit;
+pub mod loader;
 mod progress;
 mod read_ext;
-#[cfg(test)]
+pub mod machine;
+pub mod verify;
+pub mod ymodem;
+#[cfg(any(feature = "std", feature = "custom-std"))]
+pub mod zmodem;
+#[cfg(all(test, feature = "std"))]
 mod tests;
 
-pub use progress::{Progress, ProgressFn};
+pub use check::PacketCheck;
+pub use progress::{Progress, ProgressFn, Stats};
 
+use core::mem;
+
+use machine::{Action, Machine, Receiver, Transmitter};
 use read_ext::ReadExt;
+use verify::Trailer;
+use ymodem::Header;
 
 const SOH: u8 = 0x01;
+const STX: u8 = 0x02;
 const EOT: u8 = 0x04;
 const ACK: u8 = 0x06;
 const NAK: u8 = 0x15;
 const CAN: u8 = 0x18;
+const CRC: u8 = b'C';
+/// Sent by a receiver in place of `C` to ask for CRC-32 checks.
+const CRC32: u8 = b'F';
+/// Starts a receiver's request to resume an interrupted transfer.
+const RESUME: u8 = b'R';
+
+/// Payload size of a standard packet, started with `SOH`.
+const PACKET_LEN: usize = 128;
+
+/// Payload size of an XMODEM-1K packet, started with `STX`.
+const PACKET_1K_LEN: usize = 1024;
+
+/// Number of times a receiver asks for CRC mode before falling back to
+/// arithmetic checksums for senders that don't support it.
+const CRC_ATTEMPTS: usize = 3;
+
+/// Number of times a packet is sent or received in total before giving up,
+/// unless changed with `Xmodem::set_retries`.
+pub const DEFAULT_RETRIES: usize = 10;
+
+/// How packets are checked for corruption, ordered from weakest to strongest.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Checksum {
+    /// The original 8-bit arithmetic sum of the payload. Used when the
+    /// receiver starts the transfer with `NAK`.
+    Sum,
+    /// CRC-16/XMODEM of the payload, sent big-endian. Used when the receiver
+    /// starts the transfer with `C`.
+    Crc16,
+    /// CRC-32 of the payload, sent big-endian. An extension of this crate,
+    /// used when the receiver starts the transfer with `F`.
+    Crc32,
+}
+
+impl Checksum {
+    /// Returns the check's implementation.
+    pub fn packet_check(self) -> &'static dyn PacketCheck {
+        match self {
+            Checksum::Sum => &check::Sum,
+            Checksum::Crc16 => &check::Crc16,
+            Checksum::Crc32 => &check::Crc32,
+        }
+    }
+
+    /// Returns the byte a receiver starts the transfer with to ask for this
+    /// check.
+    fn request(self) -> u8 {
+        match self {
+            Checksum::Sum => NAK,
+            Checksum::Crc16 => CRC,
+            Checksum::Crc32 => CRC32,
+        }
+    }
+
+    /// Returns the check a receiver asks for by starting with `byte`.
+    fn requested_by(byte: u8) -> Option<Checksum> {
+        match byte {
+            NAK => Some(Checksum::Sum),
+            CRC => Some(Checksum::Crc16),
+            CRC32 => Some(Checksum::Crc32),
+            _ => None,
+        }
+    }
+
+    /// Returns the next weaker check, which a receiver falls back to if the
+    /// sender doesn't answer its request for this one.
+    fn weaker(self) -> Option<Checksum> {
+        match self {
+            Checksum::Sum => None,
+            Checksum::Crc16 => Some(Checksum::Sum),
+            Checksum::Crc32 => Some(Checksum::Crc16),
+        }
+    }
+}
 
 /// Implementation of the XMODEM protocol.
+///
+/// The protocol itself is in the state machines of `machine`; this drives
+/// them over a blocking stream.
 pub struct Xmodem<R> {
-    packet: u8,
     inner: R,
+    session: Session,
     started: bool,
+    checksum: Checksum,
+    use_1k: bool,
+    retries: usize,
+    cancelled: bool,
+    stats: Stats,
+    clock: progress::Clock,
     progress: ProgressFn,
 }
 
+/// The state machine of the transfer in progress, if any. Without `alloc`,
+/// the machines can't be boxed.
+#[allow(clippy::large_enum_variant)]
+enum Session {
+    Idle,
+    Receiving(Receiver),
+    Transmitting(Transmitter),
+}
+
 impl Xmodem<()> {
     /// Transmits `data` to the receiver `to` using the XMODEM protocol. If the
     /// length of the total data yielded by `data` is not a multiple of 128
@@ -56,40 +168,20 @@ impl Xmodem<()> {
     /// length of the total data yielded by `data` is not a multiple of 128
     /// bytes, the data is padded with zeroes and sent to the receiver.
     ///
+    /// If the receiver asks for CRC mode, full 1024-byte chunks of `data` are
+    /// sent as XMODEM-1K packets. If the receiver rejects the first of them,
+    /// the rest of the transfer falls back to 128-byte packets.
+    ///
     /// The function `f` is used as a callback to indicate progress throughout
     /// the transmission. See the [`Progress`] enum for more information.
     ///
     /// Returns the number of bytes written to `to`, excluding padding zeroes.
-    pub fn transmit_with_progress<R, W>(mut data: R, to: W, f: ProgressFn) -> io::Result<usize>
+    pub fn transmit_with_progress<R, W>(data: R, to: W, f: ProgressFn) -> io::Result<usize>
     where
         W: io::Read + io::Write,
         R: io::Read,
     {
-        let mut transmitter = Xmodem::new_with_progress(to, f);
-        let mut packet = [0u8; 128];
-        let mut written = 0;
-        'next_packet: loop {
-            let n = data.read_max(&mut packet)?;
-            packet[n..].iter_mut().for_each(|b| *b = 0);
-
-            if n == 0 {
-                transmitter.write_packet(&[])?;
-                return Ok(written);
-            }
-
-            for _ in 0..10 {
-                match transmitter.write_packet(&packet) {
-                    Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
-                    Err(e) => return Err(e),
-                    Ok(_) => {
-                        written += n;
-                        continue 'next_packet;
-                    }
-                }
-            }
-
-            return Err(io::Error::new(io::ErrorKind::BrokenPipe, "bad transmit"));
-        }
+        Xmodem::new_with_progress(to, f).transmit_from(data)
     }
 
     /// Receives `data` from `from` using the XMODEM protocol and writes it into
@@ -105,35 +197,16 @@ impl Xmodem<()> {
 
     /// Receives `data` from `from` using the XMODEM protocol and writes it into
     /// `into`. Returns the number of bytes read from `from`, a multiple of 128.
+    /// Both standard and XMODEM-1K packets are accepted.
     ///
     /// The function `f` is used as a callback to indicate progress throughout
     /// the reception. See the [`Progress`] enum for more information.
-    pub fn receive_with_progress<R, W>(from: R, mut into: W, f: ProgressFn) -> io::Result<usize>
+    pub fn receive_with_progress<R, W>(from: R, into: W, f: ProgressFn) -> io::Result<usize>
     where
         R: io::Read + io::Write,
         W: io::Write,
     {
-        let mut receiver = Xmodem::new_with_progress(from, f);
-        let mut packet = [0u8; 128];
-        let mut received = 0;
-        'next_packet: loop {
-            for _ in 0..10 {
-                match receiver.read_packet(&mut packet) {
-                    Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
-                    Err(e) => return Err(e),
-                    Ok(0) => break 'next_packet,
-                    Ok(n) => {
-                        received += n;
-                        into.write_all(&packet)?;
-                        continue 'next_packet;
-                    }
-                }
-            }
-
-            return Err(io::Error::new(io::ErrorKind::BrokenPipe, "bad receive"));
-        }
-
-        Ok(received)
+        Xmodem::new_with_progress(from, f).receive_into(into)
     }
 }
 
@@ -142,12 +215,7 @@ impl<T: io::Read + io::Write> Xmodem<T> {
     /// `inner`. The returned instance can be used for both receiving
     /// (downloading) and sending (uploading).
     pub fn new(inner: T) -> Self {
-        Xmodem {
-            packet: 1,
-            started: false,
-            inner,
-            progress: progress::noop,
-        }
+        Xmodem::new_with_progress(inner, progress::noop)
     }
 
     /// Returns a new `Xmodem` instance with the internal reader/writer set to
@@ -157,13 +225,367 @@ impl<T: io::Read + io::Write> Xmodem<T> {
     /// [`Progress`] enum for more information.
     pub fn new_with_progress(inner: T, f: ProgressFn) -> Self {
         Xmodem {
-            packet: 1,
-            started: false,
             inner,
+            session: Session::Idle,
+            started: false,
+            checksum: Checksum::Crc16,
+            use_1k: true,
+            retries: DEFAULT_RETRIES,
+            cancelled: false,
+            stats: Stats::default(),
+            clock: progress::Clock::default(),
             progress: f,
         }
     }
 
+    /// Allows or forbids XMODEM-1K packets. Allowed by default, in which case
+    /// `transmit` sends them to receivers that asked for CRC mode. Receivers
+    /// always accept them.
+    pub fn set_use_1k(&mut self, use_1k: bool) {
+        self.use_1k = use_1k;
+        if let Session::Transmitting(tx) = &mut self.session {
+            tx.set_use_1k(use_1k);
+        }
+    }
+
+    /// Returns the packet check in use. Before the transfer starts, this is
+    /// the check a receiver will ask for first.
+    pub fn checksum(&self) -> Checksum {
+        match &self.session {
+            Session::Receiving(rx) => rx.checksum(),
+            Session::Transmitting(tx) if tx.is_started() => tx.checksum(),
+            _ => self.checksum,
+        }
+    }
+
+    /// Makes a receiver ask for `checksum` instead of CRC-16 when it starts
+    /// the transfer. It falls back to weaker checks for senders that don't
+    /// answer, so this is the strongest check it will use; a receiver asking
+    /// for `Checksum::Sum` never uses CRC. Transmitters use whatever the
+    /// receiver asks for.
+    pub fn set_checksum(&mut self, checksum: Checksum) {
+        self.checksum = checksum;
+    }
+
+    /// Sets how many times a packet is sent or received in total before the
+    /// transfer is given up, counting attempts that fail with a bad check or
+    /// time out. Defaults to `DEFAULT_RETRIES`.
+    pub fn set_retries(&mut self, retries: usize) {
+        self.retries = retries;
+        match &mut self.session {
+            Session::Receiving(rx) => rx.set_retries(retries),
+            Session::Transmitting(tx) => tx.set_retries(retries),
+            Session::Idle => {}
+        }
+    }
+
+    /// Returns a mutable reference to the inner stream, e.g. to change its
+    /// read timeout once the transfer has started.
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+
+    /// Calls `f` with the receiver of the transfer, starting one if no
+    /// reception is in progress.
+    fn with_receiver<V, F>(&mut self, f: F) -> io::Result<V>
+    where
+        F: FnOnce(&mut Self, &mut Receiver) -> io::Result<V>,
+    {
+        let mut rx = match mem::replace(&mut self.session, Session::Idle) {
+            Session::Receiving(rx) => rx,
+            _ => {
+                let mut rx = Receiver::new(self.checksum);
+                rx.set_retries(self.retries);
+                rx
+            }
+        };
+        let result = f(self, &mut rx);
+        self.session = Session::Receiving(rx);
+        result
+    }
+
+    /// Calls `f` with the transmitter of the transfer, starting one if no
+    /// transmission is in progress.
+    fn with_transmitter<V, F>(&mut self, f: F) -> io::Result<V>
+    where
+        F: FnOnce(&mut Self, &mut Transmitter) -> io::Result<V>,
+    {
+        let mut tx = match mem::replace(&mut self.session, Session::Idle) {
+            Session::Transmitting(tx) => tx,
+            _ => {
+                let mut tx = Transmitter::new();
+                tx.set_use_1k(self.use_1k);
+                tx.set_retries(self.retries);
+                tx
+            }
+        };
+        let result = f(self, &mut tx);
+        self.session = Session::Transmitting(tx);
+        result
+    }
+
+    /// Waits for the next byte from the peer and passes it to `machine`, or
+    /// tells it about the timeout, then reports any progress.
+    fn feed<M: Machine>(&mut self, machine: &mut M) -> io::Result<()> {
+        match self.read_byte(false) {
+            Ok(byte) => {
+                machine.push_bytes(&[byte]);
+            }
+            Err(ref e) if is_timeout(e) => machine.timeout(),
+            Err(e) => return Err(e),
+        }
+        self.report(machine.stats(), machine.is_started());
+        Ok(())
+    }
+
+    /// Reports the start of the transfer, a packet transferred or a retry,
+    /// whichever `stats` and `started` show has happened since the last
+    /// report.
+    fn report(&mut self, stats: Stats, started: bool) {
+        if started && !self.started {
+            self.started = true;
+            self.clock.start();
+            (self.progress)(Progress::Started);
+        }
+
+        let last = mem::replace(&mut self.stats, stats);
+        if stats.retransmits > last.retransmits {
+            (self.progress)(Progress::Retry(self.stats()));
+        }
+        if stats.packets > last.packets {
+            (self.progress)(Progress::Packet(self.stats()));
+        }
+    }
+
+    /// Writes `bytes` a state machine asked for to the peer.
+    fn write_bytes(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.inner.write_all(bytes)?;
+        self.inner.flush()
+    }
+
+    /// Transmits everything `data` yields, padding the last packet with zeroes,
+    /// and ends the transmission. See `Xmodem::transmit_with_progress`.
+    ///
+    /// Returns the number of bytes written, excluding padding zeroes.
+    ///
+    /// If the transfer fails after it has started, the receiver is told with
+    /// `cancel`, unless it cancelled the transfer itself.
+    pub fn transmit_from<R: io::Read>(&mut self, data: R) -> io::Result<usize> {
+        self.with_transmitter(|this, tx| {
+            let result = this.transmit_packets(tx, data);
+            this.cancel_on_error(result)
+        })
+    }
+
+    fn transmit_packets<R: io::Read>(&mut self, tx: &mut Transmitter, mut data: R) -> io::Result<usize> {
+        self.start(tx)?;
+
+        let mut buf = [0u8; PACKET_1K_LEN];
+        let mut written = 0;
+        loop {
+            let n = data.read_max(&mut buf)?;
+            if n == 0 {
+                tx.finish()?;
+                self.run(tx)?;
+                return Ok(written);
+            }
+
+            tx.send(&buf[..n])?;
+            self.run(tx)?;
+            written += n;
+        }
+    }
+
+    /// Carries out `tx`'s actions until it needs data, the receiver asks to
+    /// resume, or the transfer is over.
+    fn run(&mut self, tx: &mut Transmitter) -> io::Result<()> {
+        loop {
+            let wait = match tx.next_action() {
+                Action::Write(bytes) => {
+                    self.write_bytes(bytes)?;
+                    false
+                }
+                Action::NeedData | Action::ResumeRequested(_) | Action::Done => return Ok(()),
+                Action::Wait => true,
+                Action::Failed(e) => {
+                    self.cancelled = true;
+                    return Err(e);
+                }
+                Action::Deliver(_) | Action::Header(_) => unreachable!("transmitters don't receive"),
+            };
+            if wait {
+                self.feed(tx)?;
+            }
+        }
+    }
+
+    /// Receives packets until the sender ends the transmission and writes
+    /// them into `into`. See `Xmodem::receive_with_progress`.
+    ///
+    /// Returns the number of bytes received, a multiple of 128.
+    ///
+    /// If the transfer fails after it has started, the sender is told with
+    /// `cancel`, unless it cancelled the transfer itself.
+    pub fn receive_into<W: io::Write>(&mut self, into: W) -> io::Result<usize> {
+        self.with_receiver(|this, rx| {
+            let result = this.receive_data(rx, into, false);
+            this.cancel_on_error(result)
+        })
+    }
+
+    /// Receives packets until the sender ends the transmission and streams
+    /// their data into `into`, a file or a decompressor, say. Unlike
+    /// `receive_into`, the padding of the last packet is dropped when a YMODEM
+    /// sender gave the file's length in its header; see `header`.
+    ///
+    /// Returns the number of bytes written to `into`: the file's exact length
+    /// if the sender gave it, otherwise a multiple of 128.
+    ///
+    /// If the transfer fails after it has started, the sender is told with
+    /// `cancel`, unless it cancelled the transfer itself.
+    pub fn receive_to_writer<W: io::Write>(&mut self, into: W) -> io::Result<usize> {
+        self.with_receiver(|this, rx| {
+            let result = this.receive_data(rx, into, true);
+            this.cancel_on_error(result)
+        })
+    }
+
+    /// Returns the header a YMODEM sender opened the transfer with, if any.
+    pub fn header(&self) -> Option<Header> {
+        match &self.session {
+            Session::Receiving(rx) => rx.header(),
+            _ => None,
+        }
+    }
+
+    /// Carries out `rx`'s actions, writing the data it delivers to `into`,
+    /// until the file or the transmission ends. Returns the number of bytes
+    /// written. If `exact`, data past the length in the YMODEM header is
+    /// dropped.
+    fn receive_data<W: io::Write>(&mut self, rx: &mut Receiver, mut into: W, exact: bool) -> io::Result<usize> {
+        let mut received = 0;
+        loop {
+            let limit = match rx.header().and_then(|h| h.len) {
+                Some(len) if exact => len.saturating_sub(received as u64),
+                _ => u64::MAX,
+            };
+            let wait = match rx.next_action() {
+                Action::Deliver(payload) => {
+                    let len = (payload.len() as u64).min(limit) as usize;
+                    into.write_all(&payload[..len])?;
+                    received += len;
+                    false
+                }
+                Action::Write(bytes) => {
+                    self.write_bytes(bytes)?;
+                    false
+                }
+                Action::Wait => true,
+                Action::Header(_) | Action::Done => return Ok(received),
+                Action::Failed(e) => {
+                    self.cancelled = true;
+                    return Err(e);
+                }
+                Action::NeedData | Action::ResumeRequested(_) => unreachable!("receivers don't send"),
+            };
+            if wait {
+                self.feed(rx)?;
+            }
+        }
+    }
+
+    /// Receives packets into `buf` like `receive_into`, but first asks the
+    /// sender to resume an interrupted transfer. `received` is the number of
+    /// bytes at the start of `buf` that an earlier transfer delivered; the
+    /// sender skips them if its data starts with the same bytes, and otherwise
+    /// sends everything again.
+    ///
+    /// `received` is kept up to date as packets arrive, so after an error it
+    /// can be passed back in to resume once more. It's left alone if no
+    /// sender showed up.
+    ///
+    /// Senders that don't answer the request within `CRC_ATTEMPTS` read
+    /// timeouts are taken not to support resuming.
+    pub fn resume_into(&mut self, buf: &mut [u8], received: &mut usize) -> io::Result<()> {
+        self.with_receiver(|this, rx| {
+            let result = this.resume_packets(rx, buf, received);
+            this.cancel_on_error(result)
+        })
+    }
+
+    fn resume_packets(&mut self, rx: &mut Receiver, buf: &mut [u8], received: &mut usize) -> io::Result<()> {
+        // The sender can only skip whole packets.
+        let offset = (*received).min(buf.len()) / PACKET_LEN * PACKET_LEN;
+        if offset > 0 {
+            rx.resume(Trailer::of(&buf[..offset]));
+            self.await_resume(rx)?;
+        }
+        let resumed = rx.resumed();
+        let start = if resumed { offset } else { 0 };
+
+        let mut rest = &mut buf[start..];
+        let len = rest.len();
+        let result = self.receive_data(rx, &mut rest, false);
+        if resumed || self.started {
+            *received = start + len - rest.len();
+        }
+        result.map(|_| ())
+    }
+
+    /// Carries out `rx`'s actions until the sender has answered its resume
+    /// request, or it has given up asking.
+    fn await_resume(&mut self, rx: &mut Receiver) -> io::Result<()> {
+        while rx.is_resuming() {
+            let wait = match rx.next_action() {
+                Action::Write(bytes) => {
+                    self.write_bytes(bytes)?;
+                    false
+                }
+                _ => true,
+            };
+            if wait {
+                self.feed(rx)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Cancels the transfer by sending two `CAN`s, which makes the peer's
+    /// next read of a packet start or response fail with `ConnectionAborted`.
+    pub fn cancel(&mut self) -> io::Result<()> {
+        self.cancelled = true;
+        self.inner.write_all(&[CAN, CAN])?;
+        self.inner.flush()
+    }
+
+    /// Cancels a started transfer if `result` is an error the peer doesn't
+    /// know about yet.
+    fn cancel_on_error<V>(&mut self, result: io::Result<V>) -> io::Result<V> {
+        if let Err(ref e) = result {
+            let peer_cancelled = e.kind() == io::ErrorKind::ConnectionAborted;
+            if self.started && !self.cancelled && !peer_cancelled {
+                // We're failing anyway; the original error is more useful.
+                let _ = self.cancel();
+            }
+        }
+        result
+    }
+
+    /// Returns the statistics of the transfer so far, as last reported to the
+    /// progress callback.
+    pub fn stats(&self) -> Stats {
+        Stats { elapsed: self.clock.elapsed(), ..self.stats }
+    }
+
+    /// Returns the error for a packet that has to be transferred again:
+    /// `Interrupted` if its check failed, otherwise `TimedOut`.
+    fn retry_error(&self, naks: usize) -> io::Error {
+        match self.stats.naks > naks {
+            true => io::Error::new(io::ErrorKind::Interrupted, "checksum mismatch"),
+            false => io::Error::new(io::ErrorKind::TimedOut, "packet timed out"),
+        }
+    }
+
     /// Reads a single byte from the inner I/O stream. If `abort_on_can` is
     /// `true`, an error of `ConnectionAborted` is returned if the read byte is
     /// `CAN`.
@@ -187,61 +609,18 @@ impl<T: io::Read + io::Write> Xmodem<T> {
         Ok(byte)
     }
 
-    /// Writes a single byte to the inner I/O stream.
-    ///
-    /// # Errors
-    ///
-    /// Returns an error if writing to the inner stream fails.
-    fn write_byte(&mut self, byte: u8) -> io::Result<()> {
-        self.inner.write_all(&[byte])
-    }
-
-    /// Reads a single byte from the inner I/O stream and compares it to `byte`.
-    /// If the bytes match, the byte is returned as an `Ok`. If they differ and
-    /// the read byte is not `CAN`, an error of `InvalidData` with the message
-    /// `expected` is returned. If they differ and the read byte is `CAN`, an
-    /// error of `ConnectionAborted` is returned. In either case, if they bytes
-    /// differ, a `CAN` byte is written out to the inner stream.
-    ///
-    /// # Errors
-    ///
-    /// Returns an error if reading from the inner stream fails, if the read
-    /// byte was not `byte`, if the read byte was `CAN` and `byte` is not `CAN`,
-    /// or if writing the `CAN` byte failed on byte mismatch.
-    fn expect_byte_or_cancel(&mut self, byte: u8, msg: &'static str) -> io::Result<u8> {
-        self.expect_byte(byte, msg).or_else(|e| {
-            self.write_byte(CAN)?;
-            Err(e)
-        })
-    }
-
-    /// Reads a single byte from the inner I/O stream and compares it to `byte`.
-    /// If they differ, an error of `InvalidData` with the message `expected` is
-    /// returned. Otherwise the byte is returned. If `byte` is not `CAN` and the
-    /// read byte is `CAN`, a `ConnectionAborted` error is returned.
+    /// Reads (downloads) a single packet from the inner stream using the XMODEM
+    /// protocol. On success, returns the number of bytes read: 128, or 1024
+    /// for an XMODEM-1K packet, or 0 once the transmission has ended.
     ///
-    /// # Errors
+    /// The first call starts the transfer by asking the sender for CRC-16
+    /// checks, falling back to arithmetic checksums if the sender doesn't
+    /// answer within `CRC_ATTEMPTS` read timeouts. See `set_checksum`.
     ///
-    /// Returns an error if reading from the inner stream fails, or if the read
-    /// byte was not `byte`. If the read byte differed and was `CAN`, an error
-    /// of `ConnectionAborted` is returned. Otherwise, the error kind is
-    /// `InvalidData`.
-    fn expect_byte(&mut self, byte: u8, expected: &'static str) -> io::Result<u8> {
-        let read = self.read_byte(false)?;
-        if read != byte {
-            if read == CAN {
-                return Err(io::Error::new(
-                    io::ErrorKind::ConnectionAborted,
-                    "received CAN",
-                ));
-            }
-            return Err(io::Error::new(io::ErrorKind::InvalidData, expected));
-        }
-        Ok(read)
-    }
-
-    /// Reads (downloads) a single packet from the inner stream using the XMODEM
-    /// protocol. On success, returns the number of bytes read (always 128).
+    /// If the sender opens with a YMODEM header in packet 0, it's kept for
+    /// `header` and the first data packet is read in its place. The batch is
+    /// ended after this file: once it's over, the empty header that ends the
+    /// batch is read, or the sender is cancelled if it offers another file.
     ///
     /// The progress callback is called with `Progress::Start` when reception
     /// for the first packet has started and subsequently with
@@ -257,12 +636,17 @@ impl<T: io::Read + io::Write> Xmodem<T> {
     ///   * The sender doesn't send a second `EOT` after the first.
     ///   * The received packet numbers don't match the expected values.
     ///
-    /// An error of kind `Interrupted` is returned if a packet checksum fails.
+    /// An error of kind `Interrupted` is returned, after asking for the
+    /// packet again, if a packet checksum or CRC fails, and one of kind
+    /// `TimedOut` if the packet didn't arrive in time.
     ///
-    /// An error of kind `ConnectionAborted` is returned if a `CAN` byte is
-    /// received when not expected.
+    /// An error of kind `ConnectionAborted` is returned if the sender cancels
+    /// the transfer with two `CAN`s, or a `CAN` arrives in place of the packet
+    /// number.
     ///
-    /// An error of kind `UnexpectedEof` is returned if `buf.len() < 128`.
+    /// An error of kind `UnexpectedEof` is returned if `buf.len() < 128`, or
+    /// if an XMODEM-1K packet arrives and `buf.len() < 1024`. In the latter
+    /// case the transfer is cancelled.
     pub fn read_packet(&mut self, buf: &mut [u8]) -> io::Result<usize> {
         if buf.len() < 128 {
             return Err(io::Error::new(
@@ -271,67 +655,124 @@ impl<T: io::Read + io::Write> Xmodem<T> {
             ));
         }
 
-        if !self.started {
-            self.write_byte(NAK)?;
+        self.with_receiver(|this, rx| {
+            rx.set_use_1k(buf.len() >= PACKET_1K_LEN);
+            this.read_packet_from(rx, buf)
+        })
+    }
 
-            self.started = true;
-            (self.progress)(Progress::Started);
+    fn read_packet_from(&mut self, rx: &mut Receiver, buf: &mut [u8]) -> io::Result<usize> {
+        let Stats { retransmits, naks, .. } = rx.stats();
+        let mut read = 0;
+        loop {
+            let retried = rx.stats().retransmits > retransmits;
+            let wait = match rx.next_action() {
+                Action::Deliver(payload) => {
+                    buf[..payload.len()].copy_from_slice(payload);
+                    read = payload.len();
+                    false
+                }
+                Action::Write(bytes) => {
+                    self.write_bytes(bytes)?;
+                    false
+                }
+                Action::Wait if read > 0 => return Ok(read),
+                Action::Wait if retried => return Err(self.retry_error(naks)),
+                Action::Wait => true,
+                Action::Done => return Ok(0),
+                Action::Failed(e) => {
+                    self.cancelled = true;
+                    return Err(e);
+                }
+                Action::Header(_) | Action::NeedData | Action::ResumeRequested(_) => {
+                    unreachable!("not a batch receiver")
+                }
+            };
+            if wait {
+                self.feed(rx)?;
+            }
         }
+    }
 
-        match self.read_byte(true)? {
-            SOH => {}
-            EOT => {
-                self.write_byte(NAK)?;
-                self.expect_byte_or_cancel(EOT, "expect the second EOT")?;
-                self.write_byte(ACK)?;
-                return Ok(0);
-            }
-            _ => {
-                return Err(io::Error::new(
-                    io::ErrorKind::InvalidData,
-                    "expect SOH or EOT",
-                ))
-            }
-        };
+    /// Waits for the receiver to start the transfer, which selects the packet
+    /// check, unless that has happened already. `write_packet` calls this
+    /// itself; calling it first lets the wait use a longer read timeout than
+    /// the packets that follow.
+    ///
+    /// If the receiver asks to resume an interrupted transfer instead, this
+    /// returns without starting, and `resume_request` describes the data the
+    /// receiver holds. Answer with `answer_resume`; a request left unanswered
+    /// is declined when the transfer starts.
+    pub fn start_transmit(&mut self) -> io::Result<()> {
+        self.with_transmitter(|this, tx| this.begin_transmit(tx))
+    }
 
-        self.expect_byte_or_cancel(self.packet, "packet number mismatch")?;
-        self.expect_byte(
-            255 - self.packet,
-            "1's complement of packet number mismatch",
-        )?;
+    fn begin_transmit(&mut self, tx: &mut Transmitter) -> io::Result<()> {
+        if tx.is_started() {
+            return Ok(());
+        }
+        if tx.resume_request().is_some() {
+            tx.answer_resume(false);
+        }
+
+        (self.progress)(Progress::Waiting);
+        self.run(tx)
+    }
 
-        let mut actual_checksum = 0u8;
-        for i in 0..128 {
-            let b = self.read_byte(false)?;
-            buf[i] = b;
-            actual_checksum = actual_checksum.wrapping_add(b);
+    /// Calls `begin_transmit` until the transfer has started, declining any
+    /// resume request on the way.
+    fn start(&mut self, tx: &mut Transmitter) -> io::Result<()> {
+        while !tx.is_started() {
+            self.begin_transmit(tx)?;
         }
+        Ok(())
+    }
 
-        let expect_checksum = self.read_byte(false)?;
-        if actual_checksum == expect_checksum {
-            self.write_byte(ACK)?;
-            (self.progress)(Progress::Packet(self.packet));
-            self.packet = self.packet.wrapping_add(1);
-            Ok(128)
-        } else {
-            self.write_byte(NAK)?;
-            Err(io::Error::new(
-                io::ErrorKind::Interrupted,
-                "checksum mismatch",
-            ))
+    /// Returns the length and CRC-32 of the data the receiver already holds
+    /// if it asked to resume an interrupted transfer and hasn't been
+    /// answered yet.
+    pub fn resume_request(&self) -> Option<Trailer> {
+        match &self.session {
+            Session::Transmitting(tx) => tx.resume_request(),
+            _ => None,
         }
     }
 
+    /// Answers the receiver's resume request. If `accept` is `true`, the
+    /// transfer continues after the data the request describes, so the data
+    /// passed to `transmit_from` must start there. Otherwise the receiver
+    /// starts over.
+    ///
+    /// Only accept after checking that the data starts with the receiver's;
+    /// see `verify::Trailer`.
+    pub fn answer_resume(&mut self, accept: bool) -> io::Result<()> {
+        if self.resume_request().is_none() {
+            return Ok(());
+        }
+        self.with_transmitter(|this, tx| {
+            tx.answer_resume(accept);
+            match tx.next_action() {
+                Action::Write(answer) => this.write_bytes(answer),
+                _ => Ok(()),
+            }
+        })
+    }
+
     /// Sends (uploads) a single packet to the inner stream using the XMODEM
     /// protocol. If `buf` is empty, end of transmissions is sent. Users of this
     /// interface should ensure that `write_packet(&[])` is called when data
-    /// transmission is complete. On success, returns the number of bytes
-    /// written.
+    /// transmission is complete. A `buf` of exactly 1024 bytes is sent as an
+    /// XMODEM-1K packet; otherwise, the first 128 bytes are sent. On success,
+    /// returns the number of bytes written.
+    ///
+    /// After an error of kind `Interrupted` or `TimedOut`, the packet is sent
+    /// again by the next call, whatever its `buf`.
     ///
     /// The progress callback is called with `Progress::Waiting` before waiting
-    /// for the receiver's `NAK`, `Progress::Start` when transmission of the
-    /// first packet has started and subsequently with `Progress::Packet` when a
-    /// packet is sent successfully.
+    /// for the receiver's `NAK` or `C`, `Progress::Start` when transmission of
+    /// the first packet has started and subsequently with `Progress::Packet`
+    /// when a packet is sent successfully. The receiver's first byte selects
+    /// arithmetic checksums (`NAK`) or CRC-16 (`C`) for the whole transfer.
     ///
     /// # Errors
     ///
@@ -339,7 +780,7 @@ impl<T: io::Read + io::Write> Xmodem<T> {
     /// point. Also returns an error if the XMODEM protocol indicates an error.
     /// In particular, an `InvalidData` error is returned when:
     ///
-    ///   * The receiver's first byte isn't a `NAK`.
+    ///   * The receiver's first byte isn't a `NAK` or `C`.
     ///   * The receiver doesn't respond with a `NAK` to the first `EOT`.
     ///   * The receiver doesn't respond with an `ACK` to the second `EOT`.
     ///   * The receiver responds to a complete packet with something besides
@@ -348,58 +789,60 @@ impl<T: io::Read + io::Write> Xmodem<T> {
     /// An error of kind `UnexpectedEof` is returned if `buf.len() < 128 &&
     /// buf.len() != 0`.
     ///
-    /// An error of kind `ConnectionAborted` is returned if a `CAN` byte is
-    /// received when not expected.
+    /// An error of kind `ConnectionAborted` is returned if the receiver
+    /// cancels the transfer with two `CAN`s.
     ///
-    /// An error of kind `Interrupted` is returned if a packet checksum fails.
+    /// An error of kind `Interrupted` is returned if a packet checksum fails,
+    /// and one of kind `TimedOut` if the receiver didn't respond in time.
     pub fn write_packet(&mut self, buf: &[u8]) -> io::Result<usize> {
-        if buf.len() < 128 && buf.len() != 0 {
+        if buf.len() < 128 && !buf.is_empty() {
             return Err(io::Error::new(
                 io::ErrorKind::UnexpectedEof,
                 "0 < buf.len() < 128",
             ));
         }
 
-        if !self.started {
-            (self.progress)(Progress::Waiting);
-            self.expect_byte(NAK, "sending start, expect NAK")?;
-
-            self.started = true;
-            (self.progress)(Progress::Started);
-        }
+        self.with_transmitter(|this, tx| this.write_packet_with(tx, buf))
+    }
 
-        if buf.is_empty() {
-            self.write_byte(EOT)?;
-            self.expect_byte(NAK, "sent first EOT, expect NAK")?;
-            self.write_byte(EOT)?;
-            self.expect_byte(ACK, "sent second EOT, expect ACK")?;
-            return Ok(0);
-        }
+    fn write_packet_with(&mut self, tx: &mut Transmitter, buf: &[u8]) -> io::Result<usize> {
+        self.start(tx)?;
 
-        self.write_byte(SOH)?;
-        self.write_byte(self.packet)?;
-        self.write_byte(255 - self.packet)?;
-        let mut checksum = 0u8;
-        for &b in buf {
-            self.write_byte(b)?;
-            checksum = checksum.wrapping_add(b);
+        let len = match buf.len() {
+            PACKET_1K_LEN => PACKET_1K_LEN,
+            n => n.min(PACKET_LEN),
+        };
+        // Unless the last call left the packet to be sent again.
+        if tx.needs_data() {
+            match len {
+                0 => tx.finish()?,
+                _ => tx.send(&buf[..len])?,
+            }
         }
-        self.write_byte(checksum)?;
 
-        match self.read_byte(true)? {
-            ACK => {
-                (self.progress)(Progress::Packet(self.packet));
-                self.packet = self.packet.wrapping_add(1);
-                Ok(buf.len())
+        let Stats { retransmits, naks, .. } = tx.stats();
+        loop {
+            let wait = match tx.next_action() {
+                Action::Write(bytes) => {
+                    self.write_bytes(bytes)?;
+                    false
+                }
+                Action::NeedData | Action::Done => return Ok(len),
+                Action::Wait => true,
+                Action::Failed(e) => {
+                    self.cancelled = true;
+                    return Err(e);
+                }
+                Action::ResumeRequested(_) | Action::Deliver(_) | Action::Header(_) => {
+                    unreachable!("the transfer has started")
+                }
+            };
+            if wait {
+                self.feed(tx)?;
+                if tx.stats().retransmits > retransmits && tx.is_resending() {
+                    return Err(self.retry_error(naks));
+                }
             }
-            NAK => Err(io::Error::new(
-                io::ErrorKind::Interrupted,
-                "checksum mismatch",
-            )),
-            _ => Err(io::Error::new(
-                io::ErrorKind::InvalidData,
-                "receiver respond unexpectedly",
-            )),
         }
     }
 
@@ -414,3 +857,8 @@ impl<T: io::Read + io::Write> Xmodem<T> {
         self.inner.flush()
     }
 }
+
+/// Returns `true` if `e` means that nothing arrived in time.
+fn is_timeout(e: &io::Error) -> bool {
+    e.kind() == io::ErrorKind::TimedOut || e.kind() == io::ErrorKind::WouldBlock
+}
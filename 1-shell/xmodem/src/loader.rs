@@ -0,0 +1,143 @@
+//! The loader protocol: a short exchange of messages around an XMODEM
+//! transfer that lets a host load and start a kernel on a bootloader.
+//!
+//!  1. The host sends `Query` until the bootloader answers with `Info`, its
+//!     version of the protocol and the largest image it can hold.
+//!  2. The host describes the image with `Image`. The bootloader answers
+//!     `ACK`, or `NAK` if the image doesn't fit.
+//!  3. The image follows over XMODEM.
+//!  4. The bootloader checks what it received against the `Image` message
+//!     and answers `ACK` if it matches or `NAK` if it doesn't.
+//!  5. After an `ACK`, the host sends `Jump`. The bootloader answers `ACK`
+//!     and starts the kernel.
+//!
+//! Every message starts with `MAGIC`, so stray bytes in front of it, such as
+//! a bootloader's console output, are skipped.
+
+use crate::io;
+use crate::read_ext::ReadExt;
+use crate::verify::Trailer;
+use crate::{ACK, NAK};
+
+/// The version of the protocol this module implements.
+pub const VERSION: u16 = 2;
+
+/// Starts every message.
+const MAGIC: [u8; 4] = *b"LOAD";
+
+// Message kinds, which follow `MAGIC`.
+const QUERY: u8 = b'?';
+const INFO: u8 = b'I';
+const IMAGE: u8 = b'M';
+const JUMP: u8 = b'J';
+
+/// Longest stretch of other bytes skipped before a message.
+const MAX_NOISE: usize = 256;
+
+/// A message of the loader protocol.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Message {
+    /// Asks the bootloader for `Info`.
+    Query,
+    /// The bootloader's protocol version and the largest image it accepts.
+    Info { version: u16, max_len: u32 },
+    /// The length and CRC-32 of the image about to be sent.
+    Image(Trailer),
+    /// Tells the bootloader to start the image it received.
+    Jump,
+}
+
+impl Message {
+    /// Writes the message to `to`.
+    pub fn write<T: io::Write>(&self, mut to: T) -> io::Result<()> {
+        let mut bytes = [0u8; 13];
+        bytes[..4].copy_from_slice(&MAGIC);
+        let len = match *self {
+            Message::Query => {
+                bytes[4] = QUERY;
+                5
+            }
+            Message::Info { version, max_len } => {
+                bytes[4] = INFO;
+                bytes[5..7].copy_from_slice(&version.to_le_bytes());
+                bytes[7..11].copy_from_slice(&max_len.to_le_bytes());
+                11
+            }
+            Message::Image(image) => {
+                bytes[4] = IMAGE;
+                bytes[5..9].copy_from_slice(&image.len.to_le_bytes());
+                bytes[9..13].copy_from_slice(&image.crc.to_le_bytes());
+                13
+            }
+            Message::Jump => {
+                bytes[4] = JUMP;
+                5
+            }
+        };
+
+        to.write_all(&bytes[..len])?;
+        to.flush()
+    }
+
+    /// Reads the next message from `from`. Returns `None` if none arrives
+    /// before `from` times out.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind `InvalidData` if the message is of a kind
+    /// this module doesn't know.
+    pub fn read<T: io::Read>(mut from: T) -> io::Result<Option<Message>> {
+        if !from.skip_to(&MAGIC, MAX_NOISE)? {
+            return Ok(None);
+        }
+
+        let mut kind = [0u8; 1];
+        from.read_exact(&mut kind)?;
+        let mut fields = [0u8; 8];
+        let message = match kind[0] {
+            QUERY => Message::Query,
+            INFO => {
+                from.read_exact(&mut fields[..6])?;
+                Message::Info {
+                    version: u16::from_le_bytes([fields[0], fields[1]]),
+                    max_len: u32::from_le_bytes([fields[2], fields[3], fields[4], fields[5]]),
+                }
+            }
+            IMAGE => {
+                from.read_exact(&mut fields)?;
+                Message::Image(Trailer {
+                    len: u32::from_le_bytes([fields[0], fields[1], fields[2], fields[3]]),
+                    crc: u32::from_le_bytes([fields[4], fields[5], fields[6], fields[7]]),
+                })
+            }
+            JUMP => Message::Jump,
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "unknown loader message",
+                ))
+            }
+        };
+        Ok(Some(message))
+    }
+}
+
+/// Answers a message with `ACK` if `ok`, or `NAK`.
+pub fn answer<T: io::Write>(mut to: T, ok: bool) -> io::Result<()> {
+    to.write_all(&[if ok { ACK } else { NAK }])?;
+    to.flush()
+}
+
+/// Reads the answer to a message: `true` for `ACK`, `false` for `NAK`.
+/// Other bytes, such as console output, are skipped.
+pub fn read_answer<T: io::Read>(mut from: T) -> io::Result<bool> {
+    loop {
+        let mut byte = [0u8; 1];
+        from.read_exact(&mut byte)?;
+        match byte[0] {
+            ACK => return Ok(true),
+            NAK => return Ok(false),
+            _ => continue,
+        }
+    }
+}
@@ -0,0 +1,1159 @@
+//! Sans-I/O XMODEM state machines.
+//!
+//! `Receiver` and `Transmitter` implement the protocol, including resuming
+//! and YMODEM batches; `Xmodem` drives them over a blocking stream. They
+//! never read, write or wait themselves. Their caller passes in the bytes
+//! that arrive with `push_bytes`, reports read timeouts with `timeout`, and
+//! carries out whatever `next_action` asks for until it returns
+//! `Action::Wait`. This lets a transfer be driven from a UART interrupt
+//! handler or an async task.
+//!
+//! ```ignore
+//! let mut receiver = Receiver::new(Checksum::Crc16);
+//! loop {
+//!     match receiver.next_action() {
+//!         Action::Write(bytes) => uart.write_all(bytes)?,
+//!         Action::Deliver(payload) => image.extend_from_slice(payload),
+//!         Action::Wait => match uart.read_byte() {
+//!             Ok(byte) => { receiver.push_bytes(&[byte]); }
+//!             Err(_) => receiver.timeout(),
+//!         },
+//!         Action::Done => break,
+//!         Action::Failed(e) => return Err(e),
+//!         // Only batch receivers and transmitters return these.
+//!         Action::Header(_) | Action::NeedData | Action::ResumeRequested(_) => unreachable!(),
+//!     }
+//! }
+//! ```
+
+use crate::io;
+
+use crate::check;
+use crate::verify::Trailer;
+use crate::ymodem::Header;
+use crate::{
+    Checksum, Stats, ACK, CAN, CRC_ATTEMPTS, DEFAULT_RETRIES, EOT, NAK, PACKET_1K_LEN, PACKET_LEN,
+    RESUME, SOH, STX,
+};
+
+/// Largest packet on the wire: start byte, packet number and its complement,
+/// payload, the longest check.
+const MAX_FRAME_LEN: usize = 3 + PACKET_1K_LEN + check::MAX_LEN;
+
+/// Length of a resume request: `RESUME`, then the length and CRC-32 of the
+/// data the receiver holds, each as 8 hex digits.
+const RESUME_LEN: usize = 17;
+
+/// What the caller of a state machine has to do next.
+#[derive(Debug)]
+pub enum Action<'a> {
+    /// Write these bytes to the peer.
+    Write(&'a [u8]),
+    /// A packet's payload arrived; store it. Only returned by `Receiver`.
+    Deliver(&'a [u8]),
+    /// Packet 0 of the next file in a YMODEM batch arrived, holding the
+    /// file's header; see `ymodem::Header::parse` and `ymodem::file_name`.
+    /// Call `Receiver::receive_file` to take the file, or `Receiver::cancel`.
+    /// Only returned by a receiver set up with `Receiver::set_batch`.
+    Header(&'a [u8]),
+    /// The transmitter is ready for more data: call `Transmitter::send` or,
+    /// once there is no more, `Transmitter::finish`. Between the files of a
+    /// YMODEM batch, call `Transmitter::send_header` instead.
+    NeedData,
+    /// The receiver asks to resume an interrupted transfer after the data
+    /// this describes. Call `Transmitter::answer_resume`. Only returned by
+    /// `Transmitter`.
+    ResumeRequested(Trailer),
+    /// Wait for bytes from the peer and pass them to `push_bytes`, or call
+    /// `timeout` if none arrive in time.
+    Wait,
+    /// The transfer completed.
+    Done,
+    /// The transfer failed. A peer's cancel is reported as
+    /// `ConnectionAborted`.
+    Failed(io::Error),
+}
+
+/// Why a transfer failed, kept so `next_action` can keep reporting it.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+struct Failure(io::ErrorKind, &'static str);
+
+impl Failure {
+    fn to_error(self) -> io::Error {
+        io::Error::new(self.0, self.1)
+    }
+
+    /// The failure when `byte` arrived where `expected` describes something
+    /// else: the peer's cancel if it's a `CAN`, otherwise bad data.
+    fn unexpected(byte: u8, expected: &'static str) -> Failure {
+        match byte {
+            CAN => Failure(io::ErrorKind::ConnectionAborted, "received CAN"),
+            _ => Failure(io::ErrorKind::InvalidData, expected),
+        }
+    }
+}
+
+const PEER_CANCELLED: Failure =
+    Failure(io::ErrorKind::ConnectionAborted, "transfer cancelled by peer");
+const CANCELLED: Failure = Failure(io::ErrorKind::Interrupted, "transfer cancelled");
+
+/// Tracks `CAN`s among control bytes: two in a row cancel the transfer, a lone
+/// one is line noise.
+#[derive(Debug, Default)]
+struct CanFilter {
+    seen: bool,
+}
+
+impl CanFilter {
+    /// Returns `Ok(None)` for a byte to skip, `Ok(Some(byte))` for a control
+    /// byte, or `Err` if the peer cancelled.
+    fn filter(&mut self, byte: u8) -> Result<Option<u8>, Failure> {
+        match (byte, self.seen) {
+            (CAN, true) => Err(PEER_CANCELLED),
+            (CAN, false) => {
+                self.seen = true;
+                Ok(None)
+            }
+            _ => {
+                self.seen = false;
+                Ok(Some(byte))
+            }
+        }
+    }
+}
+
+/// What `Xmodem` needs to drive either state machine over a stream.
+pub(crate) trait Machine {
+    fn push_bytes(&mut self, bytes: &[u8]) -> usize;
+    fn timeout(&mut self);
+    fn stats(&self) -> Stats;
+    fn is_started(&self) -> bool;
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum RxState {
+    /// Asking the sender to resume; `requests` have been sent.
+    Resuming { requests: usize },
+    /// Asking the sender to start; `requests` for the current check have
+    /// been sent.
+    Starting { requests: usize },
+    /// Waiting for a packet start or `EOT`.
+    Idle,
+    /// Reading a packet with a `len`-byte payload. `read` bytes of it,
+    /// starting at the packet number, are in the buffer.
+    Packet { len: usize, read: usize },
+    /// The first `EOT` was answered with `NAK`; waiting for the second.
+    Eot,
+    /// Holding the `len`-byte header of the next file in a batch until the
+    /// caller takes the file.
+    Header { len: usize },
+    Done,
+    Failed(Failure),
+}
+
+/// The packets a receiver takes next.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Next {
+    /// A YMODEM header, or the first data packet from an XMODEM sender.
+    HeaderOrData,
+    /// Only a YMODEM header.
+    Header,
+    /// Data packets.
+    Data,
+}
+
+/// The receiving side of an XMODEM transfer.
+///
+/// It asks for the check it was created with first and falls back to weaker
+/// ones after every `CRC_ATTEMPTS` timeouts, accepts standard and XMODEM-1K
+/// packets, and acknowledges a packet the sender repeats because our `ACK` got
+/// lost. A YMODEM header in packet 0 is kept for `header`; the batch is ended
+/// after the first file unless `set_batch` says otherwise.
+pub struct Receiver {
+    state: RxState,
+    checksum: Checksum,
+    packet: u8,
+    next: Next,
+    batch: bool,
+    use_1k: bool,
+    retries: usize,
+    errors: usize,
+    started: bool,
+    /// The data a resume request describes, and whether the sender agreed.
+    held: Trailer,
+    resumed: bool,
+    header: Option<Header>,
+    stats: Stats,
+    cans: CanFilter,
+    /// Packet number, its complement, payload and check of the current packet.
+    buf: [u8; MAX_FRAME_LEN - 1],
+    /// Length of the payload waiting to be delivered.
+    deliver: Option<usize>,
+    out: [u8; RESUME_LEN],
+    out_len: usize,
+}
+
+impl Receiver {
+    /// Returns a receiver that asks for `checksum` first. `Checksum::Sum`
+    /// never uses CRC.
+    pub fn new(checksum: Checksum) -> Receiver {
+        let mut receiver = Receiver {
+            state: RxState::Starting { requests: 1 },
+            checksum,
+            packet: 1,
+            next: Next::HeaderOrData,
+            batch: false,
+            use_1k: true,
+            retries: DEFAULT_RETRIES,
+            errors: 0,
+            started: false,
+            held: Trailer::default(),
+            resumed: false,
+            header: None,
+            stats: Stats::default(),
+            cans: CanFilter::default(),
+            buf: [0; MAX_FRAME_LEN - 1],
+            deliver: None,
+            out: [0; RESUME_LEN],
+            out_len: 0,
+        };
+        receiver.respond(checksum.request());
+        receiver
+    }
+
+    /// Sets how many times in a row a packet may be corrupted or lost before
+    /// the transfer is given up. Defaults to `DEFAULT_RETRIES`.
+    pub fn set_retries(&mut self, retries: usize) {
+        self.retries = retries;
+    }
+
+    /// Accepts or refuses XMODEM-1K packets. Accepted by default; a receiver
+    /// with no room for them refuses them, and cancels the transfer with an
+    /// error of kind `UnexpectedEof` when one arrives.
+    pub fn set_use_1k(&mut self, use_1k: bool) {
+        self.use_1k = use_1k;
+    }
+
+    /// Takes every file in a YMODEM batch, stopping at each file's header
+    /// with `Action::Header`, instead of ending the batch after the first
+    /// file. Call before the first byte arrives.
+    pub fn set_batch(&mut self, batch: bool) {
+        self.batch = batch;
+        self.next = if batch { Next::Header } else { Next::HeaderOrData };
+    }
+
+    /// Asks the sender to resume an interrupted transfer after the data
+    /// `held` describes before starting it. Senders that don't answer within
+    /// `CRC_ATTEMPTS` timeouts are taken not to support resuming. Call before
+    /// the first `next_action`.
+    pub fn resume(&mut self, held: Trailer) {
+        self.held = held;
+        self.state = RxState::Resuming { requests: 1 };
+        self.request_resume();
+    }
+
+    /// Returns `true` while the sender hasn't answered a resume request.
+    pub fn is_resuming(&self) -> bool {
+        matches!(self.state, RxState::Resuming { .. })
+    }
+
+    /// Returns `true` if the sender agreed to resume, so its data follows the
+    /// data the request described.
+    pub fn resumed(&self) -> bool {
+        self.resumed
+    }
+
+    /// Returns the packet check in use.
+    pub fn checksum(&self) -> Checksum {
+        self.checksum
+    }
+
+    /// Returns the header of the current file if the sender is a YMODEM
+    /// sender.
+    pub fn header(&self) -> Option<Header> {
+        self.header
+    }
+
+    /// Returns the statistics of the transfer so far. `elapsed` is left to
+    /// the caller, which has the clock.
+    pub fn stats(&self) -> Stats {
+        self.stats
+    }
+
+    /// Returns `true` once the sender has answered the request to start.
+    pub fn is_started(&self) -> bool {
+        self.started
+    }
+
+    /// Returns what the caller has to do next. A payload is delivered before
+    /// it's acknowledged.
+    pub fn next_action(&mut self) -> Action<'_> {
+        if let Some(len) = self.deliver.take() {
+            return Action::Deliver(&self.buf[2..2 + len]);
+        }
+        if self.out_len > 0 {
+            let len = core::mem::replace(&mut self.out_len, 0);
+            return Action::Write(&self.out[..len]);
+        }
+        match self.state {
+            RxState::Header { len } => Action::Header(&self.buf[2..2 + len]),
+            RxState::Done => Action::Done,
+            RxState::Failed(failure) => Action::Failed(failure.to_error()),
+            _ => Action::Wait,
+        }
+    }
+
+    /// Feeds bytes from the sender to the receiver and returns how many were
+    /// consumed. It stops early once there's an action other than waiting to
+    /// carry out; pass the rest in after that.
+    pub fn push_bytes(&mut self, bytes: &[u8]) -> usize {
+        for (i, &byte) in bytes.iter().enumerate() {
+            if self.deliver.is_some() || self.out_len > 0 {
+                return i;
+            }
+            if let RxState::Header { .. } = self.state {
+                return i;
+            }
+            self.push_byte(byte);
+        }
+        bytes.len()
+    }
+
+    /// Tells the receiver that nothing arrived within the read timeout.
+    pub fn timeout(&mut self) {
+        match self.state {
+            RxState::Resuming { requests } if requests < CRC_ATTEMPTS => {
+                self.state = RxState::Resuming { requests: requests + 1 };
+                self.request_resume();
+            }
+            RxState::Resuming { .. } => self.start(),
+            RxState::Starting { .. } if self.checksum == Checksum::Sum => {
+                self.fail(Failure(io::ErrorKind::TimedOut, "sender didn't start the transfer"))
+            }
+            RxState::Starting { requests } => {
+                self.state = match requests < CRC_ATTEMPTS {
+                    true => RxState::Starting { requests: requests + 1 },
+                    false => {
+                        self.checksum = self.checksum.weaker().unwrap_or(Checksum::Sum);
+                        RxState::Starting { requests: 1 }
+                    }
+                };
+                self.respond(self.checksum.request());
+            }
+            RxState::Idle | RxState::Packet { .. } | RxState::Eot => self.retry(true),
+            RxState::Header { .. } | RxState::Done | RxState::Failed(_) => {}
+        }
+    }
+
+    /// Takes the file whose header `next_action` returned: asks the sender
+    /// for its data, which is delivered as usual.
+    pub fn receive_file(&mut self) {
+        if let RxState::Header { .. } = self.state {
+            self.take_file();
+        }
+    }
+
+    /// Cancels the transfer. The next actions write the cancel to the sender
+    /// and then report the transfer failed.
+    pub fn cancel(&mut self) {
+        if let RxState::Done | RxState::Failed(_) = self.state {
+            return;
+        }
+        self.out[..2].copy_from_slice(&[CAN, CAN]);
+        self.out_len = 2;
+        self.state = RxState::Failed(CANCELLED);
+    }
+
+    /// Queues `byte` to be written after whatever is queued already.
+    fn respond(&mut self, byte: u8) {
+        self.out[self.out_len] = byte;
+        self.out_len += 1;
+    }
+
+    fn request_resume(&mut self) {
+        self.out[0] = RESUME;
+        write_hex(&mut self.out[1..9], self.held.len);
+        write_hex(&mut self.out[9..RESUME_LEN], self.held.crc);
+        self.out_len = RESUME_LEN;
+    }
+
+    /// Asks the sender to start, once any resume request is settled.
+    fn start(&mut self) {
+        self.state = RxState::Starting { requests: 1 };
+        self.respond(self.checksum.request());
+    }
+
+    fn take_file(&mut self) {
+        self.packet = 1;
+        self.next = Next::Data;
+        self.state = RxState::Idle;
+        self.respond(self.checksum.request());
+    }
+
+    /// Gives up the transfer because of `failure`, telling the sender if it
+    /// has started and doesn't know already.
+    fn fail(&mut self, failure: Failure) {
+        if let RxState::Done | RxState::Failed(_) = self.state {
+            return;
+        }
+        self.out_len = 0;
+        if self.started && failure != PEER_CANCELLED {
+            self.out[..2].copy_from_slice(&[CAN, CAN]);
+            self.out_len = 2;
+        }
+        self.state = RxState::Failed(failure);
+    }
+
+    /// Asks for the current packet again after it was corrupted or, if
+    /// `timed_out`, lost, or gives up if that has happened too often.
+    fn retry(&mut self, timed_out: bool) {
+        self.errors += 1;
+        self.stats.packet = self.packet;
+        self.stats.retransmits += 1;
+        if timed_out {
+            self.stats.timeouts += 1;
+        } else {
+            self.stats.naks += 1;
+        }
+        if self.errors >= self.retries {
+            return self.fail(Failure(io::ErrorKind::BrokenPipe, "bad receive"));
+        }
+
+        // A lost request for the next header is repeated, not `NAK`ed.
+        match (timed_out, self.next) {
+            (true, Next::Header) => self.respond(self.checksum.request()),
+            _ => self.respond(NAK),
+        }
+        self.state = RxState::Idle;
+    }
+
+    /// Returns `true` if packet `number` can come next.
+    fn expects(&self, number: u8) -> bool {
+        match self.next {
+            Next::HeaderOrData => number == 0 || number == self.packet,
+            Next::Header => number == 0,
+            Next::Data => number == self.packet || number == self.packet.wrapping_sub(1),
+        }
+    }
+
+    fn push_byte(&mut self, byte: u8) {
+        match self.state {
+            RxState::Resuming { .. } | RxState::Starting { .. } | RxState::Idle => {
+                let byte = match self.cans.filter(byte) {
+                    Ok(Some(byte)) => byte,
+                    Ok(None) => return,
+                    Err(failure) => return self.fail(failure),
+                };
+                if let RxState::Resuming { .. } = self.state {
+                    return match byte {
+                        ACK => {
+                            self.resumed = true;
+                            self.start();
+                        }
+                        NAK => self.start(),
+                        _ => self.fail(Failure(
+                            io::ErrorKind::InvalidData,
+                            "expected ACK or NAK for resume request",
+                        )),
+                    };
+                }
+
+                self.started = true;
+                match byte {
+                    SOH => self.state = RxState::Packet { len: PACKET_LEN, read: 0 },
+                    STX if !self.use_1k => self.fail(Failure(
+                        io::ErrorKind::UnexpectedEof,
+                        "no room for an XMODEM-1K packet",
+                    )),
+                    STX => self.state = RxState::Packet { len: PACKET_1K_LEN, read: 0 },
+                    EOT if self.next != Next::Header => {
+                        self.respond(NAK);
+                        self.state = RxState::Eot;
+                    }
+                    _ if self.next == Next::Header => self.fail(Failure(
+                        io::ErrorKind::InvalidData,
+                        "expect the header of the next file",
+                    )),
+                    _ => self.fail(Failure(io::ErrorKind::InvalidData, "expect SOH, STX or EOT")),
+                }
+            }
+            RxState::Packet { len, read } => {
+                self.buf[read] = byte;
+                let read = read + 1;
+                if read == 1 && !self.expects(byte) {
+                    return self.fail(Failure::unexpected(byte, "packet number mismatch"));
+                }
+                if read == 2 && byte != 255 - self.buf[0] {
+                    return self.fail(Failure::unexpected(
+                        byte,
+                        "1's complement of packet number mismatch",
+                    ));
+                }
+
+                if read < 2 + len + self.checksum.packet_check().wire_len() {
+                    self.state = RxState::Packet { len, read };
+                } else {
+                    self.packet_received(len);
+                }
+            }
+            RxState::Eot => match byte {
+                EOT => self.end_file(),
+                _ => self.fail(Failure::unexpected(byte, "expect the second EOT")),
+            },
+            RxState::Header { .. } | RxState::Done | RxState::Failed(_) => {}
+        }
+    }
+
+    fn packet_received(&mut self, len: usize) {
+        let number = self.buf[0];
+        let packet_check = self.checksum.packet_check();
+        let payload = &self.buf[2..2 + len];
+        let check = &self.buf[2 + len..2 + len + packet_check.wire_len()];
+        if !packet_check.verify(payload, check) {
+            return self.retry(false);
+        }
+
+        self.errors = 0;
+        if number == 0 && self.next != Next::Data {
+            return self.header_received(len);
+        }
+
+        self.state = RxState::Idle;
+        if number == self.packet {
+            self.next = Next::Data;
+            self.stats.packet = number;
+            self.stats.packets += 1;
+            self.packet = self.packet.wrapping_add(1);
+            self.deliver = Some(len);
+        }
+        // Otherwise our ACK got lost and the sender repeated the packet.
+        self.respond(ACK);
+    }
+
+    fn header_received(&mut self, len: usize) {
+        self.respond(ACK);
+        match Header::parse(&self.buf[2..2 + len]) {
+            // The empty header ends the batch.
+            None => self.state = RxState::Done,
+            // Only batch receivers take a second file.
+            Some(_) if !self.batch && self.next == Next::Header => {
+                self.respond(CAN);
+                self.respond(CAN);
+                self.state = RxState::Done;
+            }
+            Some(header) => {
+                self.header = Some(header);
+                match self.batch {
+                    true => self.state = RxState::Header { len },
+                    false => self.take_file(),
+                }
+            }
+        }
+    }
+
+    /// Acknowledges the second `EOT`, then asks a YMODEM sender for the
+    /// header of its next file.
+    fn end_file(&mut self) {
+        self.respond(ACK);
+        if self.batch || self.header.is_some() {
+            self.next = Next::Header;
+            self.state = RxState::Idle;
+            self.respond(self.checksum.request());
+        } else {
+            self.state = RxState::Done;
+        }
+    }
+}
+
+impl Machine for Receiver {
+    fn push_bytes(&mut self, bytes: &[u8]) -> usize {
+        Receiver::push_bytes(self, bytes)
+    }
+
+    fn timeout(&mut self) {
+        Receiver::timeout(self)
+    }
+
+    fn stats(&self) -> Stats {
+        self.stats
+    }
+
+    fn is_started(&self) -> bool {
+        self.started
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum TxState {
+    /// Waiting for the receiver's `NAK`, `C` or `F`, or a resume request.
+    Starting,
+    /// Reading the hex digits of a resume request; `read` have arrived.
+    ResumeRequest { read: usize },
+    /// Holding a resume request until the caller answers it.
+    ResumeRequested(Trailer),
+    /// Waiting for data from the caller.
+    Ready,
+    /// A packet was written; waiting for the receiver's response.
+    Sending,
+    /// Waiting for the receiver to ask for a packet with the request for
+    /// its check; `waited` other bytes or timeouts came first.
+    AwaitRequest { then: Then, waited: usize },
+    /// An `EOT` was written; `second` is `true` for the second one.
+    Eot { second: bool },
+    Done,
+    Failed(Failure),
+}
+
+/// What a transmitter sends once the receiver asks for it.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Then {
+    /// The header in `data`.
+    Header,
+    /// The file's data, from the caller.
+    Data,
+}
+
+/// The sending side of an XMODEM transfer.
+///
+/// The check is whatever the receiver asks for. With a CRC, full 1024-byte
+/// chunks go out as XMODEM-1K packets until the receiver rejects one. A
+/// receiver's resume request is left to the caller to answer.
+pub struct Transmitter {
+    state: TxState,
+    checksum: Checksum,
+    packet: u8,
+    use_1k: bool,
+    retries: usize,
+    errors: usize,
+    started: bool,
+    /// Stale `C`s skipped while sending the first packet.
+    stale_crcs: usize,
+    cans: CanFilter,
+    /// The hex digits of a resume request, and the last request answered.
+    hex: [u8; RESUME_LEN - 1],
+    resume_answer: Option<(Trailer, bool)>,
+    /// Whether a YMODEM header was sent, a file is open, a file has been
+    /// sent, and whether the packet being sent is a header.
+    ymodem: bool,
+    in_file: bool,
+    file_sent: bool,
+    sending_header: bool,
+    stats: Stats,
+    /// The chunk or header being sent, padded to a multiple of 128 bytes.
+    data: [u8; PACKET_1K_LEN],
+    data_len: usize,
+    /// Start of the packet being sent within `data`, and its payload length.
+    data_pos: usize,
+    packet_len: usize,
+    frame: [u8; MAX_FRAME_LEN],
+    frame_len: usize,
+}
+
+impl Default for Transmitter {
+    fn default() -> Transmitter {
+        Transmitter::new()
+    }
+}
+
+impl Transmitter {
+    /// Returns a transmitter that waits for the receiver to start the
+    /// transfer.
+    pub fn new() -> Transmitter {
+        Transmitter {
+            state: TxState::Starting,
+            checksum: Checksum::Crc16,
+            packet: 1,
+            use_1k: true,
+            retries: DEFAULT_RETRIES,
+            errors: 0,
+            started: false,
+            stale_crcs: 0,
+            cans: CanFilter::default(),
+            hex: [0; RESUME_LEN - 1],
+            resume_answer: None,
+            ymodem: false,
+            in_file: false,
+            file_sent: false,
+            sending_header: false,
+            stats: Stats::default(),
+            data: [0; PACKET_1K_LEN],
+            data_len: 0,
+            data_pos: 0,
+            packet_len: 0,
+            frame: [0; MAX_FRAME_LEN],
+            frame_len: 0,
+        }
+    }
+
+    /// Allows or forbids XMODEM-1K packets. Allowed by default.
+    pub fn set_use_1k(&mut self, use_1k: bool) {
+        self.use_1k = use_1k;
+    }
+
+    /// Sets how many times in total a packet is sent before the transfer is
+    /// given up. Defaults to `DEFAULT_RETRIES`.
+    pub fn set_retries(&mut self, retries: usize) {
+        self.retries = retries;
+    }
+
+    /// Returns the packet check in use, once the receiver has chosen it.
+    pub fn checksum(&self) -> Checksum {
+        self.checksum
+    }
+
+    /// Returns the statistics of the transfer so far. `elapsed` is left to
+    /// the caller, which has the clock.
+    pub fn stats(&self) -> Stats {
+        self.stats
+    }
+
+    /// Returns `true` once the receiver has started the transfer.
+    pub fn is_started(&self) -> bool {
+        self.started
+    }
+
+    /// Returns `true` if `next_action` asks for data.
+    pub fn needs_data(&self) -> bool {
+        self.state == TxState::Ready && self.frame_len == 0
+    }
+
+    /// Returns `true` if the packet in flight is about to be sent again.
+    pub(crate) fn is_resending(&self) -> bool {
+        self.state == TxState::Sending && self.frame_len > 0
+    }
+
+    /// Returns the length and CRC-32 of the data the receiver holds if it
+    /// asked to resume and hasn't been answered yet.
+    pub fn resume_request(&self) -> Option<Trailer> {
+        match self.state {
+            TxState::ResumeRequested(request) => Some(request),
+            _ => None,
+        }
+    }
+
+    /// Answers the receiver's resume request. If `accept` is `true`, the
+    /// transfer continues after the data the request describes, so the data
+    /// sent must start there. Otherwise the receiver starts over.
+    pub fn answer_resume(&mut self, accept: bool) {
+        if let TxState::ResumeRequested(request) = self.state {
+            self.resume_answer = Some((request, accept));
+            self.answer(accept);
+        }
+    }
+
+    /// Returns what the caller has to do next.
+    pub fn next_action(&mut self) -> Action<'_> {
+        if self.frame_len > 0 {
+            let len = core::mem::replace(&mut self.frame_len, 0);
+            return Action::Write(&self.frame[..len]);
+        }
+        match self.state {
+            TxState::Ready => Action::NeedData,
+            TxState::ResumeRequested(request) => Action::ResumeRequested(request),
+            TxState::Done => Action::Done,
+            TxState::Failed(failure) => Action::Failed(failure.to_error()),
+            _ => Action::Wait,
+        }
+    }
+
+    /// Queues up to 1024 bytes of data to send. The last chunk may be short
+    /// and is padded with zeroes; every other chunk should be 1024 bytes
+    /// long so it can go out as an XMODEM-1K packet. Padding a short chunk to
+    /// 1024 bytes would cost more than the smaller packets' overhead.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind `InvalidInput` if `next_action` didn't ask
+    /// for data, or asked for a header, or `data` is empty or longer than
+    /// 1024 bytes.
+    pub fn send(&mut self, data: &[u8]) -> io::Result<()> {
+        if !self.in_data() || data.is_empty() || data.len() > PACKET_1K_LEN {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "transmitter not ready for this data",
+            ));
+        }
+
+        self.data_len = data.len().div_ceil(PACKET_LEN) * PACKET_LEN;
+        self.data[..data.len()].copy_from_slice(data);
+        self.data[data.len()..self.data_len].iter_mut().for_each(|b| *b = 0);
+        self.data_pos = 0;
+        self.in_file = true;
+        self.write_packet();
+        Ok(())
+    }
+
+    /// Ends the transmission once the last data has been sent. In a YMODEM
+    /// batch, this ends the current file; the batch ends with an empty
+    /// header.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind `InvalidInput` if `next_action` didn't ask
+    /// for data, or asked for a header.
+    pub fn finish(&mut self) -> io::Result<()> {
+        if !self.in_data() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "transmitter not ready to finish",
+            ));
+        }
+        self.write_eot(false);
+        Ok(())
+    }
+
+    /// Sends `header`, 128 or 1024 bytes written by `ymodem::write_header`,
+    /// as packet 0 to open the next file of a YMODEM batch, or an empty
+    /// header to end the batch. After the first file, the header waits for
+    /// the receiver to ask for it. The file's data is asked for once the
+    /// receiver has acknowledged its header.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind `InvalidInput` if `next_action` didn't ask
+    /// for data, a file is still open, or `header` has the wrong length.
+    pub fn send_header(&mut self, header: &[u8]) -> io::Result<()> {
+        let len_ok = header.len() == PACKET_LEN || header.len() == PACKET_1K_LEN;
+        if !self.needs_data() || self.in_file || !len_ok {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "transmitter not ready for a header",
+            ));
+        }
+
+        self.data[..header.len()].copy_from_slice(header);
+        self.data_len = header.len();
+        self.data_pos = 0;
+        self.ymodem = true;
+        self.in_file = true;
+        if self.file_sent {
+            self.state = TxState::AwaitRequest { then: Then::Header, waited: 0 };
+        } else {
+            self.write_header();
+        }
+        Ok(())
+    }
+
+    /// Feeds the receiver's responses to the transmitter and returns how many
+    /// bytes were consumed. It stops early once there's an action other than
+    /// waiting to carry out; pass the rest in after that.
+    pub fn push_bytes(&mut self, bytes: &[u8]) -> usize {
+        for (i, &byte) in bytes.iter().enumerate() {
+            if self.frame_len > 0 {
+                return i;
+            }
+            if let TxState::Ready | TxState::ResumeRequested(_) = self.state {
+                return i;
+            }
+            self.push_byte(byte);
+        }
+        bytes.len()
+    }
+
+    /// Tells the transmitter that nothing arrived within the read timeout.
+    /// Before the receiver has started the transfer, this gives it up; don't
+    /// call it to keep waiting.
+    pub fn timeout(&mut self) {
+        match self.state {
+            TxState::Starting | TxState::ResumeRequest { .. } => {
+                self.fail(Failure(io::ErrorKind::TimedOut, "receiver didn't start the transfer"))
+            }
+            TxState::Sending => self.retry(true),
+            TxState::Eot { second } => {
+                self.count_retry(true);
+                if self.errors >= self.retries {
+                    return self.fail(Failure(io::ErrorKind::TimedOut, "receiver not responding"));
+                }
+                self.write_eot(second);
+            }
+            TxState::AwaitRequest { then, waited } => self.await_request(then, waited + 1),
+            TxState::ResumeRequested(_) | TxState::Ready | TxState::Done | TxState::Failed(_) => {}
+        }
+    }
+
+    /// Cancels the transfer. The next actions write the cancel to the
+    /// receiver and then report the transfer failed.
+    pub fn cancel(&mut self) {
+        if let TxState::Done | TxState::Failed(_) = self.state {
+            return;
+        }
+        self.frame[..2].copy_from_slice(&[CAN, CAN]);
+        self.frame_len = 2;
+        self.state = TxState::Failed(CANCELLED);
+    }
+
+    /// Returns `true` if data or the end of the file may be sent: the caller
+    /// is asked for data, and a YMODEM file is open.
+    fn in_data(&self) -> bool {
+        self.needs_data() && (self.in_file || !self.ymodem)
+    }
+
+    /// Gives up the transfer because of `failure`, telling the receiver if it
+    /// has started and doesn't know already.
+    fn fail(&mut self, failure: Failure) {
+        if let TxState::Done | TxState::Failed(_) = self.state {
+            return;
+        }
+        self.frame_len = 0;
+        if self.started && failure != PEER_CANCELLED {
+            self.frame[..2].copy_from_slice(&[CAN, CAN]);
+            self.frame_len = 2;
+        }
+        self.state = TxState::Failed(failure);
+    }
+
+    fn answer(&mut self, accept: bool) {
+        self.frame[0] = if accept { ACK } else { NAK };
+        self.frame_len = 1;
+        self.state = TxState::Starting;
+    }
+
+    fn write_header(&mut self) {
+        self.packet = 0;
+        self.sending_header = true;
+        self.write_packet();
+    }
+
+    /// Frames the next packet of the current chunk, or the header, for
+    /// writing.
+    fn write_packet(&mut self) {
+        let remaining = self.data_len - self.data_pos;
+        let len = match remaining {
+            _ if self.sending_header => self.data_len,
+            PACKET_1K_LEN if self.use_1k => PACKET_1K_LEN,
+            _ => PACKET_LEN,
+        };
+        let payload = &self.data[self.data_pos..self.data_pos + len];
+        let packet_check = self.checksum.packet_check();
+        let check_len = packet_check.wire_len();
+
+        self.frame[0] = if len == PACKET_1K_LEN { STX } else { SOH };
+        self.frame[1] = self.packet;
+        self.frame[2] = 255 - self.packet;
+        self.frame[3..3 + len].copy_from_slice(payload);
+        packet_check.write(payload, &mut self.frame[3 + len..3 + len + check_len]);
+
+        self.packet_len = len;
+        self.frame_len = 3 + len + check_len;
+        self.state = TxState::Sending;
+    }
+
+    fn write_eot(&mut self, second: bool) {
+        self.frame[0] = EOT;
+        self.frame_len = 1;
+        self.state = TxState::Eot { second };
+    }
+
+    /// Counts a retransmit of the current packet after it was rejected or,
+    /// if `timed_out`, not answered.
+    fn count_retry(&mut self, timed_out: bool) {
+        self.errors += 1;
+        self.stats.packet = self.packet;
+        self.stats.retransmits += 1;
+        if timed_out {
+            self.stats.timeouts += 1;
+        } else {
+            self.stats.naks += 1;
+        }
+    }
+
+    /// Resends the current packet, or gives up if it has been sent too
+    /// often.
+    fn retry(&mut self, timed_out: bool) {
+        self.count_retry(timed_out);
+        if self.errors >= self.retries {
+            return self.fail(Failure(io::ErrorKind::BrokenPipe, "bad transmit"));
+        }
+        self.write_packet();
+    }
+
+    /// Keeps waiting for the receiver's request after `waited` other bytes or
+    /// timeouts, or gives up.
+    fn await_request(&mut self, then: Then, waited: usize) {
+        if waited >= self.retries {
+            return self.fail(Failure(
+                io::ErrorKind::BrokenPipe,
+                "receiver didn't ask for the next packet",
+            ));
+        }
+        self.state = TxState::AwaitRequest { then, waited };
+    }
+
+    fn push_byte(&mut self, byte: u8) {
+        if let TxState::ResumeRequest { read } = self.state {
+            self.hex[read] = byte;
+            if read + 1 < self.hex.len() {
+                self.state = TxState::ResumeRequest { read: read + 1 };
+            } else {
+                self.resume_requested();
+            }
+            return;
+        }
+
+        let byte = match self.cans.filter(byte) {
+            Ok(Some(byte)) => byte,
+            Ok(None) => return,
+            Err(failure) => return self.fail(failure),
+        };
+
+        match (self.state, byte) {
+            (TxState::Starting, RESUME) => self.state = TxState::ResumeRequest { read: 0 },
+            (TxState::Starting, _) => match Checksum::requested_by(byte) {
+                Some(checksum) => {
+                    self.checksum = checksum;
+                    // Receivers that only know checksums predate XMODEM-1K.
+                    self.use_1k &= checksum != Checksum::Sum;
+                    self.started = true;
+                    self.state = TxState::Ready;
+                }
+                None => self.fail(Failure(
+                    io::ErrorKind::InvalidData,
+                    "sending start, expect NAK or C",
+                )),
+            },
+            (TxState::Sending, ACK) => self.acked(),
+            // The receiver may not understand XMODEM-1K. Falling back isn't
+            // counted against the retries.
+            (TxState::Sending, NAK) if self.packet == 1 && self.packet_len == PACKET_1K_LEN => {
+                self.count_retry(false);
+                self.errors -= 1;
+                self.use_1k = false;
+                self.write_packet();
+            }
+            (TxState::Sending, NAK) => self.retry(false),
+            // A receiver that asked for CRC mode more than once before we
+            // started may still have `C`s (or `F`s) queued up.
+            (TxState::Sending, _)
+                if byte == self.checksum.request()
+                    && self.packet == 1
+                    && self.stale_crcs < CRC_ATTEMPTS =>
+            {
+                self.stale_crcs += 1;
+                self.write_packet();
+            }
+            (TxState::Sending, _) => self.fail(Failure(
+                io::ErrorKind::InvalidData,
+                "receiver respond unexpectedly",
+            )),
+            (TxState::AwaitRequest { then, .. }, _) if byte == self.checksum.request() => {
+                match then {
+                    Then::Header => self.write_header(),
+                    Then::Data => self.state = TxState::Ready,
+                }
+            }
+            (TxState::AwaitRequest { then, waited }, _) => self.await_request(then, waited + 1),
+            (TxState::Eot { second: false }, NAK) => self.write_eot(true),
+            (TxState::Eot { .. }, ACK) => self.file_ended(),
+            (TxState::Eot { second: false }, _) => self.fail(Failure(
+                io::ErrorKind::InvalidData,
+                "sent first EOT, expect NAK",
+            )),
+            (TxState::Eot { second: true }, _) => self.fail(Failure(
+                io::ErrorKind::InvalidData,
+                "sent second EOT, expect ACK",
+            )),
+            (TxState::ResumeRequest { .. }, _)
+            | (TxState::ResumeRequested(_), _)
+            | (TxState::Ready, _)
+            | (TxState::Done, _)
+            | (TxState::Failed(_), _) => {}
+        }
+    }
+
+    /// Handles a resume request once its hex digits have arrived.
+    fn resume_requested(&mut self) {
+        let request = match (read_hex(&self.hex[..8]), read_hex(&self.hex[8..])) {
+            (Some(len), Some(crc)) => Trailer { len, crc },
+            _ => {
+                return self.fail(Failure(
+                    io::ErrorKind::InvalidData,
+                    "malformed resume request",
+                ))
+            }
+        };
+        match self.resume_answer {
+            // Our answer got lost; give it again.
+            Some((answered, accept)) if answered == request => self.answer(accept),
+            _ => self.state = TxState::ResumeRequested(request),
+        }
+    }
+
+    fn acked(&mut self) {
+        self.errors = 0;
+        if self.sending_header {
+            self.sending_header = false;
+            self.packet = 1;
+            self.state = match Header::parse(&self.data[..self.data_len]) {
+                // The empty header ends the batch.
+                None => TxState::Done,
+                Some(_) => TxState::AwaitRequest { then: Then::Data, waited: 0 },
+            };
+            return;
+        }
+
+        self.stats.packet = self.packet;
+        self.stats.packets += 1;
+        self.packet = self.packet.wrapping_add(1);
+        self.data_pos += self.packet_len;
+        if self.data_pos < self.data_len {
+            self.write_packet();
+        } else {
+            self.state = TxState::Ready;
+        }
+    }
+
+    /// Ends the transfer after the last `EOT`, or, in a YMODEM batch, the
+    /// current file.
+    fn file_ended(&mut self) {
+        self.in_file = false;
+        if self.ymodem {
+            self.file_sent = true;
+            self.state = TxState::Ready;
+        } else {
+            self.state = TxState::Done;
+        }
+    }
+}
+
+impl Machine for Transmitter {
+    fn push_bytes(&mut self, bytes: &[u8]) -> usize {
+        Transmitter::push_bytes(self, bytes)
+    }
+
+    fn timeout(&mut self) {
+        Transmitter::timeout(self)
+    }
+
+    fn stats(&self) -> Stats {
+        self.stats
+    }
+
+    fn is_started(&self) -> bool {
+        self.started
+    }
+}
+
+/// Writes `value` into `buf` as 8 lowercase hex digits, most significant
+/// first. Resume requests are sent this way so that none of their bytes is a
+/// `NAK`, `C` or `CAN` to a sender that doesn't know about them.
+fn write_hex(buf: &mut [u8], value: u32) {
+    for (i, digit) in buf.iter_mut().enumerate() {
+        let nibble = (value >> (28 - 4 * i)) & 0xf;
+        *digit = b"0123456789abcdef"[nibble as usize];
+    }
+}
+
+/// Parses 8 lowercase hex digits written by `write_hex`.
+fn read_hex(buf: &[u8]) -> Option<u32> {
+    buf.iter().try_fold(0u32, |value, &digit| {
+        let nibble = match digit {
+            b'0'..=b'9' => digit - b'0',
+            b'a'..=b'f' => digit - b'a' + 10,
+            _ => return None,
+        };
+        Some(value << 4 | nibble as u32)
+    })
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests;
@@ -0,0 +1,323 @@
+use super::*;
+use std::string::{String, ToString};
+use std::vec::Vec;
+use crate::{Xmodem, CRC};
+use std::io::{Read, Write};
+use std::sync::mpsc::{channel, Receiver as ChannelReceiver, Sender};
+
+struct Pipe(Sender<u8>, ChannelReceiver<u8>);
+
+fn pipe() -> (Pipe, Pipe) {
+    let ((tx1, rx1), (tx2, rx2)) = (channel(), channel());
+    (Pipe(tx1, rx2), Pipe(tx2, rx1))
+}
+
+impl io::Read for Pipe {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self.1.recv() {
+            Ok(byte) => {
+                buf[0] = byte;
+                Ok(1)
+            }
+            Err(_) => Ok(0),
+        }
+    }
+}
+
+impl io::Write for Pipe {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        for &byte in buf {
+            let _ = self.0.send(byte);
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn data(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i * 31 + i / 256) as u8).collect()
+}
+
+/// Receives with a `Receiver` driven over `pipe`, without timeouts.
+fn drive_receiver(mut receiver: Receiver, mut pipe: Pipe) -> io::Result<Vec<u8>> {
+    let mut output = vec![];
+    loop {
+        match receiver.next_action() {
+            Action::Write(bytes) => pipe.write_all(bytes)?,
+            Action::Deliver(payload) => output.extend_from_slice(payload),
+            Action::Wait => {
+                let mut byte = [0u8; 1];
+                pipe.read_exact(&mut byte)?;
+                assert_eq!(receiver.push_bytes(&byte), 1);
+            }
+            Action::Done => return Ok(output),
+            Action::Failed(e) => return Err(e),
+            Action::Header(_) | Action::NeedData | Action::ResumeRequested(_) => {
+                unreachable!("not a batch receiver")
+            }
+        }
+    }
+}
+
+/// Sends `data` with a `Transmitter` driven over `pipe`, without timeouts.
+fn drive_transmitter(mut transmitter: Transmitter, mut pipe: Pipe, data: &[u8]) -> io::Result<()> {
+    let mut chunks = data.chunks(PACKET_1K_LEN);
+    loop {
+        match transmitter.next_action() {
+            Action::Write(bytes) => pipe.write_all(bytes)?,
+            Action::NeedData => match chunks.next() {
+                Some(chunk) => transmitter.send(chunk)?,
+                None => transmitter.finish()?,
+            },
+            Action::Wait => {
+                let mut byte = [0u8; 1];
+                pipe.read_exact(&mut byte)?;
+                assert_eq!(transmitter.push_bytes(&byte), 1);
+            }
+            Action::Done => return Ok(()),
+            Action::Failed(e) => return Err(e),
+            Action::ResumeRequested(_) => transmitter.answer_resume(false),
+            Action::Deliver(_) | Action::Header(_) => unreachable!("transmitters don't receive"),
+        }
+    }
+}
+
+#[test]
+fn test_receiver_against_blocking_transmitter() {
+    let input = data(2500);
+    let expected = input.clone();
+    let (tx, rx) = pipe();
+    let tx_thread = std::thread::spawn(move || Xmodem::transmit(&input[..], tx));
+
+    let output = drive_receiver(Receiver::new(Checksum::Crc16), rx).expect("receive okay");
+    assert_eq!(tx_thread.join().expect("tx join okay").expect("tx okay"), 2500);
+    assert_eq!(&output[..2500], &expected[..]);
+    assert!(output[2500..].iter().all(|&b| b == 0));
+}
+
+#[test]
+fn test_transmitter_against_blocking_receiver() {
+    let input = data(3000);
+    let (tx, rx) = pipe();
+    let rx_thread = std::thread::spawn(move || {
+        let mut output = vec![];
+        Xmodem::receive(rx, &mut output).map(|_| output)
+    });
+
+    drive_transmitter(Transmitter::new(), tx, &input).expect("transmit okay");
+    let output = rx_thread.join().expect("rx join okay").expect("rx okay");
+    assert_eq!(&output[..3000], &input[..]);
+}
+
+#[test]
+fn test_machines_with_crc32() {
+    let input = data(2100);
+    let expected = input.clone();
+    let (tx, rx) = pipe();
+    let tx_thread = std::thread::spawn(move || Xmodem::transmit(&input[..], tx));
+
+    let receiver = Receiver::new(Checksum::Crc32);
+    let output = drive_receiver(receiver, rx).expect("receive okay");
+    assert_eq!(tx_thread.join().expect("tx join okay").expect("tx okay"), 2100);
+    assert_eq!(&output[..2100], &expected[..]);
+}
+
+#[test]
+fn test_machines_with_checksum() {
+    let input = data(1100);
+    let expected = input.clone();
+    let (tx, rx) = pipe();
+    let tx_thread =
+        std::thread::spawn(move || drive_transmitter(Transmitter::new(), tx, &input));
+
+    let output = drive_receiver(Receiver::new(Checksum::Sum), rx).expect("receive okay");
+    tx_thread.join().expect("tx join okay").expect("tx okay");
+    assert_eq!(&output[..1100], &expected[..]);
+}
+
+#[test]
+fn test_receiver_falls_back_to_checksum() {
+    let mut receiver = Receiver::new(Checksum::Crc16);
+    for _ in 0..CRC_ATTEMPTS {
+        assert!(matches!(receiver.next_action(), Action::Write(&[CRC])));
+        assert!(matches!(receiver.next_action(), Action::Wait));
+        receiver.timeout();
+    }
+    assert!(matches!(receiver.next_action(), Action::Write(&[NAK])));
+    assert_eq!(receiver.checksum(), Checksum::Sum);
+}
+
+#[test]
+fn test_receiver_naks_corruption_and_acks_repeats() {
+    let mut packet = vec![SOH, 1, 254];
+    packet.extend_from_slice(&[1u8; 128]);
+    packet.push(128);
+
+    let mut receiver = Receiver::new(Checksum::Sum);
+    assert!(matches!(receiver.next_action(), Action::Write(&[NAK])));
+
+    let mut corrupt = packet.clone();
+    corrupt[10] ^= 0xff;
+    assert_eq!(receiver.push_bytes(&corrupt), corrupt.len());
+    assert!(matches!(receiver.next_action(), Action::Write(&[NAK])));
+
+    assert_eq!(receiver.push_bytes(&packet), packet.len());
+    assert!(matches!(receiver.next_action(), Action::Deliver(payload) if payload == &[1u8; 128][..]));
+    assert!(matches!(receiver.next_action(), Action::Write(&[ACK])));
+
+    // The ACK got lost and the sender repeats the packet.
+    assert_eq!(receiver.push_bytes(&packet), packet.len());
+    assert!(matches!(receiver.next_action(), Action::Write(&[ACK])));
+    assert!(matches!(receiver.next_action(), Action::Wait));
+}
+
+#[test]
+fn test_push_bytes_stops_at_actions() {
+    let mut receiver = Receiver::new(Checksum::Crc16);
+    // The request for CRC mode hasn't been written yet.
+    assert_eq!(receiver.push_bytes(&[EOT, EOT]), 0);
+    assert!(matches!(receiver.next_action(), Action::Write(&[CRC])));
+    assert_eq!(receiver.push_bytes(&[EOT, EOT]), 1);
+    assert!(matches!(receiver.next_action(), Action::Write(&[NAK])));
+    assert_eq!(receiver.push_bytes(&[EOT]), 1);
+    assert!(matches!(receiver.next_action(), Action::Write(&[ACK])));
+    assert!(matches!(receiver.next_action(), Action::Done));
+}
+
+#[test]
+fn test_cancel() {
+    let mut transmitter = Transmitter::new();
+    assert_eq!(transmitter.push_bytes(&[CAN, CRC]), 2);
+    assert!(matches!(transmitter.next_action(), Action::NeedData));
+    transmitter.send(&[1; 128]).expect("ready");
+    assert!(matches!(transmitter.next_action(), Action::Write(frame) if frame.len() == 133));
+    transmitter.push_bytes(&[CAN, CAN]);
+    match transmitter.next_action() {
+        Action::Failed(e) => assert_eq!(e.kind(), io::ErrorKind::ConnectionAborted),
+        action => panic!("unexpected {:?}", action),
+    }
+
+    let mut receiver = Receiver::new(Checksum::Crc16);
+    receiver.next_action();
+    receiver.cancel();
+    assert!(matches!(receiver.next_action(), Action::Write(&[CAN, CAN])));
+    assert!(matches!(receiver.next_action(), Action::Failed(_)));
+}
+
+#[test]
+fn test_transmitter_gives_up() {
+    let mut transmitter = Transmitter::new();
+    transmitter.set_retries(2);
+    transmitter.push_bytes(&[NAK]);
+    transmitter.send(&[1; 200]).expect("ready");
+    assert!(matches!(transmitter.next_action(), Action::Write(frame) if frame.len() == 132));
+    transmitter.timeout();
+    assert!(matches!(transmitter.next_action(), Action::Write(frame) if frame.len() == 132));
+    transmitter.push_bytes(&[NAK]);
+    assert!(matches!(transmitter.next_action(), Action::Write(&[CAN, CAN])));
+    match transmitter.next_action() {
+        Action::Failed(e) => assert_eq!(e.kind(), io::ErrorKind::BrokenPipe),
+        action => panic!("unexpected {:?}", action),
+    }
+}
+
+#[test]
+fn test_ymodem_batch() {
+    let files = vec![("a.txt", data(300)), ("empty", vec![]), ("b.bin", data(2048))];
+    let sent = files.clone();
+    let (tx, rx) = pipe();
+    let tx_thread = std::thread::spawn(move || -> io::Result<()> {
+        let (mut transmitter, mut pipe) = (Transmitter::new(), tx);
+        let mut header = [0u8; PACKET_1K_LEN];
+        let mut files = sent.iter();
+        let mut chunks = [].chunks(PACKET_1K_LEN);
+        let mut in_file = false;
+        loop {
+            match transmitter.next_action() {
+                Action::Write(bytes) => pipe.write_all(bytes)?,
+                Action::NeedData if in_file => match chunks.next() {
+                    Some(chunk) => transmitter.send(chunk)?,
+                    None => {
+                        in_file = false;
+                        transmitter.finish()?;
+                    }
+                },
+                Action::NeedData => match files.next() {
+                    Some((name, data)) => {
+                        let n = crate::ymodem::write_header(&mut header, name, data.len() as u64)?;
+                        transmitter.send_header(&header[..n])?;
+                        chunks = data.chunks(PACKET_1K_LEN);
+                        in_file = true;
+                    }
+                    None => transmitter.send_header(&[0; PACKET_LEN])?,
+                },
+                Action::Wait => {
+                    let mut byte = [0u8; 1];
+                    pipe.read_exact(&mut byte)?;
+                    transmitter.push_bytes(&byte);
+                }
+                Action::Done => return Ok(()),
+                Action::Failed(e) => return Err(e),
+                action => panic!("unexpected {:?}", action),
+            }
+        }
+    });
+
+    let (mut receiver, mut pipe) = (Receiver::new(Checksum::Crc16), rx);
+    receiver.set_batch(true);
+    let mut received: Vec<(String, Vec<u8>)> = vec![];
+    loop {
+        let len = receiver.header().and_then(|h| h.len).unwrap_or(0) as usize;
+        match receiver.next_action() {
+            Action::Write(bytes) => pipe.write_all(bytes).expect("write"),
+            Action::Header(payload) => {
+                let name = crate::ymodem::file_name(payload).expect("name");
+                received.push((name.to_string(), vec![]));
+                receiver.receive_file();
+            }
+            Action::Deliver(payload) => {
+                let file = &mut received.last_mut().expect("file").1;
+                let take = payload.len().min(len - file.len());
+                file.extend_from_slice(&payload[..take]);
+            }
+            Action::Wait => {
+                let mut byte = [0u8; 1];
+                pipe.read_exact(&mut byte).expect("read");
+                receiver.push_bytes(&byte);
+            }
+            Action::Done => break,
+            action => panic!("unexpected {:?}", action),
+        }
+    }
+
+    tx_thread.join().expect("tx join okay").expect("tx okay");
+    let expected: Vec<_> = files.into_iter().map(|(name, data)| (name.to_string(), data)).collect();
+    assert_eq!(received, expected);
+}
+
+#[test]
+fn test_resume() {
+    let held = crate::verify::Trailer::of(&[3u8; 256]);
+    let mut receiver = Receiver::new(Checksum::Crc16);
+    receiver.resume(held);
+    let mut transmitter = Transmitter::new();
+
+    let request = match receiver.next_action() {
+        Action::Write(request) => request.to_vec(),
+        action => panic!("unexpected {:?}", action),
+    };
+    assert_eq!(transmitter.push_bytes(&request), request.len());
+    assert!(matches!(transmitter.next_action(), Action::ResumeRequested(t) if t == held));
+    transmitter.answer_resume(true);
+    assert!(matches!(transmitter.next_action(), Action::Write(&[ACK])));
+
+    assert!(receiver.is_resuming());
+    assert_eq!(receiver.push_bytes(&[ACK]), 1);
+    assert!(receiver.resumed());
+    assert!(matches!(receiver.next_action(), Action::Write(&[CRC])));
+    assert_eq!(transmitter.push_bytes(&[CRC]), 1);
+    assert!(matches!(transmitter.next_action(), Action::NeedData));
+}
@@ -1,3 +1,5 @@
+use core::time::Duration;
+
 /// Enum representing how much progress has been made transmitting/receiving.
 ///
 /// A value of this type is passed in to the progress callback supplied to
@@ -6,12 +8,35 @@
 /// is intended to be used by progress indicators or for debugging purposes.
 #[derive(Debug, Copy, Clone)]
 pub enum Progress {
-    /// Waiting for receiver to send NAK.
+    /// Waiting for receiver to send NAK or C.
     Waiting,
     /// Download/upload has started.
     Started,
-    /// Packet `.0` was transmitted/received.
-    Packet(u8),
+    /// Packet `.0.packet` was transmitted/received.
+    Packet(Stats),
+    /// Packet `.0.packet` was corrupted or lost on its way. It is transferred
+    /// again unless the retry limit has been reached.
+    Retry(Stats),
+}
+
+/// How a transfer has gone so far, for judging the quality of the link.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct Stats {
+    /// Number of the packet the progress is about, as sent on the wire. It
+    /// wraps around after 255.
+    pub packet: u8,
+    /// Number of packets transferred successfully.
+    pub packets: usize,
+    /// Number of times a packet had to be transferred again.
+    pub retransmits: usize,
+    /// Number of retransmits caused by a failed packet check, i.e. `NAK`s
+    /// received when sending or sent when receiving.
+    pub naks: usize,
+    /// Number of retransmits caused by the peer not responding in time.
+    pub timeouts: usize,
+    /// Time since the transfer started. Only measured with the `std` feature,
+    /// since `core` has no clock.
+    pub elapsed: Option<Duration>,
 }
 
 /// Type for progress callbacks.
@@ -19,3 +44,28 @@ pub type ProgressFn = fn(Progress);
 
 /// Noop progress callback.
 pub fn noop(_: Progress) {  }
+
+/// Measures `Stats::elapsed` where a clock is available.
+#[derive(Debug, Default)]
+pub(crate) struct Clock {
+    #[cfg(all(feature = "std", not(feature = "custom-std")))]
+    started: Option<std::time::Instant>,
+}
+
+impl Clock {
+    /// Starts measuring from now.
+    pub(crate) fn start(&mut self) {
+        #[cfg(all(feature = "std", not(feature = "custom-std")))]
+        {
+            self.started = Some(std::time::Instant::now());
+        }
+    }
+
+    /// Returns the time since `start`, if known.
+    pub(crate) fn elapsed(&self) -> Option<Duration> {
+        #[cfg(all(feature = "std", not(feature = "custom-std")))]
+        return self.started.map(|started| started.elapsed());
+        #[cfg(not(all(feature = "std", not(feature = "custom-std"))))]
+        return None;
+    }
+}
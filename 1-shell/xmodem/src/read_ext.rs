@@ -1,4 +1,4 @@
-use std::io;
+use crate::io;
 
 pub trait ReadExt: io::Read {
     fn read_max(&mut self, mut buf: &mut [u8]) -> io::Result<usize> {
@@ -14,6 +14,31 @@ pub trait ReadExt: io::Read {
 
         Ok(start_len - buf.len())
     }
+
+    /// Reads up to and including the first occurrence of `magic`, skipping at
+    /// most `max_noise` other bytes. Returns `false` if there were more, or if
+    /// the reader timed out first.
+    fn skip_to(&mut self, magic: &[u8], max_noise: usize) -> io::Result<bool> {
+        let mut matched = 0;
+        for _ in 0..max_noise + magic.len() {
+            let mut byte = [0u8; 1];
+            match self.read_exact(&mut byte) {
+                Err(ref e) if crate::is_timeout(e) => return Ok(false),
+                result => result?,
+            }
+
+            matched = match byte[0] {
+                b if b == magic[matched] => matched + 1,
+                b if b == magic[0] => 1,
+                _ => 0,
+            };
+            if matched == magic.len() {
+                return Ok(true);
+            }
+        }
+
+        Ok(false)
+    }
 }
 
 impl<T: io::Read> ReadExt for T {  }
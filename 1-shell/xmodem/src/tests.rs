@@ -11,9 +11,9 @@ fn pipe() -> (Pipe, Pipe) {
 
 impl io::Read for Pipe {
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-        for i in 0..buf.len() {
+        for (i, slot) in buf.iter_mut().enumerate() {
             match self.1.recv() {
-                Ok(byte) => buf[i] = byte,
+                Ok(byte) => *slot = byte,
                 Err(_) => return Ok(i)
             }
         }
@@ -76,54 +76,57 @@ fn read_byte() {
 
 #[test]
 fn test_expect_byte() {
-    let mut xmodem = Xmodem::new(Cursor::new(vec![1, 1]));
-    assert_eq!(xmodem.expect_byte(1, "1").expect("expected"), 1);
-    let e = xmodem.expect_byte(2, "1, please").expect_err("expect the unexpected");
+    // The start NAK and the NAK for the first EOT are expected; a NAK for the
+    // second EOT, where an ACK is expected, isn't.
+    let mut receiver = Script(vec![Some(NAK), Some(NAK), Some(NAK)].into(), vec![]);
+    let e = Xmodem::new(&mut receiver).write_packet(&[]).expect_err("expect the unexpected");
     assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+    assert_eq!(&receiver.1[..2], &[EOT, EOT]);
 }
 
 #[test]
 fn test_expect_byte_or_cancel() {
-    let mut buffer = vec![2, 0];
-    let b = Xmodem::new(Cursor::new(buffer.as_mut_slice()))
-        .expect_byte_or_cancel(2, "it's a 2")
-        .expect("got a 2");
-
-    assert_eq!(b, 2);
+    let mut sender = Script(vec![Some(EOT), Some(EOT)].into(), vec![]);
+    let n = Xmodem::new(&mut sender).read_packet(&mut [0u8; 128]).expect("got the second EOT");
+    assert_eq!(n, 0);
+    assert_eq!(&sender.1, &[CRC, NAK, ACK]);
 }
 
 #[test]
 fn test_expect_can() {
-    let mut xmodem = Xmodem::new(Cursor::new(vec![CAN]));
-    assert_eq!(xmodem.expect_byte(CAN, "hi").expect("CAN"), CAN);
+    // Packet 24 is numbered CAN; there it's data, not a cancel.
+    let mut script = vec![];
+    for number in 1..=CAN {
+        script.extend(crc_packet(number, &[number; 128]));
+    }
+
+    let mut sender = Script(script.into_iter().map(Some).collect(), vec![]);
+    let mut xmodem = Xmodem::new(&mut sender);
+    let mut packet = [0u8; 128];
+    for number in 1..=CAN {
+        assert_eq!(xmodem.read_packet(&mut packet).expect("CAN"), 128);
+        assert_eq!(packet, [number; 128]);
+    }
 }
 
 #[test]
 fn test_unexpected_can() {
-    let e = Xmodem::new(Cursor::new(vec![CAN]))
-        .expect_byte(SOH, "want SOH")
-        .expect_err("have CAN");
-
+    let mut sender = Script(vec![Some(EOT), Some(CAN)].into(), vec![]);
+    let e = Xmodem::new(&mut sender).read_packet(&mut [0u8; 128]).expect_err("have CAN");
     assert_eq!(e.kind(), io::ErrorKind::ConnectionAborted);
 }
 
 #[test]
 fn test_cancel_on_unexpected() {
-    let mut buffer = vec![CAN, 0];
-    let e = Xmodem::new(Cursor::new(buffer.as_mut_slice()))
-        .expect_byte_or_cancel(SOH, "want SOH")
-        .expect_err("have CAN");
-
+    let mut sender = Script(vec![Some(SOH), Some(CAN)].into(), vec![]);
+    let e = Xmodem::new(&mut sender).read_packet(&mut [0u8; 128]).expect_err("have CAN");
     assert_eq!(e.kind(), io::ErrorKind::ConnectionAborted);
-    assert_eq!(buffer[1], CAN);
-
-    let mut buffer = vec![0, 0];
-    let e = Xmodem::new(Cursor::new(buffer.as_mut_slice()))
-        .expect_byte_or_cancel(SOH, "want SOH")
-        .expect_err("have 0");
+    assert_eq!(&sender.1, &[CRC, CAN, CAN]);
 
+    let mut sender = Script(vec![Some(SOH), Some(1), Some(0)].into(), vec![]);
+    let e = Xmodem::new(&mut sender).read_packet(&mut [0u8; 128]).expect_err("have 0");
     assert_eq!(e.kind(), io::ErrorKind::InvalidData);
-    assert_eq!(buffer[1], CAN);
+    assert_eq!(&sender.1, &[CRC, CAN, CAN]);
 }
 
 #[test]
@@ -173,6 +176,45 @@ fn test_raw_transmission() {
     let rx_buf = tx_thread.join().expect("tx join okay");
     let tx_buf = rx_thread.join().expect("rx join okay");
 
+    // check packet 1
+    assert_eq!(&rx_buf[0..3], &[SOH, 1, 255 - 1]);
+    assert_eq!(&rx_buf[3..(3 + 128)], &input[..128]);
+    assert_eq!(&rx_buf[131..133], &crc::crc16(&input[..128]).to_be_bytes());
+
+    // check packet 2
+    assert_eq!(&rx_buf[133..136], &[SOH, 2, 255 - 2]);
+    assert_eq!(&rx_buf[136..(136 + 128)], &input[128..]);
+    assert_eq!(&rx_buf[264..266], &crc::crc16(&input[128..]).to_be_bytes());
+
+    // check EOT
+    assert_eq!(&rx_buf[266..], &[EOT, EOT]);
+
+    // check receiver responses
+    assert_eq!(&tx_buf, &[CRC, ACK, ACK, NAK, ACK]);
+}
+
+#[test]
+fn test_raw_checksum_transmission() {
+    let mut input = [0u8; 256];
+    (0..256usize).into_iter().enumerate().for_each(|(i, b)| input[i] = b as u8);
+
+    let (mut tx, mut rx) = pipe();
+    let tx_thread = std::thread::spawn(move || {
+        Xmodem::transmit(&input[..], &mut rx).expect("transmit okay");
+        rx.2
+    });
+
+    let rx_thread = std::thread::spawn(move || {
+        let mut receiver = Xmodem::new(&mut tx);
+        receiver.set_checksum(Checksum::Sum);
+        let mut packet = [0u8; 128];
+        while receiver.read_packet(&mut packet).expect("receive okay") != 0 {}
+        tx.2
+    });
+
+    let rx_buf = tx_thread.join().expect("tx join okay");
+    let tx_buf = rx_thread.join().expect("rx join okay");
+
     // check packet 1
     assert_eq!(&rx_buf[0..3], &[SOH, 1, 255 - 1]);
     assert_eq!(&rx_buf[3..(3 + 128)], &input[..128]);
@@ -190,6 +232,94 @@ fn test_raw_transmission() {
     assert_eq!(&tx_buf, &[NAK, ACK, ACK, NAK, ACK]);
 }
 
+/// A scripted sender: `None` entries time out instead of yielding a byte.
+struct Script(std::collections::VecDeque<Option<u8>>, Vec<u8>);
+
+impl io::Read for Script {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self.0.pop_front() {
+            Some(Some(byte)) => {
+                buf[0] = byte;
+                Ok(1)
+            }
+            Some(None) => Err(io::Error::new(io::ErrorKind::TimedOut, "timed out")),
+            None => Ok(0),
+        }
+    }
+}
+
+impl io::Write for Script {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.1.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn test_crc16() {
+    assert_eq!(crc::crc16(b""), 0);
+    assert_eq!(crc::crc16(b"123456789"), 0x31c3);
+}
+
+#[test]
+fn test_packet_checks() {
+    let payload = b"123456789";
+    let checks: [(Checksum, &[u8]); 3] = [
+        (Checksum::Sum, &[0xdd]),
+        (Checksum::Crc16, &[0x31, 0xc3]),
+        (Checksum::Crc32, &[0xcb, 0xf4, 0x39, 0x26]),
+    ];
+    for (checksum, expected) in checks {
+        let check = checksum.packet_check();
+        let mut out = vec![0u8; check.wire_len()];
+        check.write(payload, &mut out);
+        assert_eq!(&out[..], expected);
+        assert!(check.verify(payload, expected));
+        assert!(!check.verify(b"123456780", expected));
+    }
+}
+
+#[test]
+fn test_crc32() {
+    assert_eq!(crc::crc32(b""), 0);
+    assert_eq!(crc::crc32(b"123456789"), 0xcbf4_3926);
+    assert_eq!(crc::crc32_update(crc::crc32(b"1234"), b"56789"), 0xcbf4_3926);
+}
+
+#[test]
+fn test_receiver_falls_back_to_checksum() {
+    let payload = [7u8; 128];
+    let mut script: std::collections::VecDeque<_> = vec![None; CRC_ATTEMPTS].into();
+    script.extend([SOH, 1, 254].iter().map(|&b| Some(b)));
+    script.extend(payload.iter().map(|&b| Some(b)));
+    script.push_back(Some(payload.iter().fold(0u8, |a, b| a.wrapping_add(*b))));
+    script.extend([EOT, EOT].iter().map(|&b| Some(b)));
+
+    let mut output = [0u8; 128];
+    let mut sender = Script(script, vec![]);
+    let received = Xmodem::receive(&mut sender, &mut output[..]).expect("receive okay");
+    assert_eq!(received, 128);
+    assert_eq!(&output[..], &payload[..]);
+    assert_eq!(&sender.1, &[CRC, CRC, CRC, NAK, ACK, NAK, ACK]);
+}
+
+#[test]
+fn test_crc_packet_corruption() {
+    let mut packet = vec![SOH, 1, 254];
+    packet.extend_from_slice(&[1u8; 128]);
+    packet.extend_from_slice(&(crc::crc16(&[1u8; 128]) ^ 1).to_be_bytes());
+
+    let mut script = Script(packet.into_iter().map(Some).collect(), vec![]);
+    let mut buf = [0u8; 128];
+    let e = Xmodem::new(&mut script).read_packet(&mut buf).expect_err("bad CRC");
+    assert_eq!(e.kind(), io::ErrorKind::Interrupted);
+    assert_eq!(&script.1, &[CRC, NAK]);
+}
+
 #[test]
 fn test_small_packet_eof_error() {
     let mut xmodem = Xmodem::new(Cursor::new(vec![NAK, NAK, NAK]));
@@ -205,7 +335,7 @@ fn test_small_packet_eof_error() {
 #[test]
 fn test_bad_control() {
     let mut packet = [0; 128];
-    let e = Xmodem::new(Cursor::new(vec![0, CAN]))
+    let e = Xmodem::new(Cursor::new(vec![0, CAN, CAN]))
         .read_packet(&mut packet[..])
         .expect_err("CAN");
 
@@ -227,3 +357,554 @@ fn test_eot() {
 
     assert_eq!(&buffer[..], &[NAK, EOT, NAK, EOT, ACK]);
 }
+
+#[test]
+fn test_1k_loop() {
+    let input: Vec<u8> = (0..2500u32).map(|i| (i % 251) as u8).collect();
+    let expected = input.clone();
+
+    let (mut tx, mut rx) = pipe();
+    let tx_thread = std::thread::spawn(move || {
+        let n = Xmodem::transmit(&input[..], &mut rx).expect("transmit okay");
+        (n, rx.2)
+    });
+    let rx_thread = std::thread::spawn(move || {
+        let mut output = vec![0u8; 2560];
+        let n = Xmodem::receive(&mut tx, &mut output[..]).expect("receive okay");
+        (n, output)
+    });
+
+    let (sent, rx_buf) = tx_thread.join().expect("tx join okay");
+    let (received, output) = rx_thread.join().expect("rx join okay");
+    assert_eq!(sent, 2500);
+    assert_eq!(received, 2 * 1024 + 4 * 128);
+    assert_eq!(&output[..2500], &expected[..]);
+    assert!(output[2500..].iter().all(|&b| b == 0));
+
+    // two 1K packets, then the tail in standard packets
+    assert_eq!(&rx_buf[0..3], &[STX, 1, 255 - 1]);
+    assert_eq!(&rx_buf[1029..1032], &[STX, 2, 255 - 2]);
+    assert_eq!(&rx_buf[2058..2061], &[SOH, 3, 255 - 3]);
+}
+
+#[test]
+fn test_1k_falls_back_when_rejected() {
+    let mut responses = vec![Some(CRC), Some(NAK)];
+    responses.extend(vec![Some(ACK); 8]);
+    responses.extend(&[Some(NAK), Some(ACK)]);
+    let mut receiver = Script(responses.into(), vec![]);
+
+    let input = [3u8; 1024];
+    let sent = Xmodem::transmit(&input[..], &mut receiver).expect("transmit okay");
+    assert_eq!(sent, 1024);
+
+    let out = &receiver.1;
+    assert_eq!(&out[0..3], &[STX, 1, 255 - 1]);
+    assert_eq!(&out[1029..1032], &[SOH, 1, 255 - 1]);
+    assert_eq!(out.len(), 1029 + 8 * 133 + 2);
+}
+
+#[test]
+fn test_1k_packet_needs_large_buffer() {
+    let mut script = Script(vec![Some(STX)].into(), vec![]);
+    let mut buf = [0u8; 128];
+    let e = Xmodem::new(&mut script).read_packet(&mut buf).expect_err("buffer too small");
+    assert_eq!(e.kind(), io::ErrorKind::UnexpectedEof);
+    assert_eq!(&script.1, &[CRC, CAN, CAN]);
+}
+
+#[test]
+fn test_transmit_retries_on_timeout() {
+    let responses = vec![Some(NAK), None, Some(ACK), Some(NAK), Some(ACK)];
+    let mut receiver = Script(responses.into(), vec![]);
+
+    let input = [5u8; 128];
+    let sent = Xmodem::transmit(&input[..], &mut receiver).expect("transmit okay");
+    assert_eq!(sent, 128);
+    assert_eq!(receiver.1.len(), 2 * 132 + 2);
+}
+
+#[test]
+fn test_retry_progress() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static RETRIES: AtomicUsize = AtomicUsize::new(0);
+    fn count_retries(progress: Progress) {
+        if let Progress::Retry(Stats { packet: 1, .. }) = progress {
+            RETRIES.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    let responses = vec![Some(NAK), Some(NAK), None, Some(ACK), Some(NAK), Some(ACK)];
+    let mut receiver = Script(responses.into(), vec![]);
+    let mut xmodem = Xmodem::new_with_progress(&mut receiver, count_retries);
+    xmodem.transmit_from(&[5u8; 128][..]).expect("transmit okay");
+    assert_eq!(RETRIES.load(Ordering::SeqCst), 2);
+
+    let stats = xmodem.stats();
+    assert_eq!((stats.packet, stats.packets), (1, 1));
+    assert_eq!((stats.retransmits, stats.naks, stats.timeouts), (2, 1, 1));
+    assert!(stats.elapsed.is_some());
+}
+
+#[test]
+fn test_retry_limit() {
+    let mut receiver = Script(vec![Some(NAK), None, Some(NAK), Some(NAK)].into(), vec![]);
+    let mut transmitter = Xmodem::new(&mut receiver);
+    transmitter.set_retries(2);
+    let e = transmitter.transmit_from(&[5u8; 128][..]).expect_err("gave up");
+    assert_eq!(e.kind(), io::ErrorKind::BrokenPipe);
+    assert_eq!(&receiver.1[2 * 132..], &[CAN, CAN]);
+}
+
+#[test]
+fn test_lone_can_is_noise() {
+    let mut receiver = Script(vec![Some(CAN), Some(CRC), Some(CAN), Some(ACK)].into(), vec![]);
+    let mut transmitter = Xmodem::new(&mut receiver);
+    assert_eq!(transmitter.write_packet(&[1u8; 128]).expect("sent"), 128);
+    assert_eq!(transmitter.checksum(), Checksum::Crc16);
+}
+
+#[test]
+fn test_cancel() {
+    // The receiver cancels after the first packet.
+    let responses = vec![Some(CRC), Some(ACK), Some(CAN), Some(CAN)];
+    let mut receiver = Script(responses.into(), vec![]);
+    let e = Xmodem::transmit(&[1u8; 256][..], &mut receiver).expect_err("cancelled");
+    assert_eq!(e.kind(), io::ErrorKind::ConnectionAborted);
+    // The receiver knows already, so we don't cancel back.
+    assert_eq!(receiver.1.len(), 2 * 133);
+
+    let (tx, rx) = pipe();
+    let rx_thread = std::thread::spawn(move || {
+        let mut output = [0u8; 256];
+        Xmodem::receive(rx, &mut output[..])
+    });
+
+    let mut transmitter = Xmodem::new(tx);
+    transmitter.write_packet(&[1u8; 128]).expect("first packet");
+    transmitter.cancel().expect("cancelled");
+    let e = rx_thread.join().expect("rx join okay").expect_err("cancelled");
+    assert_eq!(e.kind(), io::ErrorKind::ConnectionAborted);
+}
+
+#[test]
+fn test_verified_transfer() {
+    let input = [9u8; 300];
+    let (tx, rx) = pipe();
+    let tx_thread = std::thread::spawn(move || {
+        let mut tx = tx;
+        let mut data = verify::Tracking::new(&input[..]);
+        Xmodem::new(&mut tx).transmit_from(&mut data).expect("transmit okay");
+        verify::send(&mut tx, data.trailer()).expect("trailer sent")
+    });
+
+    let rx_thread = std::thread::spawn(move || {
+        let mut rx = rx;
+        let mut output = [0u8; 384];
+        let n = Xmodem::receive(&mut rx, &mut output[..]).expect("receive okay");
+        verify::check(&mut rx, &output[..n]).expect("trailer checked")
+    });
+
+    assert!(tx_thread.join().expect("tx join okay"));
+    assert_eq!(rx_thread.join().expect("rx join okay"), Some(true));
+}
+
+#[test]
+fn test_verify_mismatch() {
+    let trailer = verify::Trailer::of(b"kernel");
+    assert!(trailer.matches(b"kernel\0\0"));
+    assert!(!trailer.matches(b"kerne1\0\0"));
+    assert!(!trailer.matches(b"kern"));
+
+    let (mut tx, mut rx) = pipe();
+    let rx_thread = std::thread::spawn(move || verify::check(&mut rx, b"kerne1\0\0"));
+    assert!(!verify::send(&mut tx, trailer).expect("trailer sent"));
+    assert_eq!(rx_thread.join().expect("rx join okay").expect("checked"), Some(false));
+
+    let (mut tx, mut rx) = pipe();
+    let rx_thread = std::thread::spawn(move || verify::check_trailer(&mut rx, b"kerne1\0\0"));
+    assert!(!verify::send(&mut tx, trailer).expect("trailer sent"));
+    let sent = rx_thread.join().expect("rx join okay").expect("checked");
+    assert_eq!(sent, Some(trailer));
+    assert_eq!(trailer.to_string(), format!("6 bytes with CRC-32 {:#010x}", trailer.crc));
+}
+
+#[test]
+fn test_verify_not_requested() {
+    let mut sender = Script(vec![None].into(), vec![]);
+    assert_eq!(verify::check(&mut sender, b"kernel").expect("checked"), None);
+    assert!(sender.1.is_empty());
+}
+
+#[test]
+fn test_image_header() {
+    let kernel = b"kernel image";
+    let header = image::Header::new(kernel, 0x8_0000, 4);
+    let mut packed = header.to_bytes().to_vec();
+    packed.extend_from_slice(kernel);
+    packed.extend_from_slice(&[0; 20]);
+
+    assert_eq!(image::Header::parse(&packed), Ok(Some(header)));
+    assert_eq!(header.check(&packed[image::LEN..]), Ok(()));
+    assert_eq!(header.entry(), 0x8_0004);
+    assert_eq!(header.range(), 0x8_0000..0x8_000c);
+
+    // Plain binaries have no header.
+    assert_eq!(image::Header::parse(kernel), Ok(None));
+    assert_eq!(image::Header::parse(b""), Ok(None));
+}
+
+#[test]
+fn test_image_header_rejected() {
+    let kernel = b"kernel image";
+    let bytes = |header: image::Header| header.to_bytes();
+
+    let mut damaged = bytes(image::Header::new(kernel, 0x8_0000, 0));
+    damaged[9] ^= 1;
+    assert_eq!(image::Header::parse(&damaged), Err(image::Error::BadHeader));
+    assert_eq!(image::Header::parse(&damaged[..16]), Err(image::Error::BadHeader));
+
+    let arm = image::Header { arch: 40, ..image::Header::new(kernel, 0x8_0000, 0) };
+    assert_eq!(image::Header::parse(&bytes(arm)), Err(image::Error::WrongArch(40)));
+    let future = image::Header { version: 9, ..image::Header::new(kernel, 0x8_0000, 0) };
+    assert_eq!(image::Header::parse(&bytes(future)), Err(image::Error::UnsupportedVersion(9)));
+    let outside = image::Header::new(kernel, 0x8_0000, 12);
+    assert_eq!(image::Header::parse(&bytes(outside)), Err(image::Error::BadEntry));
+
+    let header = image::Header::new(kernel, 0x8_0000, 0);
+    assert_eq!(
+        header.check(b"kernel"),
+        Err(image::Error::Truncated { expected: 12, received: 6 })
+    );
+    assert_eq!(
+        header.check(b"kernel imagf"),
+        Err(image::Error::BadCrc { expected: header.crc, actual: crc::crc32(b"kernel imagf") })
+    );
+}
+
+/// Returns 600 bytes of data and a 1024-byte receive buffer that holds the
+/// first 300 bytes of it from an interrupted transfer.
+fn interrupted() -> (Vec<u8>, Vec<u8>) {
+    let input: Vec<u8> = (0..600).map(|i| (i / 7) as u8).collect();
+    let mut output = vec![0u8; 1024];
+    output[..300].copy_from_slice(&input[..300]);
+    (input, output)
+}
+
+#[test]
+fn test_resume() {
+    let (input, mut output) = interrupted();
+    let (tx, rx) = pipe();
+    let data = input.clone();
+    let tx_thread = std::thread::spawn(move || {
+        let mut xmodem = Xmodem::new(rx);
+        xmodem.start_transmit().expect("started");
+        let held = xmodem.resume_request().expect("resume requested");
+        assert_eq!(held.len, 256);
+        assert_eq!(held, verify::Trailer::of(&data[..256]));
+        xmodem.answer_resume(true).expect("answered");
+        xmodem.transmit_from(&data[256..]).expect("transmit okay")
+    });
+
+    let mut received = 300;
+    Xmodem::new(tx).resume_into(&mut output, &mut received).expect("receive okay");
+    assert_eq!(tx_thread.join().expect("tx join okay"), 344);
+    assert_eq!(received, 640);
+    assert_eq!(&output[..600], &input[..]);
+}
+
+#[test]
+fn test_resume_declined() {
+    let (input, mut output) = interrupted();
+    output[0] ^= 1;
+    let (tx, rx) = pipe();
+    let data = input.clone();
+    let tx_thread = std::thread::spawn(move || {
+        let mut xmodem = Xmodem::new(rx);
+        xmodem.start_transmit().expect("started");
+        let held = xmodem.resume_request().expect("resume requested");
+        assert_ne!(held, verify::Trailer::of(&data[..256]));
+        xmodem.answer_resume(false).expect("answered");
+        xmodem.transmit_from(&data[..]).expect("transmit okay")
+    });
+
+    let mut received = 300;
+    Xmodem::new(tx).resume_into(&mut output, &mut received).expect("receive okay");
+    assert_eq!(tx_thread.join().expect("tx join okay"), 600);
+    assert_eq!(received, 640);
+    assert_eq!(&output[..600], &input[..]);
+}
+
+#[test]
+fn test_resume_unanswered() {
+    let (input, mut output) = interrupted();
+    let (tx, rx) = pipe();
+    let data = input.clone();
+    let tx_thread = std::thread::spawn(move || Xmodem::transmit(&data[..], rx));
+
+    let mut received = 300;
+    Xmodem::new(tx).resume_into(&mut output, &mut received).expect("receive okay");
+    assert_eq!(tx_thread.join().expect("tx join okay").expect("transmit okay"), 600);
+    assert_eq!(received, 640);
+    assert_eq!(&output[..600], &input[..]);
+}
+
+#[test]
+fn test_resume_not_supported() {
+    let mut sender = Script(vec![None, None, None, Some(CAN), Some(CAN)].into(), vec![]);
+    let mut output = [0u8; 256];
+    let mut received = 200;
+    let e = Xmodem::new(&mut sender)
+        .resume_into(&mut output, &mut received)
+        .expect_err("cancelled");
+    assert_eq!(e.kind(), io::ErrorKind::ConnectionAborted);
+    assert_eq!(received, 200);
+
+    let request: Vec<u8> = [&b"R"[..], b"00000080", b"c2a8fa9d"].concat();
+    assert_eq!(&sender.1[..17], &request[..]);
+    assert_eq!(&sender.1[51..], &[CRC]);
+}
+
+#[test]
+fn test_crc32_transfer() {
+    let input = [7u8; 1500];
+    let (tx, rx) = pipe();
+    let tx_thread = std::thread::spawn(move || {
+        let mut xmodem = Xmodem::new(rx);
+        xmodem.transmit_from(&input[..]).expect("transmit okay");
+        xmodem.checksum()
+    });
+
+    let mut receiver = Xmodem::new(tx);
+    receiver.set_checksum(Checksum::Crc32);
+    let mut output = vec![];
+    assert_eq!(receiver.receive_into(&mut output).expect("receive okay"), 1536);
+    assert_eq!(&output[..1500], &input[..]);
+    assert_eq!(tx_thread.join().expect("tx join okay"), Checksum::Crc32);
+}
+
+#[test]
+fn test_crc32_falls_back() {
+    let responses = vec![None, None, None, None, None, None, Some(CAN), Some(CAN)];
+    let mut sender = Script(responses.into(), vec![]);
+    let mut receiver = Xmodem::new(&mut sender);
+    receiver.set_checksum(Checksum::Crc32);
+    let e = receiver.read_packet(&mut [0u8; 128]).expect_err("cancelled");
+    assert_eq!(e.kind(), io::ErrorKind::ConnectionAborted);
+    assert_eq!(sender.1, [CRC32, CRC32, CRC32, CRC, CRC, CRC, NAK]);
+}
+
+#[test]
+fn test_baud_handshake() {
+    let mut host = vec![b'x', b'B', b'A', CRC];
+    host.extend_from_slice(&baud::request_bytes(460_800));
+    let mut device = Script(host.into_iter().map(Some).collect(), vec![]);
+    assert_eq!(baud::read_request(&mut device).expect("read"), Some(460_800));
+    baud::accept(&mut device, 460_800).expect("accepted");
+    assert_eq!(&device.1[..], &baud::request_bytes(460_800));
+
+    let mut device = Script(baud::PATTERN.iter().cloned().map(Some).collect(), vec![]);
+    assert!(baud::answer_probe(&mut device).expect("probed"));
+    assert_eq!(&device.1[..], &baud::PATTERN);
+
+    let mut garbled = baud::PATTERN;
+    garbled[3] ^= 0x10;
+    let mut device = Script(garbled.iter().cloned().map(Some).collect(), vec![]);
+    assert!(!baud::answer_probe(&mut device).expect("probed"));
+    assert!(device.1.is_empty());
+
+    let mut device = Script(vec![Some(b'B'), None].into(), vec![]);
+    assert_eq!(baud::read_request(&mut device).expect("read"), None);
+}
+
+/// Frames `payload` as packet `number` with a CRC-16, as a YMODEM sender does.
+fn crc_packet(number: u8, payload: &[u8]) -> Vec<u8> {
+    let mut packet = vec![SOH, number, 255 - number];
+    packet.extend_from_slice(payload);
+    packet.extend_from_slice(&crate::crc::crc16(payload).to_be_bytes());
+    packet
+}
+
+fn ymodem_header(fields: &[u8]) -> [u8; 128] {
+    let mut header = [0u8; 128];
+    header[..fields.len()].copy_from_slice(fields);
+    header
+}
+
+#[test]
+fn test_ymodem_header() {
+    let parse = |fields: &[u8]| ymodem::Header::parse(&ymodem_header(fields));
+    assert_eq!(parse(b"kernel8.img\x00200 13774625117 100644"), Some(ymodem::Header { len: Some(200) }));
+    assert_eq!(parse(b"kernel8.img\x00200"), Some(ymodem::Header { len: Some(200) }));
+    assert_eq!(parse(b"kernel8.img\x00"), Some(ymodem::Header { len: None }));
+    assert_eq!(parse(b"kernel8.img\x00x12"), Some(ymodem::Header { len: None }));
+    assert_eq!(parse(b""), None);
+}
+
+#[test]
+fn test_receive_to_writer_ymodem() {
+    let mut data = vec![1u8; 128];
+    data.extend_from_slice(&[2u8; 72]);
+    data.resize(256, 0x1A);
+
+    let mut script = crc_packet(0, &ymodem_header(b"kernel8.img\x00200 0 0"));
+    script.extend(crc_packet(1, &data[..128]));
+    script.extend(crc_packet(2, &data[128..]));
+    script.extend_from_slice(&[EOT, EOT]);
+    script.extend(crc_packet(0, &[0u8; 128]));
+
+    let mut sender = Script(script.into_iter().map(Some).collect(), vec![]);
+    let mut receiver = Xmodem::new(&mut sender);
+    let mut output = vec![];
+    assert_eq!(receiver.receive_to_writer(&mut output).expect("receive okay"), 200);
+    assert_eq!(receiver.header(), Some(ymodem::Header { len: Some(200) }));
+    assert_eq!(&output[..], &data[..200]);
+    assert_eq!(sender.1, [CRC, ACK, CRC, ACK, ACK, NAK, ACK, CRC, ACK]);
+}
+
+#[test]
+fn test_receive_to_writer_refuses_second_file() {
+    let mut script = crc_packet(0, &ymodem_header(b"a\x004"));
+    script.extend(crc_packet(1, &[9u8; 128]));
+    script.extend_from_slice(&[EOT, EOT]);
+    script.extend(crc_packet(0, &ymodem_header(b"b\x004")));
+
+    let mut sender = Script(script.into_iter().map(Some).collect(), vec![]);
+    let mut output = vec![];
+    let received = Xmodem::new(&mut sender).receive_to_writer(&mut output);
+    assert_eq!(received.expect("receive okay"), 4);
+    assert_eq!(output, [9u8; 4]);
+    assert_eq!(sender.1, [CRC, ACK, CRC, ACK, NAK, ACK, CRC, ACK, CAN, CAN]);
+}
+
+#[test]
+fn test_receive_to_writer_xmodem() {
+    let input = [5u8; 300];
+    let (tx, rx) = pipe();
+    let tx_thread = std::thread::spawn(move || Xmodem::transmit(&input[..], rx));
+
+    let mut output = vec![];
+    let mut receiver = Xmodem::new(tx);
+    assert_eq!(receiver.receive_to_writer(&mut output).expect("receive okay"), 384);
+    assert_eq!(receiver.header(), None);
+    assert_eq!(&output[..300], &input[..]);
+    tx_thread.join().expect("tx join okay").expect("transmit okay");
+}
+
+#[test]
+fn test_ymodem_write_header() {
+    let mut buf = [0xFFu8; 1024];
+    assert_eq!(ymodem::write_header(&mut buf, "kernel8.img", 200).expect("fits"), 128);
+    assert_eq!(&buf[..17], b"kernel8.img\x00200\x00\x00");
+    assert_eq!(ymodem::file_name(&buf), Some("kernel8.img"));
+    assert_eq!(ymodem::Header::parse(&buf), Some(ymodem::Header { len: Some(200) }));
+
+    let long = "x".repeat(200);
+    assert_eq!(ymodem::write_header(&mut buf, &long, 0).expect("fits"), 1024);
+    assert_eq!(ymodem::file_name(&buf), Some(&long[..]));
+
+    for name in &["", "a\0b", &"x".repeat(1022)] {
+        let e = ymodem::write_header(&mut buf, name, 0).expect_err("doesn't fit");
+        assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+    }
+    assert!(ymodem::write_header(&mut [0u8; 128], &long, 0).is_err());
+}
+
+#[test]
+fn test_ymodem_batch() {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    let files: Vec<(&str, Vec<u8>)> = vec![
+        ("config.txt", b"kernel=kernel8.img\n".to_vec()),
+        ("kernel8.img", (0..3000).map(|i| i as u8).collect()),
+        ("overlays/empty.dtbo", vec![]),
+    ];
+
+    let (tx, rx) = pipe();
+    let sent = files.clone();
+    let tx_thread = std::thread::spawn(move || {
+        let mut sender = Xmodem::new(rx);
+        sender.start_transmit()?;
+        for (name, data) in &sent {
+            let n = sender.transmit_file(name, data.len() as u64, &data[..])?;
+            assert_eq!(n, data.len());
+        }
+        sender.end_batch_transmit()
+    });
+
+    type Files = Rc<RefCell<Vec<(String, Vec<u8>)>>>;
+
+    /// Appends to the data of the last file received.
+    struct LastFile(Files);
+
+    impl io::Write for LastFile {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().last_mut().unwrap().1.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let received: Files = Rc::default();
+    let count = Xmodem::new(tx).receive_batch(|name, header| {
+        assert!(header.len.is_some());
+        received.borrow_mut().push((name.to_string(), vec![]));
+        Ok(LastFile(received.clone()))
+    });
+    tx_thread.join().expect("tx join okay").expect("transmit okay");
+
+    assert_eq!(count.expect("receive okay"), files.len());
+    let expected: Vec<_> = files.into_iter().map(|(name, data)| (name.to_string(), data)).collect();
+    assert_eq!(*received.borrow(), expected);
+}
+
+#[test]
+fn test_ymodem_batch_open_fails() {
+    let mut script = crc_packet(0, &ymodem_header(b"a\x004"));
+    script.extend(crc_packet(1, &[9u8; 128]));
+
+    let mut sender = Script(script.into_iter().map(Some).collect(), vec![]);
+    let e = Xmodem::new(&mut sender)
+        .receive_batch(|_, _| -> io::Result<Vec<u8>> { Err(io::ErrorKind::PermissionDenied.into()) })
+        .expect_err("open fails");
+    assert_eq!(e.kind(), io::ErrorKind::PermissionDenied);
+    assert_eq!(sender.1, [CRC, ACK, CAN, CAN]);
+}
+
+#[test]
+fn test_loader_messages() {
+    let messages = [
+        loader::Message::Query,
+        loader::Message::Info { version: loader::VERSION, max_len: 0x3F8_0000 },
+        loader::Message::Image(Trailer::of(b"kernel")),
+        loader::Message::Jump,
+    ];
+    let mut wire = b"Ready to receive kernel\r\n".to_vec();
+    for message in &messages {
+        message.write(&mut wire).expect("write okay");
+    }
+
+    let mut wire = Script(wire.into_iter().map(Some).chain(Some(None)).collect(), vec![]);
+    for message in &messages {
+        assert_eq!(loader::Message::read(&mut wire).expect("read okay"), Some(*message));
+    }
+    assert_eq!(loader::Message::read(&mut wire).expect("read okay"), None);
+
+    let mut wire = Cursor::new(b"LOADx".to_vec());
+    let e = loader::Message::read(&mut wire).expect_err("unknown kind");
+    assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+}
+
+#[test]
+fn test_loader_answers() {
+    let mut wire = vec![];
+    loader::answer(&mut wire, false).unwrap();
+    loader::answer(&mut wire, true).unwrap();
+    let mut wire = Cursor::new([b"\r\n".to_vec(), wire].concat());
+    assert!(!loader::read_answer(&mut wire).unwrap());
+    assert!(loader::read_answer(&mut wire).unwrap());
+}
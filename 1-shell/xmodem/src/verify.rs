@@ -0,0 +1,160 @@
+//! An optional end-to-end check that follows a transfer.
+//!
+//! XMODEM's per-packet checks can't catch data lost or duplicated between
+//! packets, or a transfer cut short, and they say nothing about the padding
+//! the receiver has to strip. Once the transfer has ended, a sender can follow
+//! it with a `Trailer` holding the length and CRC-32 of the payload. The
+//! receiver compares it against what it received and answers `ACK` if they
+//! match or `NAK` if they don't.
+//!
+//! Receivers treat a timeout while waiting for the trailer as "no check
+//! requested", so senders that don't know about it keep working.
+
+use core::fmt;
+
+use crate::io;
+
+use crate::crc::crc32_update;
+use crate::{ACK, NAK};
+
+/// Starts every trailer.
+const MAGIC: [u8; 4] = *b"CRC1";
+
+/// The length and CRC-32 of a payload.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct Trailer {
+    pub len: u32,
+    pub crc: u32,
+}
+
+impl Trailer {
+    /// Returns the trailer for `data`.
+    pub fn of(data: &[u8]) -> Trailer {
+        let mut trailer = Trailer::default();
+        trailer.update(data);
+        trailer
+    }
+
+    /// Extends the trailer over `data`, which follows the data it covers.
+    pub fn update(&mut self, data: &[u8]) {
+        self.len = self.len.wrapping_add(data.len() as u32);
+        self.crc = crc32_update(self.crc, data);
+    }
+
+    /// Returns `true` if the first `len` bytes of `received` match.
+    /// `received` may be longer, since the last packet was padded.
+    pub fn matches(&self, received: &[u8]) -> bool {
+        let len = self.len as usize;
+        len <= received.len() && Trailer::of(&received[..len]) == *self
+    }
+
+    fn to_bytes(self) -> [u8; 12] {
+        let mut bytes = [0u8; 12];
+        bytes[..4].copy_from_slice(&MAGIC);
+        bytes[4..8].copy_from_slice(&self.len.to_le_bytes());
+        bytes[8..].copy_from_slice(&self.crc.to_le_bytes());
+        bytes
+    }
+}
+
+impl fmt::Display for Trailer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} bytes with CRC-32 {:#010x}", self.len, self.crc)
+    }
+}
+
+/// Sends `trailer` to the receiver `to` after a transfer and returns whether
+/// the receiver's data matched it.
+///
+/// # Errors
+///
+/// Returns an error of kind `InvalidData` if the receiver answers with
+/// something besides `ACK` or `NAK`.
+pub fn send<T: io::Read + io::Write>(mut to: T, trailer: Trailer) -> io::Result<bool> {
+    to.write_all(&trailer.to_bytes())?;
+    to.flush()?;
+
+    let mut response = [0u8; 1];
+    to.read_exact(&mut response)?;
+    match response[0] {
+        ACK => Ok(true),
+        NAK => Ok(false),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "expected ACK or NAK for trailer",
+        )),
+    }
+}
+
+/// Waits for a trailer from the sender `from` after a transfer, compares it
+/// against `received` and tells the sender the result. Returns `None` if the
+/// sender didn't send a trailer before `from` timed out, or whether
+/// `received` matched it.
+///
+/// # Errors
+///
+/// Returns an error of kind `InvalidData` if something other than a trailer
+/// arrives.
+pub fn check<T: io::Read + io::Write>(from: T, received: &[u8]) -> io::Result<Option<bool>> {
+    Ok(check_trailer(from, received)?.map(|trailer| trailer.matches(received)))
+}
+
+/// Like `check`, but returns the sender's trailer instead of whether
+/// `received` matched it, so that a mismatch can be explained.
+///
+/// # Errors
+///
+/// Returns an error of kind `InvalidData` if something other than a trailer
+/// arrives.
+pub fn check_trailer<T: io::Read + io::Write>(
+    mut from: T,
+    received: &[u8],
+) -> io::Result<Option<Trailer>> {
+    let mut bytes = [0u8; 12];
+    match from.read_exact(&mut bytes[..1]) {
+        Err(ref e) if crate::is_timeout(e) => return Ok(None),
+        Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        result => result?,
+    }
+    from.read_exact(&mut bytes[1..])?;
+    if bytes[..4] != MAGIC {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "expected a trailer",
+        ));
+    }
+
+    let trailer = Trailer {
+        len: u32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]),
+        crc: u32::from_le_bytes([bytes[8], bytes[9], bytes[10], bytes[11]]),
+    };
+    let matches = trailer.matches(received);
+    from.write_all(&[if matches { ACK } else { NAK }])?;
+    Ok(Some(trailer))
+}
+
+/// Computes the trailer for everything read through it.
+pub struct Tracking<R> {
+    inner: R,
+    trailer: Trailer,
+}
+
+impl<R: io::Read> Tracking<R> {
+    /// Wraps `inner`.
+    pub fn new(inner: R) -> Tracking<R> {
+        Tracking { inner, trailer: Trailer::default() }
+    }
+
+    /// Returns the trailer for the data read so far.
+    pub fn trailer(&self) -> Trailer {
+        self.trailer
+    }
+}
+
+impl<R: io::Read> io::Read for Tracking<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.trailer.update(&buf[..n]);
+        Ok(n)
+    }
+}
@@ -0,0 +1,229 @@
+//! YMODEM's file header.
+//!
+//! A YMODEM sender opens each file with packet 0 instead of data. The packet
+//! holds the file's name, NUL-terminated, followed by its length in decimal
+//! and, optionally, more space-separated fields. After the last file, the
+//! sender ends the batch with a packet 0 holding an empty name.
+//!
+//! `Xmodem` receivers take the header if one arrives, so they can use the
+//! length to drop the last packet's padding, and end the batch after the
+//! first file. `Xmodem::receive_batch` takes every file in the batch instead,
+//! and `Xmodem::transmit_file` and `Xmodem::end_batch_transmit` send one.
+//! The state machines in `machine` do the same for their callers.
+
+use crate::io;
+use crate::machine::{Action, Receiver, Transmitter};
+use crate::{Xmodem, PACKET_1K_LEN, PACKET_LEN};
+
+/// What a YMODEM sender told us about a file before sending it.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct Header {
+    /// The file's length in bytes, if the sender gave one.
+    pub len: Option<u64>,
+}
+
+impl Header {
+    /// Parses the header in `payload`, the data of packet 0. Returns `None`
+    /// for the empty header that ends a batch.
+    pub fn parse(payload: &[u8]) -> Option<Header> {
+        let name_len = payload.iter().position(|&b| b == 0)?;
+        if name_len == 0 {
+            return None;
+        }
+
+        let fields = &payload[name_len + 1..];
+        let digits = fields.iter().take_while(|b| b.is_ascii_digit()).count();
+        let len = match fields.get(digits) {
+            Some(b' ') | Some(0) if digits > 0 => parse_decimal(&fields[..digits]),
+            _ => None,
+        };
+        Some(Header { len })
+    }
+}
+
+/// Returns the file name in `payload`, the data of packet 0, or `None` for
+/// the empty header or a name that isn't UTF-8.
+pub fn file_name(payload: &[u8]) -> Option<&str> {
+    let name_len = payload.iter().position(|&b| b == 0)?;
+    match core::str::from_utf8(&payload[..name_len]) {
+        Ok(name) if !name.is_empty() => Some(name),
+        _ => None,
+    }
+}
+
+/// Writes the header of the file `name`, `len` bytes long, into `buf`, which
+/// is zeroed first, and returns the length of the packet to send it in: 128,
+/// or 1024 if the name doesn't fit in 128 bytes.
+///
+/// # Errors
+///
+/// Returns an error of kind `InvalidInput` if `name` is empty, contains a
+/// NUL or doesn't fit in 1024 bytes, or if `buf` is shorter than the packet.
+pub fn write_header(buf: &mut [u8], name: &str, len: u64) -> io::Result<usize> {
+    let mut digits = [0u8; 20];
+    let mut n = digits.len();
+    let mut rest = len;
+    loop {
+        n -= 1;
+        digits[n] = b'0' + (rest % 10) as u8;
+        rest /= 10;
+        if rest == 0 {
+            break;
+        }
+    }
+    let digits = &digits[n..];
+
+    // The name and the length are each followed by a NUL.
+    let used = name.len() + 1 + digits.len() + 1;
+    let packet_len = if used <= PACKET_LEN { PACKET_LEN } else { PACKET_1K_LEN };
+    if name.is_empty() || name.contains('\0') || used > PACKET_1K_LEN || buf.len() < packet_len {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "file name can't go in a YMODEM header",
+        ));
+    }
+
+    buf.iter_mut().for_each(|b| *b = 0);
+    buf[..name.len()].copy_from_slice(name.as_bytes());
+    buf[name.len() + 1..used - 1].copy_from_slice(digits);
+    Ok(packet_len)
+}
+
+impl<T: io::Read + io::Write> Xmodem<T> {
+    /// Receives every file in a YMODEM batch. For each, `open` is called with
+    /// the file's name and header and returns the writer its data goes to,
+    /// trimmed to the length in the header as with `receive_to_writer`. The
+    /// writer is flushed once the file is complete.
+    ///
+    /// Returns the number of files received.
+    ///
+    /// If `open` or the transfer fails, the sender is told with `cancel`,
+    /// unless it cancelled the transfer itself.
+    ///
+    /// # Errors
+    ///
+    /// In addition to the errors of `receive_into`, returns an error of kind
+    /// `InvalidData` if a file's name isn't UTF-8, and any error `open`
+    /// returns.
+    pub fn receive_batch<F, W>(&mut self, mut open: F) -> io::Result<usize>
+    where
+        F: FnMut(&str, Header) -> io::Result<W>,
+        W: io::Write,
+    {
+        self.with_receiver(|this, rx| {
+            let result = this.receive_files(rx, &mut open);
+            this.cancel_on_error(result)
+        })
+    }
+
+    fn receive_files<F, W>(&mut self, rx: &mut Receiver, open: &mut F) -> io::Result<usize>
+    where
+        F: FnMut(&str, Header) -> io::Result<W>,
+        W: io::Write,
+    {
+        rx.set_batch(true);
+        let mut files = 0;
+        while let Some(mut into) = self.open_next(rx, open)? {
+            self.receive_data(rx, &mut into, true)?;
+            into.flush()?;
+            files += 1;
+        }
+        Ok(files)
+    }
+
+    /// Carries out `rx`'s actions until the sender offers its next file, then
+    /// opens it with `open` and asks for its data. Returns `None` once the
+    /// batch has ended.
+    fn open_next<F, W>(&mut self, rx: &mut Receiver, open: &mut F) -> io::Result<Option<W>>
+    where
+        F: FnMut(&str, Header) -> io::Result<W>,
+    {
+        loop {
+            let mut opened = None;
+            let wait = match rx.next_action() {
+                Action::Header(payload) => {
+                    let header = Header::parse(payload).unwrap_or_default();
+                    opened = Some(match file_name(payload) {
+                        Some(name) => open(name, header),
+                        None => Err(io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            "file name isn't UTF-8",
+                        )),
+                    });
+                    false
+                }
+                Action::Write(bytes) => {
+                    self.write_bytes(bytes)?;
+                    false
+                }
+                Action::Wait => true,
+                Action::Done => return Ok(None),
+                Action::Failed(e) => {
+                    self.cancelled = true;
+                    return Err(e);
+                }
+                Action::Deliver(_) | Action::NeedData | Action::ResumeRequested(_) => {
+                    unreachable!("no file is open")
+                }
+            };
+
+            if let Some(into) = opened {
+                let into = into?;
+                rx.receive_file();
+                return Ok(Some(into));
+            }
+            if wait {
+                self.feed(rx)?;
+            }
+        }
+    }
+
+    /// Sends the file `name`, the `len` bytes `data` yields, as the next
+    /// file of a YMODEM batch: its header in packet 0, then its data as
+    /// `transmit_from` sends it. Call `end_batch_transmit` after the last
+    /// file.
+    ///
+    /// Returns the number of bytes written, excluding padding zeroes.
+    ///
+    /// If the transfer fails after it has started, the receiver is told with
+    /// `cancel`, unless it cancelled the transfer itself.
+    ///
+    /// # Errors
+    ///
+    /// In addition to the errors of `transmit_from`, returns an error of kind
+    /// `InvalidInput` if `name` can't go in a header; see `write_header`.
+    pub fn transmit_file<R: io::Read>(&mut self, name: &str, len: u64, data: R) -> io::Result<usize> {
+        let mut header = [0u8; PACKET_1K_LEN];
+        self.with_transmitter(|this, tx| {
+            let result = write_header(&mut header, name, len)
+                .and_then(|n| this.transmit_header(tx, &header[..n]))
+                .and_then(|()| this.transmit_packets(tx, data));
+            this.cancel_on_error(result)
+        })
+    }
+
+    /// Ends a YMODEM batch by sending the empty header once the receiver asks
+    /// for the next file.
+    pub fn end_batch_transmit(&mut self) -> io::Result<()> {
+        self.with_transmitter(|this, tx| {
+            let result = this.transmit_header(tx, &[0u8; PACKET_LEN]);
+            this.cancel_on_error(result)
+        })
+    }
+
+    /// Sends `header` as packet 0 once the receiver asks for it: by starting
+    /// the transfer, or, after a file, with another request for its check.
+    /// Returns once the receiver has asked for the file's data, or has
+    /// acknowledged the empty header.
+    fn transmit_header(&mut self, tx: &mut Transmitter, header: &[u8]) -> io::Result<()> {
+        self.start(tx)?;
+        tx.send_header(header)?;
+        self.run(tx)
+    }
+}
+
+fn parse_decimal(digits: &[u8]) -> Option<u64> {
+    digits.iter().try_fold(0u64, |n, &d| {
+        n.checked_mul(10)?.checked_add((d - b'0') as u64)
+    })
+}
@@ -0,0 +1,653 @@
+//! A ZMODEM implementation for transferring a single file.
+//!
+//! Unlike XMODEM, the sender streams data subpackets without waiting for each
+//! one to be acknowledged. It only stops every `WINDOW` bytes to resynchronize.
+//! Binary frames are protected by CRC-32 when the receiver supports it. A
+//! receiver that sees a corrupt subpacket asks the sender to continue from the
+//! last good position, and a receiver that already holds part of the file can
+//! ask for the transfer to resume where it left off.
+
+use std::prelude::v1::*;
+
+use std::io;
+
+use crate::crc::{crc16, crc32, crc32_update};
+
+const ZPAD: u8 = b'*';
+const ZDLE: u8 = 0x18;
+const ZBIN: u8 = b'A';
+const ZHEX: u8 = b'B';
+const ZBIN32: u8 = b'C';
+
+// Frame types.
+const ZRQINIT: u8 = 0;
+const ZRINIT: u8 = 1;
+const ZSINIT: u8 = 2;
+const ZACK: u8 = 3;
+const ZFILE: u8 = 4;
+const ZSKIP: u8 = 5;
+const ZNAK: u8 = 6;
+const ZABORT: u8 = 7;
+const ZFIN: u8 = 8;
+const ZRPOS: u8 = 9;
+const ZDATA: u8 = 10;
+const ZEOF: u8 = 11;
+const ZFERR: u8 = 12;
+const ZCAN: u8 = 16;
+
+// Data subpacket terminators, sent after a `ZDLE`.
+/// End of frame; a header follows.
+const ZCRCE: u8 = b'h';
+/// Frame continues without acknowledgement.
+const ZCRCG: u8 = b'i';
+/// Frame continues; `ZACK` expected.
+const ZCRCQ: u8 = b'j';
+/// End of frame; `ZACK` expected.
+const ZCRCW: u8 = b'k';
+/// Escaped `0x7f`.
+const ZRUB0: u8 = b'l';
+/// Escaped `0xff`.
+const ZRUB1: u8 = b'm';
+
+// Receiver capability flags, in `ZF0` of `ZRINIT`.
+const CANFDX: u8 = 0x01;
+const CANOVIO: u8 = 0x02;
+const CANFC32: u8 = 0x20;
+
+/// `ZF0` of `ZFILE`: binary transfer.
+const ZCBIN: u8 = 1;
+
+const XON: u8 = 0x11;
+const XOFF: u8 = 0x13;
+const DLE: u8 = 0x10;
+
+/// Payload bytes per data subpacket.
+const SUBPACKET_LEN: usize = 1024;
+
+/// Bytes streamed before the sender waits for an acknowledgement.
+const WINDOW: usize = 16 * SUBPACKET_LEN;
+
+/// Longest data subpacket accepted, to bound memory use on garbage input.
+const MAX_SUBPACKET_LEN: usize = 8 * SUBPACKET_LEN;
+
+/// Errors and timeouts tolerated before a transfer is given up.
+const MAX_ERRORS: usize = 10;
+
+/// Number of consecutive `CAN`s with which a peer aborts a session.
+const ABORT_CANS: usize = 5;
+
+/// A frame header: its type and four bytes of flags or a position.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+struct Header {
+    kind: u8,
+    data: [u8; 4],
+}
+
+impl Header {
+    fn new(kind: u8, flags: u8) -> Header {
+        // `ZF0` is the last of the four bytes.
+        Header { kind, data: [0, 0, 0, flags] }
+    }
+
+    fn at(kind: u8, pos: u64) -> Header {
+        Header { kind, data: (pos as u32).to_le_bytes() }
+    }
+
+    fn pos(&self) -> u64 {
+        u32::from_le_bytes(self.data) as u64
+    }
+
+    fn flags(&self) -> u8 {
+        self.data[3]
+    }
+}
+
+/// Which CRC protects a binary frame.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Crc {
+    Crc16,
+    Crc32,
+}
+
+/// The end of a data subpacket.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Subpacket {
+    /// The subpacket was intact and ended with this terminator.
+    End(u8),
+    /// The subpacket was corrupt.
+    Corrupt,
+}
+
+/// The name and size of a file announced by the sender.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileInfo {
+    pub name: String,
+    pub size: Option<u64>,
+}
+
+/// The result of a successful `Zmodem::receive`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Received {
+    /// The file that was sent, or `None` if the sender had nothing to send.
+    pub file: Option<FileInfo>,
+    /// The number of bytes written to the sink, excluding the resumed part.
+    pub bytes: u64,
+}
+
+/// A ZMODEM session over `inner`.
+pub struct Zmodem<T> {
+    inner: T,
+    /// CRC of the last binary header received, which data subpackets use too.
+    crc: Crc,
+    /// Consecutive `CAN` bytes read.
+    cans: usize,
+}
+
+fn aborted() -> io::Error {
+    io::Error::new(io::ErrorKind::ConnectionAborted, "ZMODEM session aborted")
+}
+
+fn protocol_error(msg: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+fn is_timeout(e: &io::Error) -> bool {
+    e.kind() == io::ErrorKind::TimedOut || e.kind() == io::ErrorKind::WouldBlock
+}
+
+fn hex_value(c: u8) -> Option<u8> {
+    (c as char).to_digit(16).map(|d| d as u8)
+}
+
+/// Returns `true` if `byte` must be escaped with `ZDLE`.
+fn needs_escape(byte: u8) -> bool {
+    matches!(byte & 0x7f, ZDLE | DLE | XON | XOFF)
+}
+
+/// Parses the `ZFILE` subpacket: `name\0size [mtime [mode ...]]\0`.
+fn parse_file_info(data: &[u8]) -> FileInfo {
+    let mut fields = data.split(|&b| b == 0);
+    let name = String::from_utf8_lossy(fields.next().unwrap_or(&[])).into_owned();
+    let size = fields
+        .next()
+        .and_then(|info| core::str::from_utf8(info).ok())
+        .and_then(|info| info.split(' ').next())
+        .and_then(|size| size.parse().ok());
+    FileInfo { name, size }
+}
+
+impl Zmodem<()> {
+    /// Sends `data` as a file named `name` to the receiver `to`. Returns the
+    /// number of bytes sent, which is less than `data.len()` if the receiver
+    /// resumed a partial transfer and `0` if it skipped the file.
+    pub fn send<T: io::Read + io::Write>(to: T, name: &str, data: &[u8]) -> io::Result<usize> {
+        Zmodem::new(to).send_file(name, data)
+    }
+
+    /// Receives a file from `from` and writes it to `into`. If `offset` is not
+    /// zero, `into` already holds the first `offset` bytes from an earlier,
+    /// interrupted transfer, and the sender is asked to continue from there.
+    pub fn receive<T, W>(from: T, into: W, offset: u64) -> io::Result<Received>
+    where
+        T: io::Read + io::Write,
+        W: io::Write,
+    {
+        Zmodem::new(from).receive_file(into, offset)
+    }
+}
+
+impl<T: io::Read + io::Write> Zmodem<T> {
+    /// Returns a new session over `inner`.
+    pub fn new(inner: T) -> Zmodem<T> {
+        Zmodem { inner, crc: Crc::Crc16, cans: 0 }
+    }
+
+    /// Reads one raw byte, failing once the peer has sent `ABORT_CANS`
+    /// consecutive `CAN`s.
+    fn read_raw(&mut self) -> io::Result<u8> {
+        let mut buf = [0u8; 1];
+        self.inner.read_exact(&mut buf)?;
+        if buf[0] == ZDLE {
+            self.cans += 1;
+            if self.cans >= ABORT_CANS {
+                return Err(aborted());
+            }
+        } else {
+            self.cans = 0;
+        }
+        Ok(buf[0])
+    }
+
+    /// Reads one byte of escaped binary data. Returns `Err(terminator)` inside
+    /// the `Ok` if a subpacket terminator was read instead.
+    fn read_escaped(&mut self) -> io::Result<Result<u8, u8>> {
+        loop {
+            match self.read_raw()? {
+                // Flow control characters are never data.
+                XON | XOFF => continue,
+                ZDLE => {}
+                byte => return Ok(Ok(byte)),
+            }
+
+            loop {
+                return match self.read_raw()? {
+                    XON | XOFF => continue,
+                    ZRUB0 => Ok(Ok(0x7f)),
+                    ZRUB1 => Ok(Ok(0xff)),
+                    end @ (ZCRCE | ZCRCG | ZCRCQ | ZCRCW) => Ok(Err(end)),
+                    byte if byte & 0x60 == 0x40 => Ok(Ok(byte ^ 0x40)),
+                    _ => Err(protocol_error("bad ZDLE escape")),
+                };
+            }
+        }
+    }
+
+    /// Reads `buf.len()` escaped bytes.
+    fn read_escaped_exact(&mut self, buf: &mut [u8]) -> io::Result<()> {
+        for byte in buf.iter_mut() {
+            *byte = self
+                .read_escaped()?
+                .map_err(|_| protocol_error("unexpected subpacket end"))?;
+        }
+        Ok(())
+    }
+
+    fn write_escaped(&mut self, data: &[u8]) -> io::Result<()> {
+        let mut out = Vec::with_capacity(data.len() + data.len() / 8);
+        for &byte in data {
+            if needs_escape(byte) {
+                out.push(ZDLE);
+                out.push(byte ^ 0x40);
+            } else {
+                out.push(byte);
+            }
+        }
+        self.inner.write_all(&out)
+    }
+
+    /// Writes `header` in hex, which survives any link and is used for
+    /// everything but the headers that precede data.
+    fn write_hex_header(&mut self, header: Header) -> io::Result<()> {
+        const DIGITS: &[u8; 16] = b"0123456789abcdef";
+        let mut bytes = [0u8; 5];
+        bytes[0] = header.kind;
+        bytes[1..].copy_from_slice(&header.data);
+        let crc = crc16(&bytes);
+
+        let mut out = vec![ZPAD, ZPAD, ZDLE, ZHEX];
+        for &b in bytes.iter().chain(&crc.to_be_bytes()) {
+            out.push(DIGITS[(b >> 4) as usize]);
+            out.push(DIGITS[(b & 0xf) as usize]);
+        }
+        out.extend_from_slice(b"\r\x8a");
+        if header.kind != ZACK && header.kind != ZFIN {
+            out.push(XON);
+        }
+        self.inner.write_all(&out)
+    }
+
+    /// Writes `header` in binary, protected by `crc`.
+    fn write_bin_header(&mut self, header: Header, crc: Crc) -> io::Result<()> {
+        let mut bytes = [0u8; 5];
+        bytes[0] = header.kind;
+        bytes[1..].copy_from_slice(&header.data);
+
+        match crc {
+            Crc::Crc16 => {
+                self.inner.write_all(&[ZPAD, ZDLE, ZBIN])?;
+                self.write_escaped(&bytes)?;
+                self.write_escaped(&crc16(&bytes).to_be_bytes())
+            }
+            Crc::Crc32 => {
+                self.inner.write_all(&[ZPAD, ZDLE, ZBIN32])?;
+                self.write_escaped(&bytes)?;
+                self.write_escaped(&crc32(&bytes).to_le_bytes())
+            }
+        }
+    }
+
+    /// Writes a data subpacket ending with `end`, protected by `crc`.
+    fn write_subpacket(&mut self, data: &[u8], end: u8, crc: Crc) -> io::Result<()> {
+        self.write_escaped(data)?;
+        self.inner.write_all(&[ZDLE, end])?;
+        match crc {
+            Crc::Crc16 => {
+                let crc = crc16(&[data, &[end]].concat());
+                self.write_escaped(&crc.to_be_bytes())
+            }
+            Crc::Crc32 => {
+                let crc = crc32_update(crc32(data), &[end]);
+                self.write_escaped(&crc.to_le_bytes())
+            }
+        }
+    }
+
+    /// Reads the next header, skipping anything before it. Headers with a bad
+    /// CRC are skipped too.
+    fn read_header(&mut self) -> io::Result<Header> {
+        loop {
+            if self.read_raw()? != ZPAD {
+                continue;
+            }
+            let mut byte = self.read_raw()?;
+            while byte == ZPAD {
+                byte = self.read_raw()?;
+            }
+            if byte != ZDLE {
+                continue;
+            }
+
+            let header = match self.read_raw()? {
+                ZHEX => self.read_hex_header()?,
+                ZBIN => self.read_bin_header(Crc::Crc16)?,
+                ZBIN32 => self.read_bin_header(Crc::Crc32)?,
+                _ => None,
+            };
+            if let Some(header) = header {
+                return Ok(header);
+            }
+        }
+    }
+
+    fn read_hex_header(&mut self) -> io::Result<Option<Header>> {
+        let mut bytes = [0u8; 7];
+        for byte in bytes.iter_mut() {
+            let high = hex_value(self.read_raw()?);
+            let low = hex_value(self.read_raw()?);
+            match (high, low) {
+                (Some(high), Some(low)) => *byte = high << 4 | low,
+                _ => return Ok(None),
+            }
+        }
+
+        // Trailing CR LF (and XON) are skipped by the next header search.
+        let crc = u16::from_be_bytes([bytes[5], bytes[6]]);
+        if crc16(&bytes[..5]) != crc {
+            return Ok(None);
+        }
+        Ok(Some(Header { kind: bytes[0], data: [bytes[1], bytes[2], bytes[3], bytes[4]] }))
+    }
+
+    fn read_bin_header(&mut self, crc: Crc) -> io::Result<Option<Header>> {
+        let mut bytes = [0u8; 5];
+        self.read_escaped_exact(&mut bytes)?;
+        let intact = match crc {
+            Crc::Crc16 => {
+                let mut expected = [0u8; 2];
+                self.read_escaped_exact(&mut expected)?;
+                crc16(&bytes) == u16::from_be_bytes(expected)
+            }
+            Crc::Crc32 => {
+                let mut expected = [0u8; 4];
+                self.read_escaped_exact(&mut expected)?;
+                crc32(&bytes) == u32::from_le_bytes(expected)
+            }
+        };
+        if !intact {
+            return Ok(None);
+        }
+
+        self.crc = crc;
+        Ok(Some(Header { kind: bytes[0], data: [bytes[1], bytes[2], bytes[3], bytes[4]] }))
+    }
+
+    /// Reads a data subpacket into `data`, which is cleared first.
+    fn read_subpacket(&mut self, data: &mut Vec<u8>) -> io::Result<Subpacket> {
+        data.clear();
+        let end = loop {
+            match self.read_escaped() {
+                Ok(Ok(byte)) if data.len() < MAX_SUBPACKET_LEN => data.push(byte),
+                Ok(Ok(_)) => return Ok(Subpacket::Corrupt),
+                Ok(Err(end)) => break end,
+                Err(ref e) if e.kind() == io::ErrorKind::InvalidData => {
+                    return Ok(Subpacket::Corrupt)
+                }
+                Err(e) => return Err(e),
+            }
+        };
+
+        let intact = match self.crc {
+            Crc::Crc16 => {
+                let mut expected = [0u8; 2];
+                self.read_escaped_exact(&mut expected)?;
+                let mut crc_input = data.clone();
+                crc_input.push(end);
+                crc16(&crc_input) == u16::from_be_bytes(expected)
+            }
+            Crc::Crc32 => {
+                let mut expected = [0u8; 4];
+                self.read_escaped_exact(&mut expected)?;
+                crc32_update(crc32(data), &[end]) == u32::from_le_bytes(expected)
+            }
+        };
+
+        Ok(if intact { Subpacket::End(end) } else { Subpacket::Corrupt })
+    }
+
+    /// Reads the next header, resending `retry` after each timeout.
+    fn read_header_or_resend(&mut self, retry: Header) -> io::Result<Header> {
+        for _ in 0..MAX_ERRORS {
+            match self.read_header() {
+                Err(ref e) if is_timeout(e) => self.write_hex_header(retry)?,
+                result => return result,
+            }
+        }
+        Err(io::Error::new(io::ErrorKind::TimedOut, "ZMODEM peer not responding"))
+    }
+
+    /// Sends `data` as a file named `name`. See `Zmodem::send`.
+    pub fn send_file(&mut self, name: &str, data: &[u8]) -> io::Result<usize> {
+        let rqinit = Header::new(ZRQINIT, 0);
+        self.write_hex_header(rqinit)?;
+        let crc = loop {
+            let header = self.read_header_or_resend(rqinit)?;
+            match header.kind {
+                ZRINIT if header.flags() & CANFC32 != 0 => break Crc::Crc32,
+                ZRINIT => break Crc::Crc16,
+                ZABORT | ZCAN => return Err(aborted()),
+                _ => continue,
+            }
+        };
+
+        let mut info = Vec::from(name.as_bytes());
+        info.push(0);
+        info.extend_from_slice(format!("{} 0 0", data.len()).as_bytes());
+        info.push(0);
+
+        let mut errors = 0;
+        let mut pos = loop {
+            self.write_bin_header(Header::new(ZFILE, ZCBIN), crc)?;
+            self.write_subpacket(&info, ZCRCW, crc)?;
+            let header = self.read_header_or_resend(Header::new(ZRQINIT, 0))?;
+            match header.kind {
+                ZRPOS if header.pos() as usize <= data.len() => break header.pos() as usize,
+                ZSKIP => {
+                    self.finish()?;
+                    return Ok(0);
+                }
+                ZABORT | ZFERR | ZCAN => return Err(aborted()),
+                _ if errors < MAX_ERRORS => errors += 1,
+                _ => return Err(protocol_error("receiver rejected ZFILE")),
+            }
+        };
+        let start = pos;
+
+        'frame: loop {
+            if errors > MAX_ERRORS {
+                return Err(io::Error::new(io::ErrorKind::BrokenPipe, "too many errors"));
+            }
+
+            self.write_bin_header(Header::at(ZDATA, pos as u64), crc)?;
+            let mut unacked = 0;
+            while pos < data.len() {
+                let len = core::cmp::min(SUBPACKET_LEN, data.len() - pos);
+                let end = if pos + len == data.len() {
+                    ZCRCE
+                } else if unacked + len >= WINDOW {
+                    ZCRCW
+                } else {
+                    ZCRCG
+                };
+                self.write_subpacket(&data[pos..pos + len], end, crc)?;
+                pos += len;
+                unacked += len;
+
+                if end == ZCRCW {
+                    let header = self.read_header()?;
+                    match header.kind {
+                        ZACK => {}
+                        ZRPOS if header.pos() as usize <= data.len() => {
+                            pos = header.pos() as usize;
+                            errors += 1;
+                        }
+                        ZABORT | ZFERR | ZCAN => return Err(aborted()),
+                        _ => errors += 1,
+                    }
+                    // ZCRCW ends the frame.
+                    continue 'frame;
+                }
+            }
+
+            // An empty file still needs its (empty) frame to end.
+            if data.is_empty() {
+                self.write_subpacket(&[], ZCRCE, crc)?;
+            }
+
+            self.write_bin_header(Header::at(ZEOF, pos as u64), crc)?;
+            let header = self.read_header_or_resend(Header::at(ZEOF, pos as u64))?;
+            match header.kind {
+                ZRINIT => break,
+                ZRPOS if header.pos() as usize <= data.len() => {
+                    pos = header.pos() as usize;
+                    errors += 1;
+                }
+                ZABORT | ZFERR | ZCAN => return Err(aborted()),
+                _ => errors += 1,
+            }
+        }
+
+        self.finish()?;
+        Ok(data.len() - start)
+    }
+
+    /// Ends the session from the sender's side.
+    fn finish(&mut self) -> io::Result<()> {
+        let fin = Header::new(ZFIN, 0);
+        self.write_hex_header(fin)?;
+        loop {
+            let header = self.read_header_or_resend(fin)?;
+            if header.kind == ZFIN {
+                return self.inner.write_all(b"OO");
+            }
+        }
+    }
+
+    /// Receives one file into `into`. See `Zmodem::receive`.
+    pub fn receive_file<W: io::Write>(&mut self, mut into: W, offset: u64) -> io::Result<Received> {
+        let rinit = Header::new(ZRINIT, CANFDX | CANOVIO | CANFC32);
+        let mut subpacket = Vec::new();
+
+        self.write_hex_header(rinit)?;
+        let file = loop {
+            let header = self.read_header_or_resend(rinit)?;
+            match header.kind {
+                ZRQINIT => self.write_hex_header(rinit)?,
+                ZSINIT => {
+                    self.read_subpacket(&mut subpacket)?;
+                    self.write_hex_header(Header::new(ZACK, 0))?;
+                }
+                ZFILE => match self.read_subpacket(&mut subpacket)? {
+                    Subpacket::End(_) => break parse_file_info(&subpacket),
+                    Subpacket::Corrupt => self.write_hex_header(Header::new(ZNAK, 0))?,
+                },
+                ZFIN => {
+                    self.write_hex_header(Header::new(ZFIN, 0))?;
+                    return Ok(Received { file: None, bytes: 0 });
+                }
+                ZABORT | ZCAN => return Err(aborted()),
+                _ => continue,
+            }
+        };
+
+        let mut pos = offset;
+        let mut errors = 0;
+        self.write_hex_header(Header::at(ZRPOS, pos))?;
+        loop {
+            if errors > MAX_ERRORS {
+                return Err(io::Error::new(io::ErrorKind::BrokenPipe, "too many errors"));
+            }
+
+            let header = match self.read_header() {
+                Ok(header) => header,
+                Err(ref e) if is_timeout(e) => {
+                    errors += 1;
+                    self.write_hex_header(Header::at(ZRPOS, pos))?;
+                    continue;
+                }
+                Err(e) => return Err(e),
+            };
+
+            match header.kind {
+                ZDATA if header.pos() == pos => loop {
+                    match self.read_subpacket(&mut subpacket)? {
+                        Subpacket::End(end) => {
+                            into.write_all(&subpacket)?;
+                            pos += subpacket.len() as u64;
+                            match end {
+                                ZCRCW => {
+                                    self.write_hex_header(Header::at(ZACK, pos))?;
+                                    break;
+                                }
+                                ZCRCQ => self.write_hex_header(Header::at(ZACK, pos))?,
+                                ZCRCE => break,
+                                _ => {}
+                            }
+                        }
+                        Subpacket::Corrupt => {
+                            errors += 1;
+                            self.write_hex_header(Header::at(ZRPOS, pos))?;
+                            break;
+                        }
+                    }
+                },
+                ZEOF if header.pos() == pos => break,
+                // Data or an end for a position we don't have; ask again.
+                ZDATA | ZEOF => {
+                    errors += 1;
+                    self.write_hex_header(Header::at(ZRPOS, pos))?;
+                }
+                ZFILE => {
+                    // Our ZRPOS got lost.
+                    self.read_subpacket(&mut subpacket)?;
+                    self.write_hex_header(Header::at(ZRPOS, pos))?;
+                }
+                ZABORT | ZCAN | ZFIN => return Err(aborted()),
+                _ => continue,
+            }
+        }
+
+        // Decline any further files and wait for the sender to finish.
+        self.write_hex_header(rinit)?;
+        loop {
+            let header = self.read_header_or_resend(rinit)?;
+            match header.kind {
+                ZFIN => break,
+                ZFILE => {
+                    self.read_subpacket(&mut subpacket)?;
+                    self.write_hex_header(Header::new(ZSKIP, 0))?;
+                }
+                ZABORT | ZCAN => return Err(aborted()),
+                _ => continue,
+            }
+        }
+        self.write_hex_header(Header::new(ZFIN, 0))?;
+        // The final "OO" is a courtesy; don't fail the transfer over it.
+        let mut over = [0u8; 2];
+        let _ = self.inner.read_exact(&mut over);
+
+        Ok(Received { file: Some(file), bytes: pos - offset })
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,133 @@
+use super::*;
+use std::io::Cursor;
+use std::sync::mpsc::{channel, Receiver, Sender};
+
+/// One end of a byte pipe. Bytes written can be corrupted on their way by
+/// `corrupt`, which maps the index of each byte to an XOR mask.
+struct Pipe {
+    tx: Sender<u8>,
+    rx: Receiver<u8>,
+    sent: usize,
+    corrupt: fn(usize) -> u8,
+}
+
+fn pipe(corrupt: fn(usize) -> u8) -> (Pipe, Pipe) {
+    let ((tx1, rx1), (tx2, rx2)) = (channel(), channel());
+    (
+        Pipe { tx: tx1, rx: rx2, sent: 0, corrupt },
+        Pipe { tx: tx2, rx: rx1, sent: 0, corrupt: |_| 0 },
+    )
+}
+
+impl io::Read for Pipe {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self.rx.recv() {
+            Ok(byte) => {
+                buf[0] = byte;
+                Ok(1)
+            }
+            Err(_) => Ok(0),
+        }
+    }
+}
+
+impl io::Write for Pipe {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        for &byte in buf {
+            let _ = self.tx.send(byte ^ (self.corrupt)(self.sent));
+            self.sent += 1;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn data(len: usize) -> Vec<u8> {
+    // Include every byte value, so escaping is exercised.
+    (0..len).map(|i| (i * 7 + i / 256) as u8).collect()
+}
+
+fn transfer(corrupt: fn(usize) -> u8, data: Vec<u8>, offset: usize) -> (usize, Received, Vec<u8>) {
+    let (sender, receiver) = pipe(corrupt);
+    let expected = data.clone();
+    let tx = std::thread::spawn(move || Zmodem::send(sender, "kernel8.img", &data));
+    let rx = std::thread::spawn(move || {
+        let mut out = expected[..offset].to_vec();
+        Zmodem::receive(receiver, &mut out, offset as u64).map(|r| (r, out))
+    });
+
+    let sent = tx.join().expect("tx join okay").expect("tx okay");
+    let (received, out) = rx.join().expect("rx join okay").expect("rx okay");
+    (sent, received, out)
+}
+
+#[test]
+fn test_transfer() {
+    let input = data(3 * WINDOW + 100);
+    let (sent, received, out) = transfer(|_| 0, input.clone(), 0);
+    assert_eq!(sent, input.len());
+    assert_eq!(received.bytes, input.len() as u64);
+    let file = received.file.expect("file info");
+    assert_eq!(file.name, "kernel8.img");
+    assert_eq!(file.size, Some(input.len() as u64));
+    assert_eq!(out, input);
+}
+
+#[test]
+fn test_empty_file() {
+    let (sent, received, out) = transfer(|_| 0, vec![], 0);
+    assert_eq!((sent, received.bytes), (0, 0));
+    assert!(out.is_empty());
+}
+
+#[test]
+fn test_corruption_recovery() {
+    let input = data(2 * WINDOW);
+    // Flip bits in the middle of the data, well after the handshake.
+    let (sent, received, out) =
+        transfer(|i| if i == 5000 || i == 20000 { 0x01 } else { 0 }, input.clone(), 0);
+    assert_eq!(sent, input.len());
+    assert_eq!(received.bytes, input.len() as u64);
+    assert_eq!(out, input);
+}
+
+#[test]
+fn test_resume() {
+    let input = data(WINDOW + 500);
+    let (sent, received, out) = transfer(|_| 0, input.clone(), 4000);
+    assert_eq!(sent, input.len() - 4000);
+    assert_eq!(received.bytes, input.len() as u64 - 4000);
+    assert_eq!(out, input);
+}
+
+#[test]
+fn test_header_round_trip() {
+    let mut zmodem = Zmodem::new(Cursor::new(vec![]));
+    zmodem.write_hex_header(Header::at(ZRPOS, 0x1234)).unwrap();
+    zmodem.write_bin_header(Header::at(ZDATA, 0x18_1311), Crc::Crc32).unwrap();
+    zmodem.write_bin_header(Header::new(ZFILE, ZCBIN), Crc::Crc16).unwrap();
+
+    zmodem.inner.set_position(0);
+    assert_eq!(zmodem.read_header().unwrap(), Header::at(ZRPOS, 0x1234));
+    assert_eq!(zmodem.read_header().unwrap(), Header::at(ZDATA, 0x18_1311));
+    assert_eq!(zmodem.crc, Crc::Crc32);
+    assert_eq!(zmodem.read_header().unwrap(), Header::new(ZFILE, ZCBIN));
+    assert_eq!(zmodem.crc, Crc::Crc16);
+}
+
+#[test]
+fn test_abort_on_cancel() {
+    let mut zmodem = Zmodem::new(Cursor::new(vec![ZDLE; 8]));
+    let e = zmodem.read_header().expect_err("aborted");
+    assert_eq!(e.kind(), io::ErrorKind::ConnectionAborted);
+}
+
+#[test]
+fn test_parse_file_info() {
+    let info = parse_file_info(b"kernel8.img\x001234 13571234 100644\x00");
+    assert_eq!(info, FileInfo { name: "kernel8.img".into(), size: Some(1234) });
+    assert_eq!(parse_file_info(b"x\x00\x00").size, None);
+}
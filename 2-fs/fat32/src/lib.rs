@@ -29,5 +29,7 @@ mod util;
 
 pub mod vfat;
 pub mod traits;
+#[cfg(any(test, feature = "ktest"))]
+pub mod testing;
 
 pub use mbr::*;
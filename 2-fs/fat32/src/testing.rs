@@ -0,0 +1,38 @@
+//! Disk images for tests of this crate and of the code built on it.
+
+/// Returns the image of a disk with one freshly formatted FAT32 partition:
+/// 512-byte sectors and clusters, two FATs, and an empty root directory at
+/// cluster 2.
+pub fn blank_fat32() -> Vec<u8> {
+    const PARTITION_START: usize = 8;
+    const PARTITION_SECTORS: u32 = 600;
+    const RESERVED_SECTORS: usize = 2;
+    const SECTORS_PER_FAT: usize = 5;
+
+    let mut disk = vec![0u8; (PARTITION_START + PARTITION_SECTORS as usize) * 512];
+
+    let mbr = &mut disk[..512];
+    mbr[446 + 4] = 0xC;
+    mbr[446 + 8..446 + 12].copy_from_slice(&(PARTITION_START as u32).to_le_bytes());
+    mbr[446 + 12..446 + 16].copy_from_slice(&PARTITION_SECTORS.to_le_bytes());
+    mbr[510..].copy_from_slice(&[0x55, 0xAA]);
+
+    let bpb = &mut disk[PARTITION_START * 512..][..512];
+    bpb[11..13].copy_from_slice(&512u16.to_le_bytes());
+    bpb[13] = 1; // sectors per cluster
+    bpb[14..16].copy_from_slice(&(RESERVED_SECTORS as u16).to_le_bytes());
+    bpb[16] = 2; // number of FATs
+    bpb[32..36].copy_from_slice(&PARTITION_SECTORS.to_le_bytes());
+    bpb[36..40].copy_from_slice(&(SECTORS_PER_FAT as u32).to_le_bytes());
+    bpb[44..48].copy_from_slice(&2u32.to_le_bytes());
+    bpb[510..].copy_from_slice(&[0x55, 0xAA]);
+
+    for fat in 0..2 {
+        let start = (PARTITION_START + RESERVED_SECTORS + fat * SECTORS_PER_FAT) * 512;
+        for (i, entry) in [0x0FFFFFF8u32, 0x0FFFFFFF, 0x0FFFFFFF].iter().enumerate() {
+            disk[start + i * 4..start + i * 4 + 4].copy_from_slice(&entry.to_le_bytes());
+        }
+    }
+
+    disk
+}
@@ -1,7 +1,7 @@
 extern crate rand;
 
 use std::io::prelude::*;
-use std::io::Cursor;
+use std::io::{Cursor, SeekFrom};
 use std::path::Path;
 
 use vfat::{Shared, VFat, BiosParameterBlock};
@@ -349,3 +349,171 @@ fn shared_fs_is_sync_send_static() {
     fn f<T: Sync + Send + 'static>() {  }
     f::<Shared<VFat>>();
 }
+
+/// An in-memory disk that outlives the file systems mounted on it, so that
+/// what one wrote can be read back by another.
+#[derive(Clone)]
+struct MemoryDisk(::std::sync::Arc<::std::sync::Mutex<Vec<u8>>>);
+
+impl BlockDevice for MemoryDisk {
+    fn read_sector(&mut self, n: u64, buf: &mut [u8]) -> ::std::io::Result<usize> {
+        let disk = self.0.lock().unwrap();
+        let len = ::std::cmp::min(512, buf.len());
+        let start = n as usize * 512;
+        buf[..len].copy_from_slice(&disk[start..start + len]);
+        Ok(len)
+    }
+
+    fn write_sector(&mut self, n: u64, buf: &[u8]) -> ::std::io::Result<usize> {
+        let mut disk = self.0.lock().unwrap();
+        let len = ::std::cmp::min(512, buf.len());
+        let start = n as usize * 512;
+        disk[start..start + len].copy_from_slice(&buf[..len]);
+        Ok(len)
+    }
+}
+
+/// Returns a `MemoryDisk` holding `testing::blank_fat32()`.
+fn blank_fat32() -> MemoryDisk {
+    MemoryDisk(::std::sync::Arc::new(::std::sync::Mutex::new(::testing::blank_fat32())))
+}
+
+fn read_file(vfat: &Shared<VFat>, path: &str) -> Vec<u8> {
+    let mut data = Vec::new();
+    vfat.open_file(path).expect("file exists").read_to_end(&mut data).expect("read file");
+    data
+}
+
+#[test]
+#[cfg(feature = "write")]
+fn test_create_write_read_back() {
+    let disk = blank_fat32();
+    let vfat = VFat::from(disk.clone()).expect("mount blank file system");
+
+    let kernel: Vec<u8> = (0..3000u32).map(|i| (i * 7) as u8).collect();
+    let mut file = vfat.create_file("/kernel8.img").expect("create file");
+    file.write_all(&kernel[..1000]).unwrap();
+    file.write_all(&kernel[1000..]).unwrap();
+    file.sync().unwrap();
+
+    // Appending right at a cluster boundary needs a new cluster
+    let mut file = vfat.create_file("/CONFIG.TXT").expect("create file");
+    file.write_all(&[b'a'; 512]).unwrap();
+    file.write_all(&[b'b'; 10]).unwrap();
+    file.flush().unwrap();
+
+    vfat.create_file("/empty").expect("create file").sync().unwrap();
+
+    let e = vfat.create_file("/Kernel8.IMG").unwrap_err();
+    assert_eq!(e.kind(), ::std::io::ErrorKind::AlreadyExists);
+    let e = vfat.create_file("/missing/file").unwrap_err();
+    assert_eq!(e.kind(), ::std::io::ErrorKind::InvalidInput);
+
+    let vfat = VFat::from(disk).expect("mount written file system");
+    let mut names: Vec<String> = vfat.open_dir("/").unwrap().entries().unwrap()
+        .map(|e| e.name().to_string())
+        .collect();
+    names.sort();
+    assert_eq!(names, ["CONFIG.TXT", "empty", "kernel8.img"]);
+
+    assert_eq!(read_file(&vfat, "/kernel8.img"), kernel);
+    let config = read_file(&vfat, "/config.txt");
+    assert_eq!(config.len(), 522);
+    assert!(config[..512].iter().all(|&b| b == b'a') && config[512..].iter().all(|&b| b == b'b'));
+    assert_eq!(read_file(&vfat, "/empty"), []);
+}
+
+#[test]
+#[cfg(feature = "write")]
+fn test_overwrite_in_place() {
+    let disk = blank_fat32();
+    let vfat = VFat::from(disk.clone()).unwrap();
+
+    let mut file = vfat.create_file("/data.bin").unwrap();
+    file.write_all(&[1; 1024]).unwrap();
+    file.seek(SeekFrom::Start(500)).unwrap();
+    file.write_all(&[2; 100]).unwrap();
+    file.sync().unwrap();
+
+    let data = read_file(&VFat::from(disk).unwrap(), "/data.bin");
+    assert_eq!(data.len(), 1024);
+    assert!(data[..500].iter().all(|&b| b == 1));
+    assert!(data[500..600].iter().all(|&b| b == 2));
+    assert!(data[600..].iter().all(|&b| b == 1));
+}
+
+#[test]
+#[cfg(feature = "write")]
+fn test_directory_grows() {
+    let disk = blank_fat32();
+    let vfat = VFat::from(disk.clone()).unwrap();
+
+    // Each name takes three entries; a 512-byte cluster holds sixteen
+    for i in 0..20 {
+        let name = format!("/a file with a long name {}.txt", i);
+        let mut file = vfat.create_file(&name).unwrap();
+        file.write_all(name.as_bytes()).unwrap();
+        file.sync().unwrap();
+    }
+    vfat.create_file("/last").unwrap().sync().unwrap();
+
+    let vfat = VFat::from(disk).unwrap();
+    assert_eq!(vfat.open_dir("/").unwrap().entries().unwrap().count(), 21);
+    for i in 0..20 {
+        let name = format!("/a file with a long name {}.txt", i);
+        assert_eq!(read_file(&vfat, &name), name.as_bytes());
+    }
+}
+
+#[test]
+#[cfg(feature = "write")]
+fn test_remove_file() {
+    let disk = blank_fat32();
+    let vfat = VFat::from(disk.clone()).unwrap();
+
+    let old = vec![0xAA; 200_000];
+    for &(name, data) in [("/old.img", &old[..]), ("/keep", b"keep")].iter() {
+        let mut file = vfat.create_file(name).unwrap();
+        file.write_all(data).unwrap();
+        file.sync().unwrap();
+    }
+    vfat.remove("/old.img", false).unwrap();
+    assert_eq!(vfat.open("/old.img").unwrap_err().kind(), ::std::io::ErrorKind::NotFound);
+
+    // The removed file's clusters are free again: the file system only has
+    // room for one file of this size
+    let mut file = vfat.create_file("/new.img").unwrap();
+    file.write_all(&vec![0x55; 200_000]).unwrap();
+    file.sync().unwrap();
+
+    let e = vfat.remove("/", false).unwrap_err();
+    assert_eq!(e.kind(), ::std::io::ErrorKind::Other);
+
+    let vfat = VFat::from(disk).unwrap();
+    assert!(read_file(&vfat, "/new.img").iter().all(|&b| b == 0x55));
+    assert_eq!(read_file(&vfat, "/keep"), b"keep");
+    assert_eq!(vfat.open("/old.img").unwrap_err().kind(), ::std::io::ErrorKind::NotFound);
+}
+
+#[test]
+fn test_unsupported_operations() {
+    let vfat = VFat::from(blank_fat32()).unwrap();
+
+    let e = vfat.create_dir("/boot", false).unwrap_err();
+    assert_eq!(e.kind(), ::std::io::ErrorKind::Other);
+    let e = vfat.rename("/a", "/b").unwrap_err();
+    assert_eq!(e.kind(), ::std::io::ErrorKind::Other);
+}
+
+#[test]
+#[cfg(not(feature = "write"))]
+fn test_read_only() {
+    let disk = blank_fat32();
+    let vfat = VFat::from(disk.clone()).unwrap();
+
+    let e = vfat.create_file("/a").unwrap_err();
+    assert_eq!(e.kind(), ::std::io::ErrorKind::Other);
+    let e = vfat.remove("/a", false).unwrap_err();
+    assert_eq!(e.kind(), ::std::io::ErrorKind::Other);
+    assert!(disk.0.lock().unwrap()[..] == ::testing::blank_fat32()[..]);
+}
@@ -100,6 +100,36 @@ impl CachedDevice {
         self.get_helper(sector).map(|e| e.data.as_slice())
     }
 
+    /// Writes every dirty sector back to the disk, in sector order, and marks
+    /// them clean.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if writing a sector fails. Sectors written before the
+    /// failure are marked clean; the rest stay dirty.
+    #[cfg(feature = "write")]
+    pub fn sync(&mut self) -> io::Result<()> {
+        let mut dirty: Vec<u64> = self
+            .cache
+            .iter()
+            .filter(|(_, e)| e.dirty)
+            .map(|(&sector, _)| sector)
+            .collect();
+        dirty.sort();
+
+        for sector in dirty {
+            let (physical_start_sector, factor) = self.virtual_to_physical(sector);
+            let entry = self.cache.get_mut(&sector).unwrap();
+            let chunk = entry.data.len() / factor as usize;
+            for i in 0..factor {
+                let data = &entry.data[i as usize * chunk..(i as usize + 1) * chunk];
+                self.device.write_sector(physical_start_sector + i, data)?;
+            }
+            entry.dirty = false;
+        }
+        Ok(())
+    }
+
     fn get_helper(&mut self, sector: u64) -> io::Result<&mut CacheEntry> {
         let (physical_start_sector, factor) = self.virtual_to_physical(sector);
 
@@ -113,7 +143,7 @@ impl CachedDevice {
                 }
                 Ok(v.insert(CacheEntry {
                     data: buf,
-                    dirty: true,
+                    dirty: false,
                 }))
             }
         }
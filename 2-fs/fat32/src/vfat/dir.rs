@@ -8,6 +8,9 @@ use util::VecExt;
 use vfat::{Attributes, Date, Metadata, Time, Timestamp};
 use vfat::{Cluster, Entry, File, Shared, VFat};
 
+#[cfg(feature = "write")]
+mod write;
+
 #[derive(Debug)]
 pub struct Dir {
     pub(super) long_name: Option<String>,
@@ -18,6 +21,14 @@ pub struct Dir {
     pub(super) vfat: Shared<VFat>,
 }
 
+/// Where a file's directory entry is.
+#[derive(Debug, Copy, Clone)]
+pub(crate) struct EntrySlot {
+    pub dir: Cluster,        // first cluster of the directory holding the entry
+    pub index: usize,        // index of the regular entry in the directory
+    pub lfn_entries: usize,  // number of LFN entries right before the regular entry
+}
+
 #[repr(C, packed)]
 #[derive(Copy, Clone)]
 pub struct VFatRegularDirEntry {
@@ -222,6 +233,8 @@ impl Dir {
     pub fn name(&self) -> &str {
         self.long_name.as_ref().unwrap_or(&self.short_name)
     }
+
+
 }
 
 impl traits::Dir for Dir {
@@ -242,6 +255,7 @@ impl traits::Dir for Dir {
         Ok(EntryIter {
             entries: unsafe { buf.cast() },
             next: 0,
+            dir: self.start_cluster,
             vfat: self.vfat.clone(),
         })
     }
@@ -250,6 +264,7 @@ impl traits::Dir for Dir {
 pub struct EntryIter {
     entries: Vec<VFatDirEntry>,
     next: usize,
+    dir: Cluster, // first cluster of the directory being iterated
     vfat: Shared<VFat>,
 }
 
@@ -264,11 +279,18 @@ impl Iterator for EntryIter {
                 0xE5 => self.next += 1, // 0xE5: unused/deleted entry
                 _ => {
                     let mut long_name: Option<String> = None;
+                    let mut lfn_entries = 0;
                     if unsafe { entry.unknown.attributes.lfn() } {
                         let (name, lfn_entry_num) = self.parse_lfn(self.next).unwrap();
                         self.next += lfn_entry_num; // make self.next point to the regular entry after lfn entries
-                        long_name = Some(name)
+                        long_name = Some(name);
+                        lfn_entries = lfn_entry_num;
                     }
+                    let slot = EntrySlot {
+                        dir: self.dir,
+                        index: self.next,
+                        lfn_entries,
+                    };
 
                     let regular = unsafe { self.entries[self.next].regular };
                     self.next += 1;
@@ -292,6 +314,7 @@ impl Iterator for EntryIter {
                             absolute_offset: 0,
                             start_cluster: regular.first_cluster(),
                             curr_cluster: regular.first_cluster(),
+                            slot,
                         }));
                     }
                 }
@@ -0,0 +1,279 @@
+//! Creating and deleting directory entries.
+
+use std::cmp::min;
+use std::convert::TryInto;
+use std::ffi::OsStr;
+use std::io;
+use std::iter;
+use std::mem::size_of;
+
+use util::VecExt;
+use vfat::{Attributes, Date, Time};
+use vfat::{Cluster, File, VFat};
+
+use super::{Dir, EntrySlot, VFatDirEntry, VFatLfnDirEntry, VFatRegularDirEntry};
+
+// IDs of unused entries, see `VFatUnknownDirEntry`.
+const END_OF_DIR: u8 = 0x00;
+const DELETED: u8 = 0xE5;
+
+/// Characters allowed in short names besides upper-case letters and digits.
+const SHORT_NAME_SPECIALS: &[u8] = b"$%'-_@~`!(){}^#&";
+
+impl VFatRegularDirEntry {
+    // Checksum of the 8.3 name, stored in the name's LFN entries
+    fn checksum(short_name: &[u8; 11]) -> u8 {
+        short_name
+            .iter()
+            .fold(0u8, |sum, &b| ((sum & 1) << 7).wrapping_add(sum >> 1).wrapping_add(b))
+    }
+}
+
+impl VFatLfnDirEntry {
+    // Returns the LFN entries holding `name`, in the order they're stored
+    fn entries_for(name: &str, checksum: u8) -> Vec<VFatLfnDirEntry> {
+        let mut units: Vec<u16> = name.encode_utf16().collect();
+        let count = (units.len() + 12) / 13;
+        if units.len() % 13 != 0 {
+            // Terminated with 0x0000, then padded with 0xFFFF
+            units.push(0x0000);
+            units.resize(count * 13, 0xFFFF);
+        }
+
+        (0..count)
+            .rev()
+            .map(|i| {
+                let part = &units[i * 13..(i + 1) * 13];
+                let (mut name_part1, mut name_part2, mut name_part3) = ([0; 5], [0; 6], [0; 2]);
+                name_part1.copy_from_slice(&part[..5]);
+                name_part2.copy_from_slice(&part[5..11]);
+                name_part3.copy_from_slice(&part[11..]);
+                VFatLfnDirEntry {
+                    sequence_number: (i + 1) as u8 | if i == count - 1 { 0x40 } else { 0 },
+                    name_part1,
+                    attributes: Attributes::LFN,
+                    r#type: 0,
+                    checksum,
+                    name_part2,
+                    first_cluster: 0,
+                    name_part3,
+                }
+            })
+            .collect()
+    }
+}
+
+impl EntrySlot {
+    /// Points the regular entry at the first cluster `cluster` and sets its
+    /// file size to `size`.
+    pub(crate) fn update(&self, vfat: &mut VFat, cluster: Cluster, size: u32) -> io::Result<()> {
+        let entry = unsafe { &mut vfat.dir_entry_mut(self.dir, self.index)?.regular };
+        entry.first_cluster_high = (cluster.inner() >> 16) as u16;
+        entry.first_cluster_low = cluster.inner() as u16;
+        entry.file_size = size;
+        Ok(())
+    }
+
+    /// Marks the regular entry and its LFN entries deleted.
+    pub(crate) fn delete(&self, vfat: &mut VFat) -> io::Result<()> {
+        for index in self.index - self.lfn_entries..=self.index {
+            vfat.dir_entry_mut(self.dir, index)?.unknown.id = DELETED;
+        }
+        Ok(())
+    }
+}
+
+impl Dir {
+    /// Creates an empty file named `name` in `self` and returns it.
+    ///
+    /// The file gets an 8.3 short name derived from `name`, and LFN entries
+    /// unless `name` is a short name already. The directory grows by a
+    /// cluster if it doesn't have room for the entries.
+    ///
+    /// # Errors
+    ///
+    /// If `name` can't be a file name, an error of `InvalidInput` is returned.
+    ///
+    /// If an entry named `name` exists in `self`, compared case-insensitively,
+    /// an error of `AlreadyExists` is returned.
+    pub fn create_file<P: AsRef<OsStr>>(&self, name: P) -> io::Result<File> {
+        let name = name
+            .as_ref()
+            .to_str()
+            .filter(|name| is_valid_name(name))
+            .ok_or(io::Error::new(io::ErrorKind::InvalidInput, "invalid file name"))?;
+
+        match self.find(name) {
+            Ok(_) => return Err(io::ErrorKind::AlreadyExists.into()),
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+
+        let mut vfat = self.vfat.borrow_mut();
+        let mut buf: Vec<u8> = Vec::new();
+        vfat.read_chain(self.start_cluster, &mut buf)?;
+        let entries: Vec<VFatDirEntry> = unsafe { buf.cast() };
+
+        let (short_name, exact) = short_name(name, &entries);
+        let lfn = if exact {
+            Vec::new()
+        } else {
+            VFatLfnDirEntry::entries_for(name, VFatRegularDirEntry::checksum(&short_name))
+        };
+        let regular = VFatRegularDirEntry {
+            file_name: short_name[..8].try_into().unwrap(),
+            file_ext: short_name[8..].try_into().unwrap(),
+            attributes: Attributes::NEW_FILE,
+            reserved: 0,
+            created_in_10ms: 0,
+            created_time: Time::zero(),
+            created_date: Date::EPOCH,
+            accessed_date: Date::EPOCH,
+            first_cluster_high: 0,
+            modified_time: Time::zero(),
+            modified_date: Date::EPOCH,
+            first_cluster_low: 0,
+            file_size: 0,
+        };
+
+        // Grow the directory until the entries fit
+        let start = free_slots(&entries, lfn.len() + 1);
+        let per_cluster = vfat.cluster_size() / size_of::<VFatDirEntry>();
+        let mut capacity = entries.len();
+        if start + lfn.len() + 1 > capacity {
+            let mut last = vfat.last_cluster(self.start_cluster)?;
+            let zeroes = vec![0; vfat.cluster_size()];
+            while start + lfn.len() + 1 > capacity {
+                last = vfat.alloc_cluster(Some(last))?;
+                vfat.write_cluster(last, 0, &zeroes)?;
+                capacity += per_cluster;
+            }
+        }
+
+        let new_entries = lfn
+            .iter()
+            .map(|&long_filename| VFatDirEntry { long_filename })
+            .chain(iter::once(VFatDirEntry { regular }));
+        for (i, entry) in new_entries.enumerate() {
+            *vfat.dir_entry_mut(self.start_cluster, start + i)? = entry;
+        }
+
+        Ok(File {
+            long_name: if exact { None } else { Some(name.to_string()) },
+            short_name: regular.name()?,
+            metadata: regular.metadata(),
+            file_size: 0,
+
+            vfat: self.vfat.clone(),
+            absolute_offset: 0,
+            start_cluster: Cluster::from(0),
+            curr_cluster: Cluster::from(0),
+            slot: EntrySlot {
+                dir: self.start_cluster,
+                index: start + lfn.len(),
+                lfn_entries: lfn.len(),
+            },
+        })
+    }
+}
+
+
+// Whether `name` can be the name of a file: not empty, `.` or `..`, at most
+// 255 UTF-16 units long, and without control characters or any of `"*/:<>?\|`.
+fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name != "."
+        && name != ".."
+        && name.encode_utf16().count() <= 255
+        && !name.chars().any(|c| c < ' ' || "\"*/:<>?\\|".contains(c))
+}
+
+// Returns the index of the first run of `count` unused entries in `entries`.
+// The run may go past the end of `entries`, where the directory has to grow.
+fn free_slots(entries: &[VFatDirEntry], count: usize) -> usize {
+    let mut run = 0;
+    for (i, entry) in entries.iter().enumerate() {
+        match unsafe { entry.unknown.id } {
+            END_OF_DIR => return i - run, // every entry from here on is unused
+            DELETED => {
+                run += 1;
+                if run == count {
+                    return i + 1 - run;
+                }
+            }
+            _ => run = 0,
+        }
+    }
+    entries.len() - run
+}
+
+// Returns the 8.3 short name (padded with spaces, without the dot) for a new
+// file named `name` in a directory with `entries`, and whether it holds
+// `name` exactly, in which case the file needs no LFN entries.
+//
+// Names that only lose their case keep their short name as is, like
+// `CONFIG.TXT` for `config.txt`; other names get a numeric tail such as
+// `KERNEL~1.IMG`.
+fn short_name(name: &str, entries: &[VFatDirEntry]) -> ([u8; 11], bool) {
+    let (base, ext) = match name.rfind('.') {
+        Some(i) if i > 0 => (&name[..i], &name[i + 1..]),
+        _ => (name, ""),
+    };
+    let (base, base_lossy) = short_name_part(base);
+    let (ext, ext_lossy) = short_name_part(ext);
+
+    let mut short_name = [b' '; 11];
+    let ext_len = min(ext.len(), 3);
+    short_name[8..8 + ext_len].copy_from_slice(&ext[..ext_len]);
+
+    let lossy = base_lossy || ext_lossy || base.is_empty() || base.len() > 8 || ext.len() > 3;
+    if !lossy {
+        short_name[..base.len()].copy_from_slice(&base);
+        if !is_short_name_taken(&short_name, entries) {
+            return (short_name, !name.bytes().any(|b| b.is_ascii_lowercase()));
+        }
+    }
+
+    for n in 1.. {
+        let tail = format!("~{}", n);
+        let keep = min(base.len(), 8 - tail.len());
+        short_name[..8].copy_from_slice(b"        ");
+        short_name[..keep].copy_from_slice(&base[..keep]);
+        short_name[keep..keep + tail.len()].copy_from_slice(tail.as_bytes());
+        if !is_short_name_taken(&short_name, entries) {
+            break;
+        }
+    }
+    (short_name, false)
+}
+
+// Converts part of a name into characters allowed in short names and
+// upper-cases it. Also returns whether anything but the case was lost.
+fn short_name_part(part: &str) -> (Vec<u8>, bool) {
+    let mut lossy = false;
+    let mut converted = Vec::new();
+    for c in part.chars() {
+        match c {
+            ' ' | '.' => lossy = true,
+            c if c.is_ascii_alphanumeric() || (c.is_ascii() && SHORT_NAME_SPECIALS.contains(&(c as u8))) => {
+                converted.push(c.to_ascii_uppercase() as u8)
+            }
+            _ => {
+                lossy = true;
+                converted.push(b'_');
+            }
+        }
+    }
+    (converted, lossy)
+}
+
+fn is_short_name_taken(short_name: &[u8; 11], entries: &[VFatDirEntry]) -> bool {
+    entries
+        .iter()
+        .take_while(|entry| unsafe { entry.unknown.id } != END_OF_DIR)
+        .filter(|entry| unsafe { entry.unknown.id != DELETED && !entry.unknown.attributes.lfn() })
+        .any(|entry| {
+            let regular = unsafe { entry.regular };
+            regular.file_name[..] == short_name[..8] && regular.file_ext[..] == short_name[8..]
+        })
+}
@@ -2,10 +2,14 @@ use std::cmp::{max, min};
 use std::io::{self, SeekFrom};
 
 use traits;
+use vfat::dir::EntrySlot;
 use vfat::{Cluster, Metadata, Shared, VFat};
 
 use super::Status;
 
+#[cfg(feature = "write")]
+mod write;
+
 #[derive(Debug)]
 pub struct File {
     // FIXME: Fill me in.
@@ -16,8 +20,9 @@ pub struct File {
 
     pub(super) vfat: Shared<VFat>,
     pub(super) absolute_offset: u32, // current absolute offset in file, in bytes
-    pub(super) start_cluster: Cluster,
+    pub(super) start_cluster: Cluster, // 0 if the file is empty and has no clusters
     pub(super) curr_cluster: Cluster,
+    pub(super) slot: EntrySlot,
 }
 
 impl File {
@@ -28,8 +33,14 @@ impl File {
 
 // FIXME: Implement `traits::File` (and its supertraits) for `File`.
 impl traits::File for File {
+    /// Writes the file's size and first cluster to its directory entry and
+    /// everything changed in the file system to disk.
     fn sync(&mut self) -> io::Result<()> {
-        todo!()
+        #[cfg(feature = "write")]
+        return self.write_back();
+        // Without the `write` feature, nothing can have changed.
+        #[cfg(not(feature = "write"))]
+        return Ok(());
     }
 
     fn size(&self) -> u64 {
@@ -83,13 +94,14 @@ impl io::Read for File {
     }
 }
 
+#[cfg(not(feature = "write"))]
 impl io::Write for File {
-    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-        todo!()
+    fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+        Err(super::vfat::read_only())
     }
 
     fn flush(&mut self) -> io::Result<()> {
-        todo!()
+        Ok(())
     }
 }
 
@@ -0,0 +1,69 @@
+//! Writing to files.
+
+use std::cmp::{max, min};
+use std::io;
+
+use traits;
+use vfat::{File, Status};
+
+impl File {
+    /// Does the work of `traits::File::sync()`.
+    pub(super) fn write_back(&mut self) -> io::Result<()> {
+        let mut vfat = self.vfat.borrow_mut();
+        self.slot.update(&mut vfat, self.start_cluster, self.file_size)?;
+        vfat.sync()
+    }
+}
+
+impl io::Write for File {
+    /// Writes `buf` at the current offset, growing the file and allocating
+    /// clusters as needed. The directory entry is only updated on `flush()`
+    /// or `sync()`.
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // File sizes are 32-bit
+        let buf = &buf[..min(buf.len(), (u32::MAX - self.absolute_offset) as usize)];
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        let mut vfat = self.vfat.borrow_mut();
+        let cluster_size = vfat.cluster_size();
+        let mut total = 0;
+        while total < buf.len() {
+            let offset_in_cluster = self.absolute_offset as usize % cluster_size;
+            if self.start_cluster.inner() == 0 {
+                self.start_cluster = vfat.alloc_cluster(None)?;
+                self.curr_cluster = self.start_cluster;
+            } else if offset_in_cluster == 0
+                && self.absolute_offset > 0
+                && self.absolute_offset >= self.file_size
+            {
+                // At the end of a file whose size is a multiple of the cluster
+                // size, `curr_cluster` is the last cluster, before the offset.
+                // Chains are as long as their files need, so there's no next
+                // cluster to move on to.
+                if let Status::Eoc(_) = vfat.fat_entry(self.curr_cluster)?.status() {
+                    self.curr_cluster = vfat.alloc_cluster(Some(self.curr_cluster))?;
+                }
+            }
+
+            let n = vfat.write_cluster(self.curr_cluster, offset_in_cluster, &buf[total..])?;
+            self.absolute_offset += n as u32;
+            self.file_size = max(self.file_size, self.absolute_offset);
+            total += n;
+
+            // Move on to the next cluster like `read()` does
+            if self.absolute_offset as usize % cluster_size == 0 {
+                if let Status::Data(next) = vfat.fat_entry(self.curr_cluster)?.status() {
+                    self.curr_cluster = next;
+                }
+            }
+        }
+
+        Ok(total)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        traits::File::sync(self)
+    }
+}
@@ -11,6 +11,11 @@ pub struct Date(u16);
 // The date on which the file was created.
 // Bits 15 - 9: Year (0 = 1980). Bits 8 - 5: Month. Bits 4 - 0: Day.
 impl Date {
+    /// 1980-01-01, the earliest date FAT can hold. New files get it, since
+    /// there's no clock to ask for the current date.
+    #[cfg(feature = "write")]
+    pub(crate) const EPOCH: Date = Date(0x21);
+
     pub fn year(&self) -> usize {
         1980 + (self.0 >> 9 & 0x7F) as usize
     }
@@ -61,6 +66,14 @@ impl Time {
 pub struct Attributes(u8);
 
 impl Attributes {
+    /// The attributes of a newly created file: only ARCHIVE.
+    #[cfg(feature = "write")]
+    pub(crate) const NEW_FILE: Attributes = Attributes(0x20);
+
+    /// The attributes marking a long file name entry.
+    #[cfg(feature = "write")]
+    pub(crate) const LFN: Attributes = Attributes(0x0F);
+
     pub fn read_only(&self) -> bool {
         self.0 & 0x01 != 0
     }
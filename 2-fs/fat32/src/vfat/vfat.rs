@@ -12,6 +12,9 @@ use vfat::{Cluster, Dir, Entry, Error, FatEntry, File, Shared, Status};
 
 use super::cluster;
 
+#[cfg(feature = "write")]
+mod write;
+
 const FAT_ENTRY_SIZE: u64 = size_of::<FatEntry>() as u64;
 
 #[derive(Debug)]
@@ -20,9 +23,12 @@ pub struct VFat {
     bytes_per_sector: u16,
     sectors_per_cluster: u8,
     sectors_per_fat: u32,
+    number_of_fats: u8,
     fat_start_sector: u64,
     data_start_sector: u64,
     root_dir_cluster: Cluster,
+    cluster_count: u32, // number of data clusters, numbered from 2
+    next_free: u32,     // where to start looking for a free cluster
 }
 
 impl VFat {
@@ -57,6 +63,14 @@ impl VFat {
                     + bpb.sectors_per_cluster as u64 * max_clusters
         );
 
+        let data_sectors = bpb.total_sectors_32 as u64
+            - bpb.reserved_sectors as u64
+            - bpb.number_of_fats as u64 * bpb.sectors_per_fat_32 as u64;
+        let cluster_count = min(
+            data_sectors / bpb.sectors_per_cluster as u64,
+            max_clusters - 2, // the first two FAT entries are reserved
+        );
+
         let partition = Partition {
             start: pe.relative_sector as u64, // physical starting sector of partition
             sector_size: bpb.bytes_per_sector as u64,
@@ -67,11 +81,14 @@ impl VFat {
             bytes_per_sector: bpb.bytes_per_sector,
             sectors_per_cluster: bpb.sectors_per_cluster,
             sectors_per_fat: bpb.sectors_per_fat_32,
+            number_of_fats: bpb.number_of_fats,
             fat_start_sector: pe.relative_sector as u64 + bpb.reserved_sectors as u64,
             data_start_sector: pe.relative_sector as u64
                 + bpb.reserved_sectors as u64
                 + bpb.number_of_fats as u64 * bpb.sectors_per_fat_32 as u64,
             root_dir_cluster: Cluster::from(bpb.root_dir_cluster),
+            cluster_count: cluster_count as u32,
+            next_free: 2,
         }))
     }
 
@@ -230,15 +247,21 @@ impl<'a> FileSystem for &'a Shared<VFat> {
         }
     }
 
-    fn create_file<P: AsRef<Path>>(self, _path: P) -> io::Result<Self::File> {
-        unimplemented!("read only file system")
+    fn create_file<P: AsRef<Path>>(self, path: P) -> io::Result<Self::File> {
+        #[cfg(feature = "write")]
+        return write::create_file(self, path.as_ref());
+        #[cfg(not(feature = "write"))]
+        return Err(read_only());
     }
 
     fn create_dir<P>(self, _path: P, _parents: bool) -> io::Result<Self::Dir>
     where
         P: AsRef<Path>,
     {
-        unimplemented!("read only file system")
+        Err(io::Error::new(
+            io::ErrorKind::Other,
+            "creating directories is not supported",
+        ))
     }
 
     fn rename<P, Q>(self, _from: P, _to: Q) -> io::Result<()>
@@ -246,10 +269,23 @@ impl<'a> FileSystem for &'a Shared<VFat> {
         P: AsRef<Path>,
         Q: AsRef<Path>,
     {
-        unimplemented!("read only file system")
+        Err(io::Error::new(
+            io::ErrorKind::Other,
+            "renaming is not supported",
+        ))
     }
 
-    fn remove<P: AsRef<Path>>(self, _path: P, _children: bool) -> io::Result<()> {
-        unimplemented!("read only file system")
+    fn remove<P: AsRef<Path>>(self, path: P, _children: bool) -> io::Result<()> {
+        #[cfg(feature = "write")]
+        return write::remove(self, path.as_ref());
+        #[cfg(not(feature = "write"))]
+        return Err(read_only());
     }
 }
+
+/// The error for changes to the file system when built without the `write`
+/// feature.
+#[cfg(not(feature = "write"))]
+pub(super) fn read_only() -> io::Error {
+    io::Error::new(io::ErrorKind::Other, "read only file system")
+}
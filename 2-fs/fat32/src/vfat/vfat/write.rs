@@ -0,0 +1,199 @@
+//! Writing to a FAT32 file system: allocating and freeing clusters, and
+//! creating and removing files.
+
+use core::convert::TryInto;
+use std::cmp::min;
+use std::io::{self, Write};
+use std::mem::size_of;
+use std::path::Path;
+
+use traits::FileSystem;
+use util::SliceExt;
+use vfat::dir::VFatDirEntry;
+use vfat::{Cluster, Entry, File, Shared, Status, VFat};
+
+use super::FAT_ENTRY_SIZE;
+
+impl VFat {
+    //
+    //  * A method to write into a cluster from an offset, the counterpart of
+    //    `read_cluster`.
+    //
+    pub fn write_cluster(&mut self, cluster: Cluster, offset: usize, buf: &[u8]) -> io::Result<usize> {
+        match self.fat_entry(cluster)?.status() {
+            Status::Data(_) | Status::Eoc(_) => {
+                let start_sector = self.cluster_start_sector(cluster.inner());
+
+                let sector_offset = offset / (self.bytes_per_sector as usize);
+                let offset_in_sector = offset % (self.bytes_per_sector as usize); // in bytes
+
+                assert!(sector_offset < self.sectors_per_cluster as usize);
+
+                let mut total = 0;
+                let mut buf = buf;
+                for i in sector_offset as u64..self.sectors_per_cluster as u64 {
+                    let sector = self.device.get_mut(start_sector + i)?;
+                    let n = if i == sector_offset as u64 {
+                        (&mut sector[offset_in_sector..]).write(buf)?
+                    } else {
+                        (&mut sector[..]).write(buf)?
+                    };
+                    total += n;
+                    buf = &buf[n..];
+                    if buf.len() == 0 {
+                        return Ok(total);
+                    }
+                }
+                Ok(total)
+            }
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("cluster {} is not data cluster", cluster.inner()),
+            )),
+        }
+    }
+
+    /// Sets the FAT entry for `cluster` to `value` in every copy of the FAT.
+    /// The top four bits of the entry are reserved and kept as they are.
+    fn set_fat_entry(&mut self, cluster: Cluster, value: u32) -> io::Result<()> {
+        let sector_offset = (cluster.inner() as u64 * FAT_ENTRY_SIZE) / (self.bytes_per_sector as u64);
+        let byte_offset = ((cluster.inner() as u64 * FAT_ENTRY_SIZE) % (self.bytes_per_sector as u64)) as usize;
+
+        for fat in 0..self.number_of_fats as u64 {
+            let sector = self.device.get_mut(
+                self.fat_start_sector + fat * self.sectors_per_fat as u64 + sector_offset,
+            )?;
+            let entry = &mut sector[byte_offset..byte_offset + FAT_ENTRY_SIZE as usize];
+            let old = u32::from_le_bytes(entry.try_into().unwrap());
+            entry.copy_from_slice(&(old & 0xF000_0000 | value & 0x0FFF_FFFF).to_le_bytes());
+        }
+        Ok(())
+    }
+
+    /// Allocates a free cluster as the last one of its chain. If `prev` is
+    /// given, the chain ending at `prev` is extended with it.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of `Other` if there are no free clusters left.
+    pub fn alloc_cluster(&mut self, prev: Option<Cluster>) -> io::Result<Cluster> {
+        let end = self.cluster_count + 2;
+        let start = if self.next_free < end { self.next_free } else { 2 };
+        for raw in (start..end).chain(2..start) {
+            let cluster = Cluster::from(raw);
+            if self.fat_entry(cluster)?.status() == Status::Free {
+                self.set_fat_entry(cluster, 0x0FFF_FFFF)?;
+                if let Some(prev) = prev {
+                    self.set_fat_entry(prev, raw)?;
+                }
+                self.next_free = raw + 1;
+                return Ok(cluster);
+            }
+        }
+
+        Err(io::Error::new(io::ErrorKind::Other, "file system is full"))
+    }
+
+    /// Frees every cluster in the chain starting at `start`.
+    pub fn free_chain(&mut self, start: Cluster) -> io::Result<()> {
+        let mut curr = start;
+        loop {
+            let status = self.fat_entry(curr)?.status();
+            self.set_fat_entry(curr, 0)?;
+            self.next_free = min(self.next_free, curr.inner());
+            match status {
+                Status::Eoc(_) => return Ok(()),
+                Status::Data(next) => curr = next,
+                _ => return Err(io::ErrorKind::InvalidData.into()),
+            }
+        }
+    }
+
+    /// Returns the last cluster of the chain starting at `start`.
+    pub fn last_cluster(&mut self, start: Cluster) -> io::Result<Cluster> {
+        let mut curr = start;
+        loop {
+            match self.fat_entry(curr)?.status() {
+                Status::Eoc(_) => return Ok(curr),
+                Status::Data(next) => curr = next,
+                _ => return Err(io::ErrorKind::InvalidData.into()),
+            }
+        }
+    }
+
+    /// Returns a reference to entry `index` of the directory starting at
+    /// cluster `dir`, pointing directly into a cached sector, which is marked
+    /// dirty.
+    pub(crate) fn dir_entry_mut(&mut self, dir: Cluster, index: usize) -> io::Result<&mut VFatDirEntry> {
+        let entry_size = size_of::<VFatDirEntry>();
+        let offset = index * entry_size;
+
+        let mut cluster = dir;
+        for _ in 0..offset / self.cluster_size() {
+            cluster = match self.fat_entry(cluster)?.status() {
+                Status::Data(next) => next,
+                _ => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("entry {} is beyond the end of its directory", index),
+                    ))
+                }
+            };
+        }
+
+        let offset = offset % self.cluster_size();
+        let bytes_per_sector = self.bytes_per_sector as usize;
+        let sector = self.cluster_start_sector(cluster.inner()) + (offset / bytes_per_sector) as u64;
+        let sector = self.device.get_mut(sector)?;
+        let start = offset % bytes_per_sector;
+        let bytes = &mut sector[start..start + entry_size];
+        Ok(unsafe { &mut bytes.cast_mut::<VFatDirEntry>()[0] })
+    }
+
+    /// Writes everything changed so far back to the disk.
+    pub fn sync(&mut self) -> io::Result<()> {
+        self.device.sync()
+    }
+}
+
+pub(super) fn create_file(fs: &Shared<VFat>, path: &Path) -> io::Result<File> {
+    let (parent, name) = match (path.parent(), path.file_name()) {
+        (Some(parent), Some(name)) => (parent, name),
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("invalid path: no file name: {:?}", path),
+            ))
+        }
+    };
+
+    match fs.open(parent) {
+        Ok(Entry::Dir(dir)) => dir.create_file(name),
+        Ok(Entry::File(_)) => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "invalid path: not directory",
+        )),
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("invalid path: no such directory: {:?}", parent),
+        )),
+        Err(e) => Err(e),
+    }
+}
+
+pub(super) fn remove(fs: &Shared<VFat>, path: &Path) -> io::Result<()> {
+    match fs.open(path)? {
+        Entry::File(file) => {
+            let mut vfat = fs.borrow_mut();
+            file.slot.delete(&mut vfat)?;
+            if file.start_cluster.inner() != 0 {
+                vfat.free_chain(file.start_cluster)?;
+            }
+            vfat.sync()
+        }
+        Entry::Dir(_) => Err(io::Error::new(
+            io::ErrorKind::Other,
+            "removing directories is not supported",
+        )),
+    }
+}
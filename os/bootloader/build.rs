@@ -1,4 +1,11 @@
 pub fn main() {
+    if ::std::env::var("TARGET").unwrap() == "aarch64-unknown-none" {
+        // `libsd` is shared with the kernel.
+        println!("cargo:rustc-link-search=native=../kernel/ext");
+        println!("cargo:rustc-link-lib=static=sd");
+        println!("cargo:rerun-if-changed=../kernel/ext/libsd.a");
+    }
+
     println!("cargo:rerun-if-changed=ext/layout.ld");
     println!("cargo:rerun-if-changed=ext/init.S");
 }
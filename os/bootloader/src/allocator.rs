@@ -0,0 +1,49 @@
+//! The bootloader's heap, which the FAT32 driver needs to load a kernel from
+//! the SD card.
+//!
+//! Nothing allocated is needed once the kernel is loaded, so this is a bump
+//! allocator that never frees.
+
+use core::alloc::{GlobalAlloc, Layout};
+use core::ptr;
+
+use crate::mutex::Mutex;
+
+/// Size of the heap, which starts right after the bootloader.
+const HEAP_SIZE: usize = 64 * 1024 * 1024;
+
+extern "C" {
+    /// The end of the bootloader's image, from `layout.ld`.
+    static _end: u8;
+}
+
+#[global_allocator]
+static ALLOCATOR: Allocator = Allocator(Mutex::new(None));
+
+/// Allocates from the heap by bumping the next free address, which is `None`
+/// until the first allocation.
+struct Allocator(Mutex<Option<usize>>);
+
+/// Returns the end of the heap, the highest address the bootloader uses.
+pub fn heap_end() -> usize {
+    ptr::addr_of!(_end) as usize + HEAP_SIZE
+}
+
+unsafe impl GlobalAlloc for Allocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let heap_start = ptr::addr_of!(_end) as usize;
+        let mut next = self.0.lock();
+        let current = next.unwrap_or(heap_start);
+
+        let start = (current + layout.align() - 1) & !(layout.align() - 1);
+        match start.checked_add(layout.size()) {
+            Some(end) if end <= heap_start + HEAP_SIZE => {
+                *next = Some(end);
+                start as *mut u8
+            }
+            _ => ptr::null_mut(),
+        }
+    }
+
+    unsafe fn dealloc(&self, _: *mut u8, _: Layout) {}
+}
@@ -0,0 +1,79 @@
+//! `boot.cfg` on the SD card's FAT32 partition, for changing how the
+//! bootloader boots without rebuilding it:
+//!
+//! ```text
+//! # `key = value` lines; `#` starts a comment. Every key is optional.
+//! kernel = /kernel8.img
+//! fallback = /kernel8-fallback.img
+//! baud = 115200
+//! timeout = 5
+//! cmdline = root=initrd
+//! ```
+
+use std::string::{String, ToString};
+
+/// Where the configuration file is.
+pub const PATH: &str = "/boot.cfg";
+
+/// How the bootloader boots.
+#[derive(Debug)]
+pub struct Config {
+    /// The kernel on the SD card, loaded when nothing is sent over the UART.
+    pub kernel: String,
+    /// The kernel loaded from the SD card instead once several boots in a
+    /// row have hung, such as the last one known to work.
+    pub fallback: String,
+    /// The console's baud rate, which transfers start at as well.
+    pub baud: u32,
+    /// How long to wait for a transfer over the UART before loading the
+    /// kernel from the SD card, in seconds. The switch happens between
+    /// receive attempts, so it may take a few seconds longer.
+    pub timeout: u64,
+    /// The command line to pass the kernel instead of the firmware's, which
+    /// comes from `cmdline.txt`.
+    pub cmdline: Option<String>,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            kernel: "/kernel8.img".to_string(),
+            fallback: "/kernel8-fallback.img".to_string(),
+            baud: 115_200,
+            timeout: 5,
+            cmdline: None,
+        }
+    }
+}
+
+impl Config {
+    /// Parses the contents of a configuration file. Keys it doesn't set keep
+    /// their defaults.
+    ///
+    /// # Errors
+    ///
+    /// Returns a description of the first line that isn't a comment or a
+    /// known key with a valid value.
+    pub fn parse(text: &str) -> Result<Config, String> {
+        let mut config = Config::default();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let error = |e: &str| format!("line {}: {}", i + 1, e);
+            let (key, value) = line.split_once('=').ok_or_else(|| error("expected `key = value`"))?;
+            let value = value.trim();
+            match key.trim() {
+                "kernel" => config.kernel = value.to_string(),
+                "fallback" => config.fallback = value.to_string(),
+                "baud" => config.baud = value.parse().map_err(|_| error("invalid baud rate"))?,
+                "timeout" => config.timeout = value.parse().map_err(|_| error("invalid timeout"))?,
+                "cmdline" => config.cmdline = Some(value.to_string()),
+                _ => return Err(error("unknown key")),
+            }
+        }
+        Ok(config)
+    }
+}
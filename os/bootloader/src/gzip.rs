@@ -0,0 +1,345 @@
+//! A minimal decoder for gzip, enough to unpack a kernel that
+//! `ttywrite --compress gzip` sent or that `gzip` compressed on the SD card.
+//!
+//! Only the first member is decoded. Its CRC-32 is skipped rather than
+//! verified, like the checksums of LZ4 frames; its length is checked, which
+//! catches a stream that ended in the wrong place.
+
+/// Starts every gzip member: the two ID bytes and the DEFLATE method.
+const MAGIC: [u8; 3] = [0x1f, 0x8b, 8];
+
+/// Header flags.
+const FHCRC: u8 = 1 << 1;
+const FEXTRA: u8 = 1 << 2;
+const FNAME: u8 = 1 << 3;
+const FCOMMENT: u8 = 1 << 4;
+const FRESERVED: u8 = 0b1110_0000;
+
+/// Longest Huffman code DEFLATE uses.
+const MAX_BITS: usize = 15;
+
+/// Number of literal/length and distance codes.
+const LITLEN_CODES: usize = 288;
+const DIST_CODES: usize = 30;
+
+/// Base lengths and extra bits of length codes 257 through 285.
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115,
+    131, 163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+
+/// Base distances and extra bits of distance codes.
+const DIST_BASE: [u16; DIST_CODES] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DIST_EXTRA: [u8; DIST_CODES] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+
+/// The order code length code lengths are stored in by dynamic blocks.
+const CODE_LENGTH_ORDER: [usize; 19] =
+    [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+
+#[derive(Debug)]
+pub enum Error {
+    /// The member ended early.
+    Truncated,
+    /// The member isn't valid gzip or DEFLATE.
+    Corrupt,
+    /// The decompressed data doesn't fit into the output.
+    TooLarge,
+}
+
+/// Returns `true` if `data` starts with a gzip member.
+pub fn is_member(data: &[u8]) -> bool {
+    data.starts_with(&MAGIC)
+}
+
+/// Decompresses the gzip member at the start of `src` into `dst` and returns
+/// the number of bytes written. Anything after the member, such as the
+/// padding of the last XMODEM packet, is ignored.
+pub fn decompress(src: &[u8], dst: &mut [u8]) -> Result<usize, Error> {
+    if !is_member(src) {
+        return Err(Error::Corrupt);
+    }
+    let mut input = Bits::new(src);
+    input.skip(3)?;
+    let flags = input.byte()?;
+    if flags & FRESERVED != 0 {
+        return Err(Error::Corrupt);
+    }
+    // Modification time, extra flags and operating system.
+    input.skip(6)?;
+    if flags & FEXTRA != 0 {
+        let len = u16::from_le_bytes([input.byte()?, input.byte()?]);
+        input.skip(len as usize)?;
+    }
+    if flags & FNAME != 0 {
+        while input.byte()? != 0 {}
+    }
+    if flags & FCOMMENT != 0 {
+        while input.byte()? != 0 {}
+    }
+    if flags & FHCRC != 0 {
+        input.skip(2)?;
+    }
+
+    let mut written = 0;
+    loop {
+        let last = input.bits(1)? == 1;
+        written = match input.bits(2)? {
+            0 => stored(&mut input, dst, written)?,
+            1 => {
+                let (litlen, dist) = fixed_codes()?;
+                codes(&mut input, dst, written, &litlen, &dist)?
+            }
+            2 => {
+                let (litlen, dist) = dynamic_codes(&mut input)?;
+                codes(&mut input, dst, written, &litlen, &dist)?
+            }
+            _ => return Err(Error::Corrupt),
+        };
+        if last {
+            break;
+        }
+    }
+
+    // The CRC-32 and length of the uncompressed data, modulo 2^32.
+    input.align();
+    input.skip(4)?;
+    let len = u32::from_le_bytes([input.byte()?, input.byte()?, input.byte()?, input.byte()?]);
+    if len != written as u32 {
+        return Err(Error::Corrupt);
+    }
+    Ok(written)
+}
+
+/// Copies the stored block at `input` into `dst` at `pos` and returns the
+/// position after it.
+fn stored(input: &mut Bits, dst: &mut [u8], pos: usize) -> Result<usize, Error> {
+    input.align();
+    let len = u16::from_le_bytes([input.byte()?, input.byte()?]);
+    let nlen = u16::from_le_bytes([input.byte()?, input.byte()?]);
+    if len != !nlen {
+        return Err(Error::Corrupt);
+    }
+
+    let len = len as usize;
+    let out = dst.get_mut(pos..pos + len).ok_or(Error::TooLarge)?;
+    out.copy_from_slice(input.take(len)?);
+    Ok(pos + len)
+}
+
+/// Returns the literal/length and distance codes of fixed blocks.
+fn fixed_codes() -> Result<(Huffman, Huffman), Error> {
+    let mut lengths = [0u8; LITLEN_CODES];
+    lengths[..144].iter_mut().for_each(|len| *len = 8);
+    lengths[144..256].iter_mut().for_each(|len| *len = 9);
+    lengths[256..280].iter_mut().for_each(|len| *len = 7);
+    lengths[280..].iter_mut().for_each(|len| *len = 8);
+    Ok((Huffman::new(&lengths)?, Huffman::new(&[5; DIST_CODES])?))
+}
+
+/// Reads the literal/length and distance codes at the start of a dynamic
+/// block.
+fn dynamic_codes(input: &mut Bits) -> Result<(Huffman, Huffman), Error> {
+    let litlen_count = input.bits(5)? as usize + 257;
+    let dist_count = input.bits(5)? as usize + 1;
+    let code_length_count = input.bits(4)? as usize + 4;
+    if litlen_count > 286 || dist_count > DIST_CODES {
+        return Err(Error::Corrupt);
+    }
+
+    let mut code_lengths = [0u8; 19];
+    for &symbol in &CODE_LENGTH_ORDER[..code_length_count] {
+        code_lengths[symbol] = input.bits(3)? as u8;
+    }
+    let code_length_code = Huffman::new(&code_lengths)?;
+
+    // Literal/length and distance code lengths are run together, and runs
+    // may cross from one into the other.
+    let mut lengths = [0u8; 286 + DIST_CODES];
+    let total = litlen_count + dist_count;
+    let mut i = 0;
+    while i < total {
+        let (len, repeat) = match code_length_code.decode(input)? {
+            len @ 0..=15 => (len as u8, 1),
+            16 if i > 0 => (lengths[i - 1], 3 + input.bits(2)? as usize),
+            17 => (0, 3 + input.bits(3)? as usize),
+            18 => (0, 11 + input.bits(7)? as usize),
+            _ => return Err(Error::Corrupt),
+        };
+        if i + repeat > total {
+            return Err(Error::Corrupt);
+        }
+        lengths[i..i + repeat].iter_mut().for_each(|l| *l = len);
+        i += repeat;
+    }
+
+    // Without an end-of-block code, the block can't end.
+    if lengths[256] == 0 {
+        return Err(Error::Corrupt);
+    }
+    let litlen = Huffman::new(&lengths[..litlen_count])?;
+    let dist = Huffman::new(&lengths[litlen_count..total])?;
+    Ok((litlen, dist))
+}
+
+/// Decodes the rest of a compressed block at `input` with the codes `litlen`
+/// and `dist` into `dst` at `pos` and returns the position after the output.
+fn codes(
+    input: &mut Bits,
+    dst: &mut [u8],
+    mut pos: usize,
+    litlen: &Huffman,
+    dist: &Huffman,
+) -> Result<usize, Error> {
+    loop {
+        let symbol = litlen.decode(input)? as usize;
+        if symbol < 256 {
+            *dst.get_mut(pos).ok_or(Error::TooLarge)? = symbol as u8;
+            pos += 1;
+            continue;
+        }
+        if symbol == 256 {
+            return Ok(pos);
+        }
+
+        let code = symbol - 257;
+        if code >= LENGTH_BASE.len() {
+            return Err(Error::Corrupt);
+        }
+        let len = LENGTH_BASE[code] as usize + input.bits(LENGTH_EXTRA[code] as u32)? as usize;
+        let code = dist.decode(input)? as usize;
+        if code >= DIST_CODES {
+            return Err(Error::Corrupt);
+        }
+        let offset = DIST_BASE[code] as usize + input.bits(DIST_EXTRA[code] as u32)? as usize;
+        if offset > pos {
+            return Err(Error::Corrupt);
+        }
+        if pos + len > dst.len() {
+            return Err(Error::TooLarge);
+        }
+
+        // The match may overlap the bytes it produces, so copy one at a time.
+        for i in pos..pos + len {
+            dst[i] = dst[i - offset];
+        }
+        pos += len;
+    }
+}
+
+/// A canonical Huffman code, stored as the number of codes of each length
+/// and the symbols ordered by code.
+struct Huffman {
+    counts: [u16; MAX_BITS + 1],
+    symbols: [u16; LITLEN_CODES],
+}
+
+impl Huffman {
+    /// Builds the code whose symbols' code lengths are `lengths`, where `0`
+    /// means the symbol isn't used. Incomplete codes are allowed, since a
+    /// block with a single distance has one.
+    fn new(lengths: &[u8]) -> Result<Huffman, Error> {
+        let mut code = Huffman { counts: [0; MAX_BITS + 1], symbols: [0; LITLEN_CODES] };
+        for &len in lengths {
+            code.counts[len as usize] += 1;
+        }
+
+        let mut left = 1i32;
+        for len in 1..=MAX_BITS {
+            left = (left << 1) - code.counts[len] as i32;
+            if left < 0 {
+                return Err(Error::Corrupt);
+            }
+        }
+
+        let mut offsets = [0u16; MAX_BITS + 1];
+        for len in 1..MAX_BITS {
+            offsets[len + 1] = offsets[len] + code.counts[len];
+        }
+        for (symbol, &len) in lengths.iter().enumerate() {
+            if len != 0 {
+                code.symbols[offsets[len as usize] as usize] = symbol as u16;
+                offsets[len as usize] += 1;
+            }
+        }
+        Ok(code)
+    }
+
+    /// Reads one code from `input` and returns its symbol.
+    fn decode(&self, input: &mut Bits) -> Result<u16, Error> {
+        // Codes of each length follow the last code of the previous length,
+        // shifted left by one; `first` is the first code of length `len`.
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+        for len in 1..=MAX_BITS {
+            code |= input.bits(1)? as i32;
+            let count = self.counts[len] as i32;
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(Error::Corrupt)
+    }
+}
+
+/// The unread rest of a gzip member, read a byte or, least significant bit
+/// first, a few bits at a time.
+struct Bits<'a> {
+    data: &'a [u8],
+    buf: u32,
+    count: u32,
+}
+
+impl<'a> Bits<'a> {
+    fn new(data: &'a [u8]) -> Bits<'a> {
+        Bits { data, buf: 0, count: 0 }
+    }
+
+    /// Reads the next `n` bits, at most 16.
+    fn bits(&mut self, n: u32) -> Result<u32, Error> {
+        while self.count < n {
+            let (&byte, rest) = self.data.split_first().ok_or(Error::Truncated)?;
+            self.data = rest;
+            self.buf |= (byte as u32) << self.count;
+            self.count += 8;
+        }
+        let bits = self.buf & ((1 << n) - 1);
+        self.buf >>= n;
+        self.count -= n;
+        Ok(bits)
+    }
+
+    /// Drops the bits left of the current byte.
+    fn align(&mut self) {
+        self.buf = 0;
+        self.count = 0;
+    }
+
+    /// Reads the next `n` whole bytes, which must start at a byte boundary.
+    fn take(&mut self, n: usize) -> Result<&'a [u8], Error> {
+        if self.data.len() < n {
+            return Err(Error::Truncated);
+        }
+        let (head, tail) = self.data.split_at(n);
+        self.data = tail;
+        Ok(head)
+    }
+
+    fn byte(&mut self) -> Result<u8, Error> {
+        self.take(1).map(|b| b[0])
+    }
+
+    fn skip(&mut self, n: usize) -> Result<(), Error> {
+        self.take(n).map(|_| ())
+    }
+}
@@ -1,4 +1,4 @@
-use core::{alloc::GlobalAlloc, arch::asm};
+use core::arch::asm;
 
 use crate::console::kprint;
 
@@ -20,18 +20,3 @@ pub extern "Rust" fn panic_impl(info: &core::panic::PanicInfo) -> ! {
         unsafe { asm!("wfe") }
     }
 }
-
-#[global_allocator]
-static DUMMY_ALLOCATOR: DummyAllocator = DummyAllocator;
-
-struct DummyAllocator;
-
-unsafe impl GlobalAlloc for DummyAllocator {
-    unsafe fn alloc(&self, _: core::alloc::Layout) -> *mut u8 {
-        unimplemented!()
-    }
-
-    unsafe fn dealloc(&self, _: *mut u8, _: core::alloc::Layout) {
-        unimplemented!()
-    }
-}
@@ -0,0 +1,93 @@
+//! Blink codes on the ACT LED, so that a board without a serial console
+//! attached still shows what the bootloader is doing.
+//!
+//! There are no interrupts to drive the blinking, so `poll` has to be called
+//! regularly; `Serial` calls it while it waits for the UART.
+
+use pi::{mailbox, timer};
+
+use crate::mutex::Mutex;
+
+/// The ACT LED's pin on the Pi 3's GPIO expander, which only the firmware
+/// can reach.
+const ACT_LED: u32 = 130;
+
+/// What the bootloader is doing, as shown on the ACT LED.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Status {
+    /// Waiting for a transfer: a slow, even blink.
+    Waiting,
+    /// Receiving a kernel: a fast flicker.
+    Receiving,
+    /// A kernel failed verification: three short flashes, then a pause.
+    VerifyFailed,
+    /// Starting a kernel: steadily on.
+    Jumping,
+}
+
+impl Status {
+    /// Returns how long the LED is on, then off, then on again and so on
+    /// before the pattern repeats, in milliseconds.
+    fn pattern(self) -> &'static [u64] {
+        match self {
+            Status::Waiting => &[1000, 1000],
+            Status::Receiving => &[50, 50],
+            Status::VerifyFailed => &[150, 150, 150, 150, 150, 1000],
+            Status::Jumping => &[1000],
+        }
+    }
+}
+
+/// Where the LED is in the pattern of the current status.
+struct Blinker {
+    status: Option<Status>,
+    /// Index of the current step in the status's pattern.
+    step: usize,
+    /// When the current step started, in microseconds.
+    since: u64,
+    /// Whether the LED is on, if it's been set at all.
+    lit: Option<bool>,
+}
+
+static BLINKER: Mutex<Blinker> =
+    Mutex::new(Blinker { status: None, step: 0, since: 0, lit: None });
+
+/// Starts showing `status`, from the start of its pattern unless it's
+/// already being shown.
+pub fn set(status: Status) {
+    let mut blinker = BLINKER.lock();
+    if blinker.status != Some(status) {
+        blinker.status = Some(status);
+        blinker.step = 0;
+        blinker.since = timer::current_time();
+    }
+    blinker.update();
+}
+
+/// Moves the LED along the current status's pattern.
+pub fn poll() {
+    let mut blinker = BLINKER.lock();
+    let pattern = match blinker.status {
+        Some(status) => status.pattern(),
+        None => return,
+    };
+
+    let now = timer::current_time();
+    while now - blinker.since >= pattern[blinker.step] * 1000 {
+        blinker.since += pattern[blinker.step] * 1000;
+        blinker.step = (blinker.step + 1) % pattern.len();
+    }
+    blinker.update();
+}
+
+impl Blinker {
+    /// Switches the LED on for even steps and off for odd ones. The firmware
+    /// is only asked when that changes, since it takes a while to answer.
+    fn update(&mut self) {
+        let lit = self.step % 2 == 0;
+        if self.lit != Some(lit) {
+            mailbox::property(mailbox::SET_GPIO_STATE, &mut [ACT_LED, lit as u32]);
+            self.lit = Some(lit);
+        }
+    }
+}
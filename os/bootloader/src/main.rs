@@ -17,9 +17,18 @@ use std::prelude::v1::*;
 // #[macro_use]
 // extern crate alloc;
 
+mod allocator;
+mod config;
 mod console;
+mod gzip;
 mod lang_items;
+mod led;
+mod lz4;
+mod monitor;
 mod mutex;
+mod retry;
+mod sd;
+mod serial;
 
 use pi;
 use xmodem;
@@ -29,21 +38,222 @@ use core::{
     fmt::Write,
 };
 
+use crate::config::Config;
 use crate::console::{kprint, kprintln};
+use crate::led::Status;
+use crate::serial::Serial;
+use pi::timer;
+use pi::uart::MiniUart;
+use xmodem::image;
+use xmodem::io;
+use xmodem::loader::{self, Message};
+use xmodem::Progress;
+use xmodem::verify::Trailer;
 global_asm!(include_str!("../ext/init.S"));
 
-/// Start address of the binary to load and of the bootloader.
-const BINARY_START_ADDR: usize = 0x80000;
-const BOOTLOADER_START_ADDR: usize = 0x4000000;
+use pi::atags::Atags;
+use pi::common::{
+    BOOTLOADER_START as BOOTLOADER_START_ADDR, IO_BASE, KERNEL_START as BINARY_START_ADDR,
+};
 
 /// Pointer to where the loaded binary expects to be laoded.
 const BINARY_START: *mut u8 = BINARY_START_ADDR as *mut u8;
 
-/// Free space between the bootloader and the loaded binary's start address.
-const MAX_BINARY_SIZE: usize = BOOTLOADER_START_ADDR - BINARY_START_ADDR;
+/// Room for the bootloader's stack, which grows down from the bootloader.
+const STACK_SIZE: usize = 0x10_0000;
 
-/// Branches to the address `addr` unconditionally.
+/// Free space between the bootloader's stack and the loaded binary's start
+/// address.
+const MAX_BINARY_SIZE: usize = BOOTLOADER_START_ADDR - STACK_SIZE - BINARY_START_ADDR;
+
+/// Number of boots in a row that may end in a watchdog reset before the SD
+/// card's fallback kernel is loaded.
+const MAX_FAILED_BOOTS: u32 = 2;
+
+/// Read timeouts to wait for the command to start a kernel loaded in a
+/// `ttywrite --boot` session, before asking at the console instead.
+const JUMP_TIMEOUTS: usize = 8;
+
+/// Lets `ttywrite --auto-baud` move the link from `base`, its current rate, to
+/// faster baud rates, one at a time, until it stops asking. A rate the other
+/// end can't read the probe pattern at is dropped in favor of the last one
+/// that worked.
+fn negotiate_baud_rate(uart: &mut MiniUart, base: u32) {
+    use xmodem::baud;
+
+    let mut rate = base;
+    while let Ok(Some(requested)) = baud::read_request(Serial(uart)) {
+        if !MiniUart::supports_baud_rate(requested) {
+            continue;
+        }
+        if baud::accept(Serial(uart), requested).is_err() {
+            break;
+        }
+
+        uart.set_baud_rate(requested);
+        match baud::answer_probe(Serial(uart)) {
+            Ok(true) => rate = requested,
+            _ => {
+                uart.set_baud_rate(rate);
+            }
+        }
+    }
+}
+
+/// Serves the start of a `ttywrite --boot` session, which opens with a query
+/// and then describes the kernel it's about to send. Returns that
+/// description, or `None` if the sender doesn't speak the loader protocol.
+fn open_session(uart: &mut MiniUart) -> io::Result<Option<Trailer>> {
+    let info = Message::Info { version: loader::VERSION, max_len: MAX_BINARY_SIZE as u32 };
+    let mut queried = false;
+    loop {
+        match Message::read(Serial(uart))? {
+            // The host repeats its query until it hears from us.
+            Some(Message::Query) => {
+                info.write(Serial(uart))?;
+                queried = true;
+            }
+            Some(Message::Image(image)) if queried => {
+                let fits = image.len as usize <= MAX_BINARY_SIZE;
+                loader::answer(Serial(uart), fits)?;
+                if !fits {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "kernel too large",
+                    ));
+                }
+                return Ok(Some(image));
+            }
+            Some(_) => continue,
+            None if queried => return Err(io::ErrorKind::TimedOut.into()),
+            None => return Ok(None),
+        }
+    }
+}
+
+/// Waits for a `ttywrite --boot` session to say to start the kernel. Returns
+/// `false` if it doesn't within `JUMP_TIMEOUTS` read timeouts.
+fn wait_for_jump(uart: &mut MiniUart) -> bool {
+    for _ in 0..JUMP_TIMEOUTS {
+        if let Ok(Some(Message::Jump)) = Message::read(Serial(uart)) {
+            return loader::answer(Serial(uart), true).is_ok();
+        }
+    }
+    false
+}
+
+/// Returns the trailer for the part of `received` that should match
+/// `expected`: its first `expected.len` bytes, since the last packet was
+/// padded, or all of it if the transfer came up short.
+fn trailer_of(expected: Trailer, received: &[u8]) -> Trailer {
+    Trailer::of(&received[..core::cmp::min(expected.len as usize, received.len())])
+}
+
+/// Why a compressed kernel couldn't be unpacked.
+#[derive(Debug)]
+enum UnpackError {
+    Lz4(lz4::Error),
+    Gzip(gzip::Error),
+}
+
+/// Decompresses the kernel in the first `len` bytes of `buf` to the start of
+/// `buf` if it's an LZ4 frame or a gzip member, and returns its length.
+/// Anything else is a raw kernel, which is left as it is.
+///
+/// The compressed kernel is moved to the end of `buf` first, so the kernel
+/// can take up everything in front of it. One that would grow past that, to
+/// more than `MAX_BINARY_SIZE` less the compressed size, is `TooLarge`.
+fn unpack(buf: &mut [u8], len: usize) -> Result<Option<usize>, UnpackError> {
+    let data = &buf[..len];
+    if !lz4::is_frame(data) && !gzip::is_member(data) {
+        return Ok(None);
+    }
+
+    let top = buf.len() - len;
+    buf.copy_within(..len, top);
+    let (kernel, packed) = buf.split_at_mut(top);
+    if lz4::is_frame(packed) {
+        lz4::decompress(packed, kernel).map(Some).map_err(UnpackError::Lz4)
+    } else {
+        gzip::decompress(packed, kernel).map(Some).map_err(UnpackError::Gzip)
+    }
+}
+
+/// Reads `config::PATH` from the SD card into `buf`, which is only borrowed
+/// for it, and parses it. Without one, the defaults are used; so are they if
+/// it can't be read or parsed, after saying why.
+fn read_config(buf: &mut [u8]) -> Config {
+    let text = match sd::load(config::PATH, buf) {
+        Ok(len) => core::str::from_utf8(&buf[..len]).map_err(|_| "not UTF-8".to_string()),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Config::default(),
+        Err(err) => Err(err.to_string()),
+    };
+
+    match text.and_then(Config::parse) {
+        Ok(config) => config,
+        Err(err) => {
+            kprintln!("Ignoring {}: {}", config::PATH, err);
+            Config::default()
+        }
+    }
+}
+
+/// Returns the end of the ARM's RAM, as the firmware reports it.
+fn ram_end() -> u64 {
+    Atags::get()
+        .find_map(|atag| atag.mem())
+        .map_or(IO_BASE as u64, |mem| mem.start as u64 + mem.size as u64)
+}
+
+/// Finds the kernel in the first `len` bytes of `buf` and returns where to
+/// start it. A kernel behind an image header is checked against it and moved
+/// to the load address it gives; a plain binary starts where it is.
+///
+/// The load address may be anywhere in RAM from `BINARY_START` up, outside of
+/// the bootloader's stack, image and heap. Below `BINARY_START` are the
+/// firmware's spin tables and ATAGs.
+fn place(buf: &mut [u8], len: usize) -> Result<*mut u8, image::Error> {
+    let header = match image::Header::parse(&buf[..len])? {
+        Some(header) => header,
+        None => return Ok(BINARY_START),
+    };
+    header.check(&buf[image::LEN..len])?;
+
+    let range = header.range();
+    let (start, end) = (range.start, range.end);
+    if start < BINARY_START_ADDR as u64 || end > ram_end() {
+        return Err(image::Error::OutOfRange { start, end });
+    }
+    let loader = (BOOTLOADER_START_ADDR - STACK_SIZE) as u64..allocator::heap_end() as u64;
+    if start < loader.end && loader.start < end {
+        return Err(image::Error::Overlaps { start, end });
+    }
+
+    // The kernel may overlap where it was loaded to, in either direction.
+    unsafe {
+        let kernel = buf.as_ptr().add(image::LEN);
+        core::ptr::copy(kernel, start as *mut u8, header.size as usize);
+    }
+    Ok(header.entry() as *mut u8)
+}
+
+/// Shows on the ACT LED that a kernel is arriving, once it starts to.
+fn show_receiving(progress: Progress) {
+    if let Progress::Started | Progress::Packet(_) = progress {
+        led::set(Status::Receiving);
+    }
+}
+
+/// Starts the kernel at `addr`, leaving the ACT LED on and the watchdog
+/// armed.
 fn jump_to(addr: *mut u8) -> ! {
+    led::set(Status::Jumping);
+    retry::arm();
+    branch_to(addr)
+}
+
+/// Branches to the address `addr` unconditionally.
+fn branch_to(addr: *mut u8) -> ! {
     unsafe {
         asm!("br {}", in(reg) addr as usize);
         loop {
@@ -54,18 +264,166 @@ fn jump_to(addr: *mut u8) -> ! {
 
 #[no_mangle]
 pub extern "C" fn kmain() {
-    use std::io;
-
-    let mut uart = pi::uart::MiniUart::new();
+    let mut uart = MiniUart::new();
     uart.set_read_timeout(750);
 
     kprintln!("\nReady to receive kernel");
+    led::set(Status::Waiting);
+
+    let mut config = read_config(unsafe {
+        core::slice::from_raw_parts_mut(BINARY_START, MAX_BINARY_SIZE)
+    });
+    if !MiniUart::supports_baud_rate(config.baud) {
+        kprintln!("Ignoring unsupported baud rate {}", config.baud);
+        config.baud = Config::default().baud;
+    }
+    if config.baud != Config::default().baud {
+        kprintln!("Switching console to {} baud", config.baud);
+        uart.set_baud_rate(config.baud);
+    }
+    if let Some(cmdline) = &config.cmdline {
+        if !unsafe { pi::atags::set_cmdline(cmdline) } {
+            kprintln!("Ignoring command line: too long for the ATAGs");
+        }
+    }
 
+    let failures = retry::count_failures();
+    if failures > 0 {
+        kprintln!("The last {} boot(s) hung and were reset by the watchdog", failures);
+    }
+    let sd_path = match failures >= MAX_FAILED_BOOTS {
+        true => &config.fallback,
+        false => &config.kernel,
+    };
+
+    monitor::offer(&mut uart);
+
+    // How much of the kernel the last attempt received. The next attempt asks
+    // the sender to resume after it.
+    let mut received = 0;
+    // Whether anything has been sent over the UART, and whether the SD card
+    // has been tried; it's only tried once, and only if nothing was sent.
+    let mut uart_used = false;
+    let mut sd_tried = false;
+    let sd_boot_time = timer::current_time() + config.timeout.saturating_mul(1_000_000);
     loop {
         let buf = unsafe { core::slice::from_raw_parts_mut(BINARY_START, MAX_BINARY_SIZE) };
 
-        match xmodem::Xmodem::receive(&mut uart, buf) {
-            Ok(_) => {
+        if !uart_used && !sd_tried && timer::current_time() >= sd_boot_time {
+            sd_tried = true;
+            kprintln!("Nothing received, loading {} from SD card", sd_path);
+            match sd::load(sd_path, buf) {
+                Ok(len) => match unpack(buf, len) {
+                    Ok(unpacked) => match place(buf, unpacked.unwrap_or(len)) {
+                        Ok(entry) => {
+                            kprintln!("Starting kernel from SD card");
+                            jump_to(entry);
+                        }
+                        Err(err) => kprintln!("Kernel on SD card rejected: {}", err),
+                    },
+                    Err(err) => kprintln!("Failed to decompress kernel: {:?}", err),
+                },
+                Err(err) => kprintln!("Failed to load kernel from SD card: {}", err),
+            }
+            kprintln!("Waiting for a kernel over the UART instead");
+        }
+
+        // A failed attempt may have left the link at a rate the sender has
+        // given up on.
+        uart.set_baud_rate(config.baud);
+        negotiate_baud_rate(&mut uart, config.baud);
+
+        let image = match open_session(&mut uart) {
+            Ok(image) => image,
+            Err(err) => {
+                uart_used = true;
+                kprintln!("Loader session failed, retry: {:?}", err);
+                continue;
+            }
+        };
+        uart_used |= image.is_some();
+
+        // Ask for CRC-32 checks; senders that don't know them get CRC-16 or
+        // checksums instead.
+        let mut receiver = xmodem::Xmodem::new_with_progress(Serial(&mut uart), show_receiving);
+        receiver.set_checksum(xmodem::Checksum::Crc32);
+        match receiver.resume_into(buf, &mut received) {
+            Ok(()) => {
+                uart_used = true;
+
+                // In a loader session, the sender described the kernel up
+                // front. Otherwise it may follow up with a length and CRC-32
+                // of the kernel. Either way, don't jump to an image that
+                // doesn't match.
+                let verified = match image {
+                    Some(image) => {
+                        let actual = trailer_of(image, &buf[..received]);
+                        loader::answer(Serial(&mut uart), actual == image)
+                            .map(|_| Some((image, actual)))
+                    }
+                    None => xmodem::verify::check_trailer(Serial(&mut uart), &buf[..received])
+                        .map(|sent| sent.map(|sent| (sent, trailer_of(sent, &buf[..received])))),
+                };
+                match verified {
+                    Ok(None) => {}
+                    Ok(Some((expected, actual))) if expected == actual => {
+                        kprintln!("Kernel verified")
+                    }
+                    Ok(Some((expected, actual))) => {
+                        kprintln!(
+                            "Kernel failed verification, retry: expected {}, received {}",
+                            expected,
+                            actual
+                        );
+                        led::set(Status::VerifyFailed);
+                        received = 0;
+                        continue;
+                    }
+                    Err(err) => {
+                        kprintln!("Failed to verify kernel, retry: {:?}", err);
+                        led::set(Status::Waiting);
+                        received = 0;
+                        continue;
+                    }
+                }
+
+                // `ttywrite --compress` sends an LZ4 frame or a gzip member
+                // instead of the kernel itself.
+                let len = match unpack(buf, received) {
+                    Ok(Some(len)) => {
+                        kprintln!("Kernel decompressed to {} bytes", len);
+                        len
+                    }
+                    Ok(None) => received,
+                    Err(err) => {
+                        kprintln!("Failed to decompress kernel, retry: {:?}", err);
+                        led::set(Status::VerifyFailed);
+                        received = 0;
+                        continue;
+                    }
+                };
+
+                // `ttywrite --header` puts an image header in front of the
+                // kernel, which says where it goes.
+                let entry = match place(buf, len) {
+                    Ok(entry) => entry,
+                    Err(err) => {
+                        kprintln!("Kernel rejected, retry: {}", err);
+                        led::set(Status::VerifyFailed);
+                        received = 0;
+                        continue;
+                    }
+                };
+
+                // A loader session says when to start the kernel; without
+                // one, the user does, from a terminal at the usual rate.
+                let jump = image.is_some() && wait_for_jump(&mut uart);
+                uart.set_baud_rate(config.baud);
+                if jump {
+                    kprintln!("Starting kernel");
+                    jump_to(entry);
+                }
+
                 // Repeatedly print until receive any user input
                 loop {
                     uart.write_byte(b'\r'); // Carriage Return without Line Feed
@@ -76,13 +434,26 @@ pub extern "C" fn kmain() {
                     }
                 }
                 kprint!("\n");
-                jump_to(BINARY_START);
+                jump_to(entry);
             }
             Err(err) => match err.kind() {
-                io::ErrorKind::TimedOut => {}
-                _ => uart
-                    .write_fmt(format_args!("Failed to receive kernel, retry: {:?}\n", err))
-                    .unwrap(),
+                io::ErrorKind::TimedOut => {
+                    if received > 0 {
+                        uart_used = true;
+                        led::set(Status::Waiting);
+                    }
+                }
+                io::ErrorKind::ConnectionAborted => {
+                    uart_used = true;
+                    led::set(Status::Waiting);
+                    kprintln!("Transfer cancelled, retry")
+                }
+                _ => {
+                    uart_used = true;
+                    led::set(Status::Waiting);
+                    uart.write_fmt(format_args!("Failed to receive kernel, retry: {:?}\n", err))
+                        .unwrap()
+                }
             },
         }
     }
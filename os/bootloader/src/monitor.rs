@@ -0,0 +1,203 @@
+//! A small monitor for poking at the hardware before any kernel runs, entered
+//! by pressing `m` while the bootloader starts up.
+//!
+//! Addresses are physical; the MMU is off, so all of memory is treated as
+//! device memory and has to be accessed a whole, aligned word at a time.
+
+use core::arch::asm;
+
+use pi::timer;
+use pi::uart::MiniUart;
+
+use crate::console::{kprint, kprintln};
+use crate::led;
+
+/// The key that enters the monitor.
+const KEY: u8 = b'm';
+
+/// How long to wait for `KEY` at startup, in microseconds.
+const KEY_WAIT_US: u64 = 1_000_000;
+
+/// The longest command line.
+const MAX_LINE_LEN: usize = 80;
+
+/// The most arguments a command takes, including its name.
+const MAX_ARGS: usize = 4;
+
+/// Number of bytes `md` dumps when no length is given.
+const MD_DEFAULT_LEN: u64 = 256;
+
+/// Offers to enter the monitor, and runs it if `KEY` is pressed in time.
+/// Anything else that arrives meanwhile is dropped.
+pub fn offer(uart: &mut MiniUart) {
+    kprintln!("Press '{}' for the monitor", KEY as char);
+    let deadline = timer::current_time() + KEY_WAIT_US;
+    while timer::current_time() < deadline {
+        led::poll();
+        if uart.has_byte() && uart.read_byte() == KEY {
+            run(uart);
+            return;
+        }
+    }
+}
+
+/// Reads and executes commands until `boot` is entered.
+fn run(uart: &mut MiniUart) {
+    kprintln!("Monitor; 'help' lists the commands");
+    loop {
+        kprint!("> ");
+        let mut line = [0u8; MAX_LINE_LEN];
+        let line = read_line(uart, &mut line);
+
+        let mut args = [""; MAX_ARGS];
+        let mut argc = 0;
+        for arg in line.split(' ').filter(|a| !a.is_empty()) {
+            if argc == MAX_ARGS {
+                argc += 1;
+                break;
+            }
+            args[argc] = arg;
+            argc += 1;
+        }
+
+        match args.get(..argc) {
+            Some([]) => {}
+            Some(["boot"]) => return,
+            Some(args) => execute(args),
+            None => kprintln!("too many arguments"),
+        }
+    }
+}
+
+/// Executes the command `args[0]` with the arguments after it.
+fn execute(args: &[&str]) {
+    match args {
+        ["help"] => {
+            kprintln!("md <addr> [len]  dump memory, a word at a time");
+            kprintln!("mw <addr> <val>  write the word <val> to <addr>");
+            kprintln!("go <addr>        branch to <addr>");
+            kprintln!("regs             show system registers");
+            kprintln!("boot             leave the monitor and boot a kernel");
+        }
+        ["md", addr] => md(addr, None),
+        ["md", addr, len] => md(addr, Some(len)),
+        ["mw", addr, value] => mw(addr, value),
+        ["go", addr] => match parse_addr(addr) {
+            Some(addr) => {
+                kprintln!("Branching to {:#x}", addr);
+                crate::branch_to(addr as *mut u8)
+            }
+            None => kprintln!("invalid address: {}", addr),
+        },
+        ["regs"] => regs(),
+        [name, ..] => kprintln!("unknown command or wrong arguments: {}", name),
+        [] => {}
+    }
+}
+
+/// `md <addr> [len]`
+///
+/// Prints the `len` bytes at `addr`, rounded up to whole words, as lines of
+/// four words.
+fn md(addr: &str, len: Option<&str>) {
+    let (addr, len) = match (parse_addr(addr), len.map_or(Some(MD_DEFAULT_LEN), parse_num)) {
+        (Some(addr), Some(len)) => (addr, len),
+        _ => return kprintln!("usage: md <addr> [len], with <addr> word-aligned"),
+    };
+
+    let words = len.div_ceil(4);
+    for i in 0..words {
+        let addr = addr.wrapping_add(i * 4);
+        if i % 4 == 0 {
+            kprint!("{:08x}:", addr);
+        }
+        let word = unsafe { core::ptr::read_volatile(addr as *const u32) };
+        kprint!(" {:08x}", word);
+        if i % 4 == 3 || i + 1 == words {
+            kprintln!();
+        }
+    }
+}
+
+/// `mw <addr> <val>`
+///
+/// Writes the 32-bit word `val` to `addr`.
+fn mw(addr: &str, value: &str) {
+    match (parse_addr(addr), parse_num(value).filter(|&v| v <= u32::MAX as u64)) {
+        (Some(addr), Some(value)) => unsafe {
+            core::ptr::write_volatile(addr as *mut u32, value as u32)
+        },
+        _ => kprintln!("usage: mw <addr> <val>, with <addr> word-aligned and <val> 32 bits"),
+    }
+}
+
+/// Reads the system register `$name`.
+macro sysreg($name:literal) {{
+    let value: u64;
+    unsafe { asm!(concat!("mrs {}, ", $name), out(reg) value) };
+    value
+}}
+
+/// `regs`
+///
+/// Prints the system registers that describe how the CPU is set up, including
+/// EL2's if the bootloader runs there, as the firmware leaves it to.
+fn regs() {
+    let sp: u64;
+    unsafe { asm!("mov {}, sp", out(reg) sp) };
+    let el = (sysreg!("CurrentEL") >> 2) & 0b11;
+
+    kprintln!("CurrentEL   EL{}", el);
+    kprintln!("SP          {:016x}", sp);
+    kprintln!("MIDR_EL1    {:016x}", sysreg!("MIDR_EL1"));
+    kprintln!("MPIDR_EL1   {:016x}", sysreg!("MPIDR_EL1"));
+    kprintln!("SCTLR_EL1   {:016x}", sysreg!("SCTLR_EL1"));
+    if el >= 2 {
+        kprintln!("SCTLR_EL2   {:016x}", sysreg!("SCTLR_EL2"));
+        kprintln!("HCR_EL2     {:016x}", sysreg!("HCR_EL2"));
+    }
+    kprintln!("CNTFRQ_EL0  {:016x}", sysreg!("CNTFRQ_EL0"));
+    kprintln!("CNTPCT_EL0  {:016x}", sysreg!("CNTPCT_EL0"));
+}
+
+/// Parses `s` as a decimal number or, if prefixed with `0x`, as a hexadecimal
+/// number.
+fn parse_num(s: &str) -> Option<u64> {
+    match s.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16).ok(),
+        None => s.parse().ok(),
+    }
+}
+
+/// Parses `s` like `parse_num`, as a word-aligned address.
+fn parse_addr(s: &str) -> Option<u64> {
+    parse_num(s).filter(|addr| addr % 4 == 0)
+}
+
+/// Reads a line from `uart` into `buf`, echoing it, and returns it. Only
+/// printable characters are kept; backspace removes the last one.
+fn read_line<'a>(uart: &mut MiniUart, buf: &'a mut [u8]) -> &'a str {
+    let mut len = 0;
+    loop {
+        while uart.wait_for_byte_with(led::poll).is_err() {}
+        match uart.read_byte() {
+            b'\r' | b'\n' => {
+                kprintln!();
+                break;
+            }
+            b @ 0x20..=0x7e if len < buf.len() => {
+                buf[len] = b;
+                len += 1;
+                uart.write_byte(b);
+            }
+            8 | 127 if len > 0 => {
+                len -= 1;
+                kprint!("\u{8} \u{8}");
+            }
+            // Ring the bell at anything else.
+            _ => uart.write_byte(7),
+        }
+    }
+
+    core::str::from_utf8(&buf[..len]).unwrap_or("")
+}
@@ -0,0 +1,39 @@
+//! Counting kernels that hung, across the watchdog resets that end them.
+//!
+//! The watchdog is armed before a kernel starts. A kernel that comes up takes
+//! it over, petting or stopping it; one that hangs before then has the board
+//! reset back into the bootloader, which counts the failure. The count lives
+//! in RAM that's neither loaded nor zeroed, so it survives the reset. A power
+//! cycle, or any reset the watchdog didn't cause, starts it over.
+
+use core::ptr::addr_of_mut;
+
+use pi::watchdog::{Watchdog, MAX_TIMEOUT_MS};
+
+/// Marks a valid count. Anything else is power-on garbage or memory a
+/// kernel reused.
+const MAGIC: u32 = 0x4641_494c;
+
+/// `MAGIC` followed by the count. Placed in `.boot_record`, which
+/// `layout.ld` reserves between the BSS and `_end` without zeroing it.
+#[link_section = ".boot_record"]
+static mut RECORD: [u32; 2] = [0; 2];
+
+/// Counts the reset that started the bootloader, if the watchdog caused it,
+/// and returns how many boots in a row have now ended in a watchdog reset.
+/// Meant to be called once, at startup.
+pub fn count_failures() -> u32 {
+    let record = unsafe { &mut *addr_of_mut!(RECORD) };
+    let failures = match Watchdog::new().caused_last_reset() && record[0] == MAGIC {
+        true => record[1].saturating_add(1),
+        false => 0,
+    };
+
+    *record = [MAGIC, failures];
+    failures
+}
+
+/// Starts the watchdog, with its longest timeout, for a kernel to take over.
+pub fn arm() {
+    Watchdog::new().start(MAX_TIMEOUT_MS);
+}
@@ -0,0 +1,79 @@
+//! Loading a kernel, or the bootloader's configuration, from the SD card's
+//! FAT32 partition, through the `libsd` the kernel uses as well.
+
+use std::io::{self, Read};
+
+use fat32::traits::{BlockDevice, File as _, FileSystem as _};
+use fat32::vfat::{self, VFat};
+use pi::timer;
+
+extern "C" {
+    /// Initializes the SD card controller. Returns 0 on success, -1 on a
+    /// timeout and -2 if sending a command failed.
+    fn sd_init() -> i32;
+
+    /// Reads sector `n` (512 bytes) into `buffer`. Returns the number of bytes
+    /// read, or 0 on an error.
+    fn sd_readsector(n: i32, buffer: *mut u8) -> i32;
+}
+
+/// Sleeps for `us` microseconds. Called by `libsd`, which declares it as
+/// `void wait_micros(unsigned int);`.
+#[no_mangle]
+pub extern "C" fn wait_micros(us: u32) {
+    timer::spin_sleep_us(us as u64)
+}
+
+/// The SD card. The bootloader only ever reads it.
+struct Sd;
+
+impl Sd {
+    /// Initializes the SD card controller.
+    fn new() -> io::Result<Sd> {
+        match unsafe { sd_init() } {
+            0 => Ok(Sd),
+            -1 => Err(io::Error::new(io::ErrorKind::TimedOut, "SD card timed out")),
+            _ => Err(io::Error::new(io::ErrorKind::Other, "SD card failed to initialize")),
+        }
+    }
+}
+
+impl BlockDevice for Sd {
+    fn read_sector(&mut self, n: u64, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.len() < 512 || n > i32::MAX as u64 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "invalid sector or buffer"));
+        }
+
+        match unsafe { sd_readsector(n as i32, buf.as_mut_ptr()) } {
+            0 => Err(io::Error::new(io::ErrorKind::Other, "SD card read failed")),
+            read => Ok(read as usize),
+        }
+    }
+
+    fn write_sector(&mut self, _n: u64, _buf: &[u8]) -> io::Result<usize> {
+        Err(io::Error::new(io::ErrorKind::PermissionDenied, "SD card is read only"))
+    }
+}
+
+/// Reads the file at `path` on the SD card's first FAT32 partition into the
+/// start of `buf` and returns its length.
+///
+/// # Errors
+///
+/// Returns an error of kind `NotFound` if there's no FAT32 partition or no
+/// such file, `InvalidData` if the file doesn't fit in `buf`, and any error
+/// reading the card.
+pub fn load(path: &str, buf: &mut [u8]) -> io::Result<usize> {
+    let vfat = VFat::from(Sd::new()?).map_err(|e| match e {
+        vfat::Error::Io(e) => e,
+        _ => io::Error::new(io::ErrorKind::NotFound, "no FAT32 partition"),
+    })?;
+
+    let mut file = (&vfat).open_file(path)?;
+    let len = file.size() as usize;
+    if len > buf.len() {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "file too large"));
+    }
+    file.read_exact(&mut buf[..len])?;
+    Ok(len)
+}
@@ -0,0 +1,45 @@
+use pi::uart::MiniUart;
+use xmodem::io;
+
+use crate::led;
+
+/// Lends a `MiniUart` to `xmodem`, which is built without `std` here and so
+/// reads and writes through its own `io` traits instead of `std::io`'s.
+pub struct Serial<'a>(pub &'a mut MiniUart);
+
+impl io::Read for Serial<'_> {
+    /// Waits at most the UART's read timeout for the first byte, keeping the
+    /// ACT LED blinking meanwhile, then reads whatever else is already
+    /// available.
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        if self.0.wait_for_byte_with(led::poll).is_err() {
+            return Err(io::ErrorKind::TimedOut.into());
+        }
+
+        let mut n = 0;
+        while n < buf.len() && (n == 0 || self.0.has_byte()) {
+            buf[n] = self.0.read_byte();
+            n += 1;
+        }
+        Ok(n)
+    }
+}
+
+impl io::Write for Serial<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        for &b in buf {
+            self.0.write_byte(b);
+        }
+        Ok(buf.len())
+    }
+
+    /// Waits until everything written has been sent, so the UART can
+    /// switch baud rates without cutting it off.
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush();
+        Ok(())
+    }
+}
@@ -4,7 +4,7 @@ use core::fmt::Debug;
 use core::panic;
 
 use crate::allocator::linked_list::LinkedList;
-use crate::allocator::{pool, util::*};
+use crate::allocator::{pool, util::*, Stats};
 
 const K: usize = 16;
 
@@ -15,6 +15,10 @@ pub struct Allocator {
     bins: [LinkedList; K - 2],
 
     pool: pool::Allocator,
+
+    allocated: usize,
+    allocs: usize,
+    deallocs: usize,
 }
 
 impl Allocator {
@@ -24,6 +28,9 @@ impl Allocator {
         Allocator {
             bins: [LinkedList::new(); K - 2],
             pool: pool::Allocator::new(start, end),
+            allocated: 0,
+            allocs: 0,
+            deallocs: 0,
         }
     }
 
@@ -48,6 +55,13 @@ impl Allocator {
     /// (`AllocError::Exhausted`) or `layout` does not meet this allocator's
     /// size or alignment constraints (`AllocError::Unsupported`).
     pub fn alloc(&mut self, layout: Layout) -> Result<*mut u8, AllocError> {
+        let ptr = self.alloc_block(layout)?;
+        self.allocated += self.allocated_size(layout);
+        self.allocs += 1;
+        Ok(ptr)
+    }
+
+    fn alloc_block(&mut self, layout: Layout) -> Result<*mut u8, AllocError> {
         let block_size = self.block_size_fit(layout);
         match self.first_bin_fit(block_size) {
             Some(first_bin_idx) => {
@@ -84,6 +98,9 @@ impl Allocator {
     /// Parameters not meeting these conditions may result in undefined
     /// behavior.
     pub fn dealloc(&mut self, ptr: *mut u8, layout: Layout) {
+        self.allocated -= self.allocated_size(layout);
+        self.deallocs += 1;
+
         let block_size = self.block_size_fit(layout);
         match self.first_bin_fit(block_size) {
             Some(fit_bin) => unsafe { self.bins[fit_bin].push(ptr as *mut usize) },
@@ -91,6 +108,32 @@ impl Allocator {
         }
     }
 
+    /// Returns a snapshot of this allocator's memory usage.
+    pub fn stats(&self) -> Stats {
+        let largest_bin = (0..self.bins.len())
+            .rev()
+            .find(|&i| !self.bins[i].is_empty())
+            .map_or(0, |i| self.bin_block_size(i));
+
+        Stats {
+            start: self.pool.start(),
+            end: self.pool.end(),
+            allocated: self.allocated,
+            allocs: self.allocs,
+            deallocs: self.deallocs,
+            largest_free: max(largest_bin, self.pool.largest_free()),
+        }
+    }
+
+    /// Number of bytes taken up by an allocation of `layout`.
+    fn allocated_size(&self, layout: Layout) -> usize {
+        let block_size = self.block_size_fit(layout);
+        match self.first_bin_fit(block_size) {
+            Some(_) => block_size,
+            None => layout.size(),
+        }
+    }
+
     unsafe fn split_bin(&mut self, big_bin: usize, small_bin: usize) -> Option<*mut u8> {
         let addr = self.bins[big_bin].pop()? as *mut u8;
 
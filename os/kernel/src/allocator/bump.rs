@@ -1,12 +1,16 @@
 use core::alloc::{AllocError, Layout};
 
 use crate::allocator::util::*;
+use crate::allocator::Stats;
 
 /// A "bump" allocator: allocates memory by bumping a pointer; never frees.
 #[derive(Debug)]
 pub struct Allocator {
+    start: usize,
     current: usize,
     end: usize,
+    allocs: usize,
+    deallocs: usize,
 }
 
 impl Allocator {
@@ -14,8 +18,11 @@ impl Allocator {
     /// starting at address `start` and ending at address `end`.
     pub fn new(start: usize, end: usize) -> Allocator {
         Allocator {
+            start,
             current: start,
             end,
+            allocs: 0,
+            deallocs: 0,
         }
     }
 
@@ -51,6 +58,7 @@ impl Allocator {
         }
 
         self.current = start + layout.size();
+        self.allocs += 1;
 
         Ok(start as *mut u8)
     }
@@ -69,6 +77,19 @@ impl Allocator {
     /// Parameters not meeting these conditions may result in undefined
     /// behavior.
     pub fn dealloc(&mut self, _ptr: *mut u8, _layout: Layout) {
-        // Do nothing
+        // Nothing is ever freed; only keep count.
+        self.deallocs += 1;
+    }
+
+    /// Returns a snapshot of this allocator's memory usage.
+    pub fn stats(&self) -> Stats {
+        Stats {
+            start: self.start,
+            end: self.end,
+            allocated: self.current - self.start,
+            allocs: self.allocs,
+            deallocs: self.deallocs,
+            largest_free: self.end - self.current,
+        }
     }
 }
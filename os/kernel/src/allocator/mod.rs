@@ -8,10 +8,38 @@ mod imp;
 #[cfg(test)]
 mod tests;
 
+use crate::log::{error, info};
+use crate::memmap::{Kind, Region, MEMORY_MAP};
 use crate::mutex::Mutex;
+use crate::trace::{self, Event};
 use core::alloc::{AllocError, GlobalAlloc as Alloc, Layout};
 use std::cmp::max;
 
+/// A snapshot of an allocator's memory usage, in bytes unless noted.
+#[derive(Debug, Default, Copy, Clone)]
+pub struct Stats {
+    /// Start address of the memory region managed by the allocator.
+    pub start: usize,
+    /// End address of the memory region managed by the allocator.
+    pub end: usize,
+    /// Bytes currently handed out, including alignment and size-class
+    /// rounding.
+    pub allocated: usize,
+    /// Number of successful allocations.
+    pub allocs: usize,
+    /// Number of deallocations.
+    pub deallocs: usize,
+    /// Size of the largest block that can currently be allocated.
+    pub largest_free: usize,
+}
+
+impl Stats {
+    /// Total number of bytes managed by the allocator.
+    pub fn total(&self) -> usize {
+        self.end - self.start
+    }
+}
+
 /// Thread-safe (locking) wrapper around a particular memory allocator.
 #[derive(Debug)]
 pub struct Allocator(Mutex<Option<imp::Allocator>>);
@@ -25,14 +53,25 @@ impl Allocator {
         Allocator(Mutex::new(None))
     }
 
-    /// Initializes the memory allocator.
+    /// Initializes the memory allocator with the largest free range in the
+    /// memory map, and reserves that range for the heap.
     ///
     /// # Panics
     ///
-    /// Panics if the system's memory map could not be retrieved.
+    /// Panics if the memory map has no free RAM.
     pub fn initialize(&self) {
-        let (start, end) = memory_map().expect("failed to find memory map");
+        let mut map = MEMORY_MAP.lock();
+        let (start, end) = map.largest_free().expect("no free memory in memory map");
+        map.reserve(Region::new(start, end, Kind::Heap, "allocator"))
+            .expect("heap overlaps a reservation");
         *self.0.lock() = Some(imp::Allocator::new(start, end));
+        info!("managing {:#x}..{:#x} ({} KiB)", start, end, (end - start) / 1024);
+    }
+
+    /// Returns a snapshot of the allocator's memory usage, or `None` if the
+    /// allocator hasn't been initialized.
+    pub fn stats(&self) -> Option<Stats> {
+        self.0.lock().as_ref().map(|a| a.stats())
     }
 }
 
@@ -63,6 +102,11 @@ unsafe impl<'a> Alloc for &'a Allocator {
             .as_mut()
             .expect("allocator uninitialized")
             .alloc(layout)
+            .map_err(|e| error!("failed to allocate {:?}: {:?}", layout, e))
+            .map(|ptr| {
+                trace::record(Event::Alloc, ptr as u64, layout.size() as u64);
+                ptr
+            })
             .unwrap()
     }
 
@@ -85,31 +129,6 @@ unsafe impl<'a> Alloc for &'a Allocator {
             .as_mut()
             .expect("allocator uninitialized")
             .dealloc(ptr, layout);
+        trace::record(Event::Dealloc, ptr as u64, layout.size() as u64);
     }
 }
-
-extern "C" {
-    static _end: u8;
-}
-
-use pi::atags::{Atag, Atags};
-
-/// Returns the (start address, end address) of the available memory on this
-/// system if it can be determined. If it cannot, `None` is returned.
-///
-/// This function is expected to return `Some` under all normal cirumstances.
-fn memory_map() -> Option<(usize, usize)> {
-    let binary_end = unsafe { (&_end as *const u8) as u32 };
-
-    for atag in Atags::get() {
-        match atag {
-            Atag::Mem(mem) => {
-                let mem_end = mem.start + mem.size;
-                return Some((binary_end as usize, mem_end as usize));
-            }
-            _ => {}
-        }
-    }
-
-    None
-}
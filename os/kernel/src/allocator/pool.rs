@@ -114,6 +114,29 @@ impl Allocator {
         }
     }
 
+    /// Start address of the managed memory region.
+    pub fn start(&self) -> usize {
+        self.start
+    }
+
+    /// End address of the managed memory region.
+    pub fn end(&self) -> usize {
+        self.end
+    }
+
+    /// Size of the largest free region.
+    pub fn largest_free(&self) -> usize {
+        let mut largest = 0;
+        let mut curr = self.head.next;
+        while !curr.is_null() {
+            unsafe {
+                largest = max(largest, (*curr).size);
+                curr = (*curr).next;
+            }
+        }
+        largest
+    }
+
     unsafe fn merge_adjacent_regions(&mut self) {
         let mut curr = self.head.next;
         while !curr.is_null() {
@@ -184,6 +184,31 @@ mod allocator {
         }
     });
 
+    test_allocators!(bin_stats, bump_stats, 65536, |(start, end, mut a)| {
+        let stats = a.stats();
+        assert_eq!(stats.total(), end - start);
+        assert_eq!(stats.allocated, 0);
+        assert_eq!(stats.allocs, 0);
+        assert!(stats.largest_free <= end - start);
+
+        let layouts = [layout!(16, 16), layout!(100, 8), layout!(1024, 1024)];
+        let mut pointers = vec![];
+        for layout in &layouts {
+            pointers.push(a.alloc(layout.clone()).expect("allocation"));
+        }
+
+        let stats = a.stats();
+        assert_eq!(stats.allocs, layouts.len());
+        assert!(stats.allocated >= layouts.iter().map(|l| l.size()).sum::<usize>());
+        assert!(stats.allocated <= end - start);
+        assert!(stats.largest_free <= end - start - stats.allocated);
+
+        for (ptr, layout) in pointers.into_iter().zip(layouts.iter()) {
+            a.dealloc(ptr, layout.clone());
+        }
+        assert_eq!(a.stats().deallocs, layouts.len());
+    });
+
     test_allocators!(@bin, bin_dealloc_1, 65536, |(_, _, mut a)| {
         let layouts = [
             layout!(16, 16),
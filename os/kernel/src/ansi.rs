@@ -0,0 +1,77 @@
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::console::kprint;
+
+/// Whether escape sequences are emitted. Cleared for dumb terminals.
+static ENABLED: AtomicBool = AtomicBool::new(true);
+
+/// Enables or disables ANSI escape sequences in console output. While
+/// disabled, every helper in this module prints plain text only.
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed)
+}
+
+/// Returns `true` if ANSI escape sequences are currently emitted.
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// A foreground color.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Color {
+    Black,
+    Red,
+    Green,
+    Yellow,
+    Blue,
+    Magenta,
+    Cyan,
+    White,
+}
+
+impl Color {
+    /// Returns the SGR parameter selecting this color as the foreground.
+    fn code(self) -> u8 {
+        30 + self as u8
+    }
+}
+
+/// Displays `T` in the color `Color`, or plainly if escapes are disabled.
+pub struct Colored<T>(pub Color, pub T);
+
+impl<T: fmt::Display> fmt::Display for Colored<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match enabled() {
+            true => write!(f, "\x1b[{}m{}\x1b[0m", self.0.code(), self.1),
+            false => self.1.fmt(f),
+        }
+    }
+}
+
+/// Prints `seq` to the console if escapes are enabled.
+fn escape(seq: &str) {
+    if enabled() {
+        kprint!("{}", seq);
+    }
+}
+
+/// Erases the whole current line. The cursor doesn't move.
+pub fn clear_line() {
+    escape("\x1b[2K")
+}
+
+/// Erases from the cursor to the end of the current line.
+pub fn clear_to_end() {
+    escape("\x1b[K")
+}
+
+/// Saves the cursor position for a later `restore_cursor()`.
+pub fn save_cursor() {
+    escape("\x1b7")
+}
+
+/// Moves the cursor back to the position saved by `save_cursor()`.
+pub fn restore_cursor() {
+    escape("\x1b8")
+}
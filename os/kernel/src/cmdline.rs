@@ -0,0 +1,93 @@
+use pi::atags::Atags;
+
+/// The kernel command line: space-separated `key=value` pairs and bare `key`
+/// flags, as passed by the firmware in the `CMDLINE` ATAG (`cmdline.txt`).
+///
+/// Recognized keys:
+///
+///   * `loglevel=<error|warn|info|debug|trace|off>`: initial log level
+///   * `console=<sink>[,<sink>...]`: console output sinks to enable
+///   * `root=<sd|initrd>`: `initrd` leaves the SD card alone
+///   * `ansi=off`: disables ANSI escapes, for dumb terminals
+///   * `ktest`: runs the `kernel_test!` tests before starting the shell
+#[derive(Debug, Copy, Clone)]
+pub struct CmdLine {
+    raw: &'static str,
+}
+
+impl CmdLine {
+    /// Wraps the command line `raw`.
+    pub const fn new(raw: &'static str) -> CmdLine {
+        CmdLine { raw }
+    }
+
+    /// Returns the command line passed by the firmware, or an empty one if
+    /// there is none.
+    pub fn from_atags() -> CmdLine {
+        CmdLine::new(Atags::get().find_map(|atag| atag.cmd()).unwrap_or(""))
+    }
+
+    /// Returns the raw command line.
+    pub fn as_str(&self) -> &'static str {
+        self.raw
+    }
+
+    /// Returns every `(key, value)` pair in order. Flags have a `None` value.
+    pub fn iter(&self) -> impl Iterator<Item = (&'static str, Option<&'static str>)> {
+        self.raw.split_whitespace().map(|arg| match arg.find('=') {
+            Some(i) => (&arg[..i], Some(&arg[i + 1..])),
+            None => (arg, None),
+        })
+    }
+
+    /// Returns the value of the last `key=value` argument for `key`.
+    pub fn get(&self, key: &str) -> Option<&'static str> {
+        self.iter().filter(|&(k, _)| k == key).filter_map(|(_, v)| v).last()
+    }
+
+    /// Returns `true` if `key` is given as a bare flag or with a value other
+    /// than `0`, `off` or `false`.
+    pub fn flag(&self, key: &str) -> bool {
+        match self.iter().filter(|&(k, _)| k == key).last() {
+            Some((_, None)) => true,
+            Some((_, Some(value))) => !matches!(value, "0" | "off" | "false"),
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_pairs_and_flags() {
+        let cmdline = CmdLine::new(" loglevel=debug  ktest root=initrd console=uart,fb ");
+        let args: Vec<_> = cmdline.iter().collect();
+        assert_eq!(
+            args,
+            [
+                ("loglevel", Some("debug")),
+                ("ktest", None),
+                ("root", Some("initrd")),
+                ("console", Some("uart,fb")),
+            ]
+        );
+
+        assert_eq!(cmdline.get("loglevel"), Some("debug"));
+        assert_eq!(cmdline.get("ktest"), None);
+        assert_eq!(cmdline.get("missing"), None);
+        assert!(cmdline.flag("ktest"));
+        assert!(!cmdline.flag("missing"));
+    }
+
+    #[test]
+    fn last_occurrence_wins() {
+        let cmdline = CmdLine::new("ansi=on loglevel=warn ansi=off ktest=0 x= ");
+        assert_eq!(cmdline.get("ansi"), Some("off"));
+        assert!(!cmdline.flag("ansi"));
+        assert!(!cmdline.flag("ktest"));
+        assert_eq!(cmdline.get("x"), Some(""));
+        assert!(CmdLine::new("").iter().next().is_none());
+    }
+}
@@ -1,19 +1,51 @@
 use std::fmt;
 use std::io;
 
+use pi::timer;
 use pi::uart::MiniUart;
+use stack_vec::RingBuffer;
 
 use crate::mutex::Mutex;
+use crate::watchdog;
+
+/// Size of the console's receive buffer in bytes.
+const RX_BUFFER_SIZE: usize = 256;
+
+/// Maximum number of output sinks besides the UART.
+const MAX_SINKS: usize = 4;
+
+/// Name of the built-in UART output sink.
+pub const UART_SINK: &str = "uart";
+
+/// An additional destination for console output, such as a framebuffer
+/// console. `write` receives everything written to the console.
+#[derive(Copy, Clone)]
+struct Sink {
+    name: &'static str,
+    write: fn(&[u8]),
+    enabled: bool,
+}
 
 /// A global singleton allowing read/write access to the console.
+///
+/// Input always comes from the UART. Output goes to the UART and to every
+/// registered sink, each of which can be disabled individually.
 pub struct Console {
     inner: Option<MiniUart>,
+    uart_enabled: bool,
+    sinks: [Option<Sink>; MAX_SINKS],
+    rx_buf: RingBuffer<u8, RX_BUFFER_SIZE>,
 }
 
 impl Console {
     /// Creates a new instance of `Console`.
     const fn new() -> Console {
-        Console { inner: None }
+        Console {
+            inner: None,
+            uart_enabled: true,
+            sinks: [None; MAX_SINKS],
+            rx_buf: RingBuffer::new(),
+        }
     }
 
     /// Initializes the console if it's not already initialized.
@@ -31,26 +63,122 @@ impl Console {
         self.inner.as_mut().unwrap()
     }
 
-    /// Reads a byte from the UART device, blocking until a byte is available.
+    /// Moves all bytes pending in the UART device into the receive buffer.
+    /// Bytes received while the buffer is full are dropped.
+    ///
+    /// This is what the UART RX interrupt handler should call; until one
+    /// exists, readers poll through `try_read_byte()`.
+    pub fn poll(&mut self) {
+        while self.inner().has_byte() {
+            let byte = self.inner().read_byte();
+            let _ = self.rx_buf.push_back(byte);
+        }
+    }
+
+    /// Returns the next received byte if there is one. Never blocks.
+    pub fn try_read_byte(&mut self) -> Option<u8> {
+        self.poll();
+        self.rx_buf.pop_front()
+    }
+
+    /// Reads a byte, blocking until a byte is available.
+    ///
+    /// The caller keeps the console locked while waiting. Prefer the free
+    /// function `read_byte()`, which doesn't.
     pub fn read_byte(&mut self) -> u8 {
-        self.inner().read_byte()
+        loop {
+            if let Some(byte) = self.try_read_byte() {
+                return byte;
+            }
+        }
+    }
+
+    /// Registers an output sink named `name`. Enabled sinks are handed
+    /// everything written to the console after the UART.
+    ///
+    /// Fails if the name is taken or there is no room for another sink.
+    pub fn register_sink(&mut self, name: &'static str, write: fn(&[u8])) -> Result<(), ()> {
+        if name == UART_SINK || self.sinks.iter().flatten().any(|s| s.name == name) {
+            return Err(());
+        }
+
+        let slot = self.sinks.iter_mut().find(|s| s.is_none()).ok_or(())?;
+        *slot = Some(Sink { name, write, enabled: true });
+        Ok(())
+    }
+
+    /// Enables or disables the output sink named `name`, which may be
+    /// `UART_SINK`. Fails if there is no such sink.
+    pub fn set_sink_enabled(&mut self, name: &str, enabled: bool) -> Result<(), ()> {
+        if name == UART_SINK {
+            self.uart_enabled = enabled;
+            return Ok(());
+        }
+
+        let sink = self.sinks.iter_mut().flatten().find(|s| s.name == name).ok_or(())?;
+        sink.enabled = enabled;
+        Ok(())
+    }
+
+    /// Returns the names of all output sinks and whether they are enabled,
+    /// starting with the UART.
+    pub fn sinks(&self) -> impl Iterator<Item = (&'static str, bool)> + '_ {
+        let others = self.sinks.iter().flatten().map(|s| (s.name, s.enabled));
+        Some((UART_SINK, self.uart_enabled)).into_iter().chain(others)
     }
 
-    /// Writes the byte `byte` to the UART device.
+    /// Hands `buf` to every enabled sink other than the UART.
+    fn write_sinks(&self, buf: &[u8]) {
+        for sink in self.sinks.iter().flatten().filter(|s| s.enabled) {
+            (sink.write)(buf);
+        }
+    }
+
+    /// Writes `buf` to the UART device only, even if the UART sink is
+    /// disabled.
+    pub fn write_uart(&mut self, buf: &[u8]) -> io::Result<usize> {
+        io::Write::write(self.inner(), buf)
+    }
+
+    /// Writes the byte `byte` to every enabled output sink.
     pub fn write_byte(&mut self, byte: u8) {
-        self.inner().write_byte(byte)
+        if self.uart_enabled {
+            self.inner().write_byte(byte);
+        }
+        self.write_sinks(&[byte]);
     }
 }
 
 impl io::Read for Console {
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-        self.inner().read(buf)
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        if self.rx_buf.is_empty() {
+            return self.inner().read(buf);
+        }
+
+        let mut n = 0;
+        while n < buf.len() {
+            match self.try_read_byte() {
+                Some(byte) => buf[n] = byte,
+                None => break,
+            }
+            n += 1;
+        }
+        Ok(n)
     }
 }
 
 impl io::Write for Console {
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-        self.inner().write(buf)
+        let n = match self.uart_enabled {
+            true => self.inner().write(buf)?,
+            false => buf.len(),
+        };
+        self.write_sinks(&buf[..n]);
+        Ok(n)
     }
 
     fn flush(&mut self) -> io::Result<()> {
@@ -60,13 +188,110 @@ impl io::Write for Console {
 
 impl fmt::Write for Console {
     fn write_str(&mut self, s: &str) -> fmt::Result {
-        self.inner().write_str(s)
+        if self.uart_enabled {
+            self.inner().write_str(s)?;
+        }
+        self.write_sinks(s.as_bytes());
+        Ok(())
     }
 }
 
 /// Global `Console` singleton.
 pub static CONSOLE: Mutex<Console> = Mutex::new(Console::new());
 
+/// Reads a byte from the global console, blocking until a byte is available.
+///
+/// Unlike `Console::read_byte()`, the console is only locked while checking for
+/// input, so other users such as `kprint!` aren't blocked while waiting.
+pub fn read_byte() -> u8 {
+    loop {
+        if let Some(byte) = CONSOLE.lock().try_read_byte() {
+            return byte;
+        }
+        idle();
+    }
+}
+
+/// Called on every turn of a loop waiting for console input. Waiting for
+/// input is the kernel's idle loop for now, so this is where the watchdog is
+/// petted: a kernel stuck anywhere else never gets back here. Only commands
+/// that are slow on purpose, like `sleep`, pet it themselves.
+fn idle() {
+    watchdog::pet();
+    // FIXME: Sleep on a wait queue woken by the RX interrupt instead.
+}
+
+/// Reads at least one byte from the global console into `buf`, plus whatever
+/// else has already arrived, like `read_byte()` but giving up with an error of
+/// kind `TimedOut` if nothing arrives within `timeout_ms` milliseconds.
+pub fn read_timeout(buf: &mut [u8], timeout_ms: u64) -> io::Result<usize> {
+    if buf.is_empty() {
+        return Ok(0);
+    }
+
+    let deadline = timer::current_time() + timeout_ms * 1000;
+    loop {
+        let mut console = CONSOLE.lock();
+        let mut n = 0;
+        while n < buf.len() {
+            match console.try_read_byte() {
+                Some(byte) => buf[n] = byte,
+                None => break,
+            }
+            n += 1;
+        }
+        if n > 0 {
+            return Ok(n);
+        }
+        drop(console);
+
+        if timer::current_time() >= deadline {
+            return Err(io::ErrorKind::TimedOut.into());
+        }
+        idle();
+    }
+}
+
+/// Reads at least one byte from the global console into `buf`, plus whatever
+/// else has already arrived, blocking like `read_byte()`. Backs standard input
+/// when the kernel is built with the custom std.
+pub fn read(buf: &mut [u8]) -> io::Result<usize> {
+    if buf.is_empty() {
+        return Ok(0);
+    }
+
+    buf[0] = read_byte();
+    let mut console = CONSOLE.lock();
+    let mut n = 1;
+    while n < buf.len() {
+        match console.try_read_byte() {
+            Some(byte) => buf[n] = byte,
+            None => break,
+        }
+        n += 1;
+    }
+    Ok(n)
+}
+
+/// Writes `buf` to the global console, putting a `\r` before each `\n` like
+/// `kprint!` does. Backs standard output and error when the kernel is built
+/// with the custom std.
+pub fn write(buf: &[u8]) -> io::Result<usize> {
+    use std::io::Write;
+
+    let mut console = CONSOLE.lock();
+    for line in buf.split_inclusive(|&b| b == b'\n') {
+        match line.split_last() {
+            Some((b'\n', text)) => {
+                console.write_all(text)?;
+                console.write_all(b"\r\n")?;
+            }
+            _ => console.write_all(line)?,
+        }
+    }
+    Ok(buf.len())
+}
+
 /// Internal function called by the `kprint[ln]!` macros.
 #[doc(hidden)]
 pub fn _print(args: fmt::Arguments) {
@@ -0,0 +1,401 @@
+use std::io;
+
+use fat32::traits::BlockDevice;
+
+use crate::fs::sd::{self, Sd};
+use crate::mutex::Mutex;
+
+/// Number of sectors kept in the cache.
+const CACHE_SECTORS: usize = 64;
+
+/// Number of sectors read ahead once sequential reads are detected.
+const READ_AHEAD: u64 = 8;
+
+/// Cache hit/miss counters.
+#[derive(Debug, Default, Copy, Clone)]
+pub struct Stats {
+    pub hits: usize,
+    pub misses: usize,
+    pub read_ahead: usize,
+    pub write_backs: usize,
+}
+
+/// A cached sector.
+#[derive(Debug)]
+struct Slot {
+    sector: u64,
+    data: Vec<u8>,
+    dirty: bool,
+    last_used: u64,
+}
+
+/// A write-back LRU sector cache in front of a block device.
+///
+/// Writes only reach the device when their sector is evicted or on `sync()`.
+/// After two misses on consecutive sectors, the following `READ_AHEAD`
+/// sectors are read in as well.
+#[derive(Debug)]
+pub struct BlockCache<T> {
+    device: T,
+    slots: Vec<Slot>,
+    capacity: usize,
+    clock: u64,
+    last_miss: Option<u64>,
+    stats: Stats,
+}
+
+impl<T: BlockDevice> BlockCache<T> {
+    /// Creates a cache of `capacity` sectors in front of `device`.
+    pub fn new(device: T, capacity: usize) -> BlockCache<T> {
+        BlockCache {
+            device,
+            slots: Vec::with_capacity(capacity),
+            capacity,
+            clock: 0,
+            last_miss: None,
+            stats: Stats::default(),
+        }
+    }
+
+    /// Returns the cache's hit/miss counters.
+    pub fn stats(&self) -> Stats {
+        self.stats
+    }
+
+    /// Writes every dirty sector back to the device.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first error writing a sector back. Sectors that fail to
+    /// write are dropped from the cache, so the next read of them returns
+    /// what the device holds rather than data it never got.
+    pub fn sync(&mut self) -> io::Result<()> {
+        let mut result = Ok(());
+        let (device, stats) = (&mut self.device, &mut self.stats);
+        self.slots.retain_mut(|slot| {
+            if !slot.dirty {
+                return true;
+            }
+            match device.write_sector(slot.sector, &slot.data) {
+                Ok(_) => {
+                    slot.dirty = false;
+                    stats.write_backs += 1;
+                    true
+                }
+                Err(e) => {
+                    if result.is_ok() {
+                        result = Err(e);
+                    }
+                    false
+                }
+            }
+        });
+        result
+    }
+
+    /// Returns the index of the slot caching sector `n`, if any, and marks it
+    /// as most recently used.
+    fn lookup(&mut self, n: u64) -> Option<usize> {
+        let i = self.slots.iter().position(|s| s.sector == n)?;
+        self.clock += 1;
+        self.slots[i].last_used = self.clock;
+        Some(i)
+    }
+
+    /// Returns a slot for sector `n`, evicting the least recently used sector
+    /// (writing it back if dirty) when the cache is full. The slot's data is
+    /// stale until the caller fills it.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error if writing the evicted sector back fails. That
+    /// sector is dropped anyway, like in `sync()`, so the next call finds a
+    /// free slot.
+    fn allocate(&mut self, n: u64) -> io::Result<usize> {
+        self.clock += 1;
+        if self.slots.len() < self.capacity {
+            self.slots.push(Slot {
+                sector: n,
+                data: vec![0; self.device.sector_size() as usize],
+                dirty: false,
+                last_used: self.clock,
+            });
+            return Ok(self.slots.len() - 1);
+        }
+
+        let i = (0..self.slots.len())
+            .min_by_key(|&i| self.slots[i].last_used)
+            .expect("cache has no slots");
+        let slot = &mut self.slots[i];
+        if slot.dirty {
+            if let Err(e) = self.device.write_sector(slot.sector, &slot.data) {
+                self.slots.swap_remove(i);
+                return Err(e);
+            }
+            self.stats.write_backs += 1;
+        }
+        slot.sector = n;
+        slot.dirty = false;
+        slot.last_used = self.clock;
+        Ok(i)
+    }
+
+    /// Reads sector `n` from the device into a new slot.
+    fn fill(&mut self, n: u64) -> io::Result<usize> {
+        let i = self.allocate(n)?;
+        if let Err(e) = self.device.read_sector(n, &mut self.slots[i].data) {
+            // Don't leave stale data behind under the new sector number.
+            self.slots.swap_remove(i);
+            return Err(e);
+        }
+        Ok(i)
+    }
+
+    /// Reads the sectors following `n` that aren't cached yet. Failures are
+    /// ignored since they may just be reads past the end of the device.
+    fn read_ahead(&mut self, n: u64) {
+        for ahead in n + 1..=n + READ_AHEAD {
+            if self.lookup(ahead).is_none() && self.fill(ahead).is_ok() {
+                self.stats.read_ahead += 1;
+            }
+        }
+        // A miss just past the window continues the sequential run.
+        self.last_miss = Some(n + READ_AHEAD);
+    }
+}
+
+impl<T: BlockDevice> BlockDevice for BlockCache<T> {
+    fn sector_size(&self) -> u64 {
+        self.device.sector_size()
+    }
+
+    fn read_sector(&mut self, n: u64, buf: &mut [u8]) -> io::Result<usize> {
+        let (i, miss) = match self.lookup(n) {
+            Some(i) => (i, false),
+            None => (self.fill(n)?, true),
+        };
+
+        let data = &self.slots[i].data;
+        let len = std::cmp::min(buf.len(), data.len());
+        buf[..len].copy_from_slice(&data[..len]);
+
+        if !miss {
+            self.stats.hits += 1;
+            return Ok(len);
+        }
+
+        self.stats.misses += 1;
+        let sequential = self.last_miss.map_or(false, |last| last + 1 == n);
+        self.last_miss = Some(n);
+        if sequential {
+            self.read_ahead(n);
+        }
+        Ok(len)
+    }
+
+    fn write_sector(&mut self, n: u64, buf: &[u8]) -> io::Result<usize> {
+        let sector_size = self.sector_size() as usize;
+        if buf.len() < sector_size {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "partial sector write"));
+        }
+
+        let i = match self.lookup(n) {
+            Some(i) => i,
+            None => self.allocate(n)?,
+        };
+        let slot = &mut self.slots[i];
+        slot.data.copy_from_slice(&buf[..sector_size]);
+        slot.dirty = true;
+        Ok(sector_size)
+    }
+}
+
+/// The cache shared by everything that reads the SD card.
+static SD_CACHE: Mutex<Option<BlockCache<Sd>>> = Mutex::new(None);
+
+/// A handle to the shared SD card cache. Every `CachedSd` goes through the
+/// same cache, so the file system and raw `/dev/sd0` reads share sectors.
+#[derive(Debug)]
+pub struct CachedSd(());
+
+impl CachedSd {
+    /// Returns a handle to the shared SD card cache, initializing the SD card
+    /// the first time.
+    pub fn open() -> Result<CachedSd, sd::Error> {
+        let mut cache = SD_CACHE.lock();
+        if cache.is_none() {
+            *cache = Some(BlockCache::new(Sd::new()?, CACHE_SECTORS));
+        }
+        Ok(CachedSd(()))
+    }
+
+    /// Returns the shared cache's hit/miss counters.
+    pub fn stats() -> Option<Stats> {
+        SD_CACHE.lock().as_ref().map(|c| c.stats())
+    }
+
+    /// Returns `true` if the SD card behind the shared cache can't be written
+    /// to, or if there is no SD card.
+    pub fn read_only() -> bool {
+        match SD_CACHE.lock().as_ref() {
+            Some(cache) => cache.device.read_only(),
+            None => true,
+        }
+    }
+
+    /// Writes all dirty sectors in the shared cache back to the SD card.
+    pub fn sync() -> io::Result<()> {
+        match SD_CACHE.lock().as_mut() {
+            Some(cache) => cache.sync(),
+            None => Ok(()),
+        }
+    }
+
+    /// Calls `f` with the SD card behind the shared cache, bypassing the cache.
+    /// Sectors written through `f` may be stale in the cache afterwards.
+    pub fn uncached<R>(f: impl FnOnce(&mut Sd) -> R) -> R {
+        CachedSd::with(|cache| f(&mut cache.device))
+    }
+
+    fn with<R>(f: impl FnOnce(&mut BlockCache<Sd>) -> R) -> R {
+        f(SD_CACHE.lock().as_mut().expect("SD cache opened"))
+    }
+}
+
+impl BlockDevice for CachedSd {
+    fn read_sector(&mut self, n: u64, buf: &mut [u8]) -> io::Result<usize> {
+        CachedSd::with(|cache| cache.read_sector(n, buf))
+    }
+
+    /// Fails with `sd::Error::ReadOnlyCard` before caching anything if the card
+    /// is read only, since the sector could never be written back.
+    fn write_sector(&mut self, n: u64, buf: &[u8]) -> io::Result<usize> {
+        CachedSd::with(|cache| match cache.device.read_only() {
+            true => Err(sd::Error::ReadOnlyCard.into()),
+            false => cache.write_sector(n, buf),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    /// An in-memory device that counts the sectors read from it.
+    struct Counting {
+        disk: Cursor<Vec<u8>>,
+        reads: usize,
+        fail_writes: bool,
+    }
+
+    impl Counting {
+        fn new(sectors: usize) -> Counting {
+            let data = (0..sectors * 512).map(|i| (i / 512) as u8).collect();
+            Counting { disk: Cursor::new(data), reads: 0, fail_writes: false }
+        }
+    }
+
+    impl BlockDevice for Counting {
+        fn read_sector(&mut self, n: u64, buf: &mut [u8]) -> io::Result<usize> {
+            self.reads += 1;
+            self.disk.read_sector(n, buf)
+        }
+
+        fn write_sector(&mut self, n: u64, buf: &[u8]) -> io::Result<usize> {
+            if self.fail_writes {
+                return Err(io::Error::new(io::ErrorKind::PermissionDenied, "read only"));
+            }
+            self.disk.write_sector(n, buf)
+        }
+    }
+
+    #[test]
+    fn caches_reads() {
+        let mut cache = BlockCache::new(Counting::new(16), 4);
+        let mut buf = [0u8; 512];
+
+        cache.read_sector(3, &mut buf).unwrap();
+        cache.read_sector(3, &mut buf).unwrap();
+        assert_eq!(buf, [3; 512]);
+        assert_eq!(cache.device.reads, 1);
+        assert_eq!((cache.stats().hits, cache.stats().misses), (1, 1));
+    }
+
+    #[test]
+    fn evicts_least_recently_used() {
+        let mut cache = BlockCache::new(Counting::new(16), 2);
+        let mut buf = [0u8; 512];
+
+        cache.read_sector(0, &mut buf).unwrap();
+        cache.read_sector(5, &mut buf).unwrap();
+        cache.read_sector(0, &mut buf).unwrap();
+        cache.read_sector(9, &mut buf).unwrap(); // evicts 5
+        cache.read_sector(0, &mut buf).unwrap();
+        assert_eq!(cache.device.reads, 3);
+
+        cache.read_sector(5, &mut buf).unwrap();
+        assert_eq!(buf, [5; 512]);
+        assert_eq!(cache.device.reads, 4);
+    }
+
+    #[test]
+    fn reads_ahead_sequentially() {
+        let mut cache = BlockCache::new(Counting::new(32), 16);
+        let mut buf = [0u8; 512];
+
+        cache.read_sector(4, &mut buf).unwrap();
+        cache.read_sector(5, &mut buf).unwrap();
+        assert_eq!(cache.stats().read_ahead, READ_AHEAD as usize);
+
+        let reads = cache.device.reads;
+        for n in 6..=5 + READ_AHEAD {
+            cache.read_sector(n, &mut buf).unwrap();
+            assert_eq!(buf, [n as u8; 512]);
+        }
+        assert_eq!(cache.device.reads, reads);
+    }
+
+    #[test]
+    fn writes_back_on_eviction_and_sync() {
+        let mut cache = BlockCache::new(Counting::new(16), 2);
+        let mut buf = [0u8; 512];
+
+        cache.write_sector(1, &[0xaa; 512]).unwrap();
+        cache.write_sector(2, &[0xbb; 512]).unwrap();
+        assert_eq!(cache.device.disk.get_ref()[512], 1);
+
+        cache.read_sector(7, &mut buf).unwrap(); // evicts 1
+        assert_eq!(cache.device.disk.get_ref()[512], 0xaa);
+        assert_eq!(cache.device.disk.get_ref()[1024], 2);
+
+        cache.sync().unwrap();
+        assert_eq!(cache.device.disk.get_ref()[1024], 0xbb);
+        assert_eq!(cache.stats().write_backs, 2);
+
+        cache.read_sector(1, &mut buf).unwrap();
+        assert_eq!(buf, [0xaa; 512]);
+    }
+
+    #[test]
+    fn drops_sectors_that_fail_to_write_back() {
+        let mut cache = BlockCache::new(Counting::new(16), 2);
+        cache.device.fail_writes = true;
+        let mut buf = [0u8; 512];
+
+        cache.write_sector(1, &[0xaa; 512]).unwrap();
+        cache.read_sector(2, &mut buf).unwrap();
+        cache.read_sector(3, &mut buf).unwrap_err(); // evicting 1 fails
+        cache.read_sector(3, &mut buf).unwrap();
+        assert_eq!(buf, [3; 512]);
+        cache.read_sector(1, &mut buf).unwrap();
+        assert_eq!(buf, [1; 512]);
+
+        cache.write_sector(3, &[0xbb; 512]).unwrap();
+        cache.sync().unwrap_err();
+        cache.sync().unwrap();
+        cache.read_sector(3, &mut buf).unwrap();
+        assert_eq!(buf, [3; 512]);
+        assert_eq!(cache.stats().write_backs, 0);
+    }
+}
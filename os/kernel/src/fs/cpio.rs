@@ -0,0 +1,256 @@
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::str;
+
+/// Magic number of a "newc" (SVR4 without CRC) cpio header.
+const MAGIC: &[u8] = b"070701";
+
+/// Size of a newc header: the magic followed by 13 fields of 8 hex digits.
+const HEADER_SIZE: usize = 110;
+
+/// Name of the entry marking the end of an archive.
+const TRAILER: &str = "TRAILER!!!";
+
+/// File type bits of `mode`, and the value for regular files.
+const S_IFMT: u32 = 0o170000;
+const S_IFREG: u32 = 0o100000;
+
+/// Error type for malformed archives.
+#[derive(Debug, PartialEq)]
+pub enum Error {
+    /// An entry doesn't start with the newc magic number.
+    BadMagic,
+    /// A header field isn't a hex number or a name isn't UTF-8.
+    BadHeader,
+    /// The archive ends in the middle of an entry.
+    Truncated,
+}
+
+/// A newc-format cpio archive held in memory, such as an initramfs.
+#[derive(Debug, Copy, Clone)]
+pub struct Cpio {
+    data: &'static [u8],
+}
+
+/// A single archive member.
+#[derive(Debug, Copy, Clone)]
+pub struct Entry {
+    pub name: &'static str,
+    pub mode: u32,
+    pub data: &'static [u8],
+}
+
+impl Entry {
+    /// Returns `true` if this entry is a regular file.
+    pub fn is_file(&self) -> bool {
+        self.mode & S_IFMT == S_IFREG
+    }
+}
+
+/// Rounds `n` up to the next multiple of 4, the alignment of names and data.
+fn align4(n: usize) -> usize {
+    (n + 3) & !3
+}
+
+/// Parses the `i`th 8-digit hex field of `header`.
+fn field(header: &[u8], i: usize) -> Result<usize, Error> {
+    let start = MAGIC.len() + i * 8;
+    let digits = str::from_utf8(&header[start..start + 8]).map_err(|_| Error::BadHeader)?;
+    usize::from_str_radix(digits, 16).map_err(|_| Error::BadHeader)
+}
+
+impl Cpio {
+    /// Wraps the archive in `data`, checking that every entry is well formed.
+    pub fn new(data: &'static [u8]) -> Result<Cpio, Error> {
+        let cpio = Cpio { data };
+        for entry in cpio.iter() {
+            entry?;
+        }
+        Ok(cpio)
+    }
+
+    /// Returns an iterator over the entries of the archive.
+    pub fn iter(&self) -> Entries {
+        Entries { data: self.data, offset: 0 }
+    }
+
+    /// Finds the regular file at `path`. Leading slashes are ignored, since
+    /// archive names are relative to the root.
+    pub fn find(&self, path: &str) -> Option<Entry> {
+        let path = path.trim_start_matches('/');
+        self.iter()
+            .filter_map(|e| e.ok())
+            .find(|e| e.is_file() && e.name.trim_start_matches("./") == path)
+    }
+}
+
+/// Iterator over the entries of a `Cpio`. Stops at the trailer or after the
+/// first error.
+pub struct Entries {
+    data: &'static [u8],
+    offset: usize,
+}
+
+impl Entries {
+    fn parse(&mut self) -> Result<Option<Entry>, Error> {
+        let data = self.data;
+        let header = data.get(self.offset..self.offset + HEADER_SIZE).ok_or(Error::Truncated)?;
+        if &header[..MAGIC.len()] != MAGIC {
+            return Err(Error::BadMagic);
+        }
+
+        let mode = field(header, 1)? as u32;
+        let file_size = field(header, 6)?;
+        let name_size = field(header, 11)?;
+
+        let name_start = self.offset + HEADER_SIZE;
+        let name = data.get(name_start..name_start + name_size).ok_or(Error::Truncated)?;
+        // `name_size` counts the NUL terminator.
+        let name = name.split_last().ok_or(Error::BadHeader)?.1;
+        let name = str::from_utf8(name).map_err(|_| Error::BadHeader)?;
+
+        let data_start = align4(name_start + name_size);
+        let file = data.get(data_start..data_start + file_size).ok_or(Error::Truncated)?;
+        self.offset = align4(data_start + file_size);
+
+        match name {
+            TRAILER => Ok(None),
+            _ => Ok(Some(Entry { name, mode, data: file })),
+        }
+    }
+}
+
+impl Iterator for Entries {
+    type Item = Result<Entry, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.offset >= self.data.len() {
+            return None;
+        }
+
+        match self.parse() {
+            Ok(Some(entry)) => Some(Ok(entry)),
+            Ok(None) => {
+                self.offset = self.data.len();
+                None
+            }
+            Err(e) => {
+                self.offset = self.data.len();
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+/// An open, read-only file from a `Cpio` archive.
+#[derive(Debug)]
+pub struct File {
+    data: &'static [u8],
+    pos: u64,
+}
+
+impl From<Entry> for File {
+    fn from(entry: Entry) -> File {
+        File { data: entry.data, pos: 0 }
+    }
+}
+
+impl Read for File {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let start = std::cmp::min(self.pos, self.data.len() as u64) as usize;
+        let n = std::cmp::min(buf.len(), self.data.len() - start);
+        buf[..n].copy_from_slice(&self.data[start..start + n]);
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+impl Write for File {
+    fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+        Err(io::Error::new(io::ErrorKind::PermissionDenied, "initramfs is read only"))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Seek for File {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let (base, delta) = match pos {
+            SeekFrom::Start(offset) => (0, offset as i64),
+            SeekFrom::Current(delta) => (self.pos as i64, delta),
+            SeekFrom::End(delta) => (self.data.len() as i64, delta),
+        };
+
+        match base.checked_add(delta) {
+            Some(pos) if pos >= 0 => {
+                self.pos = pos as u64;
+                Ok(self.pos)
+            }
+            _ => Err(io::Error::new(io::ErrorKind::InvalidInput, "invalid seek")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Appends a newc entry to `archive`.
+    fn push_entry(archive: &mut Vec<u8>, name: &str, mode: u32, data: &[u8]) {
+        let header = format!(
+            "070701{:08x}{:08x}{:08x}{:08x}{:08x}{:08x}{:08x}{:08x}{:08x}{:08x}{:08x}{:08x}{:08x}",
+            0, mode, 0, 0, 1, 0, data.len(), 0, 0, 0, 0, name.len() + 1, 0
+        );
+        archive.extend_from_slice(header.as_bytes());
+        archive.extend_from_slice(name.as_bytes());
+        archive.push(0);
+        archive.resize(align4(archive.len()), 0);
+        archive.extend_from_slice(data);
+        archive.resize(align4(archive.len()), 0);
+    }
+
+    fn archive() -> &'static [u8] {
+        let mut archive = vec![];
+        push_entry(&mut archive, ".", 0o040755, b"");
+        push_entry(&mut archive, "./init.sh", 0o100644, b"echo hi\n");
+        push_entry(&mut archive, "bin/hello", 0o100755, b"\x7fELF");
+        push_entry(&mut archive, TRAILER, 0, b"");
+        Box::leak(archive.into_boxed_slice())
+    }
+
+    #[test]
+    fn lists_entries() {
+        let cpio = Cpio::new(archive()).expect("valid archive");
+        let names: Vec<_> = cpio.iter().map(|e| e.unwrap().name).collect();
+        assert_eq!(names, [".", "./init.sh", "bin/hello"]);
+    }
+
+    #[test]
+    fn finds_and_reads_files() {
+        let cpio = Cpio::new(archive()).expect("valid archive");
+        assert!(cpio.find("/").is_none());
+
+        let mut file = File::from(cpio.find("/init.sh").expect("init.sh"));
+        let mut contents = vec![];
+        file.read_to_end(&mut contents).unwrap();
+        assert_eq!(contents, b"echo hi\n");
+
+        let mut file = File::from(cpio.find("bin/hello").expect("bin/hello"));
+        file.seek(SeekFrom::End(-3)).unwrap();
+        let mut contents = vec![];
+        file.read_to_end(&mut contents).unwrap();
+        assert_eq!(contents, b"ELF");
+    }
+
+    #[test]
+    fn rejects_malformed_archives() {
+        let data = archive();
+        assert_eq!(Cpio::new(&data[..data.len() - 8]).unwrap_err(), Error::Truncated);
+
+        let mut bad = data.to_vec();
+        bad[0] = b'1';
+        let bad: &'static [u8] = Box::leak(bad.into_boxed_slice());
+        assert_eq!(Cpio::new(bad).unwrap_err(), Error::BadMagic);
+    }
+}
@@ -0,0 +1,130 @@
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+use fat32::traits::BlockDevice;
+
+use crate::console::{self, CONSOLE};
+use crate::fs::bcache::CachedSd;
+use crate::fs::sd;
+
+/// Directory under which device nodes appear.
+pub const DEV_DIR: &str = "/dev";
+
+/// Names of all device nodes, relative to `DEV_DIR`.
+pub const DEVICES: &[&str] = &["console", "uart0", "null", "zero", "sd0"];
+
+/// Size of an SD card sector in bytes.
+const SECTOR_SIZE: u64 = 512;
+
+/// An open device node.
+#[derive(Debug)]
+pub enum Device {
+    /// The kernel console: reads from the UART, writes to every console sink.
+    Console,
+    /// The UART alone, bypassing any other console sinks.
+    Uart0,
+    /// Reads nothing, discards writes.
+    Null,
+    /// Reads zeros, discards writes.
+    Zero,
+    /// The raw SD card as one seekable, read-only byte stream.
+    Sd0 { sd: CachedSd, pos: u64 },
+}
+
+impl Device {
+    /// Opens the device node named `name`, e.g. `"null"`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `NotFound` if there is no such device, or the error of
+    /// initializing the device.
+    pub fn open(name: &str) -> io::Result<Device> {
+        Ok(match name {
+            "console" => Device::Console,
+            "uart0" => Device::Uart0,
+            "null" => Device::Null,
+            "zero" => Device::Zero,
+            "sd0" => Device::Sd0 { sd: CachedSd::open()?, pos: 0 },
+            _ => return Err(io::Error::new(io::ErrorKind::NotFound, "no such device")),
+        })
+    }
+}
+
+/// Reads at least one byte from the console into `buf`, blocking until one is
+/// available, plus whatever else has already arrived.
+fn read_console(buf: &mut [u8]) -> usize {
+    if buf.is_empty() {
+        return 0;
+    }
+
+    buf[0] = console::read_byte();
+    let mut console = CONSOLE.lock();
+    let mut n = 1;
+    while n < buf.len() {
+        match console.try_read_byte() {
+            Some(byte) => buf[n] = byte,
+            None => break,
+        }
+        n += 1;
+    }
+    n
+}
+
+impl Read for Device {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Device::Console | Device::Uart0 => Ok(read_console(buf)),
+            Device::Null => Ok(0),
+            Device::Zero => {
+                buf.iter_mut().for_each(|b| *b = 0);
+                Ok(buf.len())
+            }
+            Device::Sd0 { sd, pos } => {
+                let mut sector = [0u8; SECTOR_SIZE as usize];
+                sd.read_sector(*pos / SECTOR_SIZE, &mut sector)?;
+
+                let start = (*pos % SECTOR_SIZE) as usize;
+                let n = std::cmp::min(buf.len(), sector.len() - start);
+                buf[..n].copy_from_slice(&sector[start..start + n]);
+                *pos += n as u64;
+                Ok(n)
+            }
+        }
+    }
+}
+
+impl Write for Device {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Device::Console => CONSOLE.lock().write(buf),
+            Device::Uart0 => CONSOLE.lock().write_uart(buf),
+            Device::Null | Device::Zero => Ok(buf.len()),
+            Device::Sd0 { .. } => Err(sd::Error::ReadOnlyCard.into()),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Seek for Device {
+    /// Only `sd0` is seekable, and only relative to its start or the current
+    /// position since the card's size isn't known.
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let invalid = |msg| io::Error::new(io::ErrorKind::InvalidInput, msg);
+        match self {
+            Device::Sd0 { pos: current, .. } => {
+                *current = match pos {
+                    SeekFrom::Start(offset) => offset,
+                    SeekFrom::Current(delta) if delta >= 0 => *current + delta as u64,
+                    SeekFrom::Current(delta) => current
+                        .checked_sub(delta.unsigned_abs())
+                        .ok_or_else(|| invalid("seek before start of device"))?,
+                    SeekFrom::End(_) => return Err(invalid("size of sd0 is unknown")),
+                };
+                Ok(*current)
+            }
+            _ => Err(invalid("device is not seekable")),
+        }
+    }
+}
@@ -1,33 +1,280 @@
+pub mod bcache;
+pub mod cpio;
+pub mod dev;
+pub mod proc;
+pub mod receive;
 pub mod sd;
+#[cfg(feature = "custom-std")]
+pub mod syscalls;
 
-use std::io;
+use std::io::{self, Read, Seek, SeekFrom, Write};
 use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
 
 use fat32::vfat::{self, Shared, VFat};
 pub use fat32::traits;
+use fat32::traits::{Dir as _, Entry as _, File as _, FileSystem as _};
+use pi::atags::Atags;
 
+use crate::ktest::{ensure, kernel_test};
+use crate::log::{error, info, warn};
 use crate::mutex::Mutex;
-use self::sd::Sd;
+use self::bcache::CachedSd;
+use self::cpio::Cpio;
+use self::dev::{Device, DEV_DIR};
+use self::proc::PROC_DIR;
 
-pub struct FileSystem(Mutex<Option<Shared<VFat>>>);
+/// The kernel's view of all mounted file systems: the FAT32 partition of the
+/// SD card, an optional initramfs and the `/dev` device nodes.
+pub struct FileSystem {
+    vfat: Mutex<Option<Shared<VFat>>>,
+    initrd: Mutex<Option<Cpio>>,
+    read_only: AtomicBool,
+}
 
 impl FileSystem {
     /// Returns an uninitialized `FileSystem`.
     ///
-    /// The file system must be initialized by calling `initialize()` before the
-    /// first memory allocation. Failure to do will result in panics.
+    /// The file system must be initialized by calling `initialize()` before it
+    /// is first used. Until then, every operation fails with `NotFound`.
     pub const fn uninitialized() -> Self {
-        FileSystem(Mutex::new(None))
+        FileSystem {
+            vfat: Mutex::new(None),
+            initrd: Mutex::new(None),
+            read_only: AtomicBool::new(false),
+        }
     }
 
-    /// Initializes the file system.
+    /// Mounts the initramfs passed by the bootloader, if any, then, if
+    /// `mount_sd` is set, initializes the SD card and mounts the first FAT32
+    /// partition on it. The partition is mounted read only if the card can't
+    /// be written to.
     ///
     /// # Panics
     ///
-    /// Panics if the underlying disk or file sytem failed to initialize.
-    pub fn initialize(&self) {
-        unimplemented!("FileSystem::initialize()")
+    /// Panics if the underlying disk or file sytem failed to initialize and
+    /// there is no initramfs to fall back on.
+    pub fn initialize(&self, mount_sd: bool) {
+        let have_initrd = self.mount_initrd();
+        if !mount_sd {
+            if !have_initrd {
+                warn!("not mounting the SD card, but there is no initramfs either");
+            }
+            return;
+        }
+
+        let vfat = CachedSd::open()
+            .map_err(|e| format!("failed to initialize SD card: {:?}", e))
+            .and_then(|sd| {
+                VFat::from(sd).map_err(|e| format!("failed to mount FAT32 file system: {:?}", e))
+            });
+
+        match vfat {
+            Ok(vfat) => {
+                let read_only = CachedSd::read_only();
+                self.read_only.store(read_only, Ordering::Relaxed);
+                *self.vfat.lock() = Some(vfat);
+                match read_only {
+                    true => info!("mounted FAT32 file system from SD card, read only"),
+                    false => info!("mounted FAT32 file system from SD card"),
+                }
+            }
+            Err(msg) if have_initrd => error!("{}", msg),
+            Err(msg) => panic!("{}", msg),
+        }
+    }
+
+    /// Mounts the newc cpio archive described by the `INITRD2` ATAG, if there
+    /// is one. Returns `true` if an initramfs was mounted.
+    fn mount_initrd(&self) -> bool {
+        let initrd = match Atags::get().find_map(|atag| atag.initrd()) {
+            Some(initrd) => initrd,
+            None => return false,
+        };
+
+        let data = unsafe {
+            std::slice::from_raw_parts(initrd.start as usize as *const u8, initrd.size as usize)
+        };
+        match Cpio::new(data) {
+            Ok(cpio) => {
+                *self.initrd.lock() = Some(cpio);
+                info!("mounted {} byte initramfs at {:#x}", initrd.size, initrd.start);
+                true
+            }
+            Err(e) => {
+                error!("ignoring malformed initramfs: {:?}", e);
+                false
+            }
+        }
+    }
+
+    /// Opens the file or device node at `path` for reading and writing.
+    /// Paths under `/dev` name devices and paths under `/proc` name synthetic
+    /// diagnostics files; everything else is looked up in the initramfs first
+    /// and then on the FAT32 file system.
+    ///
+    /// # Errors
+    ///
+    /// Returns `NotFound` if there is no such file or device, and any error
+    /// from opening it.
+    pub fn open_handle(&self, path: &str) -> io::Result<Handle> {
+        if let Some(name) = name_in(DEV_DIR, path) {
+            return Device::open(name).map(Handle::Device);
+        }
+
+        if let Some(name) = name_in(PROC_DIR, path) {
+            return proc::File::open(name).map(Handle::Proc);
+        }
+
+        match self.initrd.lock().and_then(|cpio| cpio.find(path)) {
+            Some(entry) => Ok(Handle::Initrd(entry.into())),
+            None => (&*self).open_file(path).map(Handle::File),
+        }
+    }
+
+    /// Returns `true` if the FAT32 file system is mounted read only.
+    pub fn is_read_only(&self) -> bool {
+        self.read_only.load(Ordering::Relaxed)
+    }
+
+    /// Returns an error of kind `PermissionDenied` if the FAT32 file system is
+    /// mounted read only.
+    fn check_writable(&self) -> io::Result<()> {
+        match self.is_read_only() {
+            true => Err(io::Error::new(io::ErrorKind::PermissionDenied, "read only file system")),
+            false => Ok(()),
+        }
+    }
+
+    /// Returns a handle to the mounted file system.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind `NotFound` if the file system hasn't been
+    /// initialized.
+    fn vfat(&self) -> io::Result<Shared<VFat>> {
+        self.vfat
+            .lock()
+            .as_ref()
+            .cloned()
+            .ok_or(io::Error::new(io::ErrorKind::NotFound, "file system not mounted"))
+    }
+}
+
+/// Returns the name of `path` relative to the directory `dir` if `path` is
+/// directly inside `dir`.
+fn name_in<'a>(dir: &str, path: &'a str) -> Option<&'a str> {
+    path.strip_prefix(dir)?.strip_prefix('/')
+}
+
+/// An open file, device node or synthetic file, as returned by
+/// `open_handle()`.
+#[derive(Debug)]
+pub enum Handle {
+    File(vfat::File),
+    Initrd(cpio::File),
+    Proc(proc::File),
+    Device(Device),
+}
+
+impl Read for Handle {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Handle::File(file) => file.read(buf),
+            Handle::Initrd(file) => file.read(buf),
+            Handle::Proc(file) => file.read(buf),
+            Handle::Device(dev) => dev.read(buf),
+        }
+    }
+}
+
+impl Write for Handle {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Handle::File(file) => {
+                crate::FILE_SYSTEM.check_writable()?;
+                file.write(buf)
+            }
+            Handle::Initrd(file) => file.write(buf),
+            Handle::Proc(file) => file.write(buf),
+            Handle::Device(dev) => dev.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Handle::File(file) => file.flush(),
+            Handle::Initrd(file) => file.flush(),
+            Handle::Proc(file) => file.flush(),
+            Handle::Device(dev) => dev.flush(),
+        }
     }
 }
 
-// FIXME: Implement `fat32::traits::FileSystem` for a useful type.
+impl Seek for Handle {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        match self {
+            Handle::File(file) => file.seek(pos),
+            Handle::Initrd(file) => file.seek(pos),
+            Handle::Proc(file) => file.seek(pos),
+            Handle::Device(dev) => dev.seek(pos),
+        }
+    }
+}
+
+impl<'a> traits::FileSystem for &'a FileSystem {
+    type File = vfat::File;
+    type Dir = vfat::Dir;
+    type Entry = vfat::Entry;
+
+    fn open<P: AsRef<Path>>(self, path: P) -> io::Result<Self::Entry> {
+        self.vfat()?.open(path)
+    }
+
+    fn create_file<P: AsRef<Path>>(self, path: P) -> io::Result<Self::File> {
+        self.check_writable()?;
+        self.vfat()?.create_file(path)
+    }
+
+    fn create_dir<P: AsRef<Path>>(self, path: P, parents: bool) -> io::Result<Self::Dir> {
+        self.check_writable()?;
+        self.vfat()?.create_dir(path, parents)
+    }
+
+    fn rename<P: AsRef<Path>, Q: AsRef<Path>>(self, from: P, to: Q) -> io::Result<()> {
+        self.check_writable()?;
+        self.vfat()?.rename(from, to)
+    }
+
+    fn remove<P: AsRef<Path>>(self, path: P, children: bool) -> io::Result<()> {
+        self.check_writable()?;
+        self.vfat()?.remove(path, children)
+    }
+}
+
+#[cfg(feature = "ktest")]
+kernel_test!(fat32_on_ram_disk, {
+    let disk = io::Cursor::new(fat32::testing::blank_fat32());
+    let vfat = VFat::from(disk).map_err(|_| "mounting the RAM disk failed")?;
+
+    // Three clusters and a bit, so the file spans a cluster chain.
+    let data: Vec<u8> = (0..1600u32).map(|i| (i * 7) as u8).collect();
+    let mut file = vfat.create_file("/kernel8.img").map_err(|_| "creating a file failed")?;
+    file.write_all(&data).map_err(|_| "writing the file failed")?;
+    file.sync().map_err(|_| "syncing the file failed")?;
+
+    let names: Vec<String> = vfat
+        .open_dir("/")
+        .and_then(|dir| dir.entries())
+        .map_err(|_| "listing the root directory failed")?
+        .map(|entry| entry.name().to_string())
+        .collect();
+    ensure!(names == ["kernel8.img"], "wrong root directory entries");
+
+    let mut read = Vec::new();
+    vfat.open_file("/KERNEL8.IMG")
+        .and_then(|mut file| file.read_to_end(&mut read))
+        .map_err(|_| "reading the file back failed")?;
+    ensure!(read == data, "file read back differs from what was written");
+    Ok(())
+});
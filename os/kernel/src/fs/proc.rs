@@ -0,0 +1,145 @@
+use std::fmt::{self, Write as _};
+use std::io::{self, Cursor, Read, Seek, SeekFrom, Write};
+
+use pi::atags::Atags;
+use pi::emmc::Emmc;
+use pi::timer;
+
+use crate::fs::bcache::CachedSd;
+use crate::fs::sd::Sd;
+use crate::memmap::MEMORY_MAP;
+use crate::ALLOCATOR;
+
+/// Directory under which the synthetic files appear.
+pub const PROC_DIR: &str = "/proc";
+
+/// Names of all files in `PROC_DIR`, with the functions producing them.
+const FILES: &[(&str, fn(&mut dyn fmt::Write) -> fmt::Result)] = &[
+    ("uptime", write_uptime),
+    ("meminfo", write_meminfo),
+    ("memmap", write_memmap),
+    ("sd", write_sd),
+];
+
+/// Returns the names of all files in `PROC_DIR`.
+pub fn files() -> impl Iterator<Item = &'static str> {
+    FILES.iter().map(|&(name, _)| name)
+}
+
+/// Writes the time elapsed since boot.
+pub fn write_uptime(w: &mut dyn fmt::Write) -> fmt::Result {
+    let us = timer::current_time();
+    writeln!(w, "{}.{:06}s ({} us)", us / 1_000_000, us % 1_000_000, us)
+}
+
+/// Writes the memory reported by the firmware and the heap allocator's usage.
+pub fn write_meminfo(w: &mut dyn fmt::Write) -> fmt::Result {
+    for mem in Atags::get().filter_map(|atag| atag.mem()) {
+        writeln!(
+            w,
+            "memory:        {:#010x}-{:#010x} ({} KiB)",
+            mem.start,
+            mem.start + mem.size,
+            mem.size / 1024
+        )?;
+    }
+
+    let stats = match ALLOCATOR.stats() {
+        Some(stats) => stats,
+        None => return writeln!(w, "heap:          uninitialized"),
+    };
+    writeln!(w, "heap:          {:#010x}-{:#010x}", stats.start, stats.end)?;
+    writeln!(w, "  total:       {} KiB", stats.total() / 1024)?;
+    writeln!(w, "  allocated:   {} KiB", stats.allocated / 1024)?;
+    writeln!(w, "  available:   {} KiB", (stats.total() - stats.allocated) / 1024)?;
+    writeln!(w, "  largest:     {} KiB", stats.largest_free / 1024)?;
+    writeln!(w, "  allocs:      {}", stats.allocs)?;
+    writeln!(w, "  deallocs:    {}", stats.deallocs)
+}
+
+/// Writes the physical memory map: RAM and the regions reserved in it.
+pub fn write_memmap(w: &mut dyn fmt::Write) -> fmt::Result {
+    MEMORY_MAP.lock().write(w)
+}
+
+/// Writes the state of the SD host controller.
+pub fn write_sd(w: &mut dyn fmt::Write) -> fmt::Result {
+    let emmc = Emmc::new();
+    let spec_version = match emmc.spec_version() {
+        0 => "1.00",
+        1 => "2.00",
+        2 => "3.00",
+        _ => "unknown",
+    };
+
+    writeln!(w, "host:")?;
+    writeln!(w, "  vendor version: {:#04x}", emmc.vendor_version())?;
+    writeln!(w, "  spec version:   {}", spec_version)?;
+    writeln!(w, "  capabilities:   {:#018x}", emmc.capabilities())?;
+    writeln!(w, "bus:")?;
+    writeln!(w, "  clock:          {} Hz (divisor {})", emmc.clock_hz(), emmc.clock_divisor())?;
+    writeln!(w, "  width:          {}-bit", emmc.bus_width())?;
+    writeln!(w, "status:")?;
+    writeln!(w, "  present state:  {:#010x}", emmc.status())?;
+    writeln!(w, "  write protect:  {}", if emmc.write_protected() { "on" } else { "off" })?;
+    writeln!(w, "  interrupt:      {:#010x}", emmc.interrupt())?;
+    writeln!(w, "  irpt mask/en:   {:#010x}/{:#010x}", emmc.interrupt_mask(), emmc.interrupt_enable())?;
+    let resp = emmc.response();
+    writeln!(w, "  last response:  {:08x} {:08x} {:08x} {:08x}", resp[3], resp[2], resp[1], resp[0])?;
+    writeln!(w, "  libsd error:    {}", Sd::last_error())?;
+
+    let stats = match CachedSd::stats() {
+        Some(stats) => stats,
+        None => return writeln!(w, "cache:          unused"),
+    };
+    writeln!(w, "cache:")?;
+    writeln!(w, "  hits/misses:    {}/{}", stats.hits, stats.misses)?;
+    writeln!(w, "  read ahead:     {}", stats.read_ahead)?;
+    writeln!(w, "  write backs:    {}", stats.write_backs)
+}
+
+/// An open file from `PROC_DIR`. Its contents are generated when it is
+/// opened and don't change afterwards.
+#[derive(Debug)]
+pub struct File(Cursor<Vec<u8>>);
+
+impl File {
+    /// Opens the file named `name`, e.g. `"meminfo"`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `NotFound` if there is no such file.
+    pub fn open(name: &str) -> io::Result<File> {
+        let generate = FILES
+            .iter()
+            .find(|&&(n, _)| n == name)
+            .map(|&(_, generate)| generate)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no such file"))?;
+
+        let mut contents = String::new();
+        generate(&mut contents).map_err(|_| io::Error::new(io::ErrorKind::Other, "formatter error"))?;
+        Ok(File(Cursor::new(contents.into_bytes())))
+    }
+}
+
+impl Read for File {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.read(buf)
+    }
+}
+
+impl Write for File {
+    fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+        Err(io::Error::new(io::ErrorKind::PermissionDenied, "/proc is read only"))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Seek for File {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.0.seek(pos)
+    }
+}
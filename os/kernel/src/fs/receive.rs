@@ -0,0 +1,78 @@
+//! Receiving files sent with `ttywrite --batch`: a YMODEM batch over the
+//! console UART, written to the FAT32 file system.
+
+use std::io::{self, Read, Write};
+use std::path::Path;
+
+use fat32::traits::FileSystem as _;
+use fat32::vfat;
+use xmodem::Xmodem;
+
+use crate::console::{self, CONSOLE};
+use crate::fs::bcache::CachedSd;
+use crate::FILE_SYSTEM;
+
+/// How long a read waits for the sender. The receiver asks again after every
+/// timeout, until it runs out of retries.
+const READ_TIMEOUT_MS: u64 = 1000;
+
+/// The console UART as a YMODEM link. Writes skip the other console sinks,
+/// which have no use for protocol bytes.
+struct Link;
+
+impl Read for Link {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        console::read_timeout(buf, READ_TIMEOUT_MS)
+    }
+}
+
+impl Write for Link {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        CONSOLE.lock().write_uart(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Receives a YMODEM batch over the console UART and writes every file in it
+/// under the directory `dir`, replacing files that are already there, then
+/// writes everything back to the SD card. Returns the number of files
+/// received.
+///
+/// File names may contain directories, such as `overlays/foo.dtbo`; these
+/// must already exist, since the file system can't create directories.
+///
+/// # Errors
+///
+/// Returns an error of kind `PermissionDenied` without receiving anything if
+/// the file system is read only. Returns an error of kind `InvalidInput` if a
+/// file name is absolute or contains `..`, and any error receiving or writing
+/// the files.
+pub fn receive_batch(dir: &str) -> io::Result<usize> {
+    // Don't change cached FAT32 structures that could never be written back.
+    if FILE_SYSTEM.is_read_only() {
+        return Err(io::Error::new(io::ErrorKind::PermissionDenied, "read only file system"));
+    }
+
+    let mut receiver = Xmodem::new(Link);
+    let received = receiver.receive_batch(|name, _| create(dir, name));
+    // Whatever was written before a failure is written back as well.
+    CachedSd::sync()?;
+    received
+}
+
+/// Creates the file `name` under `dir`, removing any file already there.
+fn create(dir: &str, name: &str) -> io::Result<vfat::File> {
+    if name.starts_with('/') || name.split('/').any(|c| c.is_empty() || c == "." || c == "..") {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "unsafe file name"));
+    }
+
+    let path = Path::new(dir).join(name);
+    match (&FILE_SYSTEM).remove(&path, false) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+        _ => {}
+    }
+    (&FILE_SYSTEM).create_file(&path)
+}
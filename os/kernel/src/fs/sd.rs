@@ -1,5 +1,12 @@
 use std::io;
 use fat32::traits::BlockDevice;
+use pi::emmc::Emmc;
+use pi::timer;
+
+use crate::ktest::{ensure, kernel_test};
+use crate::log::warn;
+use crate::trace::{self, Event};
+use crate::watchdog;
 
 extern "C" {
     /// A global representing the last SD controller error that occured.
@@ -25,22 +32,143 @@ extern "C" {
     fn sd_readsector(n: i32, buffer: *mut u8) -> i32;
 }
 
-// FIXME: Define a `#[no_mangle]` `wait_micros` function for use by `libsd`.
-// The `wait_micros` C signature is: `void wait_micros(unsigned int);`
+/// Whether `libsd` can write sectors. It has no `sd_writesector()` yet.
+const LIBSD_WRITES: bool = false;
+
+/// Sleeps for `us` microseconds. Called by `libsd`, which declares it as
+/// `void wait_micros(unsigned int);`.
+#[no_mangle]
+pub extern "C" fn wait_micros(us: u32) {
+    timer::spin_sleep_us(us as u64)
+}
 
+/// An error reported by the SD card controller.
 #[derive(Debug)]
 pub enum Error {
-    // FIXME: Fill me in.
+    /// A timeout occurred.
+    Timeout,
+    /// Sending a command to the SD controller failed.
+    SendCommand,
+    /// The card can't be written to.
+    ReadOnlyCard,
+    /// Any other error, with the raw (negative) code from `libsd`.
+    Unknown(i64),
+}
+
+impl From<i64> for Error {
+    fn from(code: i64) -> Error {
+        match code {
+            -1 => Error::Timeout,
+            -2 => Error::SendCommand,
+            code => Error::Unknown(code),
+        }
+    }
+}
+
+impl From<Error> for io::Error {
+    fn from(error: Error) -> io::Error {
+        match error {
+            Error::Timeout => io::Error::new(io::ErrorKind::TimedOut, "SD card timed out"),
+            Error::SendCommand => io::Error::new(io::ErrorKind::Other, "SD card command failed"),
+            Error::ReadOnlyCard => {
+                io::Error::new(io::ErrorKind::PermissionDenied, "SD card is read only")
+            }
+            Error::Unknown(_) => io::Error::new(io::ErrorKind::Other, "SD card error"),
+        }
+    }
+}
+
+/// How an SD operation is retried when it fails.
+#[derive(Debug, Copy, Clone)]
+pub struct RetryPolicy {
+    /// Number of attempts, including the first one.
+    pub attempts: u32,
+    /// Time to wait before the first retry, in microseconds. It doubles
+    /// before every following retry.
+    pub backoff_us: u64,
+    /// Returns `true` if a failure with the given error is worth retrying.
+    pub retryable: fn(&Error) -> bool,
+}
+
+impl RetryPolicy {
+    /// The default policy for card initialization. Marginal cards often
+    /// need a second try after a command failure as well as a timeout.
+    pub const INIT: RetryPolicy = RetryPolicy {
+        attempts: 3,
+        backoff_us: 10_000,
+        retryable: |e| matches!(e, Error::Timeout | Error::SendCommand),
+    };
+
+    /// The default policy for sector reads.
+    pub const READ: RetryPolicy = RetryPolicy {
+        attempts: 3,
+        backoff_us: 1_000,
+        retryable: |e| matches!(e, Error::Timeout),
+    };
+
+    /// Never retries.
+    pub const NEVER: RetryPolicy = RetryPolicy { attempts: 1, backoff_us: 0, retryable: |_| false };
+
+    /// Calls `f` until it succeeds, fails with an error that isn't retryable,
+    /// or has been called `attempts` times. Returns the last result.
+    fn run<T>(&self, what: &str, mut f: impl FnMut() -> Result<T, Error>) -> Result<T, Error> {
+        let mut backoff = self.backoff_us;
+        let mut attempt = 1;
+        loop {
+            match f() {
+                Err(e) if attempt < self.attempts && (self.retryable)(&e) => {
+                    warn!("{} failed ({:?}), retrying", what, e);
+                    if backoff > 0 {
+                        timer::spin_sleep_us(backoff);
+                    }
+                    backoff *= 2;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
 }
 
 /// A handle to an SD card controller.
 #[derive(Debug)]
-pub struct Sd;
+pub struct Sd {
+    read_retry: RetryPolicy,
+}
 
 impl Sd {
-    /// Initializes the SD card controller and returns a handle to it.
+    /// Initializes the SD card controller and returns a handle to it, using
+    /// the default retry policies.
     pub fn new() -> Result<Sd, Error> {
-        unimplemented!("Sd::new()")
+        Sd::with_retry(RetryPolicy::INIT, RetryPolicy::READ)
+    }
+
+    /// Initializes the SD card controller, retrying as `init` says, and
+    /// returns a handle to it that retries sector reads as `read` says.
+    pub fn with_retry(init: RetryPolicy, read: RetryPolicy) -> Result<Sd, Error> {
+        init.run("SD card initialization", || {
+            trace::record(Event::SdInitStart, 0, 0);
+            let code = unsafe { sd_init() };
+            trace::record(Event::SdInitDone, 0, code as i64 as u64);
+
+            match code {
+                0 => Ok(()),
+                code => Err(Error::from(code as i64)),
+            }
+        })?;
+        Ok(Sd { read_retry: read })
+    }
+
+    /// Returns `true` if the card can't be written to: its write-protect
+    /// switch is set, or `libsd` can't write at all, which for now it can't.
+    pub fn read_only(&self) -> bool {
+        !LIBSD_WRITES || Emmc::new().write_protected()
+    }
+
+    /// Returns the raw code of the last error reported by `libsd`. `0` means
+    /// no error has occurred.
+    pub fn last_error() -> i64 {
+        unsafe { sd_err }
     }
 }
 
@@ -58,10 +186,144 @@ impl BlockDevice for Sd {
     ///
     /// An error of kind `Other` is returned for all other errors.
     fn read_sector(&mut self, n: u64, buf: &mut [u8]) -> io::Result<usize> {
-        unimplemented!("Sd::read_sector()")
+        if buf.len() < 512 || n > i32::MAX as u64 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "invalid sector or buffer"));
+        }
+
+        let read = self.read_retry.run("SD sector read", || {
+            trace::record(Event::SdReadStart, n, 0);
+            let read = unsafe { sd_readsector(n as i32, buf.as_mut_ptr()) };
+            let err = if read == 0 { Sd::last_error() } else { 0 };
+            trace::record(Event::SdReadDone, n, err as u64);
+
+            match read {
+                0 => Err(Error::from(err)),
+                read => Ok(read as usize),
+            }
+        })?;
+        Ok(read)
     }
 
+    /// Always fails with `Error::ReadOnlyCard`: `libsd` can't write, so every
+    /// card is read only, whatever its write-protect switch says.
     fn write_sector(&mut self, _n: u64, _buf: &[u8]) -> io::Result<usize> {
-        unimplemented!("SD card and file system are read only")
+        Err(Error::ReadOnlyCard.into())
+    }
+}
+
+/// Order in which `bench_read` visits sectors.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Pattern {
+    Sequential,
+    Random,
+}
+
+/// Reads `sectors` sectors from `dev` and returns the time it took in
+/// microseconds. A sequential run reads sectors `0..sectors`; a random run
+/// reads as many sectors picked from `0..span`.
+pub fn bench_read<T: BlockDevice>(
+    dev: &mut T,
+    pattern: Pattern,
+    sectors: u64,
+    span: u64,
+) -> io::Result<u64> {
+    let mut buf = [0u8; 512];
+    // xorshift64: cheap, and the same sectors on every run.
+    let mut state = 0x2545_f491_4f6c_dd1du64;
+
+    let start = timer::current_time();
+    for i in 0..sectors {
+        let n = match pattern {
+            Pattern::Sequential => i,
+            Pattern::Random => {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state % span
+            }
+        };
+        dev.read_sector(n, &mut buf)?;
+        // Slow cards can take longer than the watchdog timeout.
+        if i % 64 == 0 {
+            watchdog::pet();
+        }
+    }
+    Ok(timer::current_time() - start)
+}
+
+kernel_test!(sd_reads_mbr, {
+    let mut sd = Sd::new().map_err(|_| "SD card failed to initialize")?;
+    let mut sector = [0u8; 512];
+    sd.read_sector(0, &mut sector).map_err(|_| "reading sector 0 failed")?;
+    ensure!(sector[510..] == [0x55, 0xaa], "bad MBR signature");
+    Ok(())
+});
+
+// Under `make qemu-test`, this drives QEMU's emulation of the Pi's SD host
+// controller, reading the FAT32 image given as `SD_IMG` cluster by cluster.
+kernel_test!(sd_mounts_fat32_partition, {
+    use fat32::traits::{Dir as _, Entry as _, File as _, FileSystem as _};
+    use fat32::vfat::VFat;
+
+    let sd = Sd::new().map_err(|_| "SD card failed to initialize")?;
+    let vfat = VFat::from(sd).map_err(|_| "mounting the FAT32 partition failed")?;
+    let entries = vfat
+        .open_dir("/")
+        .and_then(|dir| dir.entries())
+        .map_err(|_| "listing the root directory failed")?;
+
+    for entry in entries.filter(|entry| entry.is_file()) {
+        let mut file = vfat
+            .open_file(format!("/{}", entry.name()))
+            .map_err(|_| "opening a root directory file failed")?;
+        let mut data = Vec::new();
+        io::Read::read_to_end(&mut file, &mut data).map_err(|_| "reading a file failed")?;
+        ensure!(data.len() as u64 == file.size(), "file read short");
+    }
+    Ok(())
+});
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns `policy` without backoff and silences its retry warnings,
+    /// neither of which work on the host.
+    fn quiet(policy: RetryPolicy) -> RetryPolicy {
+        crate::log::set_target_level("kernel::fs::sd", None);
+        RetryPolicy { backoff_us: 0, ..policy }
+    }
+
+    #[test]
+    fn retries_only_retryable_errors() {
+        let policy = quiet(RetryPolicy::READ);
+
+        let mut calls = 0;
+        let result = policy.run("test", || {
+            calls += 1;
+            if calls < 3 { Err(Error::Timeout) } else { Ok(calls) }
+        });
+        assert_eq!(result.unwrap(), 3);
+
+        calls = 0;
+        let result: Result<(), _> = policy.run("test", || {
+            calls += 1;
+            Err(Error::SendCommand)
+        });
+        assert!(matches!(result, Err(Error::SendCommand)));
+        assert_eq!(calls, 1);
+    }
+
+    #[test]
+    fn gives_up_after_all_attempts() {
+        let policy = quiet(RetryPolicy::INIT);
+        let mut calls = 0;
+        let result: Result<(), _> = policy.run("test", || {
+            calls += 1;
+            Err(Error::Timeout)
+        });
+        assert!(matches!(result, Err(Error::Timeout)));
+        assert_eq!(calls, policy.attempts);
+        assert!(RetryPolicy::NEVER.run("test", || Err::<(), _>(Error::Timeout)).is_err());
     }
 }
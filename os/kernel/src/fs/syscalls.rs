@@ -0,0 +1,229 @@
+//! The file system calls behind the custom std's `std::fs`, registered with
+//! `std::fs::set_syscalls()`.
+//!
+//! Files are opened with `FileSystem::open_handle()`, so the initramfs,
+//! `/dev` and `/proc` work as they do in the shell. Directories are only
+//! those of the FAT32 file system.
+
+use std::fs::{OpenFlags, Stat, Syscalls};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use fat32::traits::{Dir as _, Entry as _, File as _, FileSystem as _, Metadata as _, Timestamp};
+use fat32::vfat;
+
+use crate::mutex::Mutex;
+use crate::FILE_SYSTEM;
+use super::Handle;
+
+/// The calls to register with `std::fs::set_syscalls()`.
+pub const SYSCALLS: Syscalls = Syscalls {
+    open,
+    close,
+    read,
+    write,
+    seek,
+    sync,
+    fstat,
+    stat,
+    read_dir,
+    create_dir,
+    remove,
+    rename,
+};
+
+/// A file opened by `open()`.
+struct OpenFile {
+    handle: Handle,
+    flags: OpenFlags,
+}
+
+/// The open files, indexed by descriptor. The slot of a closed file is reused
+/// by the next one opened.
+///
+/// The lock is held for the whole of a call, so a read from the console
+/// blocks calls on every other file until it returns.
+static FILES: Mutex<Vec<Option<OpenFile>>> = Mutex::new(Vec::new());
+
+/// Calls `f` with the file open as `fd`.
+///
+/// # Errors
+///
+/// Returns an error of kind `InvalidInput` if no file is open as `fd`, and
+/// any error from `f`.
+fn with_file<T>(fd: usize, f: impl FnOnce(&mut OpenFile) -> io::Result<T>) -> io::Result<T> {
+    match FILES.lock().get_mut(fd) {
+        Some(Some(file)) => f(file),
+        _ => Err(io::Error::new(io::ErrorKind::InvalidInput, "bad file descriptor")),
+    }
+}
+
+fn open(path: &str, flags: OpenFlags) -> io::Result<usize> {
+    let writing = flags.write || flags.append || flags.truncate || flags.create || flags.create_new;
+    let handle = match FILE_SYSTEM.open_handle(path) {
+        Ok(Handle::File(_)) if writing && FILE_SYSTEM.is_read_only() => {
+            return Err(io::Error::new(io::ErrorKind::PermissionDenied, "read only file system"));
+        }
+        Ok(_) if flags.create_new => {
+            return Err(io::Error::new(io::ErrorKind::AlreadyExists, "file exists"));
+        }
+        // A FAT32 file can't shrink, so it's truncated by replacing it.
+        Ok(Handle::File(file)) if flags.truncate && file.size() > 0 => {
+            drop(file);
+            (&FILE_SYSTEM).remove(path, false)?;
+            Handle::File((&FILE_SYSTEM).create_file(path)?)
+        }
+        Ok(handle) => handle,
+        Err(e) if e.kind() == io::ErrorKind::NotFound && (flags.create || flags.create_new) => {
+            Handle::File((&FILE_SYSTEM).create_file(path)?)
+        }
+        Err(e) => return Err(e),
+    };
+
+    let mut files = FILES.lock();
+    let fd = match files.iter().position(Option::is_none) {
+        Some(fd) => fd,
+        None => {
+            files.push(None);
+            files.len() - 1
+        }
+    };
+    files[fd] = Some(OpenFile { handle, flags });
+    Ok(fd)
+}
+
+/// Closes the file open as `fd`, first writing the size of a FAT32 file open
+/// for writing back to its directory entry.
+fn close(fd: usize) {
+    let file = FILES.lock().get_mut(fd).and_then(Option::take);
+    if let Some(OpenFile { handle: Handle::File(mut file), flags }) = file {
+        if flags.write || flags.append {
+            let _ = file.sync();
+        }
+    }
+}
+
+fn read(fd: usize, buf: &mut [u8]) -> io::Result<usize> {
+    with_file(fd, |file| match file.flags.read {
+        true => file.handle.read(buf),
+        false => Err(io::Error::new(io::ErrorKind::PermissionDenied, "not open for reading")),
+    })
+}
+
+fn write(fd: usize, buf: &[u8]) -> io::Result<usize> {
+    with_file(fd, |file| {
+        if !file.flags.write && !file.flags.append {
+            return Err(io::Error::new(io::ErrorKind::PermissionDenied, "not open for writing"));
+        }
+        if file.flags.append {
+            file.handle.seek(SeekFrom::End(0))?;
+        }
+        file.handle.write(buf)
+    })
+}
+
+fn seek(fd: usize, pos: SeekFrom) -> io::Result<u64> {
+    with_file(fd, |file| file.handle.seek(pos))
+}
+
+fn sync(fd: usize) -> io::Result<()> {
+    with_file(fd, |file| match &mut file.handle {
+        // Nothing to write back for a file that can't be written.
+        Handle::File(_) if !file.flags.write && !file.flags.append => Ok(()),
+        Handle::File(file) => file.sync(),
+        handle => handle.flush(),
+    })
+}
+
+fn fstat(fd: usize) -> io::Result<Stat> {
+    with_file(fd, |file| handle_stat(&mut file.handle))
+}
+
+/// Looks `path` up as a file first, as `open()` would, then as a FAT32
+/// directory.
+fn stat(path: &str) -> io::Result<Stat> {
+    if let Ok(mut handle) = FILE_SYSTEM.open_handle(path) {
+        return handle_stat(&mut handle);
+    }
+
+    let entry = (&FILE_SYSTEM).open(path)?;
+    Ok(fat_stat(entry.is_dir(), 0, entry.metadata()))
+}
+
+/// Returns what there is to know about an open file. Only FAT32 files are
+/// writable; devices have no size.
+fn handle_stat(handle: &mut Handle) -> io::Result<Stat> {
+    match handle {
+        Handle::File(file) => Ok(fat_stat(false, file.size(), &file.metadata)),
+        Handle::Initrd(_) | Handle::Proc(_) => Ok(Stat {
+            read_only: true,
+            size: len(handle)?,
+            ..Stat::default()
+        }),
+        Handle::Device(_) => Ok(Stat::default()),
+    }
+}
+
+/// Returns what FAT32 keeps about an entry with `metadata`.
+fn fat_stat(is_dir: bool, size: u64, metadata: &vfat::Metadata) -> Stat {
+    Stat {
+        is_dir,
+        read_only: metadata.read_only() || FILE_SYSTEM.is_read_only(),
+        size,
+        modified: system_time(metadata.modified()),
+        accessed: system_time(metadata.accessed()),
+        created: system_time(metadata.created()),
+    }
+}
+
+/// Returns `ts` as a `SystemTime`, or `None` if it isn't a date, as when it
+/// was never set. FAT32 doesn't say which time zone its times are in; they're
+/// taken to be UTC.
+fn system_time(ts: impl Timestamp) -> Option<SystemTime> {
+    let (year, month, day) = (ts.year() as u64, ts.month() as u64, ts.day() as u64);
+    if !(1..=12).contains(&month) || day == 0 {
+        return None;
+    }
+
+    // Count days from 0000-03-01, so a leap day is the last of its year, then
+    // subtract those up to 1970-01-01.
+    let year = if month <= 2 { year - 1 } else { year };
+    let (era, year_of_era) = (year / 400, year % 400);
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era * 146_097 + day_of_era - 719_468;
+
+    let secs = ts.hour() as u64 * 3600 + ts.minute() as u64 * 60 + ts.second() as u64;
+    Some(UNIX_EPOCH + Duration::from_secs(days * 86_400 + secs))
+}
+
+/// Returns the length of `handle` by seeking to its end, then back to where
+/// it was.
+fn len(handle: &mut Handle) -> io::Result<u64> {
+    let pos = handle.seek(SeekFrom::Current(0))?;
+    let len = handle.seek(SeekFrom::End(0))?;
+    handle.seek(SeekFrom::Start(pos))?;
+    Ok(len)
+}
+
+fn read_dir(path: &str) -> io::Result<Vec<String>> {
+    let dir = (&FILE_SYSTEM).open_dir(path)?;
+    // Like std's `read_dir`, leave out `.` and `..`: walking them never ends.
+    Ok(dir
+        .entries()?
+        .map(|entry| entry.name().to_string())
+        .filter(|name| name != "." && name != "..")
+        .collect())
+}
+
+fn create_dir(path: &str) -> io::Result<()> {
+    (&FILE_SYSTEM).create_dir(path, false).map(|_| ())
+}
+
+fn remove(path: &str) -> io::Result<()> {
+    (&FILE_SYSTEM).remove(path, false)
+}
+
+fn rename(from: &str, to: &str) -> io::Result<()> {
+    (&FILE_SYSTEM).rename(from, to)
+}
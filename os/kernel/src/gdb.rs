@@ -0,0 +1,364 @@
+use std::fmt::Write as _;
+use std::io::{self, Read, Write};
+
+use crate::fs::dev::Device;
+
+/// Largest packet payload the stub accepts, advertised to GDB.
+const MAX_PACKET: usize = 1024;
+
+/// What to send the shell to hand the UART over to the stub, before pointing
+/// GDB at the port, e.g. `printf '\002gdb\002' > /dev/ttyUSB0`. Ctrl-B is
+/// never part of a command line, so the sequence can't be typed by accident.
+pub const MAGIC: &[u8] = b"\x02gdb\x02";
+
+/// Number of registers in a `g` packet.
+const REGISTERS: usize = 34;
+
+/// Snapshot of the general purpose registers, `pc` and `cpsr`, in `g` order:
+/// `x0`-`x30`, `sp`, `pc`, `cpsr`.
+#[derive(Debug, Copy, Clone)]
+pub struct Registers(pub [u64; REGISTERS]);
+
+impl Registers {
+    /// Captures the frame pointer, link register and stack pointer of the
+    /// caller. Every other register reads as zero.
+    #[inline(always)]
+    fn capture() -> Registers {
+        let mut regs = Registers([0; REGISTERS]);
+        #[cfg(target_arch = "aarch64")]
+        unsafe {
+            core::arch::asm!(
+                "mov {fp}, x29",
+                "mov {lr}, x30",
+                "mov {sp}, sp",
+                "adr {pc}, .",
+                fp = out(reg) regs.0[29],
+                lr = out(reg) regs.0[30],
+                sp = out(reg) regs.0[31],
+                pc = out(reg) regs.0[32],
+            );
+        }
+        regs
+    }
+}
+
+/// Returns the target description telling GDB the layout of `Registers`.
+fn target_xml() -> String {
+    let mut xml = String::from(
+        "<?xml version=\"1.0\"?><!DOCTYPE target SYSTEM \"gdb-target.dtd\">\
+         <target><architecture>aarch64</architecture>\
+         <feature name=\"org.gnu.gdb.aarch64.core\">",
+    );
+    for i in 0..31 {
+        let _ = write!(xml, "<reg name=\"x{}\" bitsize=\"64\"/>", i);
+    }
+    xml.push_str(
+        "<reg name=\"sp\" bitsize=\"64\" type=\"data_ptr\"/>\
+         <reg name=\"pc\" bitsize=\"64\" type=\"code_ptr\"/>\
+         <reg name=\"cpsr\" bitsize=\"32\"/></feature></target>",
+    );
+    xml
+}
+
+fn hex_digit(c: u8) -> Option<u8> {
+    (c as char).to_digit(16).map(|d| d as u8)
+}
+
+fn parse_hex(s: &[u8]) -> Option<u64> {
+    if s.is_empty() {
+        return None;
+    }
+    s.iter().try_fold(0u64, |n, &c| Some(n.checked_mul(16)? + hex_digit(c)? as u64))
+}
+
+/// Parses `addr,len` as used by the `m`, `M` and `qXfer` packets.
+fn parse_range(s: &[u8]) -> Option<(u64, u64)> {
+    let comma = s.iter().position(|&c| c == b',')?;
+    Some((parse_hex(&s[..comma])?, parse_hex(&s[comma + 1..])?))
+}
+
+fn push_hex(out: &mut Vec<u8>, bytes: &[u8]) {
+    const DIGITS: &[u8; 16] = b"0123456789abcdef";
+    for &b in bytes {
+        out.push(DIGITS[(b >> 4) as usize]);
+        out.push(DIGITS[(b & 0xf) as usize]);
+    }
+}
+
+/// Result of handling one packet.
+enum Action {
+    Reply(Vec<u8>),
+    Detach,
+}
+
+/// A GDB session over `io`.
+struct Stub<T> {
+    io: T,
+    regs: Registers,
+    /// A byte read by whoever noticed GDB, to be processed first.
+    pending: Option<u8>,
+}
+
+impl<T: Read + Write> Stub<T> {
+    fn read_byte(&mut self) -> io::Result<u8> {
+        if let Some(b) = self.pending.take() {
+            return Ok(b);
+        }
+
+        let mut b = [0];
+        self.io.read_exact(&mut b)?;
+        Ok(b[0])
+    }
+
+    /// Reads the next well-formed packet, acknowledging it, and returns its
+    /// payload. Corrupt packets are NAKed so that GDB resends them.
+    fn read_packet(&mut self) -> io::Result<Vec<u8>> {
+        loop {
+            match self.read_byte()? {
+                b'$' => {}
+                // Ctrl-C: report that the target is stopped, which it always is.
+                0x03 => return Ok(b"?".to_vec()),
+                _ => continue,
+            }
+
+            let mut data = vec![];
+            loop {
+                match self.read_byte()? {
+                    b'#' => break,
+                    _ if data.len() > MAX_PACKET => break,
+                    b => data.push(b),
+                }
+            }
+
+            let sum = [self.read_byte()?, self.read_byte()?];
+            let expected = data.iter().fold(0u8, |s, &b| s.wrapping_add(b));
+            if data.len() <= MAX_PACKET && parse_hex(&sum) == Some(expected as u64) {
+                self.io.write_all(b"+")?;
+                return Ok(data);
+            }
+            self.io.write_all(b"-")?;
+        }
+    }
+
+    /// Sends `data` as a packet, resending until GDB acknowledges it.
+    fn write_packet(&mut self, data: &[u8]) -> io::Result<()> {
+        let sum = data.iter().fold(0u8, |s, &b| s.wrapping_add(b));
+        let mut packet = Vec::with_capacity(data.len() + 4);
+        packet.push(b'$');
+        packet.extend_from_slice(data);
+        packet.push(b'#');
+        push_hex(&mut packet, &[sum]);
+
+        loop {
+            self.io.write_all(&packet)?;
+            match self.read_byte()? {
+                b'-' => continue,
+                b'+' => return Ok(()),
+                // GDB may not ack at all; don't lose the next packet.
+                b => {
+                    self.pending = Some(b);
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    fn handle(&mut self, packet: &[u8]) -> Action {
+        let (cmd, args) = match packet.split_first() {
+            Some((&cmd, args)) => (cmd, args),
+            None => return Action::Reply(vec![]),
+        };
+
+        let reply = match cmd {
+            b'?' => b"S05".to_vec(),
+            b'g' => {
+                let mut out = vec![];
+                for reg in &self.regs.0[..REGISTERS - 1] {
+                    push_hex(&mut out, &reg.to_le_bytes());
+                }
+                push_hex(&mut out, &(self.regs.0[REGISTERS - 1] as u32).to_le_bytes());
+                out
+            }
+            b'm' => match parse_range(args) {
+                Some((addr, len)) if len as usize <= MAX_PACKET / 2 => {
+                    let mut out = vec![];
+                    for i in 0..len {
+                        let byte = unsafe { ((addr + i) as usize as *const u8).read_volatile() };
+                        push_hex(&mut out, &[byte]);
+                    }
+                    out
+                }
+                _ => b"E01".to_vec(),
+            },
+            b'M' => {
+                let colon = args.iter().position(|&c| c == b':');
+                let range = colon.and_then(|colon| parse_range(&args[..colon]));
+                match (colon, range) {
+                    (Some(colon), Some((addr, len))) if args.len() - colon - 1 == len as usize * 2 => {
+                        for (i, hex) in args[colon + 1..].chunks(2).enumerate() {
+                            let byte = parse_hex(hex).unwrap_or(0) as u8;
+                            unsafe { ((addr as usize + i) as *mut u8).write_volatile(byte) };
+                        }
+                        b"OK".to_vec()
+                    }
+                    _ => b"E01".to_vec(),
+                }
+            }
+            b'q' if args.starts_with(b"Supported") => {
+                format!("PacketSize={:x};qXfer:features:read+", MAX_PACKET).into_bytes()
+            }
+            b'q' if args.starts_with(b"Xfer:features:read:target.xml:") => {
+                let range = &args[b"Xfer:features:read:target.xml:".len()..];
+                match parse_range(range) {
+                    Some((offset, len)) => {
+                        let xml = target_xml().into_bytes();
+                        let start = std::cmp::min(offset as usize, xml.len());
+                        let end = std::cmp::min(start.saturating_add(len as usize), xml.len());
+                        let mut out = vec![if end == xml.len() { b'l' } else { b'm' }];
+                        out.extend_from_slice(&xml[start..end]);
+                        out
+                    }
+                    None => b"E01".to_vec(),
+                }
+            }
+            b'q' if args.starts_with(b"Attached") => b"1".to_vec(),
+            b'H' => b"OK".to_vec(),
+            b'D' => return Action::Detach,
+            b'k' => return Action::Detach,
+            // Includes register writes (`G`, `P`), breakpoints (`Z`, `z`),
+            // continue and step, which need a trapped context; see `serve()`.
+            _ => vec![],
+        };
+        Action::Reply(reply)
+    }
+
+    fn run(&mut self) -> io::Result<()> {
+        loop {
+            let packet = self.read_packet()?;
+            match self.handle(&packet) {
+                Action::Reply(reply) => self.write_packet(&reply)?,
+                Action::Detach => {
+                    if packet.first() == Some(&b'D') {
+                        self.write_packet(b"OK")?;
+                    }
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
+
+/// Serves the GDB remote serial protocol on the UART until GDB detaches.
+///
+/// There is no trapped context to resume: GDB can read and write memory,
+/// registers show the stub's own frame, and register writes, `c`, `s` and
+/// breakpoint packets are answered as unsupported. Supporting them needs a
+/// synchronous exception handler to catch `brk` and single-step traps, which
+/// in turn needs the kernel to run at EL1; `init.S` still stays at whatever
+/// level the firmware started it in.
+pub fn serve() -> io::Result<()> {
+    let io = Device::open("uart0")?;
+    let mut stub = Stub { io, regs: Registers::capture(), pending: None };
+    stub.run()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    /// Scripted GDB: reads come from `input`, writes collect in `output`.
+    struct Pipe {
+        input: Cursor<Vec<u8>>,
+        output: Vec<u8>,
+    }
+
+    impl Read for Pipe {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.input.read(buf)
+        }
+    }
+
+    impl Write for Pipe {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.output.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn packet(data: &str) -> String {
+        let sum = data.bytes().fold(0u8, |s, b| s.wrapping_add(b));
+        format!("${}#{:02x}", data, sum)
+    }
+
+    /// Runs a session where GDB sends `packets` (each acked by GDB) and then
+    /// detaches. Returns everything the stub sent.
+    fn session(packets: &[&str]) -> String {
+        let mut input = String::new();
+        for p in packets.iter().chain(&["D"]) {
+            input += &packet(p);
+            input += "+";
+        }
+
+        let io = Pipe { input: Cursor::new(input.into_bytes()), output: vec![] };
+        let mut stub = Stub { io, regs: Registers([0; REGISTERS]), pending: None };
+        stub.run().expect("session ran");
+        String::from_utf8(stub.io.output).unwrap()
+    }
+
+    #[test]
+    fn answers_and_detaches() {
+        let out = session(&["?"]);
+        assert_eq!(out, format!("+{}+{}", packet("S05"), packet("OK")));
+    }
+
+    #[test]
+    fn naks_corrupt_packets() {
+        let input = b"$?#00$?#3f+$D#44+".to_vec();
+        let io = Pipe { input: Cursor::new(input), output: vec![] };
+        let mut stub = Stub { io, regs: Registers([0; REGISTERS]), pending: None };
+        stub.run().unwrap();
+
+        let out = String::from_utf8(stub.io.output).unwrap();
+        assert!(out.starts_with(&format!("-+{}", packet("S05"))));
+    }
+
+    #[test]
+    fn leaves_packets_needing_a_trap_unsupported() {
+        let packets = ["G00", "P1f=00", "Z0,80000,4", "z0,80000,4", "c", "s"];
+        let out = session(&packets);
+        assert_eq!(out.matches(&packet("")).count(), packets.len());
+    }
+
+    #[test]
+    fn reads_and_writes_memory() {
+        let mut buf = [0x12u8, 0x34, 0x56, 0x78];
+        let addr = buf.as_mut_ptr() as usize;
+
+        let read = format!("m{:x},4", addr);
+        let write = format!("M{:x},2:abcd", addr + 1);
+        let out = session(&[&read, &write]);
+        assert!(out.contains(&packet("12345678")));
+        assert!(out.contains(&packet("OK")));
+        assert_eq!(buf, [0x12, 0xab, 0xcd, 0x78]);
+    }
+
+    #[test]
+    fn serves_target_description_in_chunks() {
+        let xml = target_xml();
+        let first = &xml[..16];
+        let out = session(&["qXfer:features:read:target.xml:0,10"]);
+        assert!(out.contains(&packet(&format!("m{}", first))));
+
+        let tail = format!("qXfer:features:read:target.xml:{:x},1000", 16);
+        let out = session(&[&tail]);
+        assert!(out.contains(&packet(&format!("l{}", &xml[16..]))));
+
+        let huge = "qXfer:features:read:target.xml:10,ffffffffffffffff";
+        let out = session(&[huge]);
+        assert!(out.contains(&packet(&format!("l{}", &xml[16..]))));
+    }
+}
@@ -0,0 +1,114 @@
+use crate::console::{kprint, kprintln};
+
+/// A test that runs on the target, registered with `kernel_test!`.
+pub struct Test {
+    pub name: &'static str,
+    pub func: fn() -> Result<(), &'static str>,
+}
+
+/// Registers a test that runs on the target when the kernel is built with the
+/// `ktest` feature. The body evaluates to `Result<(), &'static str>`; panics
+/// halt the whole run, so prefer `ensure!` to assertions.
+///
+/// ```ignore
+/// kernel_test!(vec_grows, {
+///     let v: Vec<u32> = (0..100).collect();
+///     ensure!(v.len() == 100, "wrong length");
+///     Ok(())
+/// });
+/// ```
+///
+/// Host builds (`cargo test`) leave registered tests out, since they may use
+/// hardware the host doesn't have.
+pub macro kernel_test($name:ident, $body:block) {
+    #[cfg(not(test))]
+    #[link_section = ".kernel_tests"]
+    #[used]
+    #[allow(non_upper_case_globals)]
+    static $name: Test = Test {
+        name: concat!(module_path!(), "::", stringify!($name)),
+        func: || $body,
+    };
+}
+
+/// Returns `Err($msg)` from the enclosing test if `$cond` is false.
+pub macro ensure($cond:expr, $msg:expr) {
+    if !$cond {
+        return Err(concat!($msg, " (", stringify!($cond), ")"));
+    }
+}
+
+extern "C" {
+    /// Bounds of the `.kernel_tests` section, defined in `layout.ld`. Only
+    /// their addresses mean anything; the `Test`s are between them.
+    static __kernel_tests_start: u8;
+    static __kernel_tests_end: u8;
+}
+
+/// Returns every test registered with `kernel_test!`.
+fn tests() -> &'static [Test] {
+    unsafe {
+        let start = &__kernel_tests_start as *const u8 as *const Test;
+        let end = &__kernel_tests_end as *const u8 as *const Test;
+        core::slice::from_raw_parts(start, end.offset_from(start) as usize)
+    }
+}
+
+/// Runs every registered test, reporting each result on the console, and
+/// returns the number of failures.
+pub fn run_all() -> usize {
+    let tests = tests();
+    kprintln!("running {} kernel tests", tests.len());
+
+    let mut failed = 0;
+    for test in tests {
+        kprint!("test {} ... ", test.name);
+        match (test.func)() {
+            Ok(()) => kprintln!("ok"),
+            Err(msg) => {
+                kprintln!("FAILED: {}", msg);
+                failed += 1;
+            }
+        }
+    }
+
+    kprintln!("kernel test result: {} passed; {} failed", tests.len() - failed, failed);
+    failed
+}
+
+/// Ends a QEMU session with `code` as QEMU's exit status, using the
+/// semihosting `SYS_EXIT` call. QEMU must be started with `-semihosting`;
+/// on real hardware this traps, so only call it under emulation.
+pub fn qemu_exit(code: u32) -> ! {
+    /// `ADP_Stopped_ApplicationExit`: a normal exit carrying a status code.
+    const APPLICATION_EXIT: u64 = 0x20026;
+    const SYS_EXIT: u64 = 0x18;
+
+    let block = [APPLICATION_EXIT, code as u64];
+    #[cfg(target_arch = "aarch64")]
+    unsafe {
+        core::arch::asm!("hlt #0xf000", in("x0") SYS_EXIT, in("x1") block.as_ptr());
+    }
+
+    loop {}
+}
+
+kernel_test!(heap_allocations_round_trip, {
+    let v: Vec<u64> = (0..1000).collect();
+    ensure!(v.iter().sum::<u64>() == 999 * 1000 / 2, "vec contents wrong");
+
+    let s = format!("{}-{}", "kernel", 42);
+    ensure!(s == "kernel-42", "formatted string wrong");
+    Ok(())
+});
+
+kernel_test!(mutex_excludes, {
+    let mutex = crate::mutex::Mutex::new(1);
+    {
+        let mut guard = mutex.lock();
+        *guard += 1;
+        ensure!(mutex.try_lock().is_none(), "locked twice");
+    }
+    ensure!(mutex.try_lock().map(|g| *g) == Some(2), "not released");
+    Ok(())
+});
@@ -1,11 +1,70 @@
 use core::arch::asm;
+use core::fmt::{self, Write};
 
-use crate::console::kprint;
+use crate::console::{kprint, CONSOLE};
+use crate::panic_log;
+
+/// Maximum number of frames printed in a panic backtrace.
+const MAX_FRAMES: usize = 32;
+
+extern "C" {
+    /// Start of the kernel image, which is also the top of the boot stack.
+    static _start: u8;
+}
+
+/// Writes the return address of every frame on the current call stack to `w`.
+///
+/// Walks the chain of AArch64 frame records (`x29` points at the caller's
+/// `x29` followed by the return address), so the kernel must be built with
+/// `-C force-frame-pointers=yes`. `init.S` zeroes `x29` before calling
+/// `kmain`, which ends the chain. The addresses can be resolved with
+/// `addr2line -e <kernel elf>`.
+fn write_backtrace(w: &mut dyn fmt::Write) -> fmt::Result {
+    let stack_top = unsafe { &_start as *const u8 as usize };
+    let mut fp: usize;
+    unsafe { asm!("mov {}, x29", out(reg) fp) };
+
+    write!(w, "\nBACKTRACE:\n")?;
+    for i in 0..MAX_FRAMES {
+        if fp == 0 || fp % 16 != 0 || fp >= stack_top {
+            break;
+        }
+
+        let record = fp as *const usize;
+        let (caller_fp, ret) = unsafe { (*record, *record.add(1)) };
+        if ret < 4 {
+            break;
+        }
+
+        // `ret` is the instruction after the `bl`; report the call itself.
+        write!(w, "  #{:<2} {:#010x}\n", i, ret - 4)?;
+
+        // Callers' frames live higher up the stack; anything else is garbage.
+        if caller_fp <= fp {
+            break;
+        }
+        fp = caller_fp;
+    }
+
+    Ok(())
+}
+
+/// Writes the location, message and backtrace of the panic `info` to `w`.
+fn write_report(w: &mut dyn fmt::Write, info: &core::panic::PanicInfo) -> fmt::Result {
+    if let Some(loc) = info.location() {
+        write!(w, "\nFILE: {}\n", loc.file())?;
+        write!(w, "LINE: {}\n", loc.line())?;
+        write!(w, "COL: {}\n", loc.column())?;
+    } else {
+        write!(w, "\npanic occurred but can't get location information...\n")?;
+    }
+    write!(w, "\n{}\n", info.message())?;
+    write_backtrace(w)
+}
 
 #[no_mangle]
 #[lang = "panic_impl"]
 pub extern "Rust" fn panic_impl(info: &core::panic::PanicInfo) -> ! {
-    // FIXME: Print `fmt`, `file`, and `line` to the console.
     let header = r#"            (
         (      )     )
           )   (    (
@@ -20,14 +79,10 @@ pub extern "Rust" fn panic_impl(info: &core::panic::PanicInfo) -> ! {
  ---------- PANIC ----------
  "#;
     kprint!("{}", header);
-    if let Some(loc) = info.location() {
-        kprint!("\nFILE: {}\n", loc.file());
-        kprint!("LINE: {}\n", loc.line());
-        kprint!("COL: {}\n", loc.column());
-    } else {
-        kprint!("\npanic occurred but can't get location information...\n");
-    }
-    kprint!("\n{}\n", info.message());
+    let _ = write_report(&mut *CONSOLE.lock(), info);
+
+    // Keep a copy in case nobody was watching the UART.
+    panic_log::record(|w| write_report(w, info));
 
     loop {
         unsafe { asm!("wfe") }
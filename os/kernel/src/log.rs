@@ -0,0 +1,164 @@
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use pi::timer;
+
+use crate::ansi::{Color, Colored};
+use crate::console::kprintln;
+use crate::mutex::Mutex;
+
+/// Severity of a log message, from most to least severe.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Error = 1,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl Level {
+    /// Parses a level from its lowercase name, e.g. `"warn"`.
+    pub fn from_name(name: &str) -> Option<Level> {
+        Some(match name {
+            "error" => Level::Error,
+            "warn" => Level::Warn,
+            "info" => Level::Info,
+            "debug" => Level::Debug,
+            "trace" => Level::Trace,
+            _ => return None,
+        })
+    }
+
+    fn from_usize(n: usize) -> Option<Level> {
+        Some(match n {
+            1 => Level::Error,
+            2 => Level::Warn,
+            3 => Level::Info,
+            4 => Level::Debug,
+            5 => Level::Trace,
+            _ => return None,
+        })
+    }
+
+    /// Returns the lowercase name of this level.
+    pub fn name(self) -> &'static str {
+        match self {
+            Level::Error => "error",
+            Level::Warn => "warn",
+            Level::Info => "info",
+            Level::Debug => "debug",
+            Level::Trace => "trace",
+        }
+    }
+
+    fn color(self) -> Color {
+        match self {
+            Level::Error => Color::Red,
+            Level::Warn => Color::Yellow,
+            Level::Info => Color::Green,
+            Level::Debug => Color::Blue,
+            Level::Trace => Color::Magenta,
+        }
+    }
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let label = match self {
+            Level::Error => "ERROR",
+            Level::Warn => "WARN ",
+            Level::Info => "INFO ",
+            Level::Debug => "DEBUG",
+            Level::Trace => "TRACE",
+        };
+        Colored(self.color(), label).fmt(f)
+    }
+}
+
+/// The level messages are logged at unless their target has an override.
+/// Zero turns logging off.
+static MAX_LEVEL: AtomicUsize = AtomicUsize::new(Level::Info as usize);
+
+/// Per-target overrides of `MAX_LEVEL`, as `(target prefix, level)` pairs.
+static TARGET_LEVELS: Mutex<Vec<(String, Option<Level>)>> = Mutex::new(Vec::new());
+
+/// Sets the most verbose level that is logged. `None` turns logging off.
+pub fn set_max_level(level: Option<Level>) {
+    MAX_LEVEL.store(level.map_or(0, |l| l as usize), Ordering::Relaxed)
+}
+
+/// Returns the most verbose level that is logged, or `None` if logging is off.
+pub fn max_level() -> Option<Level> {
+    Level::from_usize(MAX_LEVEL.load(Ordering::Relaxed))
+}
+
+/// Overrides the maximum level for targets starting with `target`, such as
+/// `"kernel::fs"`. The longest matching prefix wins.
+pub fn set_target_level(target: &str, level: Option<Level>) {
+    let mut levels = TARGET_LEVELS.lock();
+    match levels.iter_mut().find(|(t, _)| t == target) {
+        Some(entry) => entry.1 = level,
+        None => levels.push((target.to_string(), level)),
+    }
+}
+
+/// Returns `true` if a message at `level` from `target` would be logged.
+pub fn enabled(level: Level, target: &str) -> bool {
+    let levels = TARGET_LEVELS.lock();
+    let max = levels
+        .iter()
+        .filter(|(t, _)| target.starts_with(t.as_str()))
+        .max_by_key(|(t, _)| t.len())
+        .map_or_else(max_level, |&(_, l)| l);
+
+    max.map_or(false, |max| level <= max)
+}
+
+/// Internal function called by the logging macros.
+#[doc(hidden)]
+pub fn _log(level: Level, target: &str, args: fmt::Arguments) {
+    if !enabled(level, target) {
+        return;
+    }
+
+    let now = timer::current_time();
+    kprintln!(
+        "[{:5}.{:06}] {} {}: {}",
+        now / 1_000_000,
+        now % 1_000_000,
+        level,
+        target,
+        args
+    );
+}
+
+/// Logs a message at `level`, targeted at the calling module.
+pub macro log($level:expr, $($arg:tt)*) {
+    _log($level, module_path!(), format_args!($($arg)*))
+}
+
+/// Logs a message at `Level::Error`.
+pub macro error($($arg:tt)*) {
+    log!(Level::Error, $($arg)*)
+}
+
+/// Logs a message at `Level::Warn`.
+pub macro warn($($arg:tt)*) {
+    log!(Level::Warn, $($arg)*)
+}
+
+/// Logs a message at `Level::Info`.
+pub macro info($($arg:tt)*) {
+    log!(Level::Info, $($arg)*)
+}
+
+/// Logs a message at `Level::Debug`.
+pub macro debug($($arg:tt)*) {
+    log!(Level::Debug, $($arg)*)
+}
+
+/// Logs a message at `Level::Trace`.
+pub macro trace($($arg:tt)*) {
+    log!(Level::Trace, $($arg)*)
+}
@@ -27,25 +27,34 @@ use std::prelude::v1::*;
 extern crate alloc;
 
 pub mod allocator;
+pub mod ansi;
+pub mod cmdline;
 pub mod console;
 pub mod fs;
+pub mod gdb;
+pub mod ktest;
 #[cfg(feature = "custom-std")]
 pub mod lang_items;
+pub mod log;
+pub mod memmap;
 pub mod mutex;
+pub mod panic_log;
 pub mod shell;
+pub mod trace;
+pub mod watchdog;
 
 use core::arch::global_asm;
 #[cfg(not(test))]
 global_asm!(include_str!("../ext/init.S"));
 
-#[cfg(not(test))]
 use allocator::Allocator;
+use cmdline::CmdLine;
+use console::{kprintln, CONSOLE};
 use fs::FileSystem;
+use log::{warn, Level};
 
-#[cfg(not(test))]
 pub static _ALLOCATOR: Allocator = Allocator::uninitialized();
-#[cfg(not(test))]
-#[global_allocator]
+#[cfg_attr(not(test), global_allocator)]
 pub static ALLOCATOR: &Allocator = &_ALLOCATOR;
 
 pub static FILE_SYSTEM: FileSystem = FileSystem::uninitialized();
@@ -53,13 +62,76 @@ pub static FILE_SYSTEM: FileSystem = FileSystem::uninitialized();
 #[no_mangle]
 pub unsafe extern "C" fn kmain() -> ! {
     #[cfg(not(test))]
-    ALLOCATOR.initialize();
+    {
+        memmap::build();
+        ALLOCATOR.initialize();
+    }
+
+    // `println!` and the rest of the standard streams, which the custom std
+    // leaves to the kernel.
+    #[cfg(feature = "custom-std")]
+    std::io::set_console(console::read, console::write);
+    // Likewise `std::fs`, which works once `FILE_SYSTEM` is initialized, and
+    // `std::time`.
+    #[cfg(feature = "custom-std")]
+    {
+        std::fs::set_syscalls(fs::syscalls::SYSCALLS);
+        std::time::set_clock(pi::timer::current_time);
+    }
+
+    // Report before anything else gets a chance to panic and overwrite it.
+    if let Some(report) = panic_log::take() {
+        kprintln!("previous panic:\n{}", report);
+    }
+
+    let cmdline = CmdLine::from_atags();
+    configure(cmdline);
+
+    // Under QEMU, the exit status reports the result. On hardware, use the
+    // `ktest` command line flag instead and carry on to the shell.
+    #[cfg(feature = "ktest")]
+    ktest::qemu_exit(ktest::run_all() as u32);
+    if cmdline.flag("ktest") {
+        ktest::run_all();
+    }
+
+    watchdog::report_previous();
+
+    #[cfg(not(test))]
+    {
+        watchdog::start();
+        FILE_SYSTEM.initialize(cmdline.get("root") != Some("initrd"));
+    }
 
-    let mut v = vec![];
-    for i in 0..1000 {
-        v.push(i);
-        console::kprintln!("{:?}", v);
+    shell::shell("> ")
+}
+
+/// Applies the settings on the kernel command line that don't belong to a
+/// particular subsystem's initialization.
+fn configure(cmdline: CmdLine) {
+    if let Some(name) = cmdline.get("loglevel") {
+        match (name, Level::from_name(name)) {
+            ("off", _) => log::set_max_level(None),
+            (_, Some(level)) => log::set_max_level(Some(level)),
+            (_, None) => warn!("ignoring unknown log level: {}", name),
+        }
     }
 
-    loop {}
+    if cmdline.get("ansi") == Some("off") {
+        ansi::set_enabled(false);
+    }
+
+    if let Some(wanted) = cmdline.get("console") {
+        let mut console = CONSOLE.lock();
+        let sinks: Vec<_> = console.sinks().map(|(name, _)| name).collect();
+        // Turning every sink off by mistake would leave no way to find out.
+        if wanted.split(',').any(|name| sinks.contains(&name)) {
+            for name in sinks {
+                let _ = console.set_sink_enabled(name, wanted.split(',').any(|w| w == name));
+            }
+        } else {
+            drop(console);
+            warn!("ignoring console={}: no such sink", wanted);
+        }
+    }
 }
@@ -0,0 +1,289 @@
+use std::fmt;
+
+use pi::atags::Atags;
+use pi::common::{
+    BOOTLOADER_SIZE, BOOTLOADER_START, IO_BASE, IO_SIZE, KERNEL_START, LOCAL_IO_BASE, LOCAL_IO_SIZE,
+};
+
+use crate::log::{info, warn};
+use crate::mutex::Mutex;
+
+/// Maximum number of RAM ranges tracked.
+const MAX_RAM: usize = 4;
+
+/// Maximum number of reserved regions tracked.
+const MAX_REGIONS: usize = 16;
+
+/// End of the firmware's spin tables and ATAGs at the bottom of memory. The
+/// kernel stack grows down from `KERNEL_START` to here.
+const FIRMWARE_END: usize = 0x1000;
+
+/// The physical memory map, built at boot by `build()`.
+pub static MEMORY_MAP: Mutex<MemoryMap> = Mutex::new(MemoryMap::new());
+
+/// What a reserved region of the address space is used for.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Kind {
+    /// Owned by the firmware or the GPU.
+    Firmware,
+    /// The kernel's image or stack.
+    Kernel,
+    /// The bootloader, which is dead once the kernel runs.
+    Bootloader,
+    /// The initramfs passed by the bootloader.
+    Initrd,
+    /// Memory-mapped peripherals.
+    Mmio,
+    /// Buffers handed to DMA engines.
+    Dma,
+    /// The heap managed by the kernel allocator.
+    Heap,
+}
+
+impl Kind {
+    fn name(self) -> &'static str {
+        match self {
+            Kind::Firmware => "firmware",
+            Kind::Kernel => "kernel",
+            Kind::Bootloader => "bootloader",
+            Kind::Initrd => "initrd",
+            Kind::Mmio => "mmio",
+            Kind::Dma => "dma",
+            Kind::Heap => "heap",
+        }
+    }
+
+    /// Returns `true` if the region may be reused by later reservations.
+    pub fn reclaimable(self) -> bool {
+        self == Kind::Bootloader
+    }
+}
+
+/// A reserved range `start..end` of physical addresses.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Region {
+    pub start: usize,
+    pub end: usize,
+    pub kind: Kind,
+    /// A short description, shown in the memory map.
+    pub name: &'static str,
+}
+
+impl Region {
+    /// Returns a new region covering `start..end`.
+    pub const fn new(start: usize, end: usize, kind: Kind, name: &'static str) -> Region {
+        Region { start, end, kind, name }
+    }
+
+    fn overlaps(&self, start: usize, end: usize) -> bool {
+        self.start < end && start < self.end
+    }
+}
+
+impl fmt::Display for Region {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:#010x}-{:#010x} {:>8} KiB  {:<10} {}",
+            self.start,
+            self.end,
+            (self.end - self.start) / 1024,
+            self.kind.name(),
+            self.name
+        )
+    }
+}
+
+/// Error returned when a reservation can't be recorded.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// The range is empty or inverted.
+    Empty,
+    /// There is no room for more entries.
+    Full,
+    /// The range overlaps this existing reservation.
+    Overlap(Region),
+}
+
+/// RAM ranges and the reservations carved out of them and the rest of the
+/// address space, sorted by start address.
+#[derive(Debug)]
+pub struct MemoryMap {
+    ram: [Option<(usize, usize)>; MAX_RAM],
+    regions: [Option<Region>; MAX_REGIONS],
+}
+
+impl MemoryMap {
+    /// Returns an empty memory map.
+    pub const fn new() -> MemoryMap {
+        MemoryMap { ram: [None; MAX_RAM], regions: [None; MAX_REGIONS] }
+    }
+
+    /// Records that `start..end` is RAM.
+    pub fn add_ram(&mut self, start: usize, end: usize) -> Result<(), Error> {
+        if start >= end {
+            return Err(Error::Empty);
+        }
+        let slot = self.ram.iter_mut().find(|r| r.is_none()).ok_or(Error::Full)?;
+        *slot = Some((start, end));
+        Ok(())
+    }
+
+    /// Returns the RAM ranges as `(start, end)` pairs.
+    pub fn ram(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        self.ram.iter().flatten().cloned()
+    }
+
+    /// Records `region`. It may only overlap reclaimable regions, unless it is
+    /// reclaimable itself.
+    pub fn reserve(&mut self, region: Region) -> Result<(), Error> {
+        if region.start >= region.end {
+            return Err(Error::Empty);
+        }
+        if !region.kind.reclaimable() {
+            let conflict = self
+                .regions()
+                .find(|r| !r.kind.reclaimable() && r.overlaps(region.start, region.end));
+            if let Some(conflict) = conflict {
+                return Err(Error::Overlap(*conflict));
+            }
+        }
+
+        let len = self.regions().count();
+        if len == MAX_REGIONS {
+            return Err(Error::Full);
+        }
+        let i = self.regions().take_while(|r| r.start <= region.start).count();
+        self.regions[i..=len].rotate_right(1);
+        self.regions[i] = Some(region);
+        Ok(())
+    }
+
+    /// Returns every reservation, sorted by start address.
+    pub fn regions(&self) -> impl Iterator<Item = &Region> {
+        self.regions.iter().flatten()
+    }
+
+    /// Returns the largest range of RAM no unreclaimable region covers, as
+    /// `(start, end)`.
+    pub fn largest_free(&self) -> Option<(usize, usize)> {
+        let mut best: Option<(usize, usize)> = None;
+        for (ram_start, ram_end) in self.ram() {
+            let mut start = ram_start;
+            let reserved = self.regions().filter(|r| !r.kind.reclaimable());
+            // Regions are sorted, so one pass over them finds every gap.
+            for region in reserved.chain(Some(&Region::new(ram_end, ram_end, Kind::Firmware, ""))) {
+                let end = std::cmp::min(region.start, ram_end);
+                if end > start && best.map_or(true, |(s, e)| end - start > e - s) {
+                    best = Some((start, end));
+                }
+                start = std::cmp::max(start, region.end);
+            }
+        }
+        best
+    }
+
+    /// Writes the RAM ranges and reservations, one per line.
+    pub fn write(&self, w: &mut dyn fmt::Write) -> fmt::Result {
+        for (start, end) in self.ram() {
+            writeln!(w, "{:#010x}-{:#010x} {:>8} KiB  ram", start, end, (end - start) / 1024)?;
+        }
+        for region in self.regions() {
+            writeln!(w, "{}", region)?;
+        }
+        Ok(())
+    }
+}
+
+extern "C" {
+    static _start: u8;
+    static _end: u8;
+}
+
+/// Builds `MEMORY_MAP` from the ATAGs, the linker script and the board's fixed
+/// layout, and logs it. Must run before the allocator is initialized, which
+/// takes the largest free range for the heap.
+pub fn build() {
+    let image_start = unsafe { &_start as *const u8 as usize };
+    let image_end = unsafe { &_end as *const u8 as usize };
+    debug_assert_eq!(image_start, KERNEL_START);
+
+    let mut map = MEMORY_MAP.lock();
+    let mut ram_end = 0;
+    for mem in Atags::get().filter_map(|atag| atag.mem()) {
+        let (start, end) = (mem.start as usize, (mem.start + mem.size) as usize);
+        if let Err(e) = map.add_ram(start, end) {
+            warn!("ignoring RAM {:#x}-{:#x}: {:?}", start, end, e);
+        }
+        ram_end = std::cmp::max(ram_end, end);
+    }
+
+    let fixed = [
+        Region::new(0, FIRMWARE_END, Kind::Firmware, "spin tables, atags"),
+        Region::new(FIRMWARE_END, image_start, Kind::Kernel, "stack"),
+        Region::new(image_start, image_end, Kind::Kernel, "image"),
+        Region::new(
+            BOOTLOADER_START,
+            BOOTLOADER_START + BOOTLOADER_SIZE,
+            Kind::Bootloader,
+            "image",
+        ),
+        Region::new(ram_end, IO_BASE, Kind::Firmware, "gpu"),
+        Region::new(IO_BASE, IO_BASE + IO_SIZE, Kind::Mmio, "peripherals"),
+        Region::new(LOCAL_IO_BASE, LOCAL_IO_BASE + LOCAL_IO_SIZE, Kind::Mmio, "local peripherals"),
+    ];
+    let initrd = Atags::get().find_map(|atag| atag.initrd()).map(|initrd| {
+        let start = initrd.start as usize;
+        Region::new(start, start + initrd.size as usize, Kind::Initrd, "initramfs")
+    });
+
+    for region in fixed.iter().chain(initrd.as_ref()) {
+        if let Err(e) = map.reserve(*region) {
+            warn!("failed to reserve {}: {:?}", region, e);
+        }
+    }
+
+    for (start, end) in map.ram() {
+        info!("{:#010x}-{:#010x} ram", start, end);
+    }
+    for region in map.regions() {
+        info!("{}", region);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn map() -> MemoryMap {
+        let mut map = MemoryMap::new();
+        map.add_ram(0, 0x10000).unwrap();
+        map.reserve(Region::new(0x8000, 0x9000, Kind::Kernel, "image")).unwrap();
+        map.reserve(Region::new(0, 0x1000, Kind::Firmware, "atags")).unwrap();
+        map
+    }
+
+    #[test]
+    fn keeps_regions_sorted_and_rejects_overlaps() {
+        let mut map = map();
+        let overlapping = Region::new(0x8800, 0xa000, Kind::Dma, "pool");
+        assert!(matches!(map.reserve(overlapping), Err(Error::Overlap(r)) if r.name == "image"));
+        assert_eq!(map.reserve(Region::new(0x10, 0x10, Kind::Dma, "pool")), Err(Error::Empty));
+
+        map.reserve(Region::new(0x2000, 0x3000, Kind::Dma, "pool")).unwrap();
+        let starts: Vec<_> = map.regions().map(|r| r.start).collect();
+        assert_eq!(starts, [0, 0x2000, 0x8000]);
+    }
+
+    #[test]
+    fn largest_free_skips_unreclaimable_regions() {
+        let mut map = map();
+        assert_eq!(map.largest_free(), Some((0x1000, 0x8000)));
+
+        map.reserve(Region::new(0x1000, 0x7000, Kind::Bootloader, "image")).unwrap();
+        assert_eq!(map.largest_free(), Some((0x1000, 0x8000)));
+
+        map.reserve(Region::new(0x1000, 0x7000, Kind::Initrd, "initramfs")).unwrap();
+        assert_eq!(map.largest_free(), Some((0x9000, 0x10000)));
+    }
+}
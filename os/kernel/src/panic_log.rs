@@ -0,0 +1,100 @@
+use std::fmt;
+use std::ptr::addr_of_mut;
+
+/// Marks a `Log` holding a report. Anything else is uninitialized memory.
+const MAGIC: u32 = 0x5041_4e43;
+
+/// Size of the reserved region, including the header.
+const LOG_SIZE: usize = 4096;
+
+/// A panic report kept in RAM that isn't touched by the loader, `init.S` or
+/// the allocator, so it survives a warm reboot.
+#[repr(C)]
+struct Log {
+    magic: u32,
+    len: u32,
+    checksum: u32,
+    buf: [u8; LOG_SIZE - 12],
+}
+
+/// Placed in `.panic_log`, which `layout.ld` reserves between the BSS and
+/// `_end` without zeroing it.
+#[link_section = ".panic_log"]
+static mut LOG: Log = Log {
+    magic: 0,
+    len: 0,
+    checksum: 0,
+    buf: [0; LOG_SIZE - 12],
+};
+
+/// Time of the last watchdog pet, kept alongside `LOG`. The top half of
+/// `HEARTBEAT[0]` holds `MAGIC` so that power-on garbage is ignored.
+#[link_section = ".panic_log"]
+static mut HEARTBEAT: [u64; 2] = [0; 2];
+
+fn checksum(data: &[u8]) -> u32 {
+    data.iter().fold(0, |sum, &b| sum.rotate_left(5) ^ b as u32)
+}
+
+/// Appends to a `Log`, silently dropping whatever doesn't fit.
+struct Writer<'a>(&'a mut Log);
+
+impl<'a> fmt::Write for Writer<'a> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let len = self.0.len as usize;
+        let n = s.len().min(self.0.buf.len() - len);
+        self.0.buf[len..len + n].copy_from_slice(&s.as_bytes()[..n]);
+        self.0.len += n as u32;
+        Ok(())
+    }
+}
+
+/// Replaces the saved panic report with whatever `write` writes.
+///
+/// Only meant to be called from the panic handler, which never returns.
+pub fn record<F: FnOnce(&mut dyn fmt::Write) -> fmt::Result>(write: F) {
+    let log = unsafe { &mut *addr_of_mut!(LOG) };
+    log.magic = 0;
+    log.len = 0;
+
+    let _ = write(&mut Writer(log));
+
+    log.checksum = checksum(&log.buf[..log.len as usize]);
+    log.magic = MAGIC;
+}
+
+/// Returns the panic report saved before the last reboot, if any, and clears
+/// it so it is only reported once.
+pub fn take() -> Option<String> {
+    let log = unsafe { &mut *addr_of_mut!(LOG) };
+    if log.magic != MAGIC || log.len as usize > log.buf.len() {
+        return None;
+    }
+
+    let data = &log.buf[..log.len as usize];
+    let report = match checksum(data) == log.checksum {
+        true => Some(String::from_utf8_lossy(data).into_owned()),
+        false => None,
+    };
+
+    log.magic = 0;
+    report
+}
+
+/// Notes that the kernel was alive at `time` microseconds after boot.
+pub fn heartbeat(time: u64) {
+    unsafe { *addr_of_mut!(HEARTBEAT) = [(MAGIC as u64) << 32, time] };
+}
+
+/// Returns the time noted by the last `heartbeat()` before the last reboot,
+/// if any, and clears it.
+pub fn last_heartbeat() -> Option<u64> {
+    let heartbeat = unsafe { &mut *addr_of_mut!(HEARTBEAT) };
+    let time = match heartbeat[0] >> 32 == MAGIC as u64 {
+        true => Some(heartbeat[1]),
+        false => None,
+    };
+
+    heartbeat[0] = 0;
+    time
+}
@@ -1,6 +1,23 @@
-use crate::console::{kprint, kprintln, CONSOLE};
+use std::fmt;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use fat32::traits::{BlockDevice, FileSystem};
+use pi::gpio::{Function, Gpio};
+use pi::timer;
 use stack_vec::StackVec;
 
+use crate::ansi::{self, Color, Colored};
+use crate::console::{self, kprint, kprintln, CONSOLE};
+use crate::fs::{proc, receive};
+use crate::fs::bcache::CachedSd;
+use crate::fs::sd::{self, Pattern};
+use crate::gdb;
+use crate::log::{self, Level};
+use crate::trace as tracing;
+use crate::watchdog;
+use crate::FILE_SYSTEM;
+
 /// Error type for `Command` parse failures.
 #[derive(Debug)]
 enum Error {
@@ -49,35 +66,555 @@ pub fn shell(prefix: &str) -> ! {
     kprintln!("Welcome!");
     loop {
         kprint!("{}", prefix);
+        ansi::save_cursor();
 
         let cmd_buf = &mut [0; MAX_CMD_LEN];
         let args_buf = &mut [""; MAX_ARG_NUM];
         match Command::parse(read_line(cmd_buf), args_buf) {
-            Ok(cmd) => execute_cmd(cmd),
-            Err(Error::TooManyArgs) => kprintln!("error: too many arguments"),
+            Ok(cmd) => {
+                let _ = execute_cmd(cmd);
+            }
+            Err(Error::TooManyArgs) => {
+                kprintln!("{}: too many arguments", Colored(Color::Red, "error"))
+            }
             Err(Error::Empty) => continue,
         }
     }
 }
 
-fn execute_cmd(cmd: Command) {
+/// Prints an error message like `kprintln!` and evaluates to `Err(())`.
+macro fail($($arg:tt)*) {{
+    kprintln!($($arg)*);
+    Err(())
+}}
+
+/// Executes `cmd`. Returns `Err(())` if the command is unknown or failed; any
+/// error message has already been printed.
+fn execute_cmd(cmd: Command) -> Result<(), ()> {
     match cmd.path() {
-        "echo" => match &cmd.args.as_slice()[1..] {
-            [heads @ .., tail] => {
-                heads.iter().for_each(|arg| kprint!("{} ", arg));
-                kprintln!("{}", tail);
+        "echo" => {
+            match &cmd.args.as_slice()[1..] {
+                [heads @ .., tail] => {
+                    heads.iter().for_each(|arg| kprint!("{} ", arg));
+                    kprintln!("{}", tail);
+                }
+                _ => kprintln!(),
             }
-            _ => kprintln!(),
+            Ok(())
+        }
+        "xxd" => xxd(&cmd.args.as_slice()[1..]),
+        "cat" => cat(&cmd.args.as_slice()[1..]),
+        "sdinfo" => print_proc(proc::write_sd),
+        "uptime" => print_proc(proc::write_uptime),
+        "sleep" => sleep(&cmd.args.as_slice()[1..]),
+        "meminfo" => print_proc(proc::write_meminfo),
+        "memmap" => print_proc(proc::write_memmap),
+        "gpio" => gpio(&cmd.args.as_slice()[1..]),
+        "sh" => sh(&cmd.args.as_slice()[1..]),
+        "ansi" => set_ansi(&cmd.args.as_slice()[1..]),
+        "log" => set_log_level(&cmd.args.as_slice()[1..]),
+        "trace" => trace(&cmd.args.as_slice()[1..]),
+        "watchdog" => set_watchdog(&cmd.args.as_slice()[1..]),
+        "sdbench" => sdbench(&cmd.args.as_slice()[1..]),
+        "rb" => rb(&cmd.args.as_slice()[1..]),
+        path => fail!("unknown command: {}", path),
+    }
+}
+
+/// `ansi [on|off]`
+///
+/// Enables or disables ANSI escape sequences in console output, or prints
+/// whether they are enabled. Turn them off on terminals that don't support
+/// them.
+fn set_ansi(args: &[&str]) -> Result<(), ()> {
+    match args {
+        [] => kprintln!("{}", if ansi::enabled() { "on" } else { "off" }),
+        ["on"] => ansi::set_enabled(true),
+        ["off"] => ansi::set_enabled(false),
+        _ => return fail!("usage: ansi [on|off]"),
+    }
+    Ok(())
+}
+
+/// `log [level] [target]`
+///
+/// Sets the most verbose log level that is printed, either globally or for
+/// modules under `target` (e.g. `kernel::fs`). `level` is one of `error`,
+/// `warn`, `info`, `debug`, `trace` or `off`. Without arguments, prints the
+/// global level.
+fn set_log_level(args: &[&str]) -> Result<(), ()> {
+    const USAGE: &str = "usage: log [error|warn|info|debug|trace|off] [target]";
+
+    let (name, target) = match args {
+        [] => {
+            kprintln!("{}", log::max_level().map_or("off", |l| l.name()));
+            return Ok(());
+        }
+        [name] => (*name, None),
+        [name, target] => (*name, Some(*target)),
+        _ => return fail!("{}", USAGE),
+    };
+
+    let level = match (name, Level::from_name(name)) {
+        ("off", _) => None,
+        (_, Some(level)) => Some(level),
+        (_, None) => return fail!("{}", USAGE),
+    };
+
+    match target {
+        None => log::set_max_level(level),
+        Some(target) => log::set_target_level(target, level),
+    }
+    Ok(())
+}
+
+/// `trace on|off|clear|dump [n]`
+///
+/// Starts or stops recording trace events, discards the recorded events, or
+/// prints them as tab-separated `time core event a b` lines. With `n`, only
+/// the `n` most recent events are printed, e.g. to see what led up to a
+/// failure. `os/trace-decode` turns a dump into a timeline and a summary.
+fn trace(args: &[&str]) -> Result<(), ()> {
+    match args {
+        ["on"] => tracing::set_enabled(true),
+        ["off"] => tracing::set_enabled(false),
+        ["clear"] => tracing::clear(),
+        ["dump"] => {
+            // Don't record the dump's own events while the rings are locked.
+            let enabled = tracing::enabled();
+            tracing::set_enabled(false);
+            tracing::for_each(|record| kprintln!("{}", record));
+            tracing::set_enabled(enabled);
+        }
+        ["dump", n] => {
+            let n = match n.parse() {
+                Ok(n) => n,
+                Err(_) => return fail!("invalid count: {}", n),
+            };
+            let enabled = tracing::enabled();
+            tracing::set_enabled(false);
+            tracing::last(n).iter().for_each(|record| kprintln!("{}", record));
+            tracing::set_enabled(enabled);
+        }
+        _ => return fail!("usage: trace on|off|clear|dump [n]"),
+    }
+    Ok(())
+}
+
+/// `watchdog [on|off]`
+///
+/// Starts or stops the hardware watchdog, or prints whether it is running.
+/// Stop it before spending time in a debugger.
+fn set_watchdog(args: &[&str]) -> Result<(), ()> {
+    match args {
+        [] => kprintln!("{}", if watchdog::running() { "on" } else { "off" }),
+        ["on"] => watchdog::start(),
+        ["off"] => watchdog::stop(),
+        _ => return fail!("usage: watchdog [on|off]"),
+    }
+    Ok(())
+}
+
+/// Parses `s` as a decimal number or, if prefixed with `0x`, as a hexadecimal
+/// number.
+fn parse_num(s: &str) -> Option<u64> {
+    match s.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16).ok(),
+        None => s.parse().ok(),
+    }
+}
+
+/// Number of bytes dumped by `xxd` when no length is given.
+const XXD_DEFAULT_LEN: u64 = 512;
+
+/// Size of an SD card sector in bytes.
+const SECTOR_SIZE: u64 = 512;
+
+/// Where `xxd` reads its bytes from.
+enum XxdSource<'a> {
+    File(&'a str),
+    Sector(u64),
+}
+
+/// `xxd <path> [offset] [len]` or `xxd --sector <n> [offset] [len]`
+///
+/// Prints a hex dump of `len` bytes starting at `offset` of the file or device
+/// at `path`, or of the raw SD card starting at sector `n`.
+fn xxd(args: &[&str]) -> Result<(), ()> {
+    let (source, args) = match args {
+        ["--sector", n, rest @ ..] => match parse_num(n) {
+            Some(n) => (XxdSource::Sector(n), rest),
+            None => return fail!("xxd: invalid sector number: {}", n),
         },
-        path => kprintln!("unknown command: {}", path),
+        [path, rest @ ..] => (XxdSource::File(path), rest),
+        [] => return fail!("usage: xxd <path> [offset] [len] | xxd --sector <n> [offset] [len]"),
+    };
+
+    let (offset, len) = match args {
+        [] => (Some(0), Some(XXD_DEFAULT_LEN)),
+        [offset] => (parse_num(offset), Some(XXD_DEFAULT_LEN)),
+        [offset, len] => (parse_num(offset), parse_num(len)),
+        _ => return fail!("xxd: too many arguments"),
+    };
+    let (offset, len) = match (offset, len) {
+        (Some(offset), Some(len)) => (offset, len),
+        _ => return fail!("xxd: invalid offset or length"),
+    };
+
+    let result = match source {
+        XxdSource::File(path) => xxd_file(path, offset, len),
+        XxdSource::Sector(n) => xxd_sectors(n, offset, len),
+    };
+    result.or_else(|e| fail!("xxd: {}", e))
+}
+
+/// Returns `start + len`, or an error of kind `InvalidInput` if that
+/// overflows.
+fn xxd_end(start: Option<u64>, len: u64) -> io::Result<u64> {
+    start
+        .and_then(|start| start.checked_add(len))
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "offset or length too large"))
+}
+
+/// Dumps `len` bytes of the file at `path` starting at byte `offset`.
+fn xxd_file(path: &str, offset: u64, len: u64) -> io::Result<()> {
+    let end = xxd_end(Some(offset), len)?;
+    let mut file = FILE_SYSTEM.open_handle(path)?;
+    // Character devices can't seek, but can be dumped from the start.
+    if offset != 0 {
+        file.seek(SeekFrom::Start(offset))?;
+    }
+
+    let mut buf = [0u8; SECTOR_SIZE as usize];
+    let mut addr = offset;
+    while addr < end {
+        let want = std::cmp::min(buf.len() as u64, end - addr) as usize;
+        let n = file.read(&mut buf[..want])?;
+        if n == 0 {
+            break;
+        }
+        hexdump(addr, &buf[..n]);
+        addr += n as u64;
     }
+
+    Ok(())
+}
+
+/// Dumps `len` bytes of the raw SD card starting at byte `offset` of sector
+/// `sector`.
+fn xxd_sectors(sector: u64, offset: u64, len: u64) -> io::Result<()> {
+    let start = sector.checked_mul(SECTOR_SIZE).and_then(|s| s.checked_add(offset));
+    let end = xxd_end(start, len)?;
+    let mut sd = CachedSd::open()?;
+
+    let mut buf = [0u8; SECTOR_SIZE as usize];
+    let mut addr = end - len;
+    while addr < end {
+        let in_sector = (addr % SECTOR_SIZE) as usize;
+        let want = std::cmp::min((SECTOR_SIZE as usize - in_sector) as u64, end - addr);
+        sd.read_sector(addr / SECTOR_SIZE, &mut buf)?;
+        hexdump(addr, &buf[in_sector..in_sector + want as usize]);
+        addr += want;
+    }
+
+    Ok(())
+}
+
+/// Prints `data` as lines of 16 bytes in `xxd` style: the address of the first
+/// byte (starting at `addr`), the bytes in hex, and their printable ASCII.
+fn hexdump(addr: u64, data: &[u8]) {
+    for (i, line) in data.chunks(16).enumerate() {
+        kprint!("{:08x}: ", addr + (i * 16) as u64);
+        for j in 0..16 {
+            match line.get(j) {
+                Some(b) => kprint!("{:02x}", b),
+                None => kprint!("  "),
+            }
+            if j % 2 == 1 {
+                kprint!(" ");
+            }
+        }
+        kprint!(" ");
+        for &b in line {
+            match b {
+                0x20..=0x7e => kprint!("{}", b as char),
+                _ => kprint!("."),
+            }
+        }
+        kprintln!();
+    }
+}
+
+/// Prints the output of the `/proc` file generator `write` to the console.
+fn print_proc(write: fn(&mut dyn fmt::Write) -> fmt::Result) -> Result<(), ()> {
+    write(&mut *CONSOLE.lock()).map_err(|_| ())
+}
+
+/// `cat <path>...`
+///
+/// Prints the contents of each file, device or `/proc` file in turn. Devices
+/// such as `/dev/zero` never end; they're printed until the board is reset.
+fn cat(args: &[&str]) -> Result<(), ()> {
+    if args.is_empty() {
+        return fail!("usage: cat <path>...");
+    }
+
+    let mut result = Ok(());
+    for path in args {
+        if let Err(e) = FILE_SYSTEM.open_handle(path).and_then(|mut f| print_all(&mut f)) {
+            result = fail!("cat: {}: {}", path, e);
+        }
+    }
+    result
+}
+
+/// Prints everything read from `reader` a sector at a time, so that a device
+/// that never ends doesn't have to fit in memory.
+fn print_all(reader: &mut dyn Read) -> io::Result<()> {
+    let mut buf = [0u8; SECTOR_SIZE as usize];
+    loop {
+        match reader.read(&mut buf)? {
+            0 => return Ok(()),
+            n => console::write(&buf[..n])?,
+        };
+    }
+}
+
+/// Size in MiB of the data read by `sdbench` when no size is given.
+const SDBENCH_DEFAULT_MIB: u64 = 1;
+
+/// Largest size in MiB `sdbench` accepts: the largest SDHC card.
+const SDBENCH_MAX_MIB: u64 = 32 * 1024;
+
+/// `sdbench [MiB]`
+///
+/// Measures SD card read throughput over `MiB` mebibytes: sequential and
+/// random reads straight from the card, then sequential reads through the
+/// block cache. Random reads pick sectors from the first `8 * MiB` MiB.
+fn sdbench(args: &[&str]) -> Result<(), ()> {
+    let mib = match args {
+        [] => SDBENCH_DEFAULT_MIB,
+        [mib] => match parse_num(mib) {
+            Some(mib) if mib > 0 && mib <= SDBENCH_MAX_MIB => mib,
+            _ => return fail!("sdbench: invalid size (1 to {} MiB): {}", SDBENCH_MAX_MIB, mib),
+        },
+        _ => return fail!("usage: sdbench [MiB]"),
+    };
+
+    let mut cached = match CachedSd::open() {
+        Ok(sd) => sd,
+        Err(e) => return fail!("sdbench: {}", io::Error::from(e)),
+    };
+
+    let sectors = mib * 1024 * 1024 / SECTOR_SIZE;
+    let runs: [(&str, Pattern, bool); 3] = [
+        ("sequential", Pattern::Sequential, false),
+        ("random", Pattern::Random, false),
+        ("cached", Pattern::Sequential, true),
+    ];
+    for &(name, pattern, through_cache) in runs.iter() {
+        let result = if through_cache {
+            sd::bench_read(&mut cached, pattern, sectors, 8 * sectors)
+        } else {
+            CachedSd::uncached(|sd| sd::bench_read(sd, pattern, sectors, 8 * sectors))
+        };
+        match result {
+            Ok(us) => {
+                let kib_per_s = mib * 1024 * 1_000_000 / std::cmp::max(us, 1);
+                kprintln!("{:<12}{} MiB in {} ms: {} KiB/s", name, mib, us / 1000, kib_per_s);
+            }
+            Err(e) => return fail!("sdbench: {} read failed: {}", name, e),
+        }
+    }
+    Ok(())
+}
+
+/// `rb [dir]`
+///
+/// Receives the files sent by `ttywrite --batch` and writes them under `dir`,
+/// `/` by default.
+fn rb(args: &[&str]) -> Result<(), ()> {
+    let dir = match args {
+        [] => "/",
+        [dir] if dir.starts_with('/') => dir,
+        _ => return fail!("usage: rb [dir]"),
+    };
+
+    if FILE_SYSTEM.is_read_only() {
+        return fail!("rb: {} is on a read only file system", dir);
+    }
+
+    kprintln!("rb: waiting for a YMODEM batch");
+    match receive::receive_batch(dir) {
+        Ok(n) => {
+            kprintln!("rb: received {} files", n);
+            Ok(())
+        }
+        Err(e) => fail!("rb: {}", e),
+    }
+}
+
+/// `sleep <ms>`
+///
+/// Sleeps for `ms` milliseconds.
+fn sleep(args: &[&str]) -> Result<(), ()> {
+    let ms = match args {
+        [ms] => match parse_num(ms) {
+            Some(ms) => ms,
+            None => return fail!("sleep: invalid duration: {}", ms),
+        },
+        _ => return fail!("usage: sleep <ms>"),
+    };
+
+    // FIXME: Block instead of spinning once there is a scheduler to yield to.
+    // Sleep a second at a time so that a long sleep isn't taken for a lockup.
+    let mut left = ms;
+    while left > 0 {
+        let chunk = std::cmp::min(left, 1000);
+        timer::spin_sleep_ms(chunk);
+        watchdog::pet();
+        left -= chunk;
+    }
+    Ok(())
+}
+
+/// Highest GPIO pin number.
+const MAX_GPIO_PIN: u64 = 53;
+
+/// `gpio read <n>`, `gpio set <n> <0|1>` or `gpio fn <n> [function]`
+///
+/// Reads the level of pin `n`, drives output pin `n` low or high, or shows or
+/// changes the function of pin `n`. `function` is one of `in`, `out` and
+/// `alt0` through `alt5`.
+fn gpio(args: &[&str]) -> Result<(), ()> {
+    let usage = "usage: gpio read <n> | gpio set <n> <0|1> | gpio fn <n> [function]";
+    let (subcmd, pin, args) = match args {
+        [subcmd, pin, rest @ ..] => (*subcmd, pin, rest),
+        _ => return fail!("{}", usage),
+    };
+    let pin = match parse_num(pin) {
+        Some(pin) if pin <= MAX_GPIO_PIN => pin as u8,
+        _ => return fail!("gpio: invalid pin: {}", pin),
+    };
+
+    match (subcmd, args) {
+        ("read", []) => kprintln!("{}", Gpio::new(pin).level() as u8),
+        ("set", ["0"]) => Gpio::new(pin).into_output().clear(),
+        ("set", ["1"]) => Gpio::new(pin).into_output().set(),
+        ("fn", []) => kprintln!("{:?}", Gpio::new(pin).function()),
+        ("fn", [function]) => {
+            let function = match *function {
+                "in" => Function::Input,
+                "out" => Function::Output,
+                "alt0" => Function::Alt0,
+                "alt1" => Function::Alt1,
+                "alt2" => Function::Alt2,
+                "alt3" => Function::Alt3,
+                "alt4" => Function::Alt4,
+                "alt5" => Function::Alt5,
+                _ => return fail!("gpio: invalid function: {}", function),
+            };
+            Gpio::new(pin).into_alt(function);
+        }
+        _ => return fail!("{}", usage),
+    }
+    Ok(())
+}
+
+/// How deeply `sh` scripts may run one another. Each level takes a stack
+/// frame and a copy of its script, so a script that runs itself would
+/// otherwise overflow the stack.
+const MAX_SH_DEPTH: usize = 8;
+
+/// How many `sh` scripts are running, one inside the other.
+static SH_DEPTH: AtomicUsize = AtomicUsize::new(0);
+
+/// `sh [-e] <path>`
+///
+/// Executes the commands in the script at `path`, one per line. Empty lines
+/// and lines starting with `#` are skipped. With `-e`, execution stops at the
+/// first command that fails. Scripts may run scripts up to `MAX_SH_DEPTH`
+/// deep.
+fn sh(args: &[&str]) -> Result<(), ()> {
+    let (stop_on_error, path) = match args {
+        ["-e", path] => (true, *path),
+        [path] => (false, *path),
+        _ => return fail!("usage: sh [-e] <path>"),
+    };
+
+    if SH_DEPTH.fetch_add(1, Ordering::Relaxed) >= MAX_SH_DEPTH {
+        SH_DEPTH.fetch_sub(1, Ordering::Relaxed);
+        return fail!("sh: {}: scripts nested more than {} deep", path, MAX_SH_DEPTH);
+    }
+    let result = run_script(path, stop_on_error);
+    SH_DEPTH.fetch_sub(1, Ordering::Relaxed);
+    result
+}
+
+/// Runs the script at `path` for `sh`.
+fn run_script(path: &str, stop_on_error: bool) -> Result<(), ()> {
+    let mut script = vec![];
+    let read = FILE_SYSTEM
+        .open_handle(path)
+        .and_then(|mut file| file.read_to_end(&mut script));
+    if let Err(e) = read {
+        return fail!("sh: {}: {}", path, e);
+    }
+    let script = match std::str::from_utf8(&script) {
+        Ok(script) => script,
+        Err(_) => return fail!("sh: {}: not a text file", path),
+    };
+
+    let mut result = Ok(());
+    for (i, line) in script.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let args_buf = &mut [""; MAX_ARG_NUM];
+        let status = match Command::parse(line, args_buf) {
+            Ok(cmd) => execute_cmd(cmd),
+            Err(Error::TooManyArgs) => fail!("error: too many arguments"),
+            Err(Error::Empty) => continue,
+        };
+
+        if status.is_err() {
+            result = Err(());
+            if stop_on_error {
+                return fail!("sh: {}:{}: command failed: {}", path, i + 1, line);
+            }
+        }
+    }
+
+    result
 }
 
 fn read_line(buf: &mut [u8]) -> &str {
     let mut cmd_buf = StackVec::new(buf);
+    // How much of `gdb::MAGIC` has just been received. Its bytes aren't
+    // echoed, and are dropped if the rest doesn't follow.
+    let mut magic = 0;
 
     loop {
-        let b = CONSOLE.lock().read_byte();
+        let b = console::read_byte();
+        magic = match b {
+            _ if b == gdb::MAGIC[magic] => magic + 1,
+            _ if b == gdb::MAGIC[0] => 1,
+            _ => 0,
+        };
+        if magic == gdb::MAGIC.len() {
+            // hand the UART over to the stub
+            if let Err(e) = gdb::serve() {
+                kprintln!("gdb: {}", e);
+            }
+            kprintln!();
+            break;
+        }
+        if magic > 0 {
+            continue;
+        }
+
         match b {
             // enter
             b'\r' | b'\n' => {
@@ -94,6 +631,16 @@ fn read_line(buf: &mut [u8]) -> &str {
                 Some(_) => kprint!("\u{8} \u{8}"),
                 None => ring_bell(),
             },
+            // ctrl-u: erase the whole line
+            0x15 => {
+                if ansi::enabled() {
+                    ansi::restore_cursor();
+                    ansi::clear_to_end();
+                } else {
+                    (0..cmd_buf.len()).for_each(|_| kprint!("\u{8} \u{8}"));
+                }
+                cmd_buf.truncate(0);
+            }
             // other non-visiable
             _ => ring_bell(),
         }
@@ -0,0 +1,144 @@
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use pi::timer;
+use stack_vec::RingBuffer;
+
+use crate::mutex::Mutex;
+
+/// Number of events kept per core. Older events are overwritten.
+const RING_SIZE: usize = 512;
+
+/// Number of cores with their own ring.
+const NUM_CORES: usize = 4;
+
+/// Whether tracepoints record anything. Off by default.
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Kind of a traced event. `a` and `b` in `Record` depend on the kind.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Event {
+    /// Initializing the SD card.
+    SdInitStart,
+    /// Finished initializing the SD card; `b` is 0 or the (negative) error
+    /// code.
+    SdInitDone,
+    /// Reading SD sector `a`.
+    SdReadStart,
+    /// Finished reading SD sector `a`; `b` is 0 or the (negative) error code.
+    SdReadDone,
+    /// Allocated `b` bytes at `a`.
+    Alloc,
+    /// Freed `b` bytes at `a`.
+    Dealloc,
+}
+
+impl Event {
+    fn name(self) -> &'static str {
+        match self {
+            Event::SdInitStart => "sd_init_start",
+            Event::SdInitDone => "sd_init_done",
+            Event::SdReadStart => "sd_read_start",
+            Event::SdReadDone => "sd_read_done",
+            Event::Alloc => "alloc",
+            Event::Dealloc => "dealloc",
+        }
+    }
+}
+
+/// A recorded event.
+#[derive(Debug, Copy, Clone)]
+pub struct Record {
+    /// Microseconds since boot.
+    pub time: u64,
+    pub core: usize,
+    pub event: Event,
+    pub a: u64,
+    pub b: u64,
+}
+
+impl fmt::Display for Record {
+    /// Formats the record as tab-separated `time core event a b`, with `a` and
+    /// `b` in hex, for easy parsing on the host.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}\t{}\t{}\t{:#x}\t{:#x}",
+            self.time,
+            self.core,
+            self.event.name(),
+            self.a,
+            self.b
+        )
+    }
+}
+
+/// The most recent records of one core.
+type Ring = RingBuffer<Record, RING_SIZE>;
+
+static RINGS: [Mutex<Ring>; NUM_CORES] = [
+    Mutex::new(Ring::new()),
+    Mutex::new(Ring::new()),
+    Mutex::new(Ring::new()),
+    Mutex::new(Ring::new()),
+];
+
+/// Returns the index of the core this code runs on.
+fn core_id() -> usize {
+    #[cfg(target_arch = "aarch64")]
+    unsafe {
+        let mpidr: u64;
+        core::arch::asm!("mrs {}, mpidr_el1", out(reg) mpidr);
+        return (mpidr & 0b11) as usize;
+    }
+
+    #[allow(unreachable_code)]
+    0
+}
+
+/// Starts or stops recording events.
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed)
+}
+
+/// Returns `true` if events are being recorded.
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Records `event` with arguments `a` and `b` in the current core's ring, if
+/// tracing is enabled. Never allocates, so it is safe to call from the
+/// allocator.
+#[inline]
+pub fn record(event: Event, a: u64, b: u64) {
+    if !enabled() {
+        return;
+    }
+
+    let core = core_id();
+    let time = timer::current_time();
+    RINGS[core].lock().push_back_overwrite(Record { time, core, event, a, b });
+}
+
+/// Calls `f` with every recorded event, core by core, oldest first.
+pub fn for_each(mut f: impl FnMut(&Record)) {
+    for ring in RINGS.iter() {
+        ring.lock().iter().for_each(&mut f);
+    }
+}
+
+/// Returns the `n` most recent events across all cores, oldest first.
+pub fn last(n: usize) -> Vec<Record> {
+    let mut records = vec![];
+    for_each(|record| records.push(*record));
+    records.sort_by_key(|record| record.time);
+    let skip = records.len().saturating_sub(n);
+    records.split_off(skip)
+}
+
+/// Discards all recorded events.
+pub fn clear() {
+    for ring in RINGS.iter() {
+        ring.lock().clear();
+    }
+}
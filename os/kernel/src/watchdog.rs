@@ -0,0 +1,58 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use pi::timer;
+use pi::watchdog::Watchdog;
+
+use crate::log::warn;
+use crate::panic_log;
+
+/// How long the kernel may go without petting the watchdog before the board
+/// is reset.
+const TIMEOUT_MS: u32 = 15_000;
+
+/// Whether the watchdog has been started by `start()`.
+static RUNNING: AtomicBool = AtomicBool::new(false);
+
+/// Starts the hardware watchdog. From now on, `pet()` must be called at least
+/// every `TIMEOUT_MS` milliseconds or the board resets.
+pub fn start() {
+    RUNNING.store(true, Ordering::Relaxed);
+    pet();
+}
+
+/// Stops the hardware watchdog, e.g. while sitting in a debugger.
+pub fn stop() {
+    RUNNING.store(false, Ordering::Relaxed);
+    Watchdog::new().stop();
+}
+
+/// Returns `true` if the watchdog is running.
+pub fn running() -> bool {
+    RUNNING.load(Ordering::Relaxed)
+}
+
+/// Restarts the watchdog's countdown, if it is running, and notes the time in
+/// the persistent panic area so a lockup can be dated after the reset.
+pub fn pet() {
+    if running() {
+        Watchdog::new().start(TIMEOUT_MS);
+        panic_log::heartbeat(timer::current_time());
+    }
+}
+
+/// Reports whether the previous boot ended in a watchdog reset, i.e. the
+/// kernel locked up, and when it was last known to be alive.
+pub fn report_previous() {
+    if !Watchdog::new().caused_last_reset() {
+        return;
+    }
+
+    match panic_log::last_heartbeat() {
+        Some(us) => warn!(
+            "previous boot locked up and was reset by the watchdog; last alive {}.{:06}s after boot",
+            us / 1_000_000,
+            us % 1_000_000
+        ),
+        None => warn!("previous boot locked up and was reset by the watchdog"),
+    }
+}
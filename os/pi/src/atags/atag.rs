@@ -1,12 +1,13 @@
 use super::raw;
 
-pub use super::raw::{Core, Mem};
+pub use super::raw::{Core, Initrd, Mem};
 
 /// An ATAG.
 #[derive(Debug, Copy, Clone)]
 pub enum Atag {
     Core(raw::Core),
     Mem(raw::Mem),
+    Initrd(raw::Initrd),
     Cmd(&'static str),
     Unknown(u32),
     None,
@@ -29,6 +30,14 @@ impl Atag {
         }
     }
 
+    /// Returns `Some` if this is an `Initrd` ATAG. Otherwise returns `None`.
+    pub fn initrd(self) -> Option<Initrd> {
+        match self {
+            Atag::Initrd(initrd) => Some(initrd),
+            _ => None,
+        }
+    }
+
     /// Returns `Some` with the command line string if this is a `Cmd` ATAG.
     /// Otherwise returns `None`.
     pub fn cmd(self) -> Option<&'static str> {
@@ -55,6 +64,12 @@ impl From<raw::Mem> for Atag {
     }
 }
 
+impl From<raw::Initrd> for Atag {
+    fn from(value: raw::Initrd) -> Self {
+        Atag::Initrd(value)
+    }
+}
+
 impl From<&raw::Cmd> for Atag {
     fn from(value: &raw::Cmd) -> Self {
         let mut len = 0;
@@ -77,6 +92,7 @@ impl<'a> From<&'a raw::Atag> for Atag {
             match (atag.tag, &atag.kind) {
                 (raw::Atag::CORE, &raw::Kind { core }) => core.into(),
                 (raw::Atag::MEM, &raw::Kind { mem }) => mem.into(),
+                (raw::Atag::INITRD2, &raw::Kind { initrd }) => initrd.into(),
                 (raw::Atag::CMDLINE, &raw::Kind { ref cmd }) => cmd.into(),
                 (raw::Atag::NONE, _) => Atag::None,
                 (id, _) => Atag::Unknown(id),
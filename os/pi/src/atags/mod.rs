@@ -6,6 +6,9 @@ pub use self::atag::*;
 /// The address at which the firmware loads the ATAGS.
 const ATAG_BASE: usize = 0x100;
 
+/// The end of the space the ATAGS may take up, below the kernel's stack.
+const ATAG_END: usize = 0x1000;
+
 /// An iterator over the ATAGS on this system.
 pub struct Atags {
     ptr: &'static raw::Atag,
@@ -29,3 +32,51 @@ impl Iterator for Atags {
         Some(curr)
     }
 }
+
+/// Replaces the command line in the ATAGS with `cmdline`, for the kernel
+/// started after this to find. The other ATAGS are kept. Returns `false`,
+/// leaving the ATAGS alone, if they wouldn't fit below `ATAG_END` anymore.
+///
+/// # Safety
+///
+/// Nothing may still borrow from the ATAGS, such as a string from an earlier
+/// `Atag::Cmd`.
+pub unsafe fn set_cmdline(cmdline: &str) -> bool {
+    const HEADER: usize = 2;
+    let mut words = [0u32; (ATAG_END - ATAG_BASE) / 4];
+    let mut len = 0;
+
+    // Copy every ATAG but the command line and the terminating `NONE`.
+    let mut atag = ATAG_BASE as *const u32;
+    loop {
+        let (dwords, tag) = (*atag as usize, *atag.add(1));
+        if tag == raw::Atag::NONE || dwords < HEADER || len + dwords > words.len() {
+            break;
+        }
+        if tag != raw::Atag::CMDLINE {
+            let src = core::slice::from_raw_parts(atag, dwords);
+            words[len..len + dwords].copy_from_slice(src);
+            len += dwords;
+        }
+        atag = atag.add(dwords);
+    }
+
+    // The command line is NUL-terminated and padded to whole words, and a
+    // `NONE` ends the list.
+    let dwords = HEADER + (cmdline.len() + 1 + 3) / 4;
+    if len + dwords + HEADER > words.len() {
+        return false;
+    }
+    words[len] = dwords as u32;
+    words[len + 1] = raw::Atag::CMDLINE;
+    for (i, &byte) in cmdline.as_bytes().iter().enumerate() {
+        words[len + HEADER + i / 4] |= (byte as u32) << (8 * (i % 4));
+    }
+    len += dwords;
+    words[len] = HEADER as u32;
+    words[len + 1] = raw::Atag::NONE;
+    len += HEADER;
+
+    core::ptr::copy_nonoverlapping(words.as_ptr(), ATAG_BASE as *mut u32, len);
+    true
+}
@@ -34,6 +34,7 @@ impl Atag {
 pub union Kind {
     pub core: Core,
     pub mem: Mem,
+    pub initrd: Initrd,
     pub cmd: Cmd,
 }
 
@@ -54,6 +55,14 @@ pub struct Mem {
     pub start: u32,
 }
 
+/// An `INITRD2` ATAG: the physical location of the initial RAM disk.
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct Initrd {
+    pub start: u32,
+    pub size: u32,
+}
+
 /// A `CMDLINE` ATAG.
 #[repr(C)]
 #[derive(Debug, Copy, Clone)]
@@ -1,6 +1,24 @@
 /// The address where I/O peripherals are mapped to.
 pub const IO_BASE: usize = 0x3F000000;
 
+/// The size of the I/O peripheral region starting at `IO_BASE`.
+pub const IO_SIZE: usize = 0x1000000;
+
+/// The address of the ARM local peripherals (core timers and mailboxes).
+pub const LOCAL_IO_BASE: usize = 0x40000000;
+
+/// The size of the ARM local peripheral region.
+pub const LOCAL_IO_SIZE: usize = 0x40000;
+
+/// The address the kernel is loaded at, by the firmware or the bootloader.
+pub const KERNEL_START: usize = 0x80000;
+
+/// The address the bootloader is linked at. Loaded kernels must end below it.
+pub const BOOTLOADER_START: usize = 0x4000000;
+
+/// Room for the bootloader's image and data above `BOOTLOADER_START`.
+pub const BOOTLOADER_SIZE: usize = 0x100000;
+
 /// Generates `pub enums` with no variants for each `ident` passed in.
 pub macro states($($name:ident),*) {
     $(
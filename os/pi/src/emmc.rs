@@ -0,0 +1,135 @@
+use crate::common::IO_BASE;
+use volatile::prelude::*;
+use volatile::{ReadVolatile, Reserved, Volatile};
+
+/// The base address for the `EMMC` (SD host controller) registers.
+const EMMC_REG_BASE: usize = IO_BASE + 0x300000;
+
+/// The frequency of the clock feeding the EMMC controller, in Hz.
+pub const BASE_CLOCK_HZ: u32 = 41_666_666;
+
+#[repr(C)]
+#[allow(non_snake_case)]
+struct Registers {
+    ARG2: Volatile<u32>,
+    BLKSIZECNT: Volatile<u32>,
+    ARG1: Volatile<u32>,
+    CMDTM: Volatile<u32>,
+    RESP: [ReadVolatile<u32>; 4],
+    DATA: Volatile<u32>,
+    STATUS: ReadVolatile<u32>,
+    CONTROL0: Volatile<u32>,
+    CONTROL1: Volatile<u32>,
+    INTERRUPT: Volatile<u32>,
+    IRPT_MASK: Volatile<u32>,
+    IRPT_EN: Volatile<u32>,
+    CONTROL2: Volatile<u32>,
+    CAPABILITIES: [ReadVolatile<u32>; 2],
+    __r0: [Reserved<u32>; 45],
+    SLOTISR_VER: ReadVolatile<u32>,
+}
+
+/// Read-only view of the EMMC controller's state.
+///
+/// This is intended for diagnostics only: the controller itself is driven by
+/// `libsd`, and nothing here modifies any register.
+pub struct Emmc {
+    registers: &'static Registers,
+}
+
+impl Emmc {
+    /// Returns a new instance of `Emmc`.
+    pub fn new() -> Emmc {
+        Emmc {
+            registers: unsafe { &*(EMMC_REG_BASE as *const Registers) },
+        }
+    }
+
+    /// The host controller's vendor version number.
+    pub fn vendor_version(&self) -> u8 {
+        (self.registers.SLOTISR_VER.read() >> 24) as u8
+    }
+
+    /// The SD Host Controller specification version: `0` for 1.00, `1` for
+    /// 2.00 and `2` for 3.00.
+    pub fn spec_version(&self) -> u8 {
+        (self.registers.SLOTISR_VER.read() >> 16) as u8
+    }
+
+    /// The raw 64-bit `Capabilities` register.
+    pub fn capabilities(&self) -> u64 {
+        (self.registers.CAPABILITIES[1].read() as u64) << 32
+            | self.registers.CAPABILITIES[0].read() as u64
+    }
+
+    /// The raw `STATUS` (Present State) register.
+    pub fn status(&self) -> u32 {
+        self.registers.STATUS.read()
+    }
+
+    /// The raw `INTERRUPT` register. The low 16 bits are the normal interrupt
+    /// flags, the high 16 bits the error interrupt flags.
+    pub fn interrupt(&self) -> u32 {
+        self.registers.INTERRUPT.read()
+    }
+
+    /// The raw `IRPT_MASK` register.
+    pub fn interrupt_mask(&self) -> u32 {
+        self.registers.IRPT_MASK.read()
+    }
+
+    /// The raw `IRPT_EN` register.
+    pub fn interrupt_enable(&self) -> u32 {
+        self.registers.IRPT_EN.read()
+    }
+
+    /// The width, in bits, of the data bus to the card: 1, 4 or 8.
+    pub fn bus_width(&self) -> u8 {
+        let control0 = self.registers.CONTROL0.read();
+        if control0 & (1 << 5) != 0 {
+            8
+        } else if control0 & (1 << 1) != 0 {
+            4
+        } else {
+            1
+        }
+    }
+
+    /// Whether the write-protect switch of the card is set, according to the
+    /// pin level in `STATUS`. Slots without a switch report the pin's idle
+    /// level.
+    pub fn write_protected(&self) -> bool {
+        !self.registers.STATUS.has_mask(1 << 19)
+    }
+
+    /// Whether SD clock output to the card is enabled.
+    pub fn clock_enabled(&self) -> bool {
+        self.registers.CONTROL1.has_mask(1 << 2)
+    }
+
+    /// The 10-bit divided clock mode divisor `N`. The SD clock frequency is
+    /// `BASE_CLOCK_HZ / (2 * N)`, or `BASE_CLOCK_HZ` if `N` is `0`.
+    pub fn clock_divisor(&self) -> u16 {
+        let control1 = self.registers.CONTROL1.read();
+        ((control1 >> 8) & 0xFF | ((control1 >> 6) & 0b11) << 8) as u16
+    }
+
+    /// The SD clock frequency in Hz, or `0` if the clock is disabled.
+    pub fn clock_hz(&self) -> u32 {
+        match (self.clock_enabled(), self.clock_divisor()) {
+            (false, _) => 0,
+            (true, 0) => BASE_CLOCK_HZ,
+            (true, n) => BASE_CLOCK_HZ / (2 * n as u32),
+        }
+    }
+
+    /// The last response from the card, `RESP0` through `RESP3`.
+    pub fn response(&self) -> [u32; 4] {
+        [
+            self.registers.RESP[0].read(),
+            self.registers.RESP[1].read(),
+            self.registers.RESP[2].read(),
+            self.registers.RESP[3].read(),
+        ]
+    }
+}
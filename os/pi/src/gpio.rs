@@ -6,6 +6,7 @@ use volatile::{ReadVolatile, Reserved, Volatile, WriteVolatile};
 
 /// An alternative GPIO function.
 #[repr(u8)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum Function {
     Input = 0b000,
     Output = 0b001,
@@ -17,6 +18,22 @@ pub enum Function {
     Alt5 = 0b010,
 }
 
+impl Function {
+    /// Returns the function corresponding to the 3-bit `FSEL` field `bits`.
+    fn from_bits(bits: u32) -> Function {
+        match bits & 0b111 {
+            0b000 => Function::Input,
+            0b001 => Function::Output,
+            0b100 => Function::Alt0,
+            0b101 => Function::Alt1,
+            0b110 => Function::Alt2,
+            0b111 => Function::Alt3,
+            0b011 => Function::Alt4,
+            _ => Function::Alt5,
+        }
+    }
+}
+
 #[repr(C)]
 #[allow(non_snake_case)]
 struct Registers {
@@ -79,6 +96,23 @@ impl<T> Gpio<T> {
             _state: PhantomData,
         }
     }
+
+    /// Returns the function this pin is currently configured for.
+    pub fn function(&self) -> Function {
+        let reg_idx = self.pin as usize / 10;
+        let shift_bit_num = (self.pin as usize % 10) * 3;
+        Function::from_bits(self.registers.FSEL[reg_idx].read() >> shift_bit_num)
+    }
+
+    /// Reads the pin's level regardless of its function. Returns `true` if the
+    /// level is high and `false` if the level is low.
+    fn read_level(&self) -> bool {
+        let reg_idx = self.pin as usize / 32;
+        let level = self.registers.LEV[reg_idx].read();
+
+        let shift_bit_num = self.pin as usize % 32;
+        level << (31 - shift_bit_num) >> 31 == 1
+    }
 }
 
 impl Gpio<Uninitialized> {
@@ -128,6 +162,12 @@ impl Gpio<Uninitialized> {
     pub fn into_input(self) -> Gpio<Input> {
         self.into_alt(Function::Input).transition()
     }
+
+    /// Reads the pin's level without changing its function. Returns `true` if
+    /// the level is high and `false` if the level is low.
+    pub fn level(&self) -> bool {
+        self.read_level()
+    }
 }
 
 impl Gpio<Output> {
@@ -152,10 +192,6 @@ impl Gpio<Input> {
     /// Reads the pin's value. Returns `true` if the level is high and `false`
     /// if the level is low.
     pub fn level(&mut self) -> bool {
-        let reg_idx = self.pin as usize / 32;
-        let level = self.registers.LEV[reg_idx].read();
-
-        let shift_bit_num = self.pin as usize % 32;
-        level << (31 - shift_bit_num) >> 31 == 1
+        self.read_level()
     }
 }
@@ -16,6 +16,9 @@ extern crate std;
 
 pub mod atags;
 pub mod common;
+pub mod emmc;
 pub mod gpio;
+pub mod mailbox;
 pub mod timer;
 pub mod uart;
+pub mod watchdog;
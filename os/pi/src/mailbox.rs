@@ -0,0 +1,78 @@
+use core::sync::atomic::{fence, Ordering};
+
+use crate::common::IO_BASE;
+use volatile::prelude::*;
+use volatile::{ReadVolatile, Reserved, WriteVolatile};
+
+/// The base address of the VideoCore mailbox registers.
+const MAILBOX_BASE: usize = IO_BASE + 0xB880;
+
+/// The channel for property tags from the ARM to the VideoCore.
+const PROPERTY_CHANNEL: u32 = 8;
+
+/// `STATUS` bits.
+const FULL: u32 = 1 << 31;
+const EMPTY: u32 = 1 << 30;
+
+/// The code of a successfully processed property message.
+const RESPONSE_SUCCESS: u32 = 0x8000_0000;
+
+/// The most value words `property` passes along with a tag.
+const MAX_VALUE_LEN: usize = 4;
+
+/// Sets the state of a GPIO pin the firmware controls: the value is the pin
+/// number followed by `0` for off or `1` for on.
+pub const SET_GPIO_STATE: u32 = 0x0003_8041;
+
+#[repr(C)]
+#[allow(non_snake_case)]
+struct Registers {
+    READ: ReadVolatile<u32>,
+    __r0: [Reserved<u32>; 5],
+    STATUS: ReadVolatile<u32>,
+    __r1: Reserved<u32>,
+    WRITE: WriteVolatile<u32>,
+}
+
+/// A property message, which the VideoCore needs 16-byte aligned.
+#[repr(C, align(16))]
+struct Message([u32; 6 + MAX_VALUE_LEN]);
+
+/// Sends the property tag `tag` with the value `value` to the firmware and
+/// waits for its answer, which replaces `value`. Returns `false` if the
+/// firmware couldn't process the tag.
+///
+/// This is how the ARM reaches hardware that sits behind the VideoCore, such
+/// as the Pi 3's activity LED, which is on the firmware's GPIO expander.
+///
+/// # Panics
+///
+/// Panics if `value` is longer than 4 words.
+pub fn property(tag: u32, value: &mut [u32]) -> bool {
+    assert!(value.len() <= MAX_VALUE_LEN, "property value too long");
+    let registers = unsafe { &mut *(MAILBOX_BASE as *mut Registers) };
+
+    let len = value.len();
+    let mut message = Message([0; 6 + MAX_VALUE_LEN]);
+    message.0[0] = ((6 + len) * 4) as u32;
+    message.0[2] = tag;
+    message.0[3] = (len * 4) as u32;
+    message.0[5..5 + len].copy_from_slice(value);
+
+    let addr = &message as *const Message as usize as u32;
+    while registers.STATUS.has_mask(FULL) {}
+    fence(Ordering::SeqCst);
+    registers.WRITE.write(addr | PROPERTY_CHANNEL);
+
+    loop {
+        while registers.STATUS.has_mask(EMPTY) {}
+        if registers.READ.read() == addr | PROPERTY_CHANNEL {
+            break;
+        }
+    }
+    fence(Ordering::SeqCst);
+
+    let message = unsafe { core::ptr::read_volatile(&message) };
+    value.copy_from_slice(&message.0[5..5 + len]);
+    message.0[1] == RESPONSE_SUCCESS
+}
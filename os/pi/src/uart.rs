@@ -13,11 +13,20 @@ const MU_REG_BASE: usize = IO_BASE + 0x215040;
 /// The `AUXENB` register from page 9 of the BCM2837 documentation.
 const AUX_ENABLES: *mut Volatile<u8> = (IO_BASE + 0x215004) as *mut Volatile<u8>;
 
+/// The mini UART runs off the 250 MHz system clock: the baud rate is
+/// `SYSTEM_CLOCK / (8 * (divisor + 1))`.
+const SYSTEM_CLOCK: u32 = 250_000_000;
+
+/// How far, in thousandths, a baud rate the divisor can produce may be off
+/// from the one asked for. UARTs get unreliable beyond a few percent.
+const MAX_BAUD_ERROR: u32 = 20;
+
 /// Enum representing bit fields of the `AUX_MU_LSR_REG` register.
 #[repr(u8)]
 enum LsrStatus {
     DataReady = 1,
     TxAvailable = 1 << 5,
+    TxIdle = 1 << 6,
 }
 
 #[repr(u8)]
@@ -93,6 +102,52 @@ impl MiniUart {
         self.timeout = Some(milliseconds)
     }
 
+    /// Returns `true` if the mini UART can run at `baud` within 2%.
+    pub fn supports_baud_rate(baud: u32) -> bool {
+        MiniUart::divisor(baud).is_some()
+    }
+
+    /// Returns the divisor giving the rate closest to `baud`, if that rate is
+    /// close enough.
+    fn divisor(baud: u32) -> Option<u32> {
+        let step = 8 * baud as u64;
+        if step == 0 {
+            return None;
+        }
+        let divisor = ((SYSTEM_CLOCK as u64 + step / 2) / step).checked_sub(1)?;
+        if divisor > 0xFFFF {
+            return None;
+        }
+
+        let actual = SYSTEM_CLOCK as u64 / (8 * (divisor + 1));
+        let error = (actual as i64 - baud as i64).unsigned_abs();
+        if error * 1000 > baud as u64 * MAX_BAUD_ERROR as u64 {
+            return None;
+        }
+        Some(divisor as u32)
+    }
+
+    /// Switches to the baud rate `baud`, once everything written so far has
+    /// been sent at the old one. Returns `false`, leaving the rate alone, if
+    /// `supports_baud_rate(baud)` doesn't hold.
+    pub fn set_baud_rate(&mut self, baud: u32) -> bool {
+        match MiniUart::divisor(baud) {
+            Some(divisor) => {
+                self.flush();
+                self.registers
+                    .BAUD
+                    .write(self.registers.BAUD.read() & !0xFFFF | divisor);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Blocks until every byte written has left the transmitter.
+    pub fn flush(&mut self) {
+        while !self.registers.LSR.has_mask(LsrStatus::TxIdle as u32) {}
+    }
+
     /// Write the byte `byte`. This method blocks until there is space available
     /// in the output FIFO.
     pub fn write_byte(&mut self, byte: u8) {
@@ -122,6 +177,12 @@ impl MiniUart {
     /// returns `Ok(())`, a subsequent call to `read_byte` is guaranteed to
     /// return immediately.
     pub fn wait_for_byte(&self) -> Result<(), ()> {
+        self.wait_for_byte_with(|| {})
+    }
+
+    /// Like `wait_for_byte`, but calls `idle` over and over while waiting, so
+    /// the caller can keep something else going meanwhile.
+    pub fn wait_for_byte_with<F: FnMut()>(&self, mut idle: F) -> Result<(), ()> {
         match self.timeout {
             Some(timeout) => {
                 let deadline = timer::current_time() + (timeout as u64 * 1000);
@@ -132,12 +193,14 @@ impl MiniUart {
                     if timer::current_time() > deadline {
                         return Err(());
                     }
+                    idle();
                 }
             }
             None => loop {
                 if self.has_byte() {
                     return Ok(());
                 }
+                idle();
             },
         }
     }
@@ -0,0 +1,73 @@
+use crate::common::IO_BASE;
+use volatile::prelude::*;
+use volatile::{ReadVolatile, Volatile};
+
+/// The base address of the power management watchdog registers (`PM_RSTC`).
+const PM_REG_BASE: usize = IO_BASE + 0x10001c;
+
+/// Must be written in the top byte of every PM register write.
+const PM_PASSWORD: u32 = 0x5a00_0000;
+
+const PM_RSTC_WRCFG_CLR: u32 = 0xffff_ffcf;
+const PM_RSTC_WRCFG_FULL_RESET: u32 = 0x0000_0020;
+const PM_RSTC_RESET: u32 = 0x0000_0102;
+const PM_RSTS_HADWRH_SET: u32 = 0x0000_0040;
+const PM_WDOG_TIME_SET: u32 = 0x000f_ffff;
+
+/// The watchdog counts down in ticks of 1/65536 of a second.
+const TICKS_PER_SEC: u64 = 65536;
+
+/// The longest timeout the watchdog supports, in milliseconds.
+pub const MAX_TIMEOUT_MS: u32 = (PM_WDOG_TIME_SET as u64 * 1000 / TICKS_PER_SEC) as u32;
+
+#[repr(C)]
+#[allow(non_snake_case)]
+struct Registers {
+    RSTC: Volatile<u32>,
+    RSTS: ReadVolatile<u32>,
+    WDOG: Volatile<u32>,
+}
+
+/// The power management watchdog, which resets the board when it expires.
+pub struct Watchdog {
+    registers: &'static mut Registers,
+}
+
+impl Watchdog {
+    /// Returns a new instance of `Watchdog`.
+    pub fn new() -> Watchdog {
+        Watchdog {
+            registers: unsafe { &mut *(PM_REG_BASE as *mut Registers) },
+        }
+    }
+
+    /// Starts the watchdog so that the board resets in `timeout_ms`
+    /// milliseconds, capped at `MAX_TIMEOUT_MS`. Calling this again before
+    /// then restarts the countdown.
+    pub fn start(&mut self, timeout_ms: u32) {
+        let ticks = timeout_ms as u64 * TICKS_PER_SEC / 1000;
+        let ticks = core::cmp::min(ticks, PM_WDOG_TIME_SET as u64) as u32;
+
+        self.registers.WDOG.write(PM_PASSWORD | ticks);
+        let rstc = self.registers.RSTC.read() & PM_RSTC_WRCFG_CLR;
+        self.registers.RSTC.write(PM_PASSWORD | rstc | PM_RSTC_WRCFG_FULL_RESET);
+    }
+
+    /// Stops the watchdog.
+    pub fn stop(&mut self) {
+        self.registers.RSTC.write(PM_PASSWORD | PM_RSTC_RESET);
+    }
+
+    /// Returns the time left before the watchdog resets the board, in
+    /// milliseconds.
+    pub fn remaining_ms(&self) -> u32 {
+        let ticks = self.registers.WDOG.read() & PM_WDOG_TIME_SET;
+        (ticks as u64 * 1000 / TICKS_PER_SEC) as u32
+    }
+
+    /// Returns `true` if the last reset of the board was caused by the
+    /// watchdog.
+    pub fn caused_last_reset(&self) -> bool {
+        self.registers.RSTS.has_mask(PM_RSTS_HADWRH_SET)
+    }
+}
@@ -1567,31 +1567,32 @@ pub fn hard_link<P: AsRef<Path>, Q: AsRef<Path>>(src: P, dst: Q) -> io::Result<(
     fs_imp::link(src.as_ref(), dst.as_ref())
 }
 
-/// Creates a new symbolic link on the filesystem.
-///
-/// The `dst` path will be a symbolic link pointing to the `src` path.
-/// On Windows, this will be a file symlink, not a directory symlink;
-/// for this reason, the platform-specific `std::os::unix::fs::symlink`
-/// and `std::os::windows::fs::{symlink_file, symlink_dir}` should be
-/// used instead to make the intent explicit.
-///
-/// # Examples
-///
-/// ```
-/// use std::fs;
-///
-/// # fn foo() -> std::io::Result<()> {
-/// fs::soft_link("a.txt", "b.txt")?;
-/// # Ok(())
-/// # }
-/// ```
-#[stable(feature = "rust1", since = "1.0.0")]
-#[rustc_deprecated(since = "1.1.0",
-             reason = "replaced with std::os::unix::fs::symlink and \
-                       std::os::windows::fs::{symlink_file, symlink_dir}")]
-pub fn soft_link<P: AsRef<Path>, Q: AsRef<Path>>(src: P, dst: Q) -> io::Result<()> {
-    fs_imp::symlink(src.as_ref(), dst.as_ref())
-}
+//- Removed: there are no symbolic links, and `rustc_deprecated` is gone.
+//- /// Creates a new symbolic link on the filesystem.
+//- ///
+//- /// The `dst` path will be a symbolic link pointing to the `src` path.
+//- /// On Windows, this will be a file symlink, not a directory symlink;
+//- /// for this reason, the platform-specific `std::os::unix::fs::symlink`
+//- /// and `std::os::windows::fs::{symlink_file, symlink_dir}` should be
+//- /// used instead to make the intent explicit.
+//- ///
+//- /// # Examples
+//- ///
+//- /// ```
+//- /// use std::fs;
+//- ///
+//- /// # fn foo() -> std::io::Result<()> {
+//- /// fs::soft_link("a.txt", "b.txt")?;
+//- /// # Ok(())
+//- /// # }
+//- /// ```
+//- #[stable(feature = "rust1", since = "1.0.0")]
+//- #[rustc_deprecated(since = "1.1.0",
+//-              reason = "replaced with std::os::unix::fs::symlink and \
+//-                        std::os::windows::fs::{symlink_file, symlink_dir}")]
+//- pub fn soft_link<P: AsRef<Path>, Q: AsRef<Path>>(src: P, dst: Q) -> io::Result<()> {
+//-     fs_imp::symlink(src.as_ref(), dst.as_ref())
+//- }
 
 /// Reads a symbolic link, returning the file that the link points to.
 ///
@@ -1991,6 +1992,17 @@ impl AsInnerMut<fs_imp::DirBuilder> for DirBuilder {
     }
 }
 
+//- Added: the kernel's side of the file system.
+#[stable(feature = "rust1", since = "1.0.0")]
+pub use sys::fs::{OpenFlags, Stat, Syscalls};
+
+/// Makes the kernel's file system calls back this module. Until this is
+/// called, every operation fails with an error of kind `NotFound`.
+#[stable(feature = "rust1", since = "1.0.0")]
+pub fn set_syscalls(calls: Syscalls) {
+    fs_imp::set_syscalls(calls)
+}
+
 #[cfg(all(test, not(any(target_os = "cloudabi", target_os = "emscripten"))))]
 mod tests {
     use io::prelude::*;
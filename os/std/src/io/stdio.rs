@@ -8,23 +8,29 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
+//- There are no threads, so each stream is a spin `Mutex` around a handle
+//- created on first use, instead of a `Lazy` `Arc` around a `ReentrantMutex`,
+//- and there are no thread-local `set_print`/`set_panic` overrides. The
+//- mutexes aren't reentrant: nothing may use a stream while it's locked, such
+//- as a `Display` impl that prints while it's being printed.
+
 use io::prelude::*;
 
-use cell::RefCell;
+//- use cell::RefCell;
 use fmt;
-use io::lazy::Lazy;
+//- use io::lazy::Lazy;
 use io::{self, Initializer, BufReader, LineWriter};
-use sync::{Arc, Mutex, MutexGuard};
+use sync::{Mutex, MutexGuard}; //- Changed.
 use sys::stdio;
-use sys_common::remutex::{ReentrantMutex, ReentrantMutexGuard};
-use thread::{LocalKey, LocalKeyState};
+//- use sys_common::remutex::{ReentrantMutex, ReentrantMutexGuard};
+//- use thread::{LocalKey, LocalKeyState};
 
-/// Stdout used by print! and println! macros
-thread_local! {
-    static LOCAL_STDOUT: RefCell<Option<Box<Write + Send>>> = {
-        RefCell::new(None)
-    }
-}
+//- /// Stdout used by print! and println! macros
+//- thread_local! {
+//-     static LOCAL_STDOUT: RefCell<Option<Box<Write + Send>>> = {
+//-         RefCell::new(None)
+//-     }
+//- }
 
 /// A handle to a raw instance of the standard input stream of this process.
 ///
@@ -143,7 +149,7 @@ fn handle_ebadf<T>(r: io::Result<T>, default: T) -> io::Result<T> {
 /// [`BufRead`]: trait.BufRead.html
 #[stable(feature = "rust1", since = "1.0.0")]
 pub struct Stdin {
-    inner: Arc<Mutex<BufReader<Maybe<StdinRaw>>>>,
+    inner: &'static Mutex<Option<BufReader<Maybe<StdinRaw>>>>, //- Changed.
 }
 
 /// A locked reference to the `Stdin` handle.
@@ -156,7 +162,7 @@ pub struct Stdin {
 /// [`Stdin::lock`]: struct.Stdin.html#method.lock
 #[stable(feature = "rust1", since = "1.0.0")]
 pub struct StdinLock<'a> {
-    inner: MutexGuard<'a, BufReader<Maybe<StdinRaw>>>,
+    inner: MutexGuard<'a, Option<BufReader<Maybe<StdinRaw>>>>, //- Changed.
 }
 
 /// Constructs a new handle to the standard input of the current process.
@@ -197,18 +203,18 @@ pub struct StdinLock<'a> {
 /// ```
 #[stable(feature = "rust1", since = "1.0.0")]
 pub fn stdin() -> Stdin {
-    static INSTANCE: Lazy<Mutex<BufReader<Maybe<StdinRaw>>>> = Lazy::new(stdin_init);
-    return Stdin {
-        inner: INSTANCE.get().expect("cannot access stdin during shutdown"),
-    };
+    //- Changed: initialized here rather than by `Lazy`.
+    static INSTANCE: Mutex<Option<BufReader<Maybe<StdinRaw>>>> = Mutex::new(None);
+    INSTANCE.lock().unwrap().get_or_insert_with(stdin_init);
+    return Stdin { inner: &INSTANCE };
 
-    fn stdin_init() -> Arc<Mutex<BufReader<Maybe<StdinRaw>>>> {
+    fn stdin_init() -> BufReader<Maybe<StdinRaw>> {
         let stdin = match stdin_raw() {
             Ok(stdin) => Maybe::Real(stdin),
             _ => Maybe::Fake
         };
 
-        Arc::new(Mutex::new(BufReader::with_capacity(stdio::STDIN_BUF_SIZE, stdin)))
+        BufReader::with_capacity(stdio::STDIN_BUF_SIZE, stdin)
     }
 }
 
@@ -239,7 +245,7 @@ impl Stdin {
     /// ```
     #[stable(feature = "rust1", since = "1.0.0")]
     pub fn lock(&self) -> StdinLock {
-        StdinLock { inner: self.inner.lock().unwrap_or_else(|e| e.into_inner()) }
+        StdinLock { inner: self.inner.lock().unwrap() } //- Changed.
     }
 
     /// Locks this handle and reads a line of input into the specified buffer.
@@ -306,7 +312,7 @@ impl Read for Stdin {
 #[stable(feature = "rust1", since = "1.0.0")]
 impl<'a> Read for StdinLock<'a> {
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-        self.inner.read(buf)
+        self.reader().read(buf) //- Changed.
     }
     #[inline]
     unsafe fn initializer(&self) -> Initializer {
@@ -316,8 +322,16 @@ impl<'a> Read for StdinLock<'a> {
 
 #[stable(feature = "rust1", since = "1.0.0")]
 impl<'a> BufRead for StdinLock<'a> {
-    fn fill_buf(&mut self) -> io::Result<&[u8]> { self.inner.fill_buf() }
-    fn consume(&mut self, n: usize) { self.inner.consume(n) }
+    fn fill_buf(&mut self) -> io::Result<&[u8]> { self.reader().fill_buf() } //- Changed.
+    fn consume(&mut self, n: usize) { self.reader().consume(n) } //- Changed.
+}
+
+//- Added.
+impl<'a> StdinLock<'a> {
+    /// Returns the reader, which `stdin` created before handing out `Stdin`.
+    fn reader(&mut self) -> &mut BufReader<Maybe<StdinRaw>> {
+        self.inner.as_mut().expect("stdin is initialized")
+    }
 }
 
 #[stable(feature = "std_debug", since = "1.16.0")]
@@ -342,7 +356,7 @@ pub struct Stdout {
     // FIXME: this should be LineWriter or BufWriter depending on the state of
     //        stdout (tty or not). Note that if this is not line buffered it
     //        should also flush-on-panic or some form of flush-on-abort.
-    inner: Arc<ReentrantMutex<RefCell<LineWriter<Maybe<StdoutRaw>>>>>,
+    inner: &'static Mutex<Option<LineWriter<Maybe<StdoutRaw>>>>, //- Changed.
 }
 
 /// A locked reference to the `Stdout` handle.
@@ -354,7 +368,7 @@ pub struct Stdout {
 /// [`Stdout::lock`]: struct.Stdout.html#method.lock
 #[stable(feature = "rust1", since = "1.0.0")]
 pub struct StdoutLock<'a> {
-    inner: ReentrantMutexGuard<'a, RefCell<LineWriter<Maybe<StdoutRaw>>>>,
+    inner: MutexGuard<'a, Option<LineWriter<Maybe<StdoutRaw>>>>, //- Changed.
 }
 
 /// Constructs a new handle to the standard output of the current process.
@@ -395,18 +409,17 @@ pub struct StdoutLock<'a> {
 /// ```
 #[stable(feature = "rust1", since = "1.0.0")]
 pub fn stdout() -> Stdout {
-    static INSTANCE: Lazy<ReentrantMutex<RefCell<LineWriter<Maybe<StdoutRaw>>>>>
-        = Lazy::new(stdout_init);
-    return Stdout {
-        inner: INSTANCE.get().expect("cannot access stdout during shutdown"),
-    };
+    //- Changed: initialized here rather than by `Lazy`.
+    static INSTANCE: Mutex<Option<LineWriter<Maybe<StdoutRaw>>>> = Mutex::new(None);
+    INSTANCE.lock().unwrap().get_or_insert_with(stdout_init);
+    return Stdout { inner: &INSTANCE };
 
-    fn stdout_init() -> Arc<ReentrantMutex<RefCell<LineWriter<Maybe<StdoutRaw>>>>> {
+    fn stdout_init() -> LineWriter<Maybe<StdoutRaw>> {
         let stdout = match stdout_raw() {
             Ok(stdout) => Maybe::Real(stdout),
             _ => Maybe::Fake,
         };
-        Arc::new(ReentrantMutex::new(RefCell::new(LineWriter::new(stdout))))
+        LineWriter::new(stdout)
     }
 }
 
@@ -433,7 +446,7 @@ impl Stdout {
     /// ```
     #[stable(feature = "rust1", since = "1.0.0")]
     pub fn lock(&self) -> StdoutLock {
-        StdoutLock { inner: self.inner.lock().unwrap_or_else(|e| e.into_inner()) }
+        StdoutLock { inner: self.inner.lock().unwrap() } //- Changed.
     }
 }
 
@@ -462,10 +475,10 @@ impl Write for Stdout {
 #[stable(feature = "rust1", since = "1.0.0")]
 impl<'a> Write for StdoutLock<'a> {
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-        self.inner.borrow_mut().write(buf)
+        self.inner.as_mut().expect("stdout is initialized").write(buf) //- Changed.
     }
     fn flush(&mut self) -> io::Result<()> {
-        self.inner.borrow_mut().flush()
+        self.inner.as_mut().expect("stdout is initialized").flush() //- Changed.
     }
 }
 
@@ -483,7 +496,7 @@ impl<'a> fmt::Debug for StdoutLock<'a> {
 /// [`io::stderr`]: fn.stderr.html
 #[stable(feature = "rust1", since = "1.0.0")]
 pub struct Stderr {
-    inner: Arc<ReentrantMutex<RefCell<Maybe<StderrRaw>>>>,
+    inner: &'static Mutex<Option<Maybe<StderrRaw>>>, //- Changed.
 }
 
 /// A locked reference to the `Stderr` handle.
@@ -494,7 +507,7 @@ pub struct Stderr {
 /// [`Stderr::lock`]: struct.Stderr.html#method.lock
 #[stable(feature = "rust1", since = "1.0.0")]
 pub struct StderrLock<'a> {
-    inner: ReentrantMutexGuard<'a, RefCell<Maybe<StderrRaw>>>,
+    inner: MutexGuard<'a, Option<Maybe<StderrRaw>>>, //- Changed.
 }
 
 /// Constructs a new handle to the standard error of the current process.
@@ -531,17 +544,16 @@ pub struct StderrLock<'a> {
 /// ```
 #[stable(feature = "rust1", since = "1.0.0")]
 pub fn stderr() -> Stderr {
-    static INSTANCE: Lazy<ReentrantMutex<RefCell<Maybe<StderrRaw>>>> = Lazy::new(stderr_init);
-    return Stderr {
-        inner: INSTANCE.get().expect("cannot access stderr during shutdown"),
-    };
+    //- Changed: initialized here rather than by `Lazy`.
+    static INSTANCE: Mutex<Option<Maybe<StderrRaw>>> = Mutex::new(None);
+    INSTANCE.lock().unwrap().get_or_insert_with(stderr_init);
+    return Stderr { inner: &INSTANCE };
 
-    fn stderr_init() -> Arc<ReentrantMutex<RefCell<Maybe<StderrRaw>>>> {
-        let stderr = match stderr_raw() {
+    fn stderr_init() -> Maybe<StderrRaw> {
+        match stderr_raw() {
             Ok(stderr) => Maybe::Real(stderr),
             _ => Maybe::Fake,
-        };
-        Arc::new(ReentrantMutex::new(RefCell::new(stderr)))
+        }
     }
 }
 
@@ -568,7 +580,7 @@ impl Stderr {
     /// ```
     #[stable(feature = "rust1", since = "1.0.0")]
     pub fn lock(&self) -> StderrLock {
-        StderrLock { inner: self.inner.lock().unwrap_or_else(|e| e.into_inner()) }
+        StderrLock { inner: self.inner.lock().unwrap() } //- Changed.
     }
 }
 
@@ -597,10 +609,10 @@ impl Write for Stderr {
 #[stable(feature = "rust1", since = "1.0.0")]
 impl<'a> Write for StderrLock<'a> {
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-        self.inner.borrow_mut().write(buf)
+        self.inner.as_mut().expect("stderr is initialized").write(buf) //- Changed.
     }
     fn flush(&mut self) -> io::Result<()> {
-        self.inner.borrow_mut().flush()
+        self.inner.as_mut().expect("stderr is initialized").flush() //- Changed.
     }
 }
 
@@ -611,126 +623,142 @@ impl<'a> fmt::Debug for StderrLock<'a> {
     }
 }
 
-/// Resets the thread-local stderr handle to the specified writer
-///
-/// This will replace the current thread's stderr handle, returning the old
-/// handle. All future calls to `panic!` and friends will emit their output to
-/// this specified handle.
-///
-/// Note that this does not need to be called for all new threads; the default
-/// output handle is to the process's stderr stream.
-#[unstable(feature = "set_stdio",
-           reason = "this function may disappear completely or be replaced \
-                     with a more general mechanism",
-           issue = "0")]
-#[doc(hidden)]
-pub fn set_panic(sink: Option<Box<Write + Send>>) -> Option<Box<Write + Send>> {
-    use panicking::LOCAL_STDERR;
-    use mem;
-    LOCAL_STDERR.with(move |slot| {
-        mem::replace(&mut *slot.borrow_mut(), sink)
-    }).and_then(|mut s| {
-        let _ = s.flush();
-        Some(s)
-    })
-}
-
-/// Resets the thread-local stdout handle to the specified writer
-///
-/// This will replace the current thread's stdout handle, returning the old
-/// handle. All future calls to `print!` and friends will emit their output to
-/// this specified handle.
-///
-/// Note that this does not need to be called for all new threads; the default
-/// output handle is to the process's stdout stream.
-#[unstable(feature = "set_stdio",
-           reason = "this function may disappear completely or be replaced \
-                     with a more general mechanism",
-           issue = "0")]
-#[doc(hidden)]
-pub fn set_print(sink: Option<Box<Write + Send>>) -> Option<Box<Write + Send>> {
-    use mem;
-    LOCAL_STDOUT.with(move |slot| {
-        mem::replace(&mut *slot.borrow_mut(), sink)
-    }).and_then(|mut s| {
-        let _ = s.flush();
-        Some(s)
-    })
-}
-
-/// Write `args` to output stream `local_s` if possible, `global_s`
-/// otherwise. `label` identifies the stream in a panic message.
-///
-/// This function is used to print error messages, so it takes extra
-/// care to avoid causing a panic when `local_stream` is unusable.
-/// For instance, if the TLS key for the local stream is uninitialized
-/// or already destroyed, or if the local stream is locked by another
-/// thread, it will just fall back to the global stream.
-///
-/// However, if the actual I/O causes an error, this function does panic.
-fn print_to<T>(args: fmt::Arguments,
-               local_s: &'static LocalKey<RefCell<Option<Box<Write+Send>>>>,
-               global_s: fn() -> T,
-               label: &str) where T: Write {
-    let result = match local_s.state() {
-        LocalKeyState::Uninitialized |
-        LocalKeyState::Destroyed => global_s().write_fmt(args),
-        LocalKeyState::Valid => {
-            local_s.with(|s| {
-                if let Ok(mut borrowed) = s.try_borrow_mut() {
-                    if let Some(w) = borrowed.as_mut() {
-                        return w.write_fmt(args);
-                    }
-                }
-                global_s().write_fmt(args)
-            })
-        }
-    };
-    if let Err(e) = result {
+//- /// Resets the thread-local stderr handle to the specified writer
+//- ///
+//- /// This will replace the current thread's stderr handle, returning the old
+//- /// handle. All future calls to `panic!` and friends will emit their output to
+//- /// this specified handle.
+//- ///
+//- /// Note that this does not need to be called for all new threads; the default
+//- /// output handle is to the process's stderr stream.
+//- #[unstable(feature = "set_stdio",
+//-            reason = "this function may disappear completely or be replaced \
+//-                      with a more general mechanism",
+//-            issue = "0")]
+//- #[doc(hidden)]
+//- pub fn set_panic(sink: Option<Box<Write + Send>>) -> Option<Box<Write + Send>> {
+//-     use panicking::LOCAL_STDERR;
+//-     use mem;
+//-     LOCAL_STDERR.with(move |slot| {
+//-         mem::replace(&mut *slot.borrow_mut(), sink)
+//-     }).and_then(|mut s| {
+//-         let _ = s.flush();
+//-         Some(s)
+//-     })
+//- }
+//-
+//- /// Resets the thread-local stdout handle to the specified writer
+//- ///
+//- /// This will replace the current thread's stdout handle, returning the old
+//- /// handle. All future calls to `print!` and friends will emit their output to
+//- /// this specified handle.
+//- ///
+//- /// Note that this does not need to be called for all new threads; the default
+//- /// output handle is to the process's stdout stream.
+//- #[unstable(feature = "set_stdio",
+//-            reason = "this function may disappear completely or be replaced \
+//-                      with a more general mechanism",
+//-            issue = "0")]
+//- #[doc(hidden)]
+//- pub fn set_print(sink: Option<Box<Write + Send>>) -> Option<Box<Write + Send>> {
+//-     use mem;
+//-     LOCAL_STDOUT.with(move |slot| {
+//-         mem::replace(&mut *slot.borrow_mut(), sink)
+//-     }).and_then(|mut s| {
+//-         let _ = s.flush();
+//-         Some(s)
+//-     })
+//- }
+//-
+//- /// Write `args` to output stream `local_s` if possible, `global_s`
+//- /// otherwise. `label` identifies the stream in a panic message.
+//- ///
+//- /// This function is used to print error messages, so it takes extra
+//- /// care to avoid causing a panic when `local_stream` is unusable.
+//- /// For instance, if the TLS key for the local stream is uninitialized
+//- /// or already destroyed, or if the local stream is locked by another
+//- /// thread, it will just fall back to the global stream.
+//- ///
+//- /// However, if the actual I/O causes an error, this function does panic.
+//- fn print_to<T>(args: fmt::Arguments,
+//-                local_s: &'static LocalKey<RefCell<Option<Box<Write+Send>>>>,
+//-                global_s: fn() -> T,
+//-                label: &str) where T: Write {
+//-     let result = match local_s.state() {
+//-         LocalKeyState::Uninitialized |
+//-         LocalKeyState::Destroyed => global_s().write_fmt(args),
+//-         LocalKeyState::Valid => {
+//-             local_s.with(|s| {
+//-                 if let Ok(mut borrowed) = s.try_borrow_mut() {
+//-                     if let Some(w) = borrowed.as_mut() {
+//-                         return w.write_fmt(args);
+//-                     }
+//-                 }
+//-                 global_s().write_fmt(args)
+//-             })
+//-         }
+//-     };
+//-     if let Err(e) = result {
+//-         panic!("failed printing to {}: {}", label, e);
+//-     }
+//- }
+
+//- Added: the kernel's side of the standard streams.
+/// Makes the kernel's console back the standard streams: `read` standard
+/// input, and `write` standard output and error. Until this is called, output
+/// is discarded and input is at its end.
+#[stable(feature = "rust1", since = "1.0.0")]
+pub fn set_console(read: fn(&mut [u8]) -> io::Result<usize>, write: fn(&[u8]) -> io::Result<usize>) {
+    stdio::set_console(read, write)
+}
+
+//- Changed: there are no thread-local overrides to print to instead.
+fn print_to<T>(args: fmt::Arguments, global_s: fn() -> T, label: &str) where T: Write {
+    if let Err(e) = global_s().write_fmt(args) {
         panic!("failed printing to {}: {}", label, e);
     }
 }
 
 #[unstable(feature = "print_internals",
            reason = "implementation detail which may disappear or be replaced at any time",
-           issue = "0")]
+           issue = "none")]
 #[doc(hidden)]
 pub fn _print(args: fmt::Arguments) {
-    print_to(args, &LOCAL_STDOUT, stdout, "stdout");
+    print_to(args, stdout, "stdout"); //- Changed.
 }
 
 #[unstable(feature = "print_internals",
            reason = "implementation detail which may disappear or be replaced at any time",
-           issue = "0")]
+           issue = "none")]
 #[doc(hidden)]
 pub fn _eprint(args: fmt::Arguments) {
-    use panicking::LOCAL_STDERR;
-    print_to(args, &LOCAL_STDERR, stderr, "stderr");
-}
-
-#[cfg(test)]
-mod tests {
-    use thread;
-    use super::*;
-
-    #[test]
-    #[cfg_attr(target_os = "emscripten", ignore)]
-    fn panic_doesnt_poison() {
-        thread::spawn(|| {
-            let _a = stdin();
-            let _a = _a.lock();
-            let _a = stdout();
-            let _a = _a.lock();
-            let _a = stderr();
-            let _a = _a.lock();
-            panic!();
-        }).join().unwrap_err();
-
-        let _a = stdin();
-        let _a = _a.lock();
-        let _a = stdout();
-        let _a = _a.lock();
-        let _a = stderr();
-        let _a = _a.lock();
-    }
-}
+    //- use panicking::LOCAL_STDERR;
+    print_to(args, stderr, "stderr"); //- Changed.
+}
+
+//- #[cfg(test)]
+//- mod tests {
+//-     use thread;
+//-     use super::*;
+//-
+//-     #[test]
+//-     #[cfg_attr(target_os = "emscripten", ignore)]
+//-     fn panic_doesnt_poison() {
+//-         thread::spawn(|| {
+//-             let _a = stdin();
+//-             let _a = _a.lock();
+//-             let _a = stdout();
+//-             let _a = _a.lock();
+//-             let _a = stderr();
+//-             let _a = _a.lock();
+//-             panic!();
+//-         }).join().unwrap_err();
+//-
+//-         let _a = stdin();
+//-         let _a = _a.lock();
+//-         let _a = stdout();
+//-         let _a = _a.lock();
+//-         let _a = stderr();
+//-         let _a = _a.lock();
+//-     }
+//- }
@@ -488,7 +488,7 @@ pub mod collections;
 //- pub mod env;
 pub mod error;
 pub mod ffi;
-//- pub mod fs;
+pub mod fs;
 pub mod io;
 //- pub mod net;
 pub mod num;
@@ -497,7 +497,7 @@ pub mod os;
 pub mod path;
 //- pub mod process;
 pub mod sync;
-//- pub mod time;
+pub mod time;
 //- pub mod heap;
 
 //- // Platform-abstraction modules
@@ -113,12 +113,12 @@
 ///
 /// io::stdout().flush().unwrap();
 /// ```
-//- #[macro_export]
-//- #[stable(feature = "rust1", since = "1.0.0")]
-//- #[allow_internal_unstable]
-//- macro_rules! print {
-//-     ($($arg:tt)*) => ($crate::io::_print(format_args!($($arg)*)));
-//- }
+#[macro_export]
+#[stable(feature = "rust1", since = "1.0.0")]
+#[allow_internal_unstable(print_internals)]
+macro_rules! print {
+    ($($arg:tt)*) => ($crate::io::_print(format_args!($($arg)*)));
+}
 
 /// Macro for printing to the standard output, with a newline.
 ///
@@ -145,13 +145,13 @@
 /// println!("hello there!");
 /// println!("format {} arguments", "some");
 /// ```
-//- #[macro_export]
-//- #[stable(feature = "rust1", since = "1.0.0")]
-//- macro_rules! println {
-//-     () => (print!("\n"));
-//-     ($fmt:expr) => (print!(concat!($fmt, "\n")));
-//-     ($fmt:expr, $($arg:tt)*) => (print!(concat!($fmt, "\n"), $($arg)*));
-//- }
+#[macro_export]
+#[stable(feature = "rust1", since = "1.0.0")]
+macro_rules! println {
+    () => (print!("\n"));
+    ($fmt:expr) => (print!(concat!($fmt, "\n")));
+    ($fmt:expr, $($arg:tt)*) => (print!(concat!($fmt, "\n"), $($arg)*));
+}
 
 /// Macro for printing to the standard error.
 ///
@@ -174,12 +174,12 @@
 /// ```
 /// eprint!("Error: Could not complete task");
 /// ```
-//- #[macro_export]
-//- #[stable(feature = "eprint", since = "1.19.0")]
-//- #[allow_internal_unstable]
-//- macro_rules! eprint {
-//-     ($($arg:tt)*) => ($crate::io::_eprint(format_args!($($arg)*)));
-//- }
+#[macro_export]
+#[stable(feature = "eprint", since = "1.19.0")]
+#[allow_internal_unstable(print_internals)]
+macro_rules! eprint {
+    ($($arg:tt)*) => ($crate::io::_eprint(format_args!($($arg)*)));
+}
 
 /// Macro for printing to the standard error, with a newline.
 ///
@@ -202,13 +202,13 @@
 /// ```
 /// eprintln!("Error: Could not complete task");
 /// ```
-//- #[macro_export]
-//- #[stable(feature = "eprint", since = "1.19.0")]
-//- macro_rules! eprintln {
-//-     () => (eprint!("\n"));
-//-     ($fmt:expr) => (eprint!(concat!($fmt, "\n")));
-//-     ($fmt:expr, $($arg:tt)*) => (eprint!(concat!($fmt, "\n"), $($arg)*));
-//- }
+#[macro_export]
+#[stable(feature = "eprint", since = "1.19.0")]
+macro_rules! eprintln {
+    () => (eprint!("\n"));
+    ($fmt:expr) => (eprint!(concat!($fmt, "\n")));
+    ($fmt:expr, $($arg:tt)*) => (eprint!(concat!($fmt, "\n"), $($arg)*));
+}
 
 /// A macro to select an event from a number of receivers.
 ///
@@ -81,9 +81,9 @@ use borrow::{Borrow, Cow};
 use cmp;
 use error::Error;
 use fmt;
-// use fs;
+use fs;
 use hash::{Hash, Hasher};
-// use io;
+use io;
 use iter::{self, FusedIterator};
 use ops::{self, Deref};
 use rc::Rc;
@@ -2175,10 +2175,10 @@ impl Path {
     /// let metadata = path.metadata().expect("metadata call failed");
     /// println!("{:?}", metadata.file_type());
     /// ```
-    //- #[stable(feature = "path_ext", since = "1.5.0")]
-    //- pub fn metadata(&self) -> io::Result<fs::Metadata> {
-    //-     fs::metadata(self)
-    //- }
+    #[stable(feature = "path_ext", since = "1.5.0")]
+    pub fn metadata(&self) -> io::Result<fs::Metadata> {
+        fs::metadata(self)
+    }
 
     /// Queries the metadata about a file without following symlinks.
     ///
@@ -2195,10 +2195,10 @@ impl Path {
     /// let metadata = path.symlink_metadata().expect("symlink_metadata call failed");
     /// println!("{:?}", metadata.file_type());
     /// ```
-    //- #[stable(feature = "path_ext", since = "1.5.0")]
-    //- pub fn symlink_metadata(&self) -> io::Result<fs::Metadata> {
-    //-     fs::symlink_metadata(self)
-    //- }
+    #[stable(feature = "path_ext", since = "1.5.0")]
+    pub fn symlink_metadata(&self) -> io::Result<fs::Metadata> {
+        fs::symlink_metadata(self)
+    }
 
     /// Returns the canonical form of the path with all intermediate components
     /// normalized and symbolic links resolved.
@@ -2215,10 +2215,10 @@ impl Path {
     /// let path = Path::new("/foo/test/../test/bar.rs");
     /// assert_eq!(path.canonicalize().unwrap(), PathBuf::from("/foo/test/bar.rs"));
     /// ```
-    //- #[stable(feature = "path_ext", since = "1.5.0")]
-    //- pub fn canonicalize(&self) -> io::Result<PathBuf> {
-    //-     fs::canonicalize(self)
-    //- }
+    #[stable(feature = "path_ext", since = "1.5.0")]
+    pub fn canonicalize(&self) -> io::Result<PathBuf> {
+        fs::canonicalize(self)
+    }
 
     /// Reads a symbolic link, returning the file that the link points to.
     ///
@@ -2234,10 +2234,10 @@ impl Path {
     /// let path = Path::new("/laputa/sky_castle.rs");
     /// let path_link = path.read_link().expect("read_link call failed");
     /// ```
-    //- #[stable(feature = "path_ext", since = "1.5.0")]
-    //- pub fn read_link(&self) -> io::Result<PathBuf> {
-    //-     fs::read_link(self)
-    //- }
+    #[stable(feature = "path_ext", since = "1.5.0")]
+    pub fn read_link(&self) -> io::Result<PathBuf> {
+        fs::read_link(self)
+    }
 
     /// Returns an iterator over the entries within a directory.
     ///
@@ -2262,10 +2262,10 @@ impl Path {
     ///     }
     /// }
     /// ```
-    //- #[stable(feature = "path_ext", since = "1.5.0")]
-    //- pub fn read_dir(&self) -> io::Result<fs::ReadDir> {
-    //-     fs::read_dir(self)
-    //- }
+    #[stable(feature = "path_ext", since = "1.5.0")]
+    pub fn read_dir(&self) -> io::Result<fs::ReadDir> {
+        fs::read_dir(self)
+    }
 
     /// Returns whether the path points at an existing entity.
     ///
@@ -2288,10 +2288,10 @@ impl Path {
     /// check errors, call [fs::metadata].
     ///
     /// [fs::metadata]: ../../std/fs/fn.metadata.html
-    //- #[stable(feature = "path_ext", since = "1.5.0")]
-    //- pub fn exists(&self) -> bool {
-    //-     fs::metadata(self).is_ok()
-    //- }
+    #[stable(feature = "path_ext", since = "1.5.0")]
+    pub fn exists(&self) -> bool {
+        fs::metadata(self).is_ok()
+    }
 
     /// Returns whether the path exists on disk and is pointing at a regular file.
     ///
@@ -2317,10 +2317,10 @@ impl Path {
     ///
     /// [fs::metadata]: ../../std/fs/fn.metadata.html
     /// [fs::Metadata::is_file]: ../../std/fs/struct.Metadata.html#method.is_file
-    //- #[stable(feature = "path_ext", since = "1.5.0")]
-    //- pub fn is_file(&self) -> bool {
-    //-     fs::metadata(self).map(|m| m.is_file()).unwrap_or(false)
-    //- }
+    #[stable(feature = "path_ext", since = "1.5.0")]
+    pub fn is_file(&self) -> bool {
+        fs::metadata(self).map(|m| m.is_file()).unwrap_or(false)
+    }
 
     /// Returns whether the path exists on disk and is pointing at a directory.
     ///
@@ -2346,10 +2346,10 @@ impl Path {
     ///
     /// [fs::metadata]: ../../std/fs/fn.metadata.html
     /// [fs::Metadata::is_dir]: ../../std/fs/struct.Metadata.html#method.is_dir
-    //- #[stable(feature = "path_ext", since = "1.5.0")]
-    //- pub fn is_dir(&self) -> bool {
-    //-     fs::metadata(self).map(|m| m.is_dir()).unwrap_or(false)
-    //- }
+    #[stable(feature = "path_ext", since = "1.5.0")]
+    pub fn is_dir(&self) -> bool {
+        fs::metadata(self).map(|m| m.is_dir()).unwrap_or(false)
+    }
 
     /// Converts a [`Box<Path>`][`Box`] into a [`PathBuf`] without copying or
     /// allocating.
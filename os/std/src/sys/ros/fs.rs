@@ -0,0 +1,406 @@
+//! Files and directories, on whatever file system the kernel registers with
+//! `fs::set_syscalls`. The kernel hands out a descriptor for each open file
+//! and is called with it, much as a system call would be. Until it registers
+//! its calls, every operation fails with `NotFound`.
+
+use ffi::OsString;
+use fmt;
+use io::{self, Error, ErrorKind, SeekFrom};
+use path::{Component, Path, PathBuf};
+use sync::{Arc, Mutex};
+use sys::time;
+use sys_common::AsInner;
+use time::SystemTime;
+use vec;
+
+/// How a file is to be opened, as passed to `Syscalls::open`. The flags mean
+/// what the `fs::OpenOptions` methods of the same names describe, and have
+/// been checked to make sense together.
+#[stable(feature = "rust1", since = "1.0.0")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct OpenFlags {
+    #[stable(feature = "rust1", since = "1.0.0")]
+    pub read: bool,
+    #[stable(feature = "rust1", since = "1.0.0")]
+    pub write: bool,
+    #[stable(feature = "rust1", since = "1.0.0")]
+    pub append: bool,
+    #[stable(feature = "rust1", since = "1.0.0")]
+    pub truncate: bool,
+    #[stable(feature = "rust1", since = "1.0.0")]
+    pub create: bool,
+    #[stable(feature = "rust1", since = "1.0.0")]
+    pub create_new: bool,
+}
+
+/// What the kernel knows about a file or directory, as returned by
+/// `Syscalls::stat` and `Syscalls::fstat`.
+#[stable(feature = "rust1", since = "1.0.0")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Stat {
+    #[stable(feature = "rust1", since = "1.0.0")]
+    pub is_dir: bool,
+    #[stable(feature = "rust1", since = "1.0.0")]
+    pub read_only: bool,
+    /// The size in bytes; 0 for directories.
+    #[stable(feature = "rust1", since = "1.0.0")]
+    pub size: u64,
+    /// The times the file was last modified, last accessed and created, for
+    /// those that the file system keeps.
+    #[stable(feature = "rust1", since = "1.0.0")]
+    pub modified: Option<SystemTime>,
+    #[stable(feature = "rust1", since = "1.0.0")]
+    pub accessed: Option<SystemTime>,
+    #[stable(feature = "rust1", since = "1.0.0")]
+    pub created: Option<SystemTime>,
+}
+
+/// The kernel's file system calls. Paths are absolute; descriptors are the
+/// ones returned by `open`, and are passed to `close` exactly once.
+#[stable(feature = "rust1", since = "1.0.0")]
+#[derive(Clone, Copy)]
+pub struct Syscalls {
+    #[stable(feature = "rust1", since = "1.0.0")]
+    pub open: fn(&str, OpenFlags) -> io::Result<usize>,
+    #[stable(feature = "rust1", since = "1.0.0")]
+    pub close: fn(usize),
+    #[stable(feature = "rust1", since = "1.0.0")]
+    pub read: fn(usize, &mut [u8]) -> io::Result<usize>,
+    #[stable(feature = "rust1", since = "1.0.0")]
+    pub write: fn(usize, &[u8]) -> io::Result<usize>,
+    #[stable(feature = "rust1", since = "1.0.0")]
+    pub seek: fn(usize, SeekFrom) -> io::Result<u64>,
+    /// Writes everything written to the file so far to the disk.
+    #[stable(feature = "rust1", since = "1.0.0")]
+    pub sync: fn(usize) -> io::Result<()>,
+    #[stable(feature = "rust1", since = "1.0.0")]
+    pub fstat: fn(usize) -> io::Result<Stat>,
+    #[stable(feature = "rust1", since = "1.0.0")]
+    pub stat: fn(&str) -> io::Result<Stat>,
+    /// Returns the names of the entries in a directory, in any order.
+    #[stable(feature = "rust1", since = "1.0.0")]
+    pub read_dir: fn(&str) -> io::Result<Vec<String>>,
+    #[stable(feature = "rust1", since = "1.0.0")]
+    pub create_dir: fn(&str) -> io::Result<()>,
+    /// Removes a file or an empty directory.
+    #[stable(feature = "rust1", since = "1.0.0")]
+    pub remove: fn(&str) -> io::Result<()>,
+    #[stable(feature = "rust1", since = "1.0.0")]
+    pub rename: fn(&str, &str) -> io::Result<()>,
+}
+
+static SYSCALLS: Mutex<Option<Syscalls>> = Mutex::new(None);
+
+/// Makes `calls` back `fs` from now on.
+pub fn set_syscalls(calls: Syscalls) {
+    *SYSCALLS.lock().unwrap() = Some(calls);
+}
+
+/// Returns the registered calls. They're copied out, so the lock isn't held
+/// during a call.
+fn syscalls() -> io::Result<Syscalls> {
+    let calls = *SYSCALLS.lock().unwrap();
+    calls.ok_or_else(|| Error::new(ErrorKind::NotFound, "no file system"))
+}
+
+/// Returns `path` as the kernel takes it.
+fn path_str(path: &Path) -> io::Result<&str> {
+    path.to_str().ok_or_else(|| Error::new(ErrorKind::InvalidInput, "path is not valid UTF-8"))
+}
+
+fn unsupported(what: &'static str) -> Error {
+    Error::new(ErrorKind::Other, what)
+}
+
+pub struct File {
+    fd: usize,
+}
+
+#[derive(Clone)]
+pub struct FileAttr {
+    stat: Stat,
+}
+
+pub struct ReadDir {
+    names: vec::IntoIter<String>,
+    root: Arc<PathBuf>,
+}
+
+pub struct DirEntry {
+    root: Arc<PathBuf>,
+    name: String,
+}
+
+#[derive(Clone, Debug)]
+pub struct OpenOptions {
+    flags: OpenFlags,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct FilePermissions { read_only: bool }
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct FileType { is_dir: bool }
+
+#[derive(Debug)]
+pub struct DirBuilder {}
+
+impl FileAttr {
+    pub fn size(&self) -> u64 { self.stat.size }
+    pub fn perm(&self) -> FilePermissions {
+        FilePermissions { read_only: self.stat.read_only }
+    }
+
+    pub fn file_type(&self) -> FileType {
+        FileType { is_dir: self.stat.is_dir }
+    }
+
+    pub fn modified(&self) -> io::Result<time::SystemTime> {
+        file_time(self.stat.modified)
+    }
+
+    pub fn accessed(&self) -> io::Result<time::SystemTime> {
+        file_time(self.stat.accessed)
+    }
+
+    pub fn created(&self) -> io::Result<time::SystemTime> {
+        file_time(self.stat.created)
+    }
+}
+
+/// Returns a time from a `Stat`, if the file system keeps it.
+fn file_time(kept: Option<SystemTime>) -> io::Result<time::SystemTime> {
+    kept.map(|t| *t.as_inner())
+        .ok_or_else(|| Error::new(ErrorKind::Other, "not kept for this file"))
+}
+
+impl FilePermissions {
+    pub fn readonly(&self) -> bool { self.read_only }
+    pub fn set_readonly(&mut self, readonly: bool) { self.read_only = readonly }
+}
+
+impl FileType {
+    pub fn is_dir(&self) -> bool { self.is_dir }
+    pub fn is_file(&self) -> bool { !self.is_dir }
+    pub fn is_symlink(&self) -> bool { false }
+}
+
+impl fmt::Debug for ReadDir {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // This will only be called from std::fs::ReadDir, which will add a "ReadDir()" frame.
+        // Thus the result will be e g 'ReadDir("/home")'
+        fmt::Debug::fmt(&*self.root, f)
+    }
+}
+
+impl Iterator for ReadDir {
+    type Item = io::Result<DirEntry>;
+
+    fn next(&mut self) -> Option<io::Result<DirEntry>> {
+        let root = &self.root;
+        self.names.by_ref()
+            .find(|name| name != "." && name != "..")
+            .map(|name| Ok(DirEntry { root: root.clone(), name }))
+    }
+}
+
+impl DirEntry {
+    pub fn path(&self) -> PathBuf {
+        self.root.join(&self.name)
+    }
+
+    pub fn file_name(&self) -> OsString {
+        OsString::from(self.name.clone())
+    }
+
+    pub fn metadata(&self) -> io::Result<FileAttr> {
+        stat(&self.path())
+    }
+
+    pub fn file_type(&self) -> io::Result<FileType> {
+        stat(&self.path()).map(|m| m.file_type())
+    }
+}
+
+impl OpenOptions {
+    pub fn new() -> OpenOptions {
+        OpenOptions { flags: OpenFlags::default() }
+    }
+
+    pub fn read(&mut self, read: bool) { self.flags.read = read; }
+    pub fn write(&mut self, write: bool) { self.flags.write = write; }
+    pub fn append(&mut self, append: bool) { self.flags.append = append; }
+    pub fn truncate(&mut self, truncate: bool) { self.flags.truncate = truncate; }
+    pub fn create(&mut self, create: bool) { self.flags.create = create; }
+    pub fn create_new(&mut self, create_new: bool) { self.flags.create_new = create_new; }
+
+    /// Returns the flags if they make sense together, by the same rules as
+    /// the other platforms.
+    fn flags(&self) -> io::Result<OpenFlags> {
+        let flags = self.flags;
+        let invalid = match (flags.read, flags.write, flags.append) {
+            (false, false, false) => true,
+            (_, false, false) => flags.truncate || flags.create || flags.create_new,
+            (_, _, true) => flags.truncate && !flags.create_new,
+            (_, true, false) => false,
+        };
+
+        match invalid {
+            true => Err(Error::new(ErrorKind::InvalidInput, "invalid combination of open options")),
+            false => Ok(flags),
+        }
+    }
+}
+
+impl File {
+    pub fn open(path: &Path, opts: &OpenOptions) -> io::Result<File> {
+        let fd = (syscalls()?.open)(path_str(path)?, opts.flags()?)?;
+        Ok(File { fd })
+    }
+
+    pub fn file_attr(&self) -> io::Result<FileAttr> {
+        (syscalls()?.fstat)(self.fd).map(|stat| FileAttr { stat })
+    }
+
+    pub fn fsync(&self) -> io::Result<()> {
+        (syscalls()?.sync)(self.fd)
+    }
+
+    pub fn datasync(&self) -> io::Result<()> {
+        self.fsync()
+    }
+
+    pub fn truncate(&self, _size: u64) -> io::Result<()> {
+        Err(unsupported("changing a file's size is not supported"))
+    }
+
+    pub fn read(&self, buf: &mut [u8]) -> io::Result<usize> {
+        (syscalls()?.read)(self.fd, buf)
+    }
+
+    pub fn write(&self, buf: &[u8]) -> io::Result<usize> {
+        (syscalls()?.write)(self.fd, buf)
+    }
+
+    pub fn flush(&self) -> io::Result<()> { Ok(()) }
+
+    pub fn seek(&self, pos: SeekFrom) -> io::Result<u64> {
+        (syscalls()?.seek)(self.fd, pos)
+    }
+
+    pub fn duplicate(&self) -> io::Result<File> {
+        Err(unsupported("duplicating files is not supported"))
+    }
+
+    pub fn set_permissions(&self, _perm: FilePermissions) -> io::Result<()> {
+        Err(unsupported("changing permissions is not supported"))
+    }
+}
+
+impl Drop for File {
+    fn drop(&mut self) {
+        // A descriptor is only ever handed out by registered calls.
+        if let Ok(calls) = syscalls() {
+            (calls.close)(self.fd);
+        }
+    }
+}
+
+impl DirBuilder {
+    pub fn new() -> DirBuilder {
+        DirBuilder {}
+    }
+
+    pub fn mkdir(&self, p: &Path) -> io::Result<()> {
+        (syscalls()?.create_dir)(path_str(p)?)
+    }
+}
+
+impl fmt::Debug for File {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("File").field("fd", &self.fd).finish()
+    }
+}
+
+pub fn readdir(p: &Path) -> io::Result<ReadDir> {
+    let names = (syscalls()?.read_dir)(path_str(p)?)?;
+    Ok(ReadDir { names: names.into_iter(), root: Arc::new(p.to_path_buf()) })
+}
+
+pub fn unlink(p: &Path) -> io::Result<()> {
+    if stat(p)?.file_type().is_dir() {
+        return Err(Error::new(ErrorKind::Other, "is a directory"));
+    }
+    (syscalls()?.remove)(path_str(p)?)
+}
+
+pub fn rename(old: &Path, new: &Path) -> io::Result<()> {
+    (syscalls()?.rename)(path_str(old)?, path_str(new)?)
+}
+
+pub fn set_perm(_p: &Path, _perm: FilePermissions) -> io::Result<()> {
+    Err(unsupported("changing permissions is not supported"))
+}
+
+pub fn rmdir(p: &Path) -> io::Result<()> {
+    if !stat(p)?.file_type().is_dir() {
+        return Err(Error::new(ErrorKind::Other, "not a directory"));
+    }
+    (syscalls()?.remove)(path_str(p)?)
+}
+
+pub fn remove_dir_all(path: &Path) -> io::Result<()> {
+    for child in readdir(path)? {
+        let child = child?;
+        if child.file_type()?.is_dir() {
+            remove_dir_all(&child.path())?;
+        } else {
+            unlink(&child.path())?;
+        }
+    }
+    rmdir(path)
+}
+
+pub fn readlink(_p: &Path) -> io::Result<PathBuf> {
+    Err(unsupported("there are no symbolic links"))
+}
+
+pub fn link(_src: &Path, _dst: &Path) -> io::Result<()> {
+    Err(unsupported("there are no hard links"))
+}
+
+pub fn stat(p: &Path) -> io::Result<FileAttr> {
+    (syscalls()?.stat)(path_str(p)?).map(|stat| FileAttr { stat })
+}
+
+/// There are no symbolic links, so this is `stat`.
+pub fn lstat(p: &Path) -> io::Result<FileAttr> {
+    stat(p)
+}
+
+/// There are no symbolic links to resolve, so `.` and `..` are resolved by
+/// the path alone.
+pub fn canonicalize(p: &Path) -> io::Result<PathBuf> {
+    let mut canonical = PathBuf::new();
+    for component in p.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => { canonical.pop(); }
+            component => canonical.push(component.as_os_str()),
+        }
+    }
+    stat(&canonical)?;
+    Ok(canonical)
+}
+
+pub fn copy(from: &Path, to: &Path) -> io::Result<u64> {
+    use fs::File;
+    if !from.is_file() {
+        return Err(Error::new(ErrorKind::InvalidInput,
+                              "the source path is not an existing regular file"))
+    }
+
+    let mut reader = File::open(from)?;
+    let mut writer = File::create(to)?;
+    io::copy(&mut reader, &mut writer)
+}
@@ -1,5 +1,9 @@
 use os::raw::c_char;
 
+pub mod fs;
+pub mod stdio;
+pub mod time;
+
 pub fn decode_error_kind(_errno: i32) -> ::io::ErrorKind {
     ::io::ErrorKind::Other
 }
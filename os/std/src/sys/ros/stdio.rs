@@ -0,0 +1,87 @@
+//! The standard streams, on whatever console the kernel registers with
+//! `io::set_console`. Until it does, output is discarded and input is at its
+//! end, as if the streams were closed.
+
+use io;
+use sync::Mutex;
+
+/// The kernel's functions for reading from and writing to its console.
+#[derive(Clone, Copy)]
+struct Console {
+    read: fn(&mut [u8]) -> io::Result<usize>,
+    write: fn(&[u8]) -> io::Result<usize>,
+}
+
+static CONSOLE: Mutex<Option<Console>> = Mutex::new(None);
+
+/// Makes `read` and `write` back standard input, and standard output and
+/// error, from now on.
+pub fn set_console(read: fn(&mut [u8]) -> io::Result<usize>, write: fn(&[u8]) -> io::Result<usize>) {
+    *CONSOLE.lock().unwrap() = Some(Console { read, write });
+}
+
+/// Returns the registered console. It's copied out, so the lock isn't held
+/// while the console is in use.
+fn console() -> Option<Console> {
+    *CONSOLE.lock().unwrap()
+}
+
+pub struct Stdin(());
+pub struct Stdout(());
+pub struct Stderr(());
+
+impl Stdin {
+    pub fn new() -> io::Result<Stdin> { Ok(Stdin(())) }
+
+    pub fn read(&self, data: &mut [u8]) -> io::Result<usize> {
+        match console() {
+            Some(console) => (console.read)(data),
+            None => Ok(0),
+        }
+    }
+}
+
+impl Stdout {
+    pub fn new() -> io::Result<Stdout> { Ok(Stdout(())) }
+
+    pub fn write(&self, data: &[u8]) -> io::Result<usize> {
+        match console() {
+            Some(console) => (console.write)(data),
+            None => Ok(data.len()),
+        }
+    }
+
+    /// The console is unbuffered; `io::stdout` buffers lines on top of it.
+    pub fn flush(&self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Stderr {
+    pub fn new() -> io::Result<Stderr> { Ok(Stderr(())) }
+
+    pub fn write(&self, data: &[u8]) -> io::Result<usize> {
+        Stdout(()).write(data)
+    }
+
+    pub fn flush(&self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl io::Write for Stderr {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        Stderr::write(self, data)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Stderr::flush(self)
+    }
+}
+
+/// There are no file descriptors to be bad.
+pub fn is_ebadf(_err: &io::Error) -> bool {
+    false
+}
+
+pub const STDIN_BUF_SIZE: usize = 512;
@@ -0,0 +1,100 @@
+//! `Instant` and `SystemTime`, in microseconds on the clock the kernel
+//! registers with `time::set_clock`. Until it does, the clock stands still at
+//! 0. `SystemTime` is the clock plus the time set with
+//! `time::set_system_time`; until that is called, the clock is taken to have
+//! started at `UNIX_EPOCH`.
+
+use sync::Mutex;
+use time::Duration;
+
+/// The kernel's clock: microseconds since boot, never going backwards.
+static CLOCK: Mutex<Option<fn() -> u64>> = Mutex::new(None);
+
+/// Microseconds from `UNIX_EPOCH` to the time the clock read 0.
+static BOOT_TIME: Mutex<u64> = Mutex::new(0);
+
+pub fn set_clock(now: fn() -> u64) {
+    *CLOCK.lock().unwrap() = Some(now);
+}
+
+/// Makes `SystemTime::now` return `now` at this moment, and the clock's
+/// progress from it after.
+pub fn set_system_time(now: SystemTime) {
+    *BOOT_TIME.lock().unwrap() = now.us.saturating_sub(clock());
+}
+
+/// Reads the registered clock, or returns 0 if there's none. The lock isn't
+/// held while it's read.
+fn clock() -> u64 {
+    let clock = *CLOCK.lock().unwrap();
+    clock.map_or(0, |now| now())
+}
+
+/// Returns `dur` in whole microseconds, or `None` if that overflows a `u64`.
+fn micros(dur: &Duration) -> Option<u64> {
+    dur.as_secs()
+        .checked_mul(1_000_000)?
+        .checked_add(dur.subsec_micros() as u64)
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Hash)]
+pub struct Instant {
+    us: u64,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Hash)]
+pub struct SystemTime {
+    us: u64,
+}
+
+pub const UNIX_EPOCH: SystemTime = SystemTime { us: 0 };
+
+impl Instant {
+    pub fn now() -> Instant {
+        Instant { us: clock() }
+    }
+
+    pub fn sub_instant(&self, other: &Instant) -> Duration {
+        let us = self.us.checked_sub(other.us)
+            .expect("other was created after self");
+        Duration::from_micros(us)
+    }
+
+    pub fn add_duration(&self, other: &Duration) -> Instant {
+        let us = micros(other).and_then(|us| self.us.checked_add(us))
+            .expect("overflow when adding duration to instant");
+        Instant { us }
+    }
+
+    pub fn sub_duration(&self, other: &Duration) -> Instant {
+        let us = micros(other).and_then(|us| self.us.checked_sub(us))
+            .expect("overflow when subtracting duration from instant");
+        Instant { us }
+    }
+}
+
+impl SystemTime {
+    pub fn now() -> SystemTime {
+        let boot_time = *BOOT_TIME.lock().unwrap();
+        SystemTime { us: boot_time.saturating_add(clock()) }
+    }
+
+    pub fn sub_time(&self, other: &SystemTime) -> Result<Duration, Duration> {
+        match self.us.checked_sub(other.us) {
+            Some(us) => Ok(Duration::from_micros(us)),
+            None => Err(Duration::from_micros(other.us - self.us)),
+        }
+    }
+
+    pub fn add_duration(&self, other: &Duration) -> SystemTime {
+        let us = micros(other).and_then(|us| self.us.checked_add(us))
+            .expect("overflow when adding duration to time");
+        SystemTime { us }
+    }
+
+    pub fn sub_duration(&self, other: &Duration) -> SystemTime {
+        let us = micros(other).and_then(|us| self.us.checked_sub(us))
+            .expect("overflow when subtracting duration from time");
+        SystemTime { us }
+    }
+}
@@ -26,7 +26,7 @@ use error::Error;
 use fmt;
 use ops::{Add, Sub, AddAssign, SubAssign};
 use sys::time;
-use sys_common::FromInner;
+use sys_common::{AsInner, FromInner};
 
 #[stable(feature = "time", since = "1.3.0")]
 pub use self::duration::Duration;
@@ -446,6 +446,30 @@ impl FromInner<time::SystemTime> for SystemTime {
     }
 }
 
+//- Added.
+impl AsInner<time::SystemTime> for SystemTime {
+    fn as_inner(&self) -> &time::SystemTime {
+        &self.0
+    }
+}
+
+//- Added: the kernel's side of the clocks.
+/// Makes `now` the clock behind `Instant` and `SystemTime`. It returns the
+/// microseconds since boot and must never go backwards. Until this is called,
+/// the clock stands still at 0.
+#[stable(feature = "rust1", since = "1.0.0")]
+pub fn set_clock(now: fn() -> u64) {
+    time::set_clock(now)
+}
+
+/// Makes `SystemTime::now()` return `now` at this moment, and advance with the
+/// clock from there. Until this is called, the clock is taken to have started
+/// at `UNIX_EPOCH`.
+#[stable(feature = "rust1", since = "1.0.0")]
+pub fn set_system_time(now: SystemTime) {
+    time::set_system_time(now.0)
+}
+
 #[cfg(test)]
 mod tests {
     use super::{Instant, SystemTime, Duration, UNIX_EPOCH};
@@ -0,0 +1,324 @@
+//! Decodes the output of the kernel shell's `trace dump`: merges the records
+//! of all cores into one timeline, pairs SD card operations with their
+//! completions, and summarizes what the heap and the SD card were doing.
+//!
+//! Usage: `trace-decode [--summary] [FILE]`. Reads standard input without
+//! `FILE`, so a console log can be piped in as is: lines that aren't records,
+//! such as the shell prompt, are skipped.
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::process;
+
+const USAGE: &str = "usage: trace-decode [--summary] [FILE]";
+
+/// Operations traced as a start and a done event with the same `a`, and their
+/// names. The done event's `b` is 0 or an error code.
+const OPERATIONS: &[(&str, &str, &str)] = &[
+    ("sd_init_start", "sd_init_done", "sd init"),
+    ("sd_read_start", "sd_read_done", "sd read"),
+];
+
+/// How many live allocations the summary lists.
+const MAX_LISTED: usize = 10;
+
+/// A record as printed by `trace dump`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Record {
+    /// Microseconds since boot.
+    time: u64,
+    core: usize,
+    event: String,
+    a: u64,
+    b: u64,
+}
+
+impl Record {
+    /// Parses a tab-separated `time core event a b` line, with `a` and `b` in
+    /// hex. Returns `None` if `line` isn't one.
+    fn parse(line: &str) -> Option<Record> {
+        let mut fields = line.trim().split('\t');
+        let record = Record {
+            time: fields.next()?.parse().ok()?,
+            core: fields.next()?.parse().ok()?,
+            event: fields.next()?.to_string(),
+            a: parse_hex(fields.next()?)?,
+            b: parse_hex(fields.next()?)?,
+        };
+        match fields.next() {
+            None => Some(record),
+            Some(_) => None,
+        }
+    }
+
+    /// Describes `a` and `b` as what they mean for the record's event.
+    fn describe(&self) -> String {
+        match self.event.as_str() {
+            "sd_init_start" => String::new(),
+            "sd_init_done" => format!("code={}", self.b as i64),
+            "sd_read_start" => format!("sector={}", self.a),
+            "sd_read_done" => format!("sector={} err={}", self.a, self.b as i64),
+            "alloc" | "dealloc" => format!("ptr={:#x} size={}", self.a, self.b),
+            _ => format!("a={:#x} b={:#x}", self.a, self.b),
+        }
+    }
+}
+
+fn parse_hex(s: &str) -> Option<u64> {
+    u64::from_str_radix(s.strip_prefix("0x")?, 16).ok()
+}
+
+/// Parses every record in `input`, sorted by time. Also returns the number of
+/// non-empty lines that weren't records.
+fn parse(input: &str) -> (Vec<Record>, usize) {
+    let mut records = vec![];
+    let mut skipped = 0;
+    for line in input.lines().filter(|line| !line.trim().is_empty()) {
+        match Record::parse(line) {
+            Some(record) => records.push(record),
+            None => skipped += 1,
+        }
+    }
+    // `trace dump` prints core by core. The sort is stable, so records with
+    // the same time stay in the order one core recorded them.
+    records.sort_by_key(|record| record.time);
+    (records, skipped)
+}
+
+/// Durations of one kind of operation, in microseconds.
+#[derive(Debug, Default)]
+struct Latencies {
+    count: u64,
+    errors: u64,
+    total: u64,
+    min: u64,
+    max: u64,
+}
+
+impl Latencies {
+    fn add(&mut self, us: u64, failed: bool) {
+        self.min = if self.count == 0 { us } else { self.min.min(us) };
+        self.max = self.max.max(us);
+        self.count += 1;
+        self.total += us;
+        self.errors += failed as u64;
+    }
+}
+
+/// What has been seen so far in a timeline.
+#[derive(Debug, Default)]
+struct Summary {
+    counts: BTreeMap<String, u64>,
+    latencies: BTreeMap<&'static str, Latencies>,
+    /// Operations started but not done yet, by name, core and `a`, with the
+    /// time they started.
+    pending: BTreeMap<(&'static str, usize, u64), u64>,
+    /// Allocations not freed yet, by address, with their size and time.
+    live: BTreeMap<u64, (u64, u64)>,
+    allocated: u64,
+    freed: u64,
+}
+
+impl Summary {
+    /// Adds `record`. Returns how long the operation took if `record`
+    /// completes one.
+    fn add(&mut self, record: &Record) -> Option<u64> {
+        *self.counts.entry(record.event.clone()).or_insert(0) += 1;
+        match record.event.as_str() {
+            "alloc" => {
+                self.allocated += record.b;
+                self.live.insert(record.a, (record.b, record.time));
+            }
+            // Memory allocated before tracing started is freed too.
+            "dealloc" => {
+                self.freed += record.b;
+                self.live.remove(&record.a);
+            }
+            _ => {}
+        }
+
+        for &(start, done, name) in OPERATIONS {
+            if record.event == start {
+                self.pending.insert((name, record.core, record.a), record.time);
+            } else if record.event == done {
+                let started = self.pending.remove(&(name, record.core, record.a))?;
+                let us = record.time - started;
+                self.latencies.entry(name).or_default().add(us, record.b != 0);
+                return Some(us);
+            }
+        }
+        None
+    }
+
+    fn write(&self, out: &mut dyn Write) -> io::Result<()> {
+        writeln!(out, "events:")?;
+        for (event, count) in &self.counts {
+            writeln!(out, "  {:<16}{}", event, count)?;
+        }
+
+        for (name, l) in &self.latencies {
+            writeln!(
+                out,
+                "{}: {} done, {} failed, min {}us, avg {}us, max {}us",
+                name,
+                l.count,
+                l.errors,
+                l.min,
+                l.total / l.count,
+                l.max
+            )?;
+        }
+        // A hang inside an operation shows up as one that never finished.
+        for ((name, core, a), time) in &self.pending {
+            writeln!(out, "{} {:#x} on core {} started at {}us, never done", name, a, core, time)?;
+        }
+
+        if self.counts.contains_key("alloc") || self.counts.contains_key("dealloc") {
+            let bytes: u64 = self.live.values().map(|&(size, _)| size).sum();
+            writeln!(
+                out,
+                "heap: {} bytes allocated, {} freed; {} allocations ({} bytes) still live",
+                self.allocated,
+                self.freed,
+                self.live.len(),
+                bytes
+            )?;
+            let mut largest: Vec<_> = self.live.iter().collect();
+            largest.sort_by_key(|&(_, &(size, _))| std::cmp::Reverse(size));
+            for (ptr, (size, time)) in largest.into_iter().take(MAX_LISTED) {
+                writeln!(out, "  {:#x}: {} bytes, allocated at {}us", ptr, size, time)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Writes the timeline of `records`, unless `summary_only` is set, then the
+/// summary.
+fn decode(records: &[Record], summary_only: bool, out: &mut dyn Write) -> io::Result<()> {
+    let mut summary = Summary::default();
+    let mut prev = records.first().map_or(0, |record| record.time);
+    for record in records {
+        let took = summary.add(record);
+        if summary_only {
+            continue;
+        }
+
+        write!(
+            out,
+            "{:>12} {:>+9} core {} {:<15} {}",
+            record.time,
+            record.time - prev,
+            record.core,
+            record.event,
+            record.describe()
+        )?;
+        match took {
+            Some(us) => writeln!(out, " ({}us)", us)?,
+            None => writeln!(out)?,
+        }
+        prev = record.time;
+    }
+
+    if !summary_only {
+        writeln!(out)?;
+    }
+    summary.write(out)
+}
+
+fn main() {
+    let mut summary_only = false;
+    let mut path = None;
+    for arg in env::args().skip(1) {
+        match arg.as_str() {
+            "-s" | "--summary" => summary_only = true,
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return;
+            }
+            _ if path.is_none() && !arg.starts_with('-') => path = Some(arg),
+            _ => {
+                eprintln!("{}", USAGE);
+                process::exit(2);
+            }
+        }
+    }
+
+    let input = match &path {
+        Some(path) => fs::read_to_string(path),
+        None => {
+            let mut input = String::new();
+            io::stdin().read_to_string(&mut input).map(|_| input)
+        }
+    };
+    let input = input.unwrap_or_else(|e| {
+        eprintln!("trace-decode: {}", e);
+        process::exit(1);
+    });
+
+    let (records, skipped) = parse(&input);
+    if skipped > 0 {
+        eprintln!("trace-decode: skipped {} lines that aren't trace records", skipped);
+    }
+    if let Err(e) = decode(&records, summary_only, &mut io::stdout().lock()) {
+        eprintln!("trace-decode: {}", e);
+        process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decoded(input: &str, summary_only: bool) -> String {
+        let mut out = vec![];
+        decode(&parse(input).0, summary_only, &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn parses_records_and_skips_the_rest() {
+        let input = "> trace dump\r\n120\t1\tsd_read_start\t0x5\t0x0\r\n\r\n\
+                     100\t0\talloc\t0x1000\t0x40\r\n100\t0\tbogus\n> ";
+        let (records, skipped) = parse(input);
+        assert_eq!(skipped, 3);
+        assert_eq!(
+            records,
+            [
+                Record { time: 100, core: 0, event: "alloc".into(), a: 0x1000, b: 0x40 },
+                Record { time: 120, core: 1, event: "sd_read_start".into(), a: 5, b: 0 },
+            ]
+        );
+    }
+
+    #[test]
+    fn pairs_operations_per_core() {
+        let input = "10\t0\tsd_read_start\t0x7\t0x0\n\
+                     12\t1\tsd_read_start\t0x7\t0x0\n\
+                     40\t0\tsd_read_done\t0x7\t0x0\n\
+                     100\t1\tsd_read_done\t0x7\t0xffffffffffffffff\n";
+        let out = decoded(input, false);
+        assert!(out.contains("sd_read_done    sector=7 err=0 (30us)"), "{}", out);
+        assert!(out.contains("sd_read_done    sector=7 err=-1 (88us)"), "{}", out);
+        assert!(out.contains("sd read: 2 done, 1 failed, min 30us, avg 59us, max 88us"), "{}", out);
+    }
+
+    #[test]
+    fn reports_unfinished_operations() {
+        let out = decoded("10\t2\tsd_read_start\t0x2a\t0x0\n", true);
+        assert_eq!(out.lines().last(), Some("sd read 0x2a on core 2 started at 10us, never done"));
+    }
+
+    #[test]
+    fn tracks_live_allocations() {
+        let input = "1\t0\talloc\t0x1000\t0x10\n\
+                     2\t0\talloc\t0x2000\t0x20\n\
+                     3\t0\tdealloc\t0x1000\t0x10\n\
+                     4\t0\tdealloc\t0x9000\t0x8\n";
+        let out = decoded(input, true);
+        assert!(out.contains("heap: 48 bytes allocated, 24 freed; 1 allocations (32 bytes) still live"));
+        assert!(out.contains("  0x2000: 32 bytes, allocated at 2us"));
+    }
+}